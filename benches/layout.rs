@@ -0,0 +1,86 @@
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use textslabs::*;
+use wgpu::*;
+
+const PARAGRAPH: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat.\n";
+
+fn document(paragraphs: usize) -> String {
+    PARAGRAPH.repeat(paragraphs)
+}
+
+fn typing(c: &mut Criterion) {
+    let mut text = Text::new_without_auto_wakeup();
+    let handle = text.add_text_edit(document(20), (0.0, 0.0), (600.0, 400.0), 0.0);
+    text.refresh_text_edit_layout(&handle);
+    let initial_text = document(20);
+
+    c.bench_function("type a character in a 20 paragraph edit", |b| {
+        b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                text.get_text_edit_mut(&handle).set_text(initial_text.clone());
+                text.refresh_text_edit_layout(&handle);
+
+                let start = Instant::now();
+                text.get_text_edit_mut(&handle).type_text("a");
+                total += start.elapsed();
+            }
+            total
+        })
+    });
+
+    text.remove_text_edit(handle);
+}
+
+fn relayout(c: &mut Criterion) {
+    let mut text = Text::new_without_auto_wakeup();
+    let handle = text.add_text_box(document(500), (0.0, 0.0), (600.0, 400.0), 0.0);
+    text.refresh_text_box_layout(&handle);
+
+    let mut width = 600.0;
+    c.bench_function("relayout a 500 paragraph document", |b| {
+        b.iter(|| {
+            // Changing the width forces a full relayout.
+            width = if width == 600.0 { 601.0 } else { 600.0 };
+            text.get_text_box_mut(&handle).set_size((width, 400.0));
+            text.refresh_text_box_layout(&handle);
+        })
+    });
+
+    text.remove_text_box(handle);
+}
+
+fn prepare_all(c: &mut Criterion) {
+    let instance = Instance::new(InstanceDescriptor::default());
+    let Some(adapter) = pollster::block_on(instance.request_adapter(&RequestAdapterOptions::default())) else {
+        eprintln!("No GPU adapter available, skipping the prepare_all benchmark");
+        return;
+    };
+    let (device, queue) = pollster::block_on(adapter.request_device(&DeviceDescriptor::default(), None)).unwrap();
+    let mut text_renderer = TextRenderer::new(&device, &queue, TextureFormat::Bgra8UnormSrgb);
+
+    let mut text = Text::new_without_auto_wakeup();
+    let handles: Vec<TextBoxHandle> = (0..100)
+        .map(|i| text.add_text_box(document(1), (0.0, i as f64 * 50.0), (600.0, 50.0), 0.0))
+        .collect();
+
+    let mut left = 0.0;
+    c.bench_function("prepare_all with 100 boxes", |b| {
+        b.iter(|| {
+            // Moving a box makes the next prepare_all redo its work.
+            left = if left == 0.0 { 1.0 } else { 0.0 };
+            text.get_text_box_mut(&handles[0]).set_pos((left, 0.0));
+            text.prepare_all(&mut text_renderer);
+            text_renderer.gpu_load(&device, &queue);
+        })
+    });
+
+    for handle in handles {
+        text.remove_text_box(handle);
+    }
+}
+
+criterion_group!(benches, typing, relayout, prepare_all);
+criterion_main!(benches);
@@ -49,6 +49,7 @@ impl State {
 
         let text_renderer_params = TextRendererParams {
             atlas_page_size: AtlasPageSize::Flat(300), // tiny page to test out multi-page stuff
+            ..Default::default()
         };
         
         let text_renderer = TextRenderer::new_with_params(&device, &queue, surface_config.format, None, text_renderer_params);
@@ -1,4 +1,5 @@
-// This example shows how you can draw text on multiple windows by just making separate Text and TextRenderer structs. There is no further multi-window support at the moment. 
+// This example shows how you can draw text on multiple windows by making a separate Text and TextRenderer for each window. The windows share the same wgpu device and the same GlyphCache, and fonts and layouts are shared between all Text structs on the same thread.
+// It's also possible to prepare the same Text for more than one TextRenderer, to show the same scene in several windows. See Text::prepare_all() for the details.
 
 use textslabs::*;
 use std::sync::Arc;
@@ -18,8 +19,6 @@ fn main() {
 }
 
 struct WindowState {
-    device: wgpu::Device,
-    queue: wgpu::Queue,
     surface: wgpu::Surface<'static>,
     surface_config: SurfaceConfiguration,
     window: Arc<Window>,
@@ -29,41 +28,52 @@ struct WindowState {
 }
 
 struct State {
+    instance: Instance,
+    adapter: Adapter,
+    device: Device,
+    queue: Queue,
     windows: Vec<WindowState>,
 }
 
 impl State {
     fn new() -> Self {
+        let instance = Instance::new(InstanceDescriptor::default());
+        let adapter =
+            pollster::block_on(instance.request_adapter(&RequestAdapterOptions::default()))
+                .unwrap();
+        let (device, queue) =
+            pollster::block_on(adapter.request_device(&DeviceDescriptor::default(), None)).unwrap();
         Self {
+            instance,
+            adapter,
+            device,
+            queue,
             windows: Vec::new(),
         }
     }
 
     fn add_window(&mut self, window: Arc<Window>, window_title: &str) {
         let physical_size = window.inner_size();
-        let instance = Instance::new(InstanceDescriptor::default());
-        let adapter =
-            pollster::block_on(instance.request_adapter(&RequestAdapterOptions::default()))
-                .unwrap();
-        let (device, queue) =
-            pollster::block_on(adapter.request_device(&DeviceDescriptor::default(), None)).unwrap();
-        let surface = instance
+        let surface = self.instance
             .create_surface(window.clone())
             .expect("Create surface");
         let surface_config = surface
-            .get_default_config(&adapter, physical_size.width, physical_size.height)
+            .get_default_config(&self.adapter, physical_size.width, physical_size.height)
             .unwrap();
-        surface.configure(&device, &surface_config);
-
-        let text_renderer = TextRenderer::new(&device, &queue, surface_config.format);
+        surface.configure(&self.device, &surface_config);
+
+        // The renderers share their glyph cache, so each glyph is rasterized once for all windows.
+        let params = TextRendererParams {
+            glyph_cache: self.windows.first().map(|window_state| window_state.text_renderer.glyph_cache()),
+            ..Default::default()
+        };
+        let text_renderer = TextRenderer::new_with_params(&self.device, &self.queue, surface_config.format, None, params);
         let mut text = Text::new_without_auto_wakeup();
         
         let _text_box = text.add_text_box(format!("Text in {}", window_title), (50.0, 50.0), (400.0, 100.0), 0.0);
         let _text_edit = text.add_text_edit( format!("Edit text in {}", window_title), (50.0, 200.0), (400.0, 40.0), 0.0);
 
         self.windows.push(WindowState {
-            device,
-            queue,
             surface,
             surface_config,
             window,
@@ -75,11 +85,11 @@ impl State {
     fn render_window(&mut self, window_id: winit::window::WindowId) {
         if let Some(window_state) = self.windows.iter_mut().find(|w| w.window.id() == window_id) {
             window_state.text.prepare_all(&mut window_state.text_renderer);
-            window_state.text_renderer.gpu_load(&window_state.device, &window_state.queue);
+            window_state.text_renderer.gpu_load(&self.device, &self.queue);
 
             let frame = window_state.surface.get_current_texture().unwrap();
             let view = frame.texture.create_view(&TextureViewDescriptor::default());
-            let mut encoder = window_state.device.create_command_encoder(&CommandEncoderDescriptor::default());
+            let mut encoder = self.device.create_command_encoder(&CommandEncoderDescriptor::default());
 
             {
                 let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
@@ -97,7 +107,7 @@ impl State {
                 window_state.text_renderer.render(&mut render_pass);
             }
 
-            window_state.queue.submit(std::iter::once(encoder.finish()));
+            self.queue.submit(std::iter::once(encoder.finish()));
             frame.present();
             window_state.window.request_redraw();
         }
@@ -151,7 +161,7 @@ impl winit::application::ApplicationHandler for Application {
                     if let Some(window_state) = state.windows.iter_mut().find(|w| w.window.id() == window_id) {
                         window_state.surface_config.width = physical_size.width.max(1);
                         window_state.surface_config.height = physical_size.height.max(1);
-                        window_state.surface.configure(&window_state.device, &window_state.surface_config);
+                        window_state.surface.configure(&state.device, &window_state.surface_config);
                     }
                 }
                 WindowEvent::RedrawRequested => {
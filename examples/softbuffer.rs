@@ -0,0 +1,91 @@
+// Text editing without wgpu: the text is rasterized on the CPU with `Text::render_to_image()` and presented with softbuffer.
+// Useful for small tools, or for X forwarding and other environments without a GPU.
+use textslabs::*;
+use image::RgbaImage;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use winit::{dpi::LogicalSize, event::WindowEvent, event_loop::EventLoop, window::Window};
+
+fn main() {
+    let event_loop = EventLoop::new().unwrap();
+    event_loop.run_app(&mut Application { state: None }).unwrap();
+}
+
+struct State {
+    surface: softbuffer::Surface<Arc<Window>, Arc<Window>>,
+    window: Arc<Window>,
+    image: RgbaImage,
+    text: Text,
+}
+
+impl State {
+    fn new(window: Arc<Window>) -> Self {
+        let context = softbuffer::Context::new(window.clone()).unwrap();
+        let surface = softbuffer::Surface::new(&context, window.clone()).unwrap();
+
+        let mut text = Text::new(window.clone());
+        let _text_edit_handle = text.add_text_edit("Type here...".to_string(), (50.0, 50.0), (400.0, 40.0), 0.0);
+        let _text_box_handle = text.add_text_box("This window doesn't use the GPU.", (50.0, 120.0), (400.0, 40.0), 0.0);
+
+        Self { surface, window, image: RgbaImage::new(1, 1), text }
+    }
+
+    fn render(&mut self) {
+        let size = self.window.inner_size();
+        let (Some(width), Some(height)) = (NonZeroU32::new(size.width), NonZeroU32::new(size.height)) else {
+            return;
+        };
+        self.surface.resize(width, height).unwrap();
+
+        if self.image.dimensions() != (size.width, size.height) {
+            self.image = RgbaImage::new(size.width, size.height);
+        }
+        // Background color.
+        for pixel in self.image.pixels_mut() {
+            pixel.0 = [0x20, 0x20, 0x20, 0xff];
+        }
+        self.text.render_to_image(&mut self.image);
+
+        // softbuffer wants 0RGB pixels.
+        let mut buffer = self.surface.buffer_mut().unwrap();
+        for (dst, pixel) in buffer.iter_mut().zip(self.image.pixels()) {
+            let [r, g, b, _] = pixel.0;
+            *dst = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+        }
+        buffer.present().unwrap();
+    }
+}
+
+struct Application { state: Option<State> }
+
+impl winit::application::ApplicationHandler for Application {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if self.state.is_none() {
+            let window = Arc::new(event_loop.create_window(
+                Window::default_attributes()
+                    .with_title("Softbuffer Example")
+                    .with_inner_size(LogicalSize::new(500, 300))
+            ).unwrap());
+            self.state = Some(State::new(window));
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &winit::event_loop::ActiveEventLoop, _: winit::window::WindowId, event: WindowEvent) {
+        let state = self.state.as_mut().unwrap();
+
+        state.text.handle_event(&event, &state.window);
+
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::RedrawRequested => {
+                state.render();
+            },
+            _ => {}
+        }
+
+        // Redraw only when something changed, since every frame is rasterized from scratch.
+        if state.text.need_rerender() {
+            state.window.request_redraw();
+        }
+    }
+}
@@ -28,12 +28,10 @@ impl Text {
                 if let Some(accesskit::ActionData::SetTextSelection(selection)) = &request.data {
                     let mut text_box = match target_box {
                         AnyBox::TextEdit(i) => {
-                            let handle = TextEditHandle { i };
-                            self.get_text_edit_mut(&handle).text_box
+                            self.get_full_text_edit_at(i).text_box
                         }
                         AnyBox::TextBox(i) => {
-                            let handle = TextBoxHandle { i };
-                            self.get_text_box_mut(&handle)
+                            self.get_full_text_box_at(i)
                         }
                     };
 
@@ -43,6 +41,7 @@ impl Text {
                         &text_box.inner.layout_access
                     ) {
                         text_box.set_selection(selection);
+                        text_box.shared.decorations_changed = true;
                         return true;
                     }
                 }
@@ -51,8 +50,7 @@ impl Text {
                 if let Some(accesskit::ActionData::Value(text)) = &request.data {
                     match target_box {
                         AnyBox::TextEdit(i) => {
-                            let handle = TextEditHandle { i };
-                            self.get_text_edit_mut(&handle).replace_selection(&text);
+                            self.get_full_text_edit_at(i).replace_selection(&text);
                             return true;
                         }
                         _ => {}
@@ -0,0 +1,131 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+use crate::*;
+
+/// Progress of an asynchronous layout, passed to the callback set with [`Text::set_layout_progress_callback()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayoutProgress {
+    /// How many bytes of the text are laid out in the layout that's currently displayed.
+    pub laid_out_bytes: usize,
+    /// The total length of the text.
+    pub total_bytes: usize,
+}
+
+impl LayoutProgress {
+    pub fn is_done(&self) -> bool {
+        self.laid_out_bytes >= self.total_bytes
+    }
+}
+
+/// Returned by [`Text::prepare_async()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsyncPrepare {
+    /// How many boxes are still waiting for their layout from the background thread.
+    pub pending_layouts: usize,
+}
+
+impl AsyncPrepare {
+    /// Returns true if all layouts were up to date when the frame was prepared.
+    pub fn is_ready(&self) -> bool {
+        self.pending_layouts == 0
+    }
+}
+
+/// Everything needed to build a layout, owned so that it can be sent to the layout worker thread.
+pub(crate) struct LayoutJob {
+    pub(crate) text: String,
+    pub(crate) style: TextStyle2,
+    pub(crate) spans: OwnedLayoutSpans,
+    pub(crate) color_override: Option<ColorBrush>,
+    pub(crate) single_line: bool,
+    pub(crate) max_advance: f32,
+    pub(crate) alignment: Alignment,
+    pub(crate) scale: f32,
+}
+
+impl LayoutJob {
+    pub(crate) fn build(&self) -> Layout<ColorBrush> {
+        build_layout(
+            &self.text,
+            &self.style,
+            self.spans.as_refs(),
+            self.color_override,
+            self.single_line,
+            self.max_advance,
+            self.alignment,
+            self.scale,
+        )
+    }
+}
+
+/// A layout that's being built on the worker thread. The box keeps displaying its previous layout until it arrives.
+///
+/// Dropping it cancels the job, so that the worker skips it if it didn't start yet.
+pub(crate) struct PendingLayout {
+    pub(crate) receiver: Receiver<Layout<ColorBrush>>,
+    pub(crate) total_bytes: usize,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Drop for PendingLayout {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl PendingLayout {
+    /// Returns the finished layout if it's ready. If the worker went away, the pending layout is also considered finished, but there's nothing to swap in.
+    pub(crate) fn try_take(&self) -> Result<Option<Layout<ColorBrush>>, ()> {
+        match self.receiver.try_recv() {
+            Ok(layout) => Ok(Some(layout)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(()),
+        }
+    }
+
+    /// Block until the layout is ready. Returns an error if the worker went away.
+    pub(crate) fn wait(&self) -> Result<Option<Layout<ColorBrush>>, ()> {
+        self.receiver.recv().map(Some).map_err(|_| ())
+    }
+}
+
+type WorkItem = (LayoutJob, Sender<Layout<ColorBrush>>, Arc<AtomicBool>);
+
+static LAYOUT_WORKER: OnceLock<Mutex<Sender<WorkItem>>> = OnceLock::new();
+
+/// Send a layout job to the worker thread, starting it if needed.
+///
+/// The worker has its own font and layout contexts, so it doesn't interfere with layouts built on the main thread. If a job's result is no longer wanted, the [`PendingLayout`] can just be dropped: jobs that were superseded before the worker got to them are skipped, so typing in a long text doesn't pile up a full layout per keystroke.
+pub(crate) fn spawn_layout_job(job: LayoutJob) -> PendingLayout {
+    let total_bytes = job.text.len();
+    let (result_sender, receiver) = mpsc::channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    let worker = LAYOUT_WORKER.get_or_init(|| {
+        let (sender, jobs) = mpsc::channel::<WorkItem>();
+        thread::Builder::new()
+            .name("textslabs layout".into())
+            .spawn(move || {
+                for (job, result_sender, cancelled) in jobs {
+                    if cancelled.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    profile_span!("async layout job");
+                    let layout = job.build();
+                    result_sender.send(layout).ok();
+                }
+            })
+            .expect("Failed to spawn layout worker thread");
+        Mutex::new(sender)
+    });
+    worker.lock().unwrap().send((job, result_sender, cancelled.clone())).ok();
+
+    PendingLayout {
+        receiver,
+        total_bytes,
+        cancelled,
+    }
+}
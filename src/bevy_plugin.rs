@@ -0,0 +1,172 @@
+use bevy::app::{App, Plugin, PreUpdate};
+use bevy::core_pipeline::core_2d::graph::{Core2d, Node2d};
+use bevy::core_pipeline::core_3d::graph::{Core3d, Node3d};
+use bevy::ecs::prelude::*;
+use bevy::ecs::query::QueryItem;
+use bevy::render::render_graph::{NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner};
+use bevy::render::renderer::{RenderContext, RenderDevice, RenderQueue};
+use bevy::render::view::ViewTarget;
+use bevy::render::{ExtractSchedule, MainWorld, Render, RenderApp, RenderSet};
+use bevy::winit::{RawWinitWindowEvent, WinitWindows};
+use wgpu::{LoadOp, Operations, RenderPassColorAttachment, RenderPassDescriptor, StoreOp, TextureFormat};
+
+use crate::{Text, TextRenderer};
+
+/// A Bevy plugin that draws a [`Text`] on top of every 2D and 3D camera, and feeds it the window events.
+///
+/// The [`Text`] is in the [`TextslabsText`] resource of the main world, where systems can add and edit boxes like in any other app:
+///
+/// ```ignore
+/// fn setup(mut commands: Commands, mut text: ResMut<TextslabsText>) {
+///     commands.spawn(Camera2d);
+///     text.add_text_edit("Type here", (10.0, 10.0), (300.0, 40.0), 0.0);
+/// }
+///
+/// App::new()
+///     .add_plugins((DefaultPlugins, TextslabsPlugin))
+///     .add_systems(Startup, setup)
+///     .run();
+/// ```
+///
+/// The raw `winit` events are forwarded to [`Text::handle_event()`] in `PreUpdate`, so editing works without any glue, but Bevy's own input resources still see the same keys. [`Text::event_consumed()`] tells whether the last event went to a text box.
+///
+/// The text is prepared for the [`TextslabsRenderer`] in the render world during extraction, and drawn after the main pass and its post processing, before upscaling. Boxes are positioned in the physical pixels of the window, and the screen size follows the `Resized` events, so a single window is supported.
+///
+/// The cursor doesn't wake up the event loop when it blinks, so with Bevy's reactive update modes the blink only shows when something else causes an update.
+pub struct TextslabsPlugin;
+
+/// The [`Text`] drawn by [`TextslabsPlugin`].
+#[derive(Resource)]
+pub struct TextslabsText(pub Text);
+
+impl std::ops::Deref for TextslabsText {
+    type Target = Text;
+    fn deref(&self) -> &Text {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for TextslabsText {
+    fn deref_mut(&mut self) -> &mut Text {
+        &mut self.0
+    }
+}
+
+/// The [`TextRenderer`] used by [`TextslabsPlugin`]. It lives in the render world.
+#[derive(Resource)]
+pub struct TextslabsRenderer(pub TextRenderer);
+
+impl std::ops::Deref for TextslabsRenderer {
+    type Target = TextRenderer;
+    fn deref(&self) -> &TextRenderer {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for TextslabsRenderer {
+    fn deref_mut(&mut self) -> &mut TextRenderer {
+        &mut self.0
+    }
+}
+
+impl FromWorld for TextslabsRenderer {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let queue = world.resource::<RenderQueue>();
+        // The format of Bevy's main texture without HDR. It's switched in `upload_text` if the cameras use something else.
+        Self(TextRenderer::new(device.wgpu_device(), queue, TextureFormat::Rgba8UnormSrgb))
+    }
+}
+
+/// The render graph node that draws the text.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct TextslabsLabel;
+
+impl Plugin for TextslabsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TextslabsText(Text::new_without_auto_wakeup()))
+            .add_systems(PreUpdate, forward_window_events);
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .add_systems(ExtractSchedule, prepare_text)
+            .add_systems(Render, upload_text.in_set(RenderSet::Prepare))
+            .add_render_graph_node::<ViewNodeRunner<TextslabsNode>>(Core2d, TextslabsLabel)
+            .add_render_graph_edges(Core2d, (Node2d::EndMainPassPostProcessing, TextslabsLabel, Node2d::Upscaling))
+            .add_render_graph_node::<ViewNodeRunner<TextslabsNode>>(Core3d, TextslabsLabel)
+            .add_render_graph_edges(Core3d, (Node3d::EndMainPassPostProcessing, TextslabsLabel, Node3d::Upscaling));
+    }
+
+    fn finish(&self, app: &mut App) {
+        if let Some(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.init_resource::<TextslabsRenderer>();
+        }
+    }
+}
+
+fn forward_window_events(mut events: EventReader<RawWinitWindowEvent>, mut text: ResMut<TextslabsText>, winit_windows: NonSend<WinitWindows>) {
+    for event in events.read() {
+        // `get_window()` takes an entity, but raw events only carry the winit id.
+        if let Some(window) = winit_windows.windows.get(&event.window_id) {
+            text.handle_event(&event.event, window);
+        }
+    }
+}
+
+/// Runs during extraction, which happens on the main thread, so the layouts use the same fonts as the rest of the app.
+fn prepare_text(mut main_world: ResMut<MainWorld>, mut renderer: ResMut<TextslabsRenderer>) {
+    let Some(mut text) = main_world.get_resource_mut::<TextslabsText>() else {
+        return;
+    };
+    text.prepare_all(&mut renderer.0);
+}
+
+fn upload_text(mut renderer: ResMut<TextslabsRenderer>, views: Query<&ViewTarget>, device: Res<RenderDevice>, queue: Res<RenderQueue>) {
+    // The pipeline is built for a single format, so follow the cameras when they turn HDR on or off.
+    if let Some(view) = views.iter().next() {
+        let format = view.main_texture_format();
+        if format != renderer.0.text_renderer.format {
+            renderer.recreate(device.wgpu_device(), &queue, format);
+        }
+    }
+    renderer.gpu_load(device.wgpu_device(), &queue);
+}
+
+#[derive(Default)]
+struct TextslabsNode;
+
+impl ViewNode for TextslabsNode {
+    type ViewQuery = &'static ViewTarget;
+
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        view_target: QueryItem<'w, Self::ViewQuery>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        let Some(renderer) = world.get_resource::<TextslabsRenderer>() else {
+            return Ok(());
+        };
+        // Cameras with a different format than the first one are skipped.
+        if renderer.0.text_renderer.format != view_target.main_texture_format() {
+            return Ok(());
+        }
+
+        let mut pass = render_context.command_encoder().begin_render_pass(&RenderPassDescriptor {
+            label: Some("textslabs"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: view_target.main_texture_view(),
+                resolve_target: None,
+                ops: Operations { load: LoadOp::Load, store: StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        renderer.render(&mut pass);
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+use crate::*;
+
+/// Render a text box into a new RGBA image on the CPU, without a GPU device.
+///
+/// The image has the size of the box and starts out transparent. Only the text is drawn: image replacements, selections and other decorations are skipped. The box's layout should be up to date.
+///
+/// This is slower than [`TextRenderer::rasterize_box_to_texture()`], because glyphs aren't cached. It's meant for screenshot tests, thumbnails and headless programs.
+pub fn render_box_to_image(text_box: &TextBox) -> RgbaImage {
+    let width = (text_box.inner.width.ceil() as u32).max(1);
+    let height = (text_box.inner.height.ceil() as u32).max(1);
+    let mut image = RgbaImage::new(width, height);
+
+    let (scroll_x, scroll_y) = text_box.inner.scroll_offset;
+    render_layout_to_image(&text_box.inner.layout, &mut image, -scroll_x, -scroll_y);
+    image
+}
+
+/// Draw a layout into an existing RGBA image on the CPU, with its top left corner at (`left`, `top`). Glyphs are blended over the existing pixels.
+pub fn render_layout_to_image(layout: &Layout<ColorBrush>, image: &mut RgbaImage, left: f32, top: f32) {
+    let clip = [0, 0, image.width() as i32, image.height() as i32];
+    render_layout_clipped(layout, image, left, top, clip, &mut ScaleContext::new());
+}
+
+impl Text {
+    /// Draw all visible text boxes and text edits into an RGBA image on the CPU, for presenting without a GPU, for example with `softbuffer`. The image usually has the size of the window.
+    ///
+    /// Boxes are drawn back to front by depth, clipped like on the GPU, and blended over the existing pixels, so the image should be cleared first. The selection and cursor of the focused box are drawn on top. Image replacements, scrollbars, fades and other effects are skipped.
+    ///
+    /// This rasterizes every glyph each time it's called, so it's much slower than [`Text::prepare_all()`] with a [`TextRenderer`]. See the `softbuffer.rs` example.
+    pub fn render_to_image(&mut self, image: &mut RgbaImage) {
+        profile_span!("Text::render_to_image");
+        let bounds = [0, 0, image.width() as i32, image.height() as i32];
+        let mut scale_cx = ScaleContext::new();
+        self.poll_pending_layouts();
+        self.update_linked_labels();
+
+        let depth_order = self.take_depth_order();
+        for &(any_box, _) in &depth_order {
+            let mut text_box = match any_box {
+                AnyBox::TextEdit(i) => {
+                    let (text_edit, text_box) = &mut self.text_edits[i as usize];
+                    let mut text_edit = get_full_text_edit_free_function_but_for_iterating((text_edit, text_box), &mut self.shared);
+                    text_edit.refresh_layout_for_drawing();
+                    text_edit.text_box
+                }
+                AnyBox::TextBox(i) => {
+                    let mut text_box = get_full_text_box_free_function_but_for_iterating(&mut self.text_boxes[i as usize], &mut self.shared);
+                    text_box.refresh_layout_for_drawing();
+                    text_box
+                }
+            };
+            let (left, top, clip) = screen_clip(&mut text_box, bounds);
+            render_layout_clipped(&text_box.inner.layout, image, left, top, clip, &mut scale_cx);
+        }
+        self.depth_order = depth_order;
+
+        let (show_cursor, _) = self.cursor_blinked_out(true);
+        self.draw_focused_decorations(image, bounds, show_cursor);
+
+        // Renderers that this Text is also prepared for can't know what changed in the meantime.
+        self.scene_revision += 1;
+        self.end_frame();
+    }
+
+    fn draw_focused_decorations(&mut self, image: &mut RgbaImage, bounds: [i32; 4], show_cursor: bool) {
+        let (Some(focused), true) = (self.focused, self.window_focused) else {
+            return;
+        };
+        let mut text_box = match focused {
+            AnyBox::TextEdit(i) => {
+                let (_, text_box) = &mut self.text_edits[i as usize];
+                get_full_text_box_free_function_but_for_iterating(text_box, &mut self.shared)
+            }
+            AnyBox::TextBox(i) => get_full_text_box_free_function_but_for_iterating(&mut self.text_boxes[i as usize], &mut self.shared),
+        };
+        if text_box.hidden() {
+            return;
+        }
+        let (left, top, clip) = screen_clip(&mut text_box, bounds);
+        // The same colors as on the GPU, including the high contrast palette.
+        let (selection_color, cursor_color) = decoration_colors(&text_box.shared.system_settings, DEFAULT_SELECTION_COLOR);
+        text_box.selection().geometry_with(&text_box.inner.layout, |rect, _line_i| {
+            fill_rect(image, rect, left, top, clip, selection_color.to_be_bytes());
+        });
+        if show_cursor && matches!(focused, AnyBox::TextEdit(_)) && text_box.selection().is_collapsed() {
+            let rect = text_box.selection().focus().geometry(&text_box.inner.layout, CURSOR_WIDTH);
+            fill_rect(image, rect, left, top, clip, cursor_color.to_be_bytes());
+        }
+    }
+}
+
+/// The screen position of a box's content, and the area it's clipped to, intersected with `bounds`.
+fn screen_clip(text_box: &mut TextBoxMut, bounds: [i32; 4]) -> (f32, f32, [i32; 4]) {
+    let (left, top) = text_box.pos();
+    let (scroll_x, scroll_y) = text_box.scroll_offset();
+    let content_left = left as f32 - scroll_x;
+    let content_top = top as f32 - scroll_y;
+    let clip = match text_box.effective_clip_rect() {
+        Some(clip) => [
+            (content_left + clip.x0 as f32) as i32,
+            (content_top + clip.y0 as f32) as i32,
+            (content_left + clip.x1 as f32) as i32,
+            (content_top + clip.y1 as f32) as i32,
+        ],
+        None => bounds,
+    };
+    let clip = [clip[0].max(bounds[0]), clip[1].max(bounds[1]), clip[2].min(bounds[2]), clip[3].min(bounds[3])];
+    (content_left, content_top, clip)
+}
+
+fn fill_rect(image: &mut RgbaImage, rect: parley::Rect, left: f32, top: f32, clip: [i32; 4], color: [u8; 4]) {
+    let x0 = ((left as f64 + rect.x0) as i32).max(clip[0]);
+    let y0 = ((top as f64 + rect.y0) as i32).max(clip[1]);
+    let x1 = ((left as f64 + rect.x1) as i32).min(clip[2]);
+    let y1 = ((top as f64 + rect.y1) as i32).min(clip[3]);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let dst = image.get_pixel_mut(x as u32, y as u32);
+            dst.0 = blend_over(dst.0, color);
+        }
+    }
+}
+
+/// Draw a layout, only touching the pixels inside `clip`, which has to be inside the image.
+fn render_layout_clipped(layout: &Layout<ColorBrush>, image: &mut RgbaImage, left: f32, top: f32, clip: [i32; 4], scale_cx: &mut ScaleContext) {
+    if clip[2] <= clip[0] || clip[3] <= clip[1] {
+        return;
+    }
+    let mut glyph_image = Image::new();
+
+    for line in layout.lines() {
+        for item in line.items() {
+            let PositionedLayoutItem::GlyphRun(glyph_run) = item else {
+                continue;
+            };
+            let style = glyph_run.style();
+            // Same as on the GPU: transparent runs are skipped, which is also how hidden text stays hidden.
+            if style.brush.0[3] == 0 {
+                continue;
+            }
+
+            let run = glyph_run.run();
+            let font = run.font();
+            let font_size = run.font_size();
+            let font_ref = FontRef::from_index(font.data.as_ref(), font.index as usize).unwrap();
+            let font_key = font.data.id();
+
+            let mut scaler = scale_cx
+                .builder(font_ref)
+                .size(font_size)
+                .hint(true)
+                .normalized_coords(run.normalized_coords())
+                .build();
+
+            let mut run_x = left + glyph_run.offset();
+            let run_y = top + glyph_run.baseline();
+
+            for glyph in glyph_run.glyphs() {
+                let glyph_ctx = GlyphWithContext::new(glyph, run_x, run_y, font_key, font_size, style.brush);
+                run_x += glyph.advance;
+
+                glyph_image.clear();
+                let rendered = Render::new(SOURCES)
+                    .format(Format::Alpha)
+                    .offset(glyph_ctx.frac_offset())
+                    .render_into(&mut scaler, glyph_ctx.glyph.id, &mut glyph_image);
+                if !rendered {
+                    continue;
+                }
+
+                let x0 = glyph_ctx.quantized_pos_x + glyph_image.placement.left;
+                let y0 = glyph_ctx.quantized_pos_y - glyph_image.placement.top;
+                blend_glyph(image, &glyph_image, x0, y0, style.brush, clip);
+            }
+        }
+    }
+}
+
+fn blend_glyph(image: &mut RgbaImage, glyph_image: &Image, x0: i32, y0: i32, color: ColorBrush, clip: [i32; 4]) {
+    let (glyph_width, glyph_height) = (glyph_image.placement.width as i32, glyph_image.placement.height as i32);
+
+    for gy in 0..glyph_height {
+        let y = y0 + gy;
+        if y < clip[1] || y >= clip[3] {
+            continue;
+        }
+        for gx in 0..glyph_width {
+            let x = x0 + gx;
+            if x < clip[0] || x >= clip[2] {
+                continue;
+            }
+
+            let i = (gy * glyph_width + gx) as usize;
+            let src = match glyph_image.content {
+                Content::Mask => {
+                    let coverage = glyph_image.data[i] as u32;
+                    let [r, g, b, a] = color.0;
+                    [r, g, b, (a as u32 * coverage / 255) as u8]
+                }
+                Content::Color => {
+                    let d = &glyph_image.data[i * 4..i * 4 + 4];
+                    [d[0], d[1], d[2], d[3]]
+                }
+                Content::SubpixelMask => unreachable!(),
+            };
+
+            let dst = image.get_pixel_mut(x as u32, y as u32);
+            dst.0 = blend_over(dst.0, src);
+        }
+    }
+}
+
+/// Source-over blending with straight alpha.
+fn blend_over(dst: [u8; 4], src: [u8; 4]) -> [u8; 4] {
+    let src_a = src[3] as f32 / 255.0;
+    let dst_a = dst[3] as f32 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    if out_a <= 0.0 {
+        return [0, 0, 0, 0];
+    }
+
+    let mut out = [0u8; 4];
+    for c in 0..3 {
+        let value = (src[c] as f32 * src_a + dst[c] as f32 * dst_a * (1.0 - src_a)) / out_a;
+        out[c] = value.round() as u8;
+    }
+    out[3] = (out_a * 255.0).round() as u8;
+    out
+}
@@ -0,0 +1,116 @@
+use crate::*;
+
+/// What the debug overlay set with [`Text::set_debug_overlay()`] shows. All parts are enabled by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DebugOverlay {
+    /// Outline of the area of each box, which is also the area used for hit testing. Green, or red for boxes whose layout was rebuilt in the last prepared frame, when `dirty_flags` is enabled.
+    pub box_bounds: bool,
+    /// Outline of the clip rect of each box that has one, in yellow.
+    pub clip_rects: bool,
+    /// The baseline of each line, in cyan.
+    pub baselines: bool,
+    /// The anchor of the selection in magenta, and its focus in orange.
+    pub selection_anchors: bool,
+    /// Tint boxes whose layout was rebuilt in the last prepared frame in red.
+    ///
+    /// To keep the tint current, the decorations are prepared again on every [`Text::prepare_all()`] call while this is enabled.
+    pub dirty_flags: bool,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self {
+            box_bounds: true,
+            clip_rects: true,
+            baselines: true,
+            selection_anchors: true,
+            dirty_flags: true,
+        }
+    }
+}
+
+const BOUNDS_COLOR: u32 = 0x20_e0_20_c0;
+const DIRTY_BOUNDS_COLOR: u32 = 0xff_30_30_e0;
+const DIRTY_FILL_COLOR: u32 = 0xff_00_00_30;
+const CLIP_COLOR: u32 = 0xff_e0_00_c0;
+const BASELINE_COLOR: u32 = 0x00_e0_e0_a0;
+const ANCHOR_COLOR: u32 = 0xff_00_ff_e0;
+const FOCUS_COLOR: u32 = 0xff_90_00_e0;
+
+impl Text {
+    /// Draw debugging aids over every visible box, to help with positioning, clipping and hit testing problems. `None` turns the overlay off, which is the default.
+    ///
+    /// The overlay is drawn together with the selections and the other decorations, so it follows the boxes' groups and isn't clipped.
+    pub fn set_debug_overlay(&mut self, overlay: Option<DebugOverlay>) {
+        if overlay != self.shared.debug_overlay {
+            self.shared.debug_overlay = overlay;
+            self.decorations_changed = true;
+        }
+    }
+
+    pub fn debug_overlay(&self) -> Option<DebugOverlay> {
+        self.shared.debug_overlay
+    }
+
+    /// Called from [`Text::prepare_all()`] after the layouts were refreshed, whenever the decorations were prepared again.
+    pub(crate) fn prepare_debug_overlay(&mut self, text_renderer: &mut TextRenderer, overlay: DebugOverlay) {
+        let current_frame = self.current_visibility_frame;
+        for (_, (_, text_box)) in self.text_edits.iter_mut() {
+            if !text_box.hidden && text_box.last_frame_touched == current_frame && !outside_viewport(self.viewport, &self.shared, text_box) {
+                let text_box = get_full_text_box_free_function_but_for_iterating(text_box, &mut self.shared);
+                text_renderer.prepare_box_debug_overlay(&text_box, overlay);
+            }
+        }
+        for (_, text_box) in self.text_boxes.iter_mut() {
+            if !text_box.hidden && text_box.last_frame_touched == current_frame && !outside_viewport(self.viewport, &self.shared, text_box) {
+                let text_box = get_full_text_box_free_function_but_for_iterating(text_box, &mut self.shared);
+                text_renderer.prepare_box_debug_overlay(&text_box, overlay);
+            }
+        }
+    }
+}
+
+impl TextRenderer {
+    fn prepare_box_debug_overlay(&mut self, text_box: &TextBoxMut, overlay: DebugOverlay) {
+        let decorations_start = self.text_renderer.decorations.len();
+        let (left, top) = text_box.pos();
+        let (left, top) = (left as f32, top as f32);
+        let content_left = left - text_box.scroll_offset().0;
+        let content_top = top - text_box.scroll_offset().1;
+        let inner = &text_box.inner;
+        let dirty = overlay.dirty_flags && inner.relaid_out_at == text_box.shared.frames_prepared;
+
+        let bounds = parley::Rect { x0: 0.0, y0: 0.0, x1: inner.width as f64, y1: inner.height as f64 };
+        if dirty {
+            self.text_renderer.add_selection_rect(bounds, left, top, DIRTY_FILL_COLOR, None);
+        }
+        if overlay.box_bounds {
+            let color = if dirty { DIRTY_BOUNDS_COLOR } else { BOUNDS_COLOR };
+            self.text_renderer.add_outline(bounds, left, top, color, None);
+        }
+        if overlay.clip_rects {
+            if let Some(clip) = text_box.effective_clip_rect() {
+                self.text_renderer.add_outline(clip, content_left, content_top, CLIP_COLOR, None);
+            }
+        }
+        if overlay.baselines {
+            for line in inner.layout.lines() {
+                let metrics = line.metrics();
+                let x0 = metrics.offset as f64;
+                let baseline = metrics.baseline as f64;
+                let rect = parley::Rect { x0, y0: baseline, x1: x0 + metrics.advance as f64, y1: baseline + 1.0 };
+                self.text_renderer.add_selection_rect(rect, content_left, content_top, BASELINE_COLOR, None);
+            }
+        }
+        if overlay.selection_anchors && inner.selectable {
+            let selection = text_box.selection();
+            let anchor = selection.anchor().geometry(&inner.layout, 2.0);
+            let focus = selection.focus().geometry(&inner.layout, 2.0);
+            self.text_renderer.add_selection_rect(anchor, content_left, content_top, ANCHOR_COLOR, None);
+            self.text_renderer.add_selection_rect(focus, content_left, content_top, FOCUS_COLOR, None);
+        }
+
+        self.text_renderer.finish_box_decorations(decorations_start, inner.group, inner.depth);
+        self.text_renderer.needs_gpu_sync = true;
+    }
+}
@@ -0,0 +1,118 @@
+use crate::*;
+
+/// Styles that a label linked with [`Text::link_label()`] switches to, following the state of its text edit. Set with [`Text::set_label_styles()`].
+///
+/// When both apply, the error style wins.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LabelStyles {
+    /// Used while the edit is focused.
+    pub focused: Option<StyleHandle>,
+    /// Used while the edit is marked with [`TextEditMut::set_error()`].
+    pub error: Option<StyleHandle>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LabelLink {
+    pub(crate) edit: WeakTextEditHandle,
+    /// The style used when no variant applies.
+    pub(crate) normal: StyleHandle,
+    pub(crate) styles: LabelStyles,
+}
+
+impl<'a> TextEditMut<'a> {
+    /// Mark the edit as having an invalid value. Labels linked to it with [`Text::link_label()`] switch to their [`LabelStyles::error`] style.
+    pub fn set_error(&mut self, error: bool) {
+        if self.inner.error != error {
+            self.inner.error = error;
+            // Linked labels are updated in the next prepare_all().
+            self.text_box.shared.decorations_changed = true;
+        }
+    }
+}
+
+impl Text {
+    /// Link a label to a text edit, so that clicking the label focuses the edit.
+    ///
+    /// The label can also switch styles when the edit is focused or has an error, see [`Text::set_label_styles()`]. The link is dropped automatically if the edit is removed.
+    pub fn link_label(&mut self, label: &TextBoxHandle, edit: &TextEditHandle) {
+        self.check_handle(label);
+        let edit = self.downgrade_text_edit(edit);
+        let text_box = &mut self.text_boxes[label.i as usize];
+        let (normal, styles) = match text_box.label_link {
+            Some(link) => (link.normal, link.styles),
+            None => (text_box.style, LabelStyles::default()),
+        };
+        text_box.label_link = Some(LabelLink { edit, normal, styles });
+        self.update_linked_labels();
+    }
+
+    /// Remove a link made with [`Text::link_label()`], and give the label back its normal style.
+    pub fn unlink_label(&mut self, label: &TextBoxHandle) {
+        self.check_handle(label);
+        if let Some(link) = self.text_boxes[label.i as usize].label_link.take() {
+            self.get_text_box_mut(label).set_style(&link.normal);
+        }
+    }
+
+    /// Set the styles that a linked label uses while its edit is focused or has an error. Does nothing if the label isn't linked.
+    ///
+    /// Styles set on the label with [`TextBoxMut::set_style()`] while it's linked become its normal style.
+    pub fn set_label_styles(&mut self, label: &TextBoxHandle, styles: LabelStyles) {
+        self.check_handle(label);
+        if let Some(link) = &mut self.text_boxes[label.i as usize].label_link {
+            link.styles = styles;
+        }
+        self.update_linked_labels();
+    }
+
+    /// If `target` is a label linked to an edit that still exists, returns the edit.
+    pub(crate) fn label_focus_target(&self, target: AnyBox) -> Option<AnyBox> {
+        let AnyBox::TextBox(i) = target else {
+            return None;
+        };
+        let link = self.text_boxes.get(i as usize)?.label_link?;
+        link.edit.is_alive(self).then_some(AnyBox::TextEdit(link.edit.i))
+    }
+
+    /// Switch the style of linked labels to match the state of their edits.
+    pub(crate) fn update_linked_labels(&mut self) {
+        for (_, text_box) in self.text_boxes.iter_mut() {
+            let Some(link) = &mut text_box.label_link else {
+                continue;
+            };
+
+            let current = text_box.style.i;
+            let is_variant = |style: Option<StyleHandle>| style.is_some_and(|style| style.i == current);
+            if current != link.normal.i && !is_variant(link.styles.focused) && !is_variant(link.styles.error) {
+                // The style was changed by hand.
+                link.normal = text_box.style;
+            }
+
+            let edit = self.text_edits.get(link.edit.i as usize).filter(|(_, edit_box)| edit_box.generation == link.edit.generation);
+            let Some((edit, _)) = edit else {
+                let normal = link.normal;
+                text_box.label_link = None;
+                set_label_style(text_box, &mut self.shared, normal);
+                continue;
+            };
+
+            let focused = self.focused == Some(AnyBox::TextEdit(link.edit.i));
+            let target = match (edit.error, focused) {
+                (true, _) if link.styles.error.is_some() => link.styles.error,
+                (_, true) if link.styles.focused.is_some() => link.styles.focused,
+                _ => None,
+            };
+            let target = target.unwrap_or(link.normal);
+            set_label_style(text_box, &mut self.shared, target);
+        }
+    }
+}
+
+fn set_label_style(text_box: &mut TextBoxInner, shared: &mut Shared, style: StyleHandle) {
+    if text_box.style.i != style.i {
+        text_box.style = style;
+        text_box.style_version = shared.styles[style.i as usize].version;
+        text_box.needs_relayout = true;
+        shared.text_changed = true;
+    }
+}
@@ -0,0 +1,218 @@
+use std::time::Instant;
+
+use crate::*;
+
+/// Information about a glyph, passed to a [`GlyphAnimation`] function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphAnimationInput {
+    /// Byte index of the start of the glyph's cluster in the text. This is found by hit testing the glyph's position, so it can be off for glyphs that overlap their neighbors.
+    pub byte_index: usize,
+    /// Position of the glyph among the glyphs of the box, in drawing order.
+    pub glyph_index: usize,
+    /// Number of glyphs in the box.
+    pub glyph_count: usize,
+    /// Seconds since the animation was set with [`TextBoxMut::set_glyph_animation()`].
+    pub time: f32,
+}
+
+/// How a glyph is drawn in the current frame, returned by a [`GlyphAnimation`] function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphAnimationFrame {
+    /// Offset in pixels from the glyph's normal position.
+    pub offset: (f32, f32),
+    /// Opacity, multiplied with the glyph color.
+    pub alpha: f32,
+    /// True if the glyph is at rest and will stay this way. Once all the glyphs of a box are settled, the animation stops and the box no longer needs to be redrawn every frame.
+    pub settled: bool,
+}
+
+impl Default for GlyphAnimationFrame {
+    fn default() -> Self {
+        Self { offset: (0.0, 0.0), alpha: 1.0, settled: true }
+    }
+}
+
+/// Function that animates each glyph of a text box, set with [`TextBoxMut::set_glyph_animation()`].
+///
+/// It's called for every glyph of the box on every frame while the animation is running, so it should be cheap. Some ready-made animations are [`typewriter_fade_animation()`], [`wave_animation()`] and [`shake_animation()`].
+pub type GlyphAnimation = fn(glyph: &GlyphAnimationInput) -> GlyphAnimationFrame;
+
+/// Seconds between the start of two consecutive glyphs in [`typewriter_fade_animation()`].
+const TYPEWRITER_GLYPH_DELAY: f32 = 0.03;
+/// Seconds that each glyph takes to fade in in [`typewriter_fade_animation()`].
+const TYPEWRITER_FADE_DURATION: f32 = 0.15;
+
+/// Fades the glyphs in one after the other, like dialogue text in games.
+pub fn typewriter_fade_animation(glyph: &GlyphAnimationInput) -> GlyphAnimationFrame {
+    let start = glyph.glyph_index as f32 * TYPEWRITER_GLYPH_DELAY;
+    let progress = ((glyph.time - start) / TYPEWRITER_FADE_DURATION).clamp(0.0, 1.0);
+    GlyphAnimationFrame {
+        offset: (0.0, (1.0 - progress) * 4.0),
+        alpha: progress,
+        settled: progress >= 1.0,
+    }
+}
+
+/// Moves the glyphs up and down in a wave that travels along the text. It never settles.
+pub fn wave_animation(glyph: &GlyphAnimationInput) -> GlyphAnimationFrame {
+    let phase = glyph.time * 6.0 - glyph.glyph_index as f32 * 0.5;
+    GlyphAnimationFrame {
+        offset: (0.0, phase.sin() * 3.0),
+        alpha: 1.0,
+        settled: false,
+    }
+}
+
+/// Jitters each glyph randomly, for angry or scared dialogue. It never settles.
+pub fn shake_animation(glyph: &GlyphAnimationInput) -> GlyphAnimationFrame {
+    // A new offset 20 times per second. The hash only needs to look random, not be good.
+    let step = (glyph.time * 20.0) as u32;
+    let hash = (glyph.glyph_index as u32).wrapping_mul(0x9e37_79b9) ^ step.wrapping_mul(0x85eb_ca6b);
+    let hash = hash ^ (hash >> 15);
+    let x = (hash & 0xff) as f32 / 255.0 - 0.5;
+    let y = ((hash >> 8) & 0xff) as f32 / 255.0 - 0.5;
+    GlyphAnimationFrame {
+        offset: (x * 2.0, y * 2.0),
+        alpha: 1.0,
+        settled: false,
+    }
+}
+
+pub(crate) struct GlyphAnimationState {
+    pub function: GlyphAnimation,
+    pub start_time: Instant,
+    /// The quads of the box as they were prepared, before any animation, with the byte index of their cluster. Taken again every time the box is prepared.
+    pub base_quads: Vec<(Quad, usize)>,
+    /// The scroll offset of the quads in `base_quads`, so that the scroll fast path can still move them.
+    pub base_offset: (f32, f32),
+    pub settled: bool,
+}
+
+impl<'a> TextBoxMut<'a> {
+    /// Animate each glyph of the box with a function that returns its offset and opacity for the current frame, or stop animating with `None`.
+    ///
+    /// The animation starts over every time this is called. While it's running, [`Text::need_rerender()`] returns true, and only the quads of animated boxes are updated: other boxes aren't prepared again. When all glyphs report that they're settled, the animation stops.
+    ///
+    /// If [`SystemSettings::reduce_motion`] is set, glyphs are drawn as they would be after a long time, so animations that settle skip to the end and the others stay still.
+    pub fn set_glyph_animation(&mut self, animation: Option<GlyphAnimation>) {
+        self.inner.glyph_animation = animation.map(|function| GlyphAnimationState {
+            function,
+            start_time: self.shared.now(),
+            base_quads: Vec::new(),
+            base_offset: (0.0, 0.0),
+            settled: false,
+        });
+        self.shared.text_changed = true;
+    }
+
+    pub fn glyph_animation(&self) -> Option<GlyphAnimation> {
+        self.inner.glyph_animation.as_ref().map(|animation| animation.function)
+    }
+}
+
+impl<'a> TextEditMut<'a> {
+    /// Animate each glyph of the text edit. See [`TextBoxMut::set_glyph_animation()`].
+    pub fn set_glyph_animation(&mut self, animation: Option<GlyphAnimation>) {
+        self.text_box.set_glyph_animation(animation);
+    }
+}
+
+impl Text {
+    /// Returns true if any box has a glyph animation that hasn't settled yet.
+    pub fn has_running_glyph_animations(&self) -> bool {
+        self.shared.glyph_animations_running
+    }
+
+    /// Update the quads of every visible box with a glyph animation.
+    pub(crate) fn animate_glyphs(&mut self, text_renderer: &mut TextRenderer) {
+        let now = self.shared.now();
+        let reduce_motion = self.shared.system_settings.reduce_motion;
+        let current_frame = self.current_visibility_frame;
+        let mut running = false;
+
+        let text_boxes = self.text_boxes.iter_mut().map(|(_, text_box)| text_box);
+        let text_edit_boxes = self.text_edits.iter_mut().map(|(_, (_, text_box))| text_box);
+        for text_box in text_boxes.chain(text_edit_boxes) {
+            if text_box.glyph_animation.is_none() {
+                continue;
+            }
+            // Boxes that weren't prepared don't own any quads.
+            if text_box.hidden || text_box.last_frame_touched != current_frame || outside_viewport(self.viewport, &self.shared, text_box) {
+                continue;
+            }
+            let animation = text_box.glyph_animation.as_mut().unwrap();
+            if animation.settled {
+                continue;
+            }
+            text_renderer.text_renderer.apply_glyph_animation(animation, &text_box.quad_storage, now, reduce_motion);
+            text_renderer.text_renderer.needs_gpu_sync = true;
+            if !animation.settled {
+                running = true;
+            }
+        }
+
+        self.shared.glyph_animations_running = running;
+    }
+}
+
+impl ContextlessTextRenderer {
+    /// Save the quads of an animated box as they were just prepared, so that each frame of the animation can start from them.
+    pub(crate) fn capture_glyph_animation_base(&mut self, animation: &mut GlyphAnimationState, quad_storage: &QuadStorage, layout: &Layout<ColorBrush>, content_left: f32, content_top: f32) {
+        animation.base_quads.clear();
+        animation.base_offset = quad_storage.last_offset;
+        animation.settled = false;
+        for page_range in &quad_storage.pages {
+            let quads = match page_range.page_type {
+                AtlasPageType::Mask => &self.mask_atlas_pages[page_range.page_index as usize].quads,
+                AtlasPageType::Color => &self.color_atlas_pages[page_range.page_index as usize].quads,
+            };
+            for quad in &quads[page_range.quad_start as usize..page_range.quad_end as usize] {
+                // Hit test a bit to the left of the center, so that the result is the start of the cluster and not the end.
+                let x = quad.pos[0] as f32 + quad.dim[0] as f32 * 0.25 - content_left;
+                let y = quad.pos[1] as f32 + quad.dim[1] as f32 * 0.5 - content_top;
+                let byte_index = parley::Selection::from_point(layout, x, y).focus().index();
+                animation.base_quads.push((*quad, byte_index));
+            }
+        }
+    }
+
+    pub(crate) fn apply_glyph_animation(&mut self, animation: &mut GlyphAnimationState, quad_storage: &QuadStorage, now: Instant, reduce_motion: bool) {
+        let time = if reduce_motion {
+            f32::MAX
+        } else {
+            now.saturating_duration_since(animation.start_time).as_secs_f32()
+        };
+        let scroll_delta_x = (quad_storage.last_offset.0 - animation.base_offset.0) as i32;
+        let scroll_delta_y = (quad_storage.last_offset.1 - animation.base_offset.1) as i32;
+        let glyph_count = animation.base_quads.len();
+
+        let mut settled = true;
+        let mut base_quads = animation.base_quads.iter().enumerate();
+        for page_range in &quad_storage.pages {
+            let quads = match page_range.page_type {
+                AtlasPageType::Mask => &mut self.mask_atlas_pages[page_range.page_index as usize].quads,
+                AtlasPageType::Color => &mut self.color_atlas_pages[page_range.page_index as usize].quads,
+            };
+            for quad in &mut quads[page_range.quad_start as usize..page_range.quad_end as usize] {
+                let Some((glyph_index, (base_quad, byte_index))) = base_quads.next() else {
+                    return;
+                };
+                let input = GlyphAnimationInput { byte_index: *byte_index, glyph_index, glyph_count, time };
+                let frame = if reduce_motion {
+                    GlyphAnimationFrame { offset: (0.0, 0.0), ..(animation.function)(&input) }
+                } else {
+                    (animation.function)(&input)
+                };
+                settled &= frame.settled;
+
+                *quad = *base_quad;
+                quad.pos[0] += frame.offset.0.round() as i32 - scroll_delta_x;
+                quad.pos[1] += frame.offset.1.round() as i32 - scroll_delta_y;
+                let alpha = (quad.color & 0xff) as f32 * frame.alpha.clamp(0.0, 1.0);
+                quad.color = (quad.color & !0xff) | alpha.round() as u32;
+            }
+        }
+        // With reduce motion, the glyphs are drawn once and left there.
+        animation.settled = settled || reduce_motion;
+    }
+}
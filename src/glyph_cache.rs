@@ -0,0 +1,337 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rustc_hash::FxHashMap;
+
+use crate::*;
+
+/// Gives each [`TextRenderer`] an id, so that the glyph cache can tell which frames are still in use.
+static NEXT_RENDERER_ID: AtomicU64 = AtomicU64::new(1);
+
+pub(crate) fn next_renderer_id() -> u64 {
+    NEXT_RENDERER_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Rasterized glyphs and images registered with [`TextRenderer::register_image()`], on the CPU side.
+///
+/// A `GlyphCache` can be shared by any number of [`TextRenderer`]s, for example one per window, by cloning it into [`TextRendererParams::glyph_cache`]. Then each glyph is rasterized only once, and registered images are available in all the renderers. Each renderer still keeps its own copy of the atlas textures on its device.
+///
+/// The cache is behind a mutex, so renderers on different threads can use it at the same time.
+#[derive(Clone)]
+pub struct GlyphCache {
+    pub(crate) inner: Arc<Mutex<GlyphCacheInner>>,
+}
+
+impl GlyphCache {
+    /// Create an empty cache. The atlas pages have the size given by `atlas_page_size` on `device`.
+    pub fn new(device: &Device, atlas_page_size: AtlasPageSize) -> Self {
+        let atlas_size = atlas_page_size.size(device);
+        Self::with_atlas_size(atlas_size)
+    }
+
+    pub(crate) fn with_atlas_size(atlas_size: u32) -> Self {
+        let inner = GlyphCacheInner {
+            atlas_size,
+            glyphs: LruCache::unbounded_with_hasher(BuildHasherDefault::<FxHasher>::default()),
+            mask_pages: vec![CpuAtlasPage::new(GrayImage::from_pixel(atlas_size, atlas_size, Luma([0])), atlas_size)],
+            color_pages: vec![CpuAtlasPage::new(RgbaImage::from_pixel(atlas_size, atlas_size, Rgba([0, 0, 0, 0])), atlas_size)],
+            images: Vec::new(),
+            frame: 1,
+            renderer_frames: FxHashMap::default(),
+            last_frame_evicted: 0,
+            evictions: 0,
+        };
+        Self { inner: Arc::new(Mutex::new(inner)) }
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, GlyphCacheInner> {
+        // A panic while rasterizing doesn't leave the cache in a state that's worse than a missing glyph.
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Size of the atlas pages in pixels.
+    pub fn atlas_size(&self) -> u32 {
+        self.lock().atlas_size
+    }
+
+    /// Number of glyphs currently stored, including empty ones like spaces.
+    pub fn glyph_count(&self) -> usize {
+        self.lock().glyphs.len()
+    }
+}
+
+pub(crate) struct CpuAtlasPage<ImageType> {
+    pub(crate) packer: BucketedAtlasAllocator,
+    pub(crate) image: ImageType,
+    /// Incremented every time something is drawn into the image, so that renderers know when to upload it again.
+    pub(crate) version: u64,
+}
+
+impl<ImageType> CpuAtlasPage<ImageType> {
+    fn new(image: ImageType, atlas_size: u32) -> Self {
+        Self {
+            packer: BucketedAtlasAllocator::new(size2(atlas_size as i32, atlas_size as i32)),
+            image,
+            version: 1,
+        }
+    }
+}
+
+pub(crate) struct GlyphCacheInner {
+    pub(crate) atlas_size: u32,
+    pub(crate) glyphs: LruCache<GlyphKey, Option<StoredGlyph>, BuildHasherDefault<FxHasher>>,
+    pub(crate) mask_pages: Vec<CpuAtlasPage<GrayImage>>,
+    pub(crate) color_pages: Vec<CpuAtlasPage<RgbaImage>>,
+    /// Slots for registered images, with a generation that changes every time a slot is freed, so that old [`ImageId`]s for reused slots don't show the new image.
+    pub(crate) images: Vec<(u32, Option<StoredImage>)>,
+    /// Frame counter shared by all the renderers that use the cache.
+    pub(crate) frame: u64,
+    /// The frame in which each renderer started preparing its current quads. Glyphs used in or after the oldest of these can't be evicted.
+    pub(crate) renderer_frames: FxHashMap<u64, u64>,
+    pub(crate) last_frame_evicted: u64,
+    /// Incremented every time glyphs are evicted, so that renderers know that their cached runs might point to freed slots.
+    pub(crate) evictions: u64,
+}
+
+impl GlyphCacheInner {
+    /// Start a new frame for a renderer, and return its number.
+    pub(crate) fn begin_frame(&mut self, renderer_id: u64) -> u64 {
+        self.frame += 1;
+        self.renderer_frames.insert(renderer_id, self.frame);
+        self.frame
+    }
+
+    pub(crate) fn remove_renderer(&mut self, renderer_id: u64) {
+        self.renderer_frames.remove(&renderer_id);
+    }
+
+    // for now, we're evicting both masks and colors at the same time even if only one spills over
+    // separating them would mean that they can't share the same cache and it would make things more complex
+    fn evict_old_glyphs(&mut self) {
+        self.last_frame_evicted = self.frame;
+        self.evictions += 1;
+        let oldest_in_use = self.renderer_frames.values().copied().min().unwrap_or(self.frame);
+
+        while let Some((_key, value)) = self.glyphs.peek_lru() {
+
+            if let Some(stored_glyph) = value {
+                if stored_glyph.frame >= oldest_in_use {
+                    break;
+                }
+
+                let page = stored_glyph.page as usize;
+                match stored_glyph.content_type {
+                    Content::Mask => self.mask_pages[page].packer.deallocate(stored_glyph.alloc.id),
+                    Content::Color => self.color_pages[page].packer.deallocate(stored_glyph.alloc.id),
+                    Content::SubpixelMask => unreachable!()
+                }
+            }
+
+            self.glyphs.pop_lru();
+        }
+    }
+
+    fn needs_evicting(&self) -> bool {
+        self.last_frame_evicted != self.frame
+    }
+
+    /// Look up a glyph, and mark it as used in `frame`. The outer `None` means that the glyph isn't in the cache.
+    pub(crate) fn get_glyph(&mut self, key: &GlyphKey, frame: u64) -> Option<Option<StoredGlyph>> {
+        let stored_glyph = self.glyphs.get_mut(key)?;
+        if let Some(stored_glyph) = stored_glyph {
+            stored_glyph.frame = stored_glyph.frame.max(frame);
+        }
+        Some(*stored_glyph)
+    }
+
+    /// Rasterizes the glyph in a texture atlas and returns where it was stored, or None if the glyph was just empty (like a space).
+    pub(crate) fn prepare_glyph(&mut self, glyph: &GlyphWithContext, scaler: &mut Scaler, tmp_image: &mut Image, frame: u64, glyphs_rasterized: &mut usize) -> Option<StoredGlyph> {
+        tmp_image.clear();
+        Render::new(SOURCES)
+            .format(Format::Alpha)
+            .offset(glyph.frac_offset())
+            .render_into(scaler, glyph.glyph.id, tmp_image);
+        let (content, placement) = (tmp_image.content, tmp_image.placement);
+        let size = placement.size();
+
+        // For some glyphs there's no image to store, like spaces.
+        if size.is_empty() {
+            self.glyphs.push(glyph.key(), None);
+            return None;
+        }
+
+        let n_pages = match content {
+            Content::Mask => self.mask_pages.len(),
+            Content::Color => self.color_pages.len(),
+            Content::SubpixelMask => unreachable!(),
+        };
+        // Try to allocate on existing pages
+        let mut found = None;
+        for page in 0..n_pages {
+            if let Some(alloc) = self.pack_rectangle(size, content, page) {
+                found = Some((page, alloc));
+                break;
+            }
+
+            // Try evicting glyphs from previous frames and retry
+            if self.needs_evicting() {
+                self.evict_old_glyphs();
+
+                if let Some(alloc) = self.pack_rectangle(size, content, page) {
+                    found = Some((page, alloc));
+                    break;
+                }
+            }
+        }
+
+        if found.is_none() {
+            // Create a new page and try to allocate there
+            let new_page = self.make_new_page(content);
+            found = self.pack_rectangle(size, content, new_page).map(|alloc| (new_page, alloc));
+        }
+
+        let Some((page, alloc)) = found else {
+            // Glyph is too large to fit even in a new empty page. It's time to give up.
+            // todo: should probably try to catch these earlier by checking for unreasonable font sizes
+            // todo2: technically, we could split the huge glyph across multiple pages, or render it on the surface directly.
+            self.glyphs.push(glyph.key(), None);
+            return None;
+        };
+
+        self.copy_glyph_to_atlas(tmp_image, size, &alloc, page, content);
+        *glyphs_rasterized += 1;
+        let stored_glyph = StoredGlyph::create(&alloc, &placement, page, frame, content);
+        self.glyphs.push(glyph.key(), Some(stored_glyph));
+        Some(stored_glyph)
+    }
+
+    fn copy_glyph_to_atlas(&mut self, tmp_image: &Image, size: Size2D<i32, UnknownUnit>, alloc: &Allocation, page: usize, content_type: Content) {
+        for y in 0..size.height as i32 {
+            let src_start = (y as usize) * (size.width as usize);
+            let src_slice =
+                &tmp_image.data[src_start..(src_start + size.width as usize)];
+
+            let dst_y = (alloc.rectangle.min.y + y) as u32;
+            let dst_x = alloc.rectangle.min.x as u32;
+
+            match content_type {
+                Content::Mask => {
+                    let layout = self.mask_pages[page].image.as_flat_samples().layout;
+                    let mut samples = self.mask_pages[page].image.as_flat_samples_mut();
+                    let samples = samples.as_mut_slice();
+                    let dst_start =
+                    (dst_y as usize) * layout.height_stride + (dst_x as usize) * layout.width_stride;
+
+                samples[dst_start..(dst_start + size.width as usize)].copy_from_slice(src_slice);
+                },
+                Content::Color => {
+                    // todo: rewrite this with a cool copy_from_slice
+                    let layout = self.color_pages[page].image.as_flat_samples().layout;
+                    let mut samples = self.color_pages[page].image.as_flat_samples_mut();
+                    let samples = samples.as_mut_slice();
+
+                    // For RGBA, each pixel is 4 bytes
+                    for x in 0..size.width as usize {
+                        let src_idx = src_start + x;
+                        let dst_idx = (dst_y as usize) * layout.height_stride + (dst_x as usize + x) * layout.width_stride;
+
+                        // Copy all 4 channels
+                        for c in 0..4 {
+                            samples[dst_idx + c] = tmp_image.data[src_idx * 4 + c];
+                        }
+                    }
+                },
+                Content::SubpixelMask => unreachable!(),
+            };
+        }
+
+        match content_type {
+            Content::Mask => self.mask_pages[page].version += 1,
+            Content::Color => self.color_pages[page].version += 1,
+            Content::SubpixelMask => unreachable!(),
+        }
+    }
+
+    fn pack_rectangle(&mut self, size: Size2D<i32, UnknownUnit>, content_type: Content, page: usize) -> Option<Allocation> {
+        match content_type {
+            Content::Mask => self.mask_pages[page].packer.allocate(size),
+            Content::Color => self.color_pages[page].packer.allocate(size),
+            Content::SubpixelMask => unreachable!(),
+        }
+    }
+
+    fn make_new_page(&mut self, content_type: Content) -> usize {
+        let atlas_size = self.atlas_size;
+
+        match content_type {
+            Content::Mask => {
+                self.mask_pages.push(CpuAtlasPage::new(GrayImage::from_pixel(atlas_size, atlas_size, Luma([0])), atlas_size));
+                self.mask_pages.len() - 1
+            },
+            Content::Color => {
+                self.color_pages.push(CpuAtlasPage::new(RgbaImage::from_pixel(atlas_size, atlas_size, Rgba([0, 0, 0, 0])), atlas_size));
+                self.color_pages.len() - 1
+            },
+            Content::SubpixelMask => unreachable!()
+        }
+    }
+
+    pub(crate) fn register_image(&mut self, image: &RgbaImage) -> Option<ImageId> {
+        let size = size2(image.width() as i32, image.height() as i32);
+        if size.is_empty() {
+            return None;
+        }
+
+        let mut found = None;
+        for page in 0..self.color_pages.len() {
+            if let Some(alloc) = self.pack_rectangle(size, Content::Color, page) {
+                found = Some((page, alloc));
+                break;
+            }
+        }
+        let (page, alloc) = match found {
+            Some(found) => found,
+            None => {
+                let new_page = self.make_new_page(Content::Color);
+                let alloc = self.pack_rectangle(size, Content::Color, new_page)?;
+                (new_page, alloc)
+            }
+        };
+
+        let x = alloc.rectangle.min.x as u32;
+        let y = alloc.rectangle.min.y as u32;
+        image::GenericImage::copy_from(&mut self.color_pages[page].image, image, x, y).ok()?;
+        self.color_pages[page].version += 1;
+
+        let stored_image = StoredImage { page: page as u16, alloc, size };
+        if let Some(i) = self.images.iter().position(|(_, slot)| slot.is_none()) {
+            let (generation, slot) = &mut self.images[i];
+            *slot = Some(stored_image);
+            Some(ImageId { i: i as u32, generation: *generation })
+        } else {
+            self.images.push((0, Some(stored_image)));
+            Some(ImageId { i: self.images.len() as u32 - 1, generation: 0 })
+        }
+    }
+
+    pub(crate) fn remove_image(&mut self, id: ImageId) {
+        let Some((generation, slot)) = self.images.get_mut(id.i as usize) else {
+            return;
+        };
+        if *generation != id.generation {
+            return;
+        }
+        if let Some(stored_image) = slot.take() {
+            *generation = generation.wrapping_add(1);
+            self.color_pages[stored_image.page as usize].packer.deallocate(stored_image.alloc.id);
+        }
+    }
+
+    /// The image for `id`, or `None` if it was removed, even if its slot was reused by another image.
+    pub(crate) fn image(&self, id: ImageId) -> Option<StoredImage> {
+        match self.images.get(id.i as usize) {
+            Some((generation, slot)) if *generation == id.generation => *slot,
+            _ => None,
+        }
+    }
+}
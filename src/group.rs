@@ -0,0 +1,333 @@
+use winit::event::WindowEvent;
+
+use crate::*;
+
+/// Maximum number of groups that can exist at the same time, including the implicit identity group used by boxes without a group.
+pub const MAX_GROUPS: usize = 128;
+
+/// Bit offset of the group index inside a quad's flags.
+pub(crate) const GROUP_SHIFT: u32 = 16;
+
+/// A translation followed by a uniform scale, applied to all the boxes in a group. See [`Text::add_group()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform2D {
+    pub translation: (f32, f32),
+    pub scale: f32,
+}
+
+impl Default for Transform2D {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform2D {
+    pub const IDENTITY: Self = Self { translation: (0.0, 0.0), scale: 1.0 };
+
+    pub fn new(translation: (f32, f32), scale: f32) -> Self {
+        Self { translation, scale }
+    }
+
+    /// Map a point from the boxes' coordinates to screen coordinates.
+    pub fn apply(&self, point: (f64, f64)) -> (f64, f64) {
+        (
+            point.0 * self.scale as f64 + self.translation.0 as f64,
+            point.1 * self.scale as f64 + self.translation.1 as f64,
+        )
+    }
+
+    /// Map a point from screen coordinates to the boxes' coordinates.
+    pub fn inverse_apply(&self, point: (f64, f64)) -> (f64, f64) {
+        (
+            (point.0 - self.translation.0 as f64) / self.scale as f64,
+            (point.1 - self.translation.1 as f64) / self.scale as f64,
+        )
+    }
+}
+
+/// A 3D transform for a group, for placing labels in a 3D scene. See [`Text::set_group_world_transform()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorldTransform {
+    /// A column-major matrix, in the same layout as `glam::Mat4::to_cols_array_2d()`.
+    ///
+    /// If `billboard` is false, it maps the boxes' coordinates, after the group's [`Transform2D`], to clip space. These coordinates are in pixels with the y axis pointing down, so the matrix is usually `projection * view * model`, where `model` includes the scale from pixels to world units and flips the y axis.
+    ///
+    /// If `billboard` is true, it maps the origin of the group to clip space, and the boxes are drawn facing the camera around that point at their normal pixel size. In this case, the matrix is usually `projection * view * translation`.
+    pub matrix: [[f32; 4]; 4],
+    pub billboard: bool,
+}
+
+impl WorldTransform {
+    fn to_screen(&self, point: (f64, f64), screen_size: (f32, f32)) -> Option<(f64, f64)> {
+        if self.billboard {
+            let anchor = self.project([0.0, 0.0, 0.0, 1.0], screen_size)?;
+            Some((anchor.0 + point.0, anchor.1 + point.1))
+        } else {
+            self.project([point.0, point.1, 0.0, 1.0], screen_size)
+        }
+    }
+
+    fn to_plane(&self, point: (f64, f64), screen_size: (f32, f32)) -> Option<(f64, f64)> {
+        if self.billboard {
+            let anchor = self.project([0.0, 0.0, 0.0, 1.0], screen_size)?;
+            return Some((point.0 - anchor.0, point.1 - anchor.1));
+        }
+
+        // Cast a ray from the near plane to the far plane and intersect it with the boxes' plane (z = 0).
+        let ndc_x = 2.0 * point.0 / screen_size.0 as f64 - 1.0;
+        let ndc_y = 1.0 - 2.0 * point.1 / screen_size.1 as f64;
+        let inverse = invert(&self.matrix)?;
+        let near = unproject(&inverse, [ndc_x, ndc_y, 0.0, 1.0])?;
+        let far = unproject(&inverse, [ndc_x, ndc_y, 1.0, 1.0])?;
+
+        let dz = far[2] - near[2];
+        if dz.abs() < 1e-9 {
+            return None;
+        }
+        let t = -near[2] / dz;
+        Some((near[0] + t * (far[0] - near[0]), near[1] + t * (far[1] - near[1])))
+    }
+
+    fn project(&self, point: [f64; 4], screen_size: (f32, f32)) -> Option<(f64, f64)> {
+        let clip = mul(&self.matrix, point);
+        if clip[3] <= 0.0 {
+            return None;
+        }
+        let (ndc_x, ndc_y) = (clip[0] / clip[3], clip[1] / clip[3]);
+        Some((
+            (ndc_x + 1.0) / 2.0 * screen_size.0 as f64,
+            (1.0 - ndc_y) / 2.0 * screen_size.1 as f64,
+        ))
+    }
+}
+
+fn mul(m: &[[f32; 4]; 4], v: [f64; 4]) -> [f64; 4] {
+    let mut out = [0.0; 4];
+    for (col, column) in m.iter().enumerate() {
+        for (row, value) in column.iter().enumerate() {
+            out[row] += *value as f64 * v[col];
+        }
+    }
+    out
+}
+
+fn unproject(inverse: &[[f64; 4]; 4], v: [f64; 4]) -> Option<[f64; 3]> {
+    let mut out = [0.0; 4];
+    for col in 0..4 {
+        for row in 0..4 {
+            out[row] += inverse[col][row] * v[col];
+        }
+    }
+    if out[3].abs() < 1e-12 {
+        return None;
+    }
+    Some([out[0] / out[3], out[1] / out[3], out[2] / out[3]])
+}
+
+/// Invert a column-major 4x4 matrix with cofactor expansion. Returns `None` if it's not invertible.
+fn invert(m: &[[f32; 4]; 4]) -> Option<[[f64; 4]; 4]> {
+    let a: [f64; 16] = std::array::from_fn(|i| m[i / 4][i % 4] as f64);
+    let mut inv = [0.0f64; 16];
+
+    inv[0] = a[5] * a[10] * a[15] - a[5] * a[11] * a[14] - a[9] * a[6] * a[15] + a[9] * a[7] * a[14] + a[13] * a[6] * a[11] - a[13] * a[7] * a[10];
+    inv[4] = -a[4] * a[10] * a[15] + a[4] * a[11] * a[14] + a[8] * a[6] * a[15] - a[8] * a[7] * a[14] - a[12] * a[6] * a[11] + a[12] * a[7] * a[10];
+    inv[8] = a[4] * a[9] * a[15] - a[4] * a[11] * a[13] - a[8] * a[5] * a[15] + a[8] * a[7] * a[13] + a[12] * a[5] * a[11] - a[12] * a[7] * a[9];
+    inv[12] = -a[4] * a[9] * a[14] + a[4] * a[10] * a[13] + a[8] * a[5] * a[14] - a[8] * a[6] * a[13] - a[12] * a[5] * a[10] + a[12] * a[6] * a[9];
+    inv[1] = -a[1] * a[10] * a[15] + a[1] * a[11] * a[14] + a[9] * a[2] * a[15] - a[9] * a[3] * a[14] - a[13] * a[2] * a[11] + a[13] * a[3] * a[10];
+    inv[5] = a[0] * a[10] * a[15] - a[0] * a[11] * a[14] - a[8] * a[2] * a[15] + a[8] * a[3] * a[14] + a[12] * a[2] * a[11] - a[12] * a[3] * a[10];
+    inv[9] = -a[0] * a[9] * a[15] + a[0] * a[11] * a[13] + a[8] * a[1] * a[15] - a[8] * a[3] * a[13] - a[12] * a[1] * a[11] + a[12] * a[3] * a[9];
+    inv[13] = a[0] * a[9] * a[14] - a[0] * a[10] * a[13] - a[8] * a[1] * a[14] + a[8] * a[2] * a[13] + a[12] * a[1] * a[10] - a[12] * a[2] * a[9];
+    inv[2] = a[1] * a[6] * a[15] - a[1] * a[7] * a[14] - a[5] * a[2] * a[15] + a[5] * a[3] * a[14] + a[13] * a[2] * a[7] - a[13] * a[3] * a[6];
+    inv[6] = -a[0] * a[6] * a[15] + a[0] * a[7] * a[14] + a[4] * a[2] * a[15] - a[4] * a[3] * a[14] - a[12] * a[2] * a[7] + a[12] * a[3] * a[6];
+    inv[10] = a[0] * a[5] * a[15] - a[0] * a[7] * a[13] - a[4] * a[1] * a[15] + a[4] * a[3] * a[13] + a[12] * a[1] * a[7] - a[12] * a[3] * a[5];
+    inv[14] = -a[0] * a[5] * a[14] + a[0] * a[6] * a[13] + a[4] * a[1] * a[14] - a[4] * a[2] * a[13] - a[12] * a[1] * a[6] + a[12] * a[2] * a[5];
+    inv[3] = -a[1] * a[6] * a[11] + a[1] * a[7] * a[10] + a[5] * a[2] * a[11] - a[5] * a[3] * a[10] - a[9] * a[2] * a[7] + a[9] * a[3] * a[6];
+    inv[7] = a[0] * a[6] * a[11] - a[0] * a[7] * a[10] - a[4] * a[2] * a[11] + a[4] * a[3] * a[10] + a[8] * a[2] * a[7] - a[8] * a[3] * a[6];
+    inv[11] = -a[0] * a[5] * a[11] + a[0] * a[7] * a[9] + a[4] * a[1] * a[11] - a[4] * a[3] * a[9] - a[8] * a[1] * a[7] + a[8] * a[3] * a[5];
+    inv[15] = a[0] * a[5] * a[10] - a[0] * a[6] * a[9] - a[4] * a[1] * a[10] + a[4] * a[2] * a[9] + a[8] * a[1] * a[6] - a[8] * a[2] * a[5];
+
+    let det = a[0] * inv[0] + a[1] * inv[4] + a[2] * inv[8] + a[3] * inv[12];
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    Some(std::array::from_fn(|col| std::array::from_fn(|row| inv[col * 4 + row] / det)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct GroupInner {
+    pub(crate) transform: Transform2D,
+    pub(crate) world: Option<WorldTransform>,
+}
+
+impl GroupInner {
+    /// Map a point from screen coordinates to the coordinates of the boxes in the group. Returns `None` if the point doesn't land on the boxes' plane.
+    pub(crate) fn to_local(&self, point: (f64, f64), screen_size: (f32, f32)) -> Option<(f64, f64)> {
+        let point = match &self.world {
+            Some(world) => world.to_plane(point, screen_size)?,
+            None => point,
+        };
+        Some(self.transform.inverse_apply(point))
+    }
+
+    pub(crate) fn rect_to_screen(&self, rect: parley::Rect, screen_size: (f32, f32)) -> Option<parley::Rect> {
+        let to_screen = |point| {
+            let point = self.transform.apply(point);
+            match &self.world {
+                Some(world) => world.to_screen(point, screen_size),
+                None => Some(point),
+            }
+        };
+        // With a perspective projection, the corners don't stay aligned, so all four are needed.
+        let corners = [(rect.x0, rect.y0), (rect.x1, rect.y0), (rect.x0, rect.y1), (rect.x1, rect.y1)];
+        let mut bounds = parley::Rect { x0: f64::MAX, y0: f64::MAX, x1: f64::MIN, y1: f64::MIN };
+        for corner in corners {
+            let (x, y) = to_screen(corner)?;
+            bounds = parley::Rect { x0: bounds.x0.min(x), y0: bounds.y0.min(y), x1: bounds.x1.max(x), y1: bounds.y1.max(y) };
+        }
+        Some(bounds)
+    }
+
+    /// The transform as (x, y, scale, mode), where the mode is 0 for 2D, 1 for world space and 2 for billboards.
+    pub(crate) fn to_gpu(&self) -> [f32; 4] {
+        let mode = match &self.world {
+            None => 0.0,
+            Some(world) if !world.billboard => 1.0,
+            Some(_) => 2.0,
+        };
+        [self.transform.translation.0, self.transform.translation.1, self.transform.scale, mode]
+    }
+}
+
+/// Handle for a group of boxes that share a [`Transform2D`]. Created with [`Text::add_group()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupHandle {
+    pub(crate) i: u32,
+}
+
+impl GroupHandle {
+    /// Index of the group's transform on the GPU. Index 0 is the identity transform, used by boxes without a group.
+    pub(crate) fn gpu_index(&self) -> u32 {
+        self.i + 1
+    }
+}
+
+impl Shared {
+    pub(crate) fn group(&self, group: Option<GroupHandle>) -> Option<&GroupInner> {
+        group.and_then(|group| self.groups.get(group.i as usize))
+    }
+
+    /// Map a point from screen coordinates to the coordinates of a box in the given group.
+    pub(crate) fn screen_to_box(&self, group: Option<GroupHandle>, point: (f64, f64)) -> Option<(f64, f64)> {
+        match self.group(group) {
+            Some(group) => group.to_local(point, self.screen_size),
+            None => Some(point),
+        }
+    }
+
+    /// Map a rect from the coordinates of a box in the given group to its bounding rect on screen. Points behind the camera are left untransformed.
+    pub(crate) fn box_rect_to_screen(&self, group: Option<GroupHandle>, rect: parley::Rect) -> parley::Rect {
+        match self.group(group) {
+            Some(group) => group.rect_to_screen(rect, self.screen_size).unwrap_or(rect),
+            None => rect,
+        }
+    }
+}
+
+impl Text {
+    /// Add a group with the given transform.
+    ///
+    /// Boxes are added to a group with [`TextBoxMut::set_group()`] or [`TextEditMut::set_group()`]. The transform is applied on the GPU when rendering and to the mouse position when hit-testing, so changing it with [`Text::set_group_transform()`] doesn't require preparing the boxes again, unless a viewport is set with [`Text::set_viewport()`] and some boxes might come into view. This makes it cheap to pan and zoom a canvas with many labels.
+    ///
+    /// Glyphs are still rasterized at the size of the untransformed layout, so they get blurry at large zoom factors. For a sharp result, change the font size of the boxes when the zoom settles.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are already [`MAX_GROUPS`]` - 1` groups.
+    pub fn add_group(&mut self, transform: Transform2D) -> GroupHandle {
+        assert!(self.shared.groups.len() < MAX_GROUPS - 1, "textslabs: too many groups, the maximum is {}", MAX_GROUPS - 1);
+        let i = self.shared.groups.insert(GroupInner { transform, world: None });
+        self.shared.group_transforms_changed = true;
+        GroupHandle { i: i as u32 }
+    }
+
+    /// Change the transform of a group.
+    pub fn set_group_transform(&mut self, group: &GroupHandle, transform: Transform2D) {
+        self.shared.groups[group.i as usize].transform = transform;
+        self.shared.group_transforms_changed = true;
+        // Boxes culled by the viewport were never prepared, so they have to be prepared if the transform brings them into view.
+        if self.viewport.is_some() {
+            self.shared.text_changed = true;
+        }
+    }
+
+    pub fn group_transform(&self, group: &GroupHandle) -> Transform2D {
+        self.shared.groups[group.i as usize].transform
+    }
+
+    /// Place a group in a 3D scene, or return it to screen space with `None`.
+    ///
+    /// The group's [`Transform2D`] is applied first, then the world transform. The matrix is applied in the vertex shader, so updating it every frame as the camera moves doesn't require preparing the boxes again, unless a viewport is set with [`Text::set_viewport()`].
+    ///
+    /// For the text to be hidden behind other objects, the [`TextRenderer`] should be created with a depth stencil state, and the text should be rendered in a pass that uses the same depth buffer as the scene. In world space, the depth comes from the matrix and the box's [`depth`](TextBoxMut::set_depth()) is ignored.
+    pub fn set_group_world_transform(&mut self, group: &GroupHandle, world: Option<WorldTransform>) {
+        self.shared.groups[group.i as usize].world = world;
+        self.shared.group_transforms_changed = true;
+        if self.viewport.is_some() {
+            self.shared.text_changed = true;
+        }
+    }
+
+    pub fn group_world_transform(&self, group: &GroupHandle) -> Option<WorldTransform> {
+        self.shared.groups[group.i as usize].world
+    }
+
+    /// Remove a group. Boxes that were in the group are drawn without a transform.
+    pub fn remove_group(&mut self, group: GroupHandle) {
+        self.shared.groups.remove(group.i as usize);
+        for (_, (_text_edit, text_box)) in self.text_edits.iter_mut() {
+            if text_box.group == Some(group) {
+                text_box.group = None;
+            }
+        }
+        for (_, text_box) in self.text_boxes.iter_mut() {
+            if text_box.group == Some(group) {
+                text_box.group = None;
+            }
+        }
+        self.shared.text_changed = true;
+    }
+
+    pub(crate) fn any_box_group(&self, any_box: AnyBox) -> Option<GroupHandle> {
+        match any_box {
+            AnyBox::TextEdit(i) => self.text_edits.get(i as usize).and_then(|(_, text_box)| text_box.group),
+            AnyBox::TextBox(i) => self.text_boxes.get(i as usize).and_then(|text_box| text_box.group),
+        }
+    }
+
+    /// Returns the event and the input state as seen by a box in the given group, with mouse positions mapped into the box's coordinates. Returns `None` if the box isn't in a group.
+    pub(crate) fn transformed_input(&self, group: Option<GroupHandle>, event: &WindowEvent) -> Option<(WindowEvent, TextInputState)> {
+        let transform = self.shared.group(group)?.transform;
+        // Positions that miss the boxes' plane are left as they are.
+        let to_local = |point| self.shared.screen_to_box(group, point).unwrap_or(point);
+
+        let mut input_state = self.input_state.clone();
+        input_state.mouse.cursor_pos = to_local(input_state.mouse.cursor_pos);
+
+        let event = match event {
+            WindowEvent::CursorMoved { device_id, position } => {
+                let (x, y) = to_local((position.x, position.y));
+                WindowEvent::CursorMoved { device_id: *device_id, position: winit::dpi::PhysicalPosition::new(x, y) }
+            }
+            WindowEvent::MouseWheel { device_id, delta: winit::event::MouseScrollDelta::PixelDelta(delta), phase } => {
+                let scale = transform.scale as f64;
+                WindowEvent::MouseWheel {
+                    device_id: *device_id,
+                    delta: winit::event::MouseScrollDelta::PixelDelta(winit::dpi::PhysicalPosition::new(delta.x / scale, delta.y / scale)),
+                    phase: *phase,
+                }
+            }
+            other => other.clone(),
+        };
+        Some((event, input_state))
+    }
+}
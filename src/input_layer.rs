@@ -0,0 +1,245 @@
+use winit::event::WindowEvent;
+use winit::keyboard::{Key, ModifiersState, NamedKey};
+
+use crate::*;
+
+/// A cursor movement, used by [`EditAction::Move`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    /// Move to the previous boundary inside words, at `camelCase` humps and underscores.
+    SubWordLeft,
+    /// Move to the next boundary inside words, at `camelCase` humps and underscores.
+    SubWordRight,
+    Up,
+    Down,
+    LineStart,
+    LineEnd,
+    TextStart,
+    TextEnd,
+}
+
+/// What a key press does to the focused box, independent of the keys that are bound to it.
+///
+/// Input layers receive the action that an event maps to, and can replace it with a different one. See [`Text::add_pre_input_layer()`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditAction {
+    /// Move the cursor. If `extend` is true, the selection is extended instead of collapsed.
+    Move { motion: Motion, extend: bool },
+    SelectAll,
+    Copy,
+    Cut,
+    Paste,
+    Undo,
+    Redo,
+    /// Insert text at the cursor, replacing the selection.
+    Insert(String),
+    /// Insert a line break. Edits in single line mode ignore it.
+    Newline,
+    Backspace,
+    BackspaceWord,
+    /// Delete back to the previous sub-word boundary.
+    BackspaceSubWord,
+    Delete,
+    DeleteWord,
+    /// Delete up to the next sub-word boundary.
+    DeleteSubWord,
+    /// Move the cursor to the bracket matching the one next to it. See [`TextEditMut::jump_to_matching_bracket()`].
+    JumpToMatchingBracket,
+    /// Insert the ghost text set with [`TextEditMut::set_ghost_text()`]. Edits without ghost text ignore it.
+    AcceptGhostText,
+    ZoomIn,
+    ZoomOut,
+    ResetZoom,
+}
+
+impl EditAction {
+    /// Returns the action that a key press maps to with the given modifiers and [`KeyBindings`], or `None` if the key doesn't do anything by default.
+    ///
+    /// This doesn't depend on the state of any particular box. For example, Right arrow maps to a movement even when the focused edit would accept its ghost text instead, and Enter maps to [`EditAction::Newline`] regardless of the edit's [`NewlineMode`].
+    ///
+    /// The built-in key handling uses the same mapping, and then adjusts the action for the focused box before performing it. Post input layers receive the adjusted action.
+    pub fn from_key(key: &KeyInput, modifiers: ModifiersState, key_bindings: KeyBindings) -> Option<EditAction> {
+        if !key.pressed {
+            return None;
+        }
+        let mods = key_bindings.mods(modifiers);
+        let extend = mods.shift;
+
+        if mods.action {
+            if let Key::Character(c) = &key.key_without_modifiers {
+                let action = match c.as_str() {
+                    "=" | "+" => Some(EditAction::ZoomIn),
+                    "-" => Some(EditAction::ZoomOut),
+                    "0" => Some(EditAction::ResetZoom),
+                    #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+                    "a" => Some(EditAction::SelectAll),
+                    #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+                    "c" if !mods.shift => Some(EditAction::Copy),
+                    "x" if !mods.shift => Some(EditAction::Cut),
+                    "v" if !mods.shift => Some(EditAction::Paste),
+                    "\\" if mods.shift => Some(EditAction::JumpToMatchingBracket),
+                    "z" if mods.shift => Some(EditAction::Redo),
+                    "z" => Some(EditAction::Undo),
+                    _ => None,
+                };
+                if action.is_some() {
+                    return action;
+                }
+            }
+        }
+
+        let motion = |motion| Some(EditAction::Move { motion, extend });
+        match &key.logical_key {
+            Key::Named(NamedKey::ArrowLeft) if mods.line => motion(Motion::LineStart),
+            Key::Named(NamedKey::ArrowLeft) if mods.sub_word => motion(Motion::SubWordLeft),
+            Key::Named(NamedKey::ArrowLeft) if mods.word => motion(Motion::WordLeft),
+            Key::Named(NamedKey::ArrowLeft) => motion(Motion::Left),
+            Key::Named(NamedKey::ArrowRight) if mods.line => motion(Motion::LineEnd),
+            Key::Named(NamedKey::ArrowRight) if mods.sub_word => motion(Motion::SubWordRight),
+            Key::Named(NamedKey::ArrowRight) if mods.word => motion(Motion::WordRight),
+            Key::Named(NamedKey::ArrowRight) => motion(Motion::Right),
+            Key::Named(NamedKey::ArrowUp) if mods.line => motion(Motion::TextStart),
+            Key::Named(NamedKey::ArrowUp) => motion(Motion::Up),
+            Key::Named(NamedKey::ArrowDown) if mods.line => motion(Motion::TextEnd),
+            Key::Named(NamedKey::ArrowDown) => motion(Motion::Down),
+            Key::Named(NamedKey::Home) if mods.action => motion(Motion::TextStart),
+            Key::Named(NamedKey::Home) => motion(Motion::LineStart),
+            Key::Named(NamedKey::End) if mods.action => motion(Motion::TextEnd),
+            Key::Named(NamedKey::End) => motion(Motion::LineEnd),
+            Key::Named(NamedKey::Delete) if mods.shift && !mods.action => Some(EditAction::Cut),
+            Key::Named(NamedKey::Insert) if mods.shift && !mods.action => Some(EditAction::Paste),
+            #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+            Key::Named(NamedKey::Insert) if mods.action && !mods.shift => Some(EditAction::Copy),
+            Key::Named(NamedKey::Delete) if mods.sub_word => Some(EditAction::DeleteSubWord),
+            Key::Named(NamedKey::Delete) if mods.word => Some(EditAction::DeleteWord),
+            Key::Named(NamedKey::Delete) => Some(EditAction::Delete),
+            Key::Named(NamedKey::Backspace) if mods.sub_word => Some(EditAction::BackspaceSubWord),
+            Key::Named(NamedKey::Backspace) if mods.word => Some(EditAction::BackspaceWord),
+            Key::Named(NamedKey::Backspace) => Some(EditAction::Backspace),
+            Key::Named(NamedKey::Enter) => Some(EditAction::Newline),
+            Key::Named(NamedKey::Tab) if !mods.shift && !mods.action => Some(EditAction::AcceptGhostText),
+            Key::Named(NamedKey::Space) if !mods.action => Some(EditAction::Insert(" ".to_string())),
+            Key::Character(c) if mods.emacs && c.eq_ignore_ascii_case("a") => motion(Motion::LineStart),
+            Key::Character(c) if mods.emacs && c.eq_ignore_ascii_case("e") => motion(Motion::LineEnd),
+            Key::Character(s) if !mods.action && !mods.emacs => Some(EditAction::Insert(s.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// What an input layer passed to [`Text::add_pre_input_layer()`] sees for each event that reaches the focused box.
+#[derive(Debug)]
+pub struct InputLayerEvent<'a> {
+    /// The focused box that the event is for.
+    pub target: AnyBox,
+    /// The window event. This is `None` for key presses passed to [`Text::handle_key_input()`].
+    pub event: Option<&'a WindowEvent>,
+    /// The key press, for keyboard events.
+    pub key: Option<&'a KeyInput>,
+    /// The action that the event maps to, after any replacements made by earlier layers. For post layers, this is the action that was performed after adjusting it for the box, or `None` if the event didn't perform one, as for keys handled by modal editing.
+    pub action: Option<EditAction>,
+    pub modifiers: ModifiersState,
+}
+
+/// Returned by a pre input layer to decide what happens to an event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputLayerResponse {
+    /// Pass the event on to the next layer, and then to the built-in handling.
+    Continue,
+    /// Stop the event. Later layers and the built-in handling don't see it, and post layers aren't called.
+    Consume,
+    /// Perform this action instead of the built-in handling of the event. Later layers see the replacement in [`InputLayerEvent::action`].
+    Replace(EditAction),
+}
+
+/// Called before the focused box handles an event. See [`Text::add_pre_input_layer()`].
+pub type PreInputLayer = fn(event: &InputLayerEvent) -> InputLayerResponse;
+
+/// Called after the focused box handled an event. See [`Text::add_post_input_layer()`].
+pub type PostInputLayer = fn(event: &InputLayerEvent);
+
+pub(crate) enum PreLayerOutcome {
+    Continue(Option<EditAction>),
+    Consume,
+    Replace(EditAction),
+}
+
+impl Text {
+    /// Add a layer that sees every event sent to the focused box before it's handled, together with the [`EditAction`] that it maps to.
+    ///
+    /// Layers run in the order they were added. Each one can let the event through, consume it, or replace its action with a different one. This can be used for global shortcuts, or for modal editing schemes that reinterpret keys.
+    pub fn add_pre_input_layer(&mut self, layer: PreInputLayer) {
+        self.pre_input_layers.push(layer);
+    }
+
+    /// Add a layer that is called after the focused box handled an event, with the action that was performed. Events consumed by a pre layer don't reach post layers.
+    pub fn add_post_input_layer(&mut self, layer: PostInputLayer) {
+        self.post_input_layers.push(layer);
+    }
+
+    /// Remove all layers added with [`Text::add_pre_input_layer()`] and [`Text::add_post_input_layer()`].
+    pub fn clear_input_layers(&mut self) {
+        self.pre_input_layers.clear();
+        self.post_input_layers.clear();
+    }
+
+    pub(crate) fn run_pre_input_layers(&self, target: AnyBox, event: Option<&WindowEvent>, key: Option<&KeyInput>) -> PreLayerOutcome {
+        let modifiers = self.input_state.modifiers.state();
+        let mut action = key.and_then(|key| EditAction::from_key(key, modifiers, self.shared.key_bindings));
+        let mut replaced = false;
+
+        for layer in &self.pre_input_layers {
+            let layer_event = InputLayerEvent { target, event, key, action: action.clone(), modifiers };
+            match layer(&layer_event) {
+                InputLayerResponse::Continue => {}
+                InputLayerResponse::Consume => return PreLayerOutcome::Consume,
+                InputLayerResponse::Replace(new_action) => {
+                    action = Some(new_action);
+                    replaced = true;
+                }
+            }
+        }
+
+        match action {
+            Some(action) if replaced => PreLayerOutcome::Replace(action),
+            action => PreLayerOutcome::Continue(action),
+        }
+    }
+
+    pub(crate) fn run_post_input_layers(&self, target: AnyBox, event: Option<&WindowEvent>, key: Option<&KeyInput>, action: Option<EditAction>) {
+        let modifiers = self.input_state.modifiers.state();
+        for layer in &self.post_input_layers {
+            let layer_event = InputLayerEvent { target, event, key, action: action.clone(), modifiers };
+            layer(&layer_event);
+        }
+    }
+
+    /// Give the modal editing rules of the focused edit a chance to handle a key press, after the pre input layers let it through. Returns true if the key was used. Always false without the `modal` feature.
+    pub(crate) fn handle_modal_key_if_enabled(&mut self, focused: AnyBox, key: Option<&KeyInput>) -> bool {
+        #[cfg(feature = "modal")]
+        {
+            key.is_some_and(|key| self.handle_modal_key(focused, key))
+        }
+        #[cfg(not(feature = "modal"))]
+        {
+            let _ = (focused, key);
+            false
+        }
+    }
+
+    /// Perform an action on a box as if the keys bound to it were pressed.
+    pub(crate) fn apply_edit_action(&mut self, target: AnyBox, action: &EditAction) {
+        match target {
+            AnyBox::TextEdit(i) => {
+                get_full_text_edit_free_function(&mut self.text_edits, &mut self.shared, i).apply_action(action);
+            },
+            AnyBox::TextBox(i) => {
+                get_full_text_box_free_function(&mut self.text_boxes, &mut self.shared, i).apply_action(action);
+            },
+        }
+    }
+}
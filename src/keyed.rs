@@ -0,0 +1,70 @@
+use std::borrow::Cow;
+
+use crate::*;
+
+impl Text {
+    /// Add a text box identified by `key`, or reuse the box that was added with the same key before, and return it.
+    ///
+    /// This is meant for immediate mode interfaces that declare all their text every frame. When a box with the key already exists, its text, position, size and depth are updated, but the layout is only rebuilt if the text or the size actually changed. The box is also refreshed as with [`Text::refresh_text_box()`].
+    ///
+    /// Setting the same style on the returned box with [`TextBoxMut::set_style()`] every frame doesn't cause a relayout either.
+    ///
+    /// Keyed boxes don't have handles. Boxes that weren't declared since the last [`Text::advance_frame_and_hide_boxes()`] can be removed with [`Text::remove_unused_keyed_boxes()`], or a single one with [`Text::remove_text_box_keyed()`].
+    pub fn add_text_box_keyed(&mut self, key: u64, text: impl Into<Cow<'static, str>>, pos: (f64, f64), size: (f32, f32), depth: f32) -> TextBoxMut<'_> {
+        let existing = self.keyed_text_boxes.get(&key).filter(|weak| weak.is_alive(self)).copied();
+        let weak = match existing {
+            Some(weak) => {
+                self.refresh_text_box_at(weak.i);
+                let mut text_box = self.get_full_text_box_at(weak.i);
+                // These setters don't mark anything as changed if the values are the same.
+                text_box.set_text(text);
+                text_box.set_pos(pos);
+                text_box.set_size(size);
+                text_box.set_depth(depth);
+                weak
+            }
+            None => {
+                let handle = self.add_text_box(text, pos, size, depth);
+                let weak = self.downgrade_text_box(&handle);
+                std::mem::forget(handle);
+                self.keyed_text_boxes.insert(key, weak);
+                weak
+            }
+        };
+        self.get_full_text_box_at(weak.i)
+    }
+
+    /// Get the box that was added with [`Text::add_text_box_keyed()`] with this key, if it still exists.
+    pub fn get_text_box_keyed(&mut self, key: u64) -> Option<TextBoxMut<'_>> {
+        let weak = *self.keyed_text_boxes.get(&key)?;
+        weak.upgrade(self)
+    }
+
+    /// Remove the box that was added with [`Text::add_text_box_keyed()`] with this key, if there is one.
+    pub fn remove_text_box_keyed(&mut self, key: u64) {
+        if let Some(weak) = self.keyed_text_boxes.remove(&key) {
+            if weak.is_alive(self) {
+                self.remove_text_box_at(weak.i);
+            }
+        }
+    }
+
+    /// Remove all keyed boxes that weren't declared with [`Text::add_text_box_keyed()`] since the last call to [`Text::advance_frame_and_hide_boxes()`].
+    pub fn remove_unused_keyed_boxes(&mut self) {
+        let current_frame = self.current_visibility_frame;
+        let mut unused = Vec::new();
+        self.keyed_text_boxes.retain(|_, weak| {
+            match self.text_boxes.get(weak.i as usize).filter(|text_box| text_box.generation == weak.generation) {
+                Some(text_box) if text_box.last_frame_touched == current_frame => true,
+                Some(_) => {
+                    unused.push(weak.i);
+                    false
+                }
+                None => false,
+            }
+        });
+        for i in unused {
+            self.remove_text_box_at(i);
+        }
+    }
+}
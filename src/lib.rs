@@ -75,12 +75,23 @@
 //! The `occlusion.rs` example shows how this works.
 
 
+/// Enter a `tracing` span until the end of the current block, if the `tracing` feature is enabled.
+macro_rules! profile_span {
+    ($name:literal) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!($name).entered();
+    };
+}
+
 mod setup;
 pub use setup::*;
 
 mod text_renderer;
 pub use text_renderer::*;
 
+mod glyph_cache;
+pub use glyph_cache::*;
+
 mod text;
 pub use text::*;
 
@@ -90,11 +101,86 @@ pub use text_box::*;
 mod text_edit;
 pub use text_edit::*;
 
+mod word_rules;
+pub use word_rules::*;
+
+mod rich_text;
+pub use rich_text::*;
+
+mod async_layout;
+pub use async_layout::*;
+
+mod cpu_render;
+pub use cpu_render::*;
+
+mod replay;
+pub use replay::*;
+
+mod group;
+pub use group::*;
+
+mod screen_anchor;
+pub use screen_anchor::*;
+
+mod scrollbar;
+pub use scrollbar::*;
+
+mod debug_overlay;
+pub use debug_overlay::*;
+
+mod system_settings;
+pub use system_settings::*;
+
+mod spoken_text;
+pub use spoken_text::*;
+
+mod weak_handle;
+pub use weak_handle::*;
+
+mod material;
+pub use material::*;
+
+mod glyph_animation;
+pub use glyph_animation::*;
+
+mod text_paths;
+pub use text_paths::*;
+
+mod sanitize;
+pub use sanitize::*;
+
+mod input_layer;
+pub use input_layer::*;
+
+mod form;
+pub use form::*;
+
+mod keyed;
+pub use keyed::*;
+
+#[cfg(feature = "modal")]
+mod modal;
+#[cfg(feature = "modal")]
+pub use modal::*;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "serde")]
+mod state;
+#[cfg(feature = "serde")]
+pub use state::*;
+
 #[cfg(feature = "accessibility")]
 mod accessibility;
 #[cfg(feature = "accessibility")]
 pub use accessibility::*;
 
+#[cfg(feature = "bevy")]
+mod bevy_plugin;
+#[cfg(feature = "bevy")]
+pub use bevy_plugin::*;
+
 pub use parley::TextStyle as ParleyTextStyle;
 
 /// Text style.
@@ -111,6 +197,16 @@ pub struct TextEditStyle {
     pub disabled_text_color: ColorBrush,
     /// Color to use for placeholder text
     pub placeholder_text_color: ColorBrush,
+    /// Color of the markers drawn on whitespace when [`TextEditMut::set_show_invisibles()`] is enabled
+    pub invisibles_color: ColorBrush,
+    /// Color of the bars marking suspicious characters when the edit uses [`SuspiciousCharPolicy::Highlight`]
+    pub suspicious_char_color: ColorBrush,
+    /// Color of the outlines around matching brackets, see [`TextEditMut::set_highlight_matching_brackets()`]
+    pub bracket_match_color: ColorBrush,
+    /// Built-in scrollbars shown when the text doesn't fit in the edit. `None` disables them.
+    pub scrollbar: Option<ScrollbarStyle>,
+    /// Fade used when fadeout clipping is enabled, unless the edit has its own set with [`TextEditMut::set_fadeout_style()`].
+    pub fadeout: FadeoutStyle,
 }
 
 impl Default for TextEditStyle {
@@ -118,6 +214,11 @@ impl Default for TextEditStyle {
         Self {
             disabled_text_color: ColorBrush([128, 128, 128, 255]), // Gray
             placeholder_text_color: ColorBrush([160, 160, 160, 255]), // Lighter gray
+            invisibles_color: ColorBrush([160, 160, 160, 140]), // Faint gray
+            suspicious_char_color: ColorBrush([230, 40, 40, 255]), // Red
+            bracket_match_color: ColorBrush([200, 200, 200, 160]), // Light gray
+            scrollbar: None,
+            fadeout: FadeoutStyle::default(),
         }
     }
 }
@@ -149,4 +250,4 @@ use wgpu::{
 };
 use swash::zeno::Placement;
 
-pub use parley::{FontWeight, FontStyle, LineHeight, FontStack, Alignment, AlignmentOptions, OverflowWrap};
\ No newline at end of file
+pub use parley::{FontWeight, FontStyle, LineHeight, FontStack, Alignment, AlignmentOptions, OverflowWrap, InlineBox, PositionedInlineBox};
\ No newline at end of file
@@ -0,0 +1,278 @@
+use std::ops::Range;
+
+use crate::*;
+
+/// Maximum number of materials that a [`TextRenderer`] can hold.
+pub const MAX_MATERIALS: usize = 32;
+
+/// Bits 8 to 15 of the quad flags hold the material index plus one.
+pub(crate) const MATERIAL_SHIFT: u32 = 8;
+
+pub(crate) const MATERIAL_KIND_SHIMMER: u32 = 1;
+pub(crate) const MATERIAL_KIND_CUSTOM: u32 = 2;
+pub(crate) const MATERIAL_KIND_LINEAR_GRADIENT: u32 = 3;
+pub(crate) const MATERIAL_KIND_RADIAL_GRADIENT: u32 = 4;
+
+/// A special fill for text, drawn by the [`TextRenderer`] instead of the flat style color.
+///
+/// Add a material with [`TextRenderer::add_material()`], then use it for a style with [`Text::set_style_material()`], or for parts of a box with [`TextBoxMut::set_material_ranges()`]. Materials only apply to regular glyphs: color emoji, images and decorations are drawn as usual.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Material {
+    /// A bright band that sweeps across the text, like on loading placeholders.
+    Shimmer {
+        /// Color of the band. Its alpha controls how much it covers the text color.
+        highlight: ColorBrush,
+        /// Width of the band in pixels.
+        band_width: f32,
+        /// Speed of the band in pixels per second.
+        speed: f32,
+    },
+    /// A linear gradient between two points, in pixels relative to the top left corner of the box. The colors replace the style color, and the text is drawn in `colors[0]` before `start` and in `colors[1]` after `end`.
+    LinearGradient {
+        start: (f32, f32),
+        end: (f32, f32),
+        colors: [ColorBrush; 2],
+    },
+    /// A radial gradient from `colors[0]` at `center` to `colors[1]` at `radius` pixels from it. `center` is relative to the top left corner of the box.
+    RadialGradient {
+        center: (f32, f32),
+        radius: f32,
+        colors: [ColorBrush; 2],
+    },
+    /// Calls the `custom_material` function set with [`TextRenderer::set_custom_material_shader()`]. The parameters and colors are passed to it unchanged.
+    Custom {
+        params: [f32; 4],
+        colors: [ColorBrush; 2],
+        /// Set to true if the function uses `input.time`, so that the renderer keeps updating it.
+        animated: bool,
+    },
+}
+
+impl Material {
+    /// Returns true if the material changes over time, in which case the text needs to be redrawn every frame.
+    pub fn is_animated(&self) -> bool {
+        match self {
+            Material::Shimmer { .. } => true,
+            Material::Custom { animated, .. } => *animated,
+            Material::LinearGradient { .. } | Material::RadialGradient { .. } => false,
+        }
+    }
+
+    pub(crate) fn to_gpu(&self) -> GpuMaterial {
+        match *self {
+            Material::Shimmer { highlight, band_width, speed } => GpuMaterial {
+                kind: MATERIAL_KIND_SHIMMER,
+                _pad: [0; 3],
+                color_a: color_to_f32(highlight),
+                color_b: [0.0; 4],
+                params: [band_width, speed, 0.0, 0.0],
+            },
+            Material::LinearGradient { start, end, colors } => GpuMaterial {
+                kind: MATERIAL_KIND_LINEAR_GRADIENT,
+                _pad: [0; 3],
+                color_a: color_to_f32(colors[0]),
+                color_b: color_to_f32(colors[1]),
+                params: [start.0, start.1, end.0, end.1],
+            },
+            Material::RadialGradient { center, radius, colors } => GpuMaterial {
+                kind: MATERIAL_KIND_RADIAL_GRADIENT,
+                _pad: [0; 3],
+                color_a: color_to_f32(colors[0]),
+                color_b: color_to_f32(colors[1]),
+                params: [center.0, center.1, radius, 0.0],
+            },
+            Material::Custom { params, colors, .. } => GpuMaterial {
+                kind: MATERIAL_KIND_CUSTOM,
+                _pad: [0; 3],
+                color_a: color_to_f32(colors[0]),
+                color_b: color_to_f32(colors[1]),
+                params,
+            },
+        }
+    }
+}
+
+/// Handle for a material added with [`TextRenderer::add_material()`].
+///
+/// Each renderer has its own materials, so the id only works with the renderer that returned it. Text prepared into other renderers doesn't use it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialId {
+    pub(crate) renderer_id: u64,
+    pub(crate) i: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub(crate) struct GpuMaterial {
+    pub kind: u32,
+    pub _pad: [u32; 3],
+    /// Colors are sent as sRGB values between 0 and 1, the shader converts them.
+    pub color_a: [f32; 4],
+    pub color_b: [f32; 4],
+    pub params: [f32; 4],
+}
+
+fn color_to_f32(color: ColorBrush) -> [f32; 4] {
+    color.0.map(|c| c as f32 / 255.0)
+}
+
+impl TextRenderer {
+    /// Add a material, so that it can be used in styles with [`Text::set_style_material()`].
+    ///
+    /// Panics if there are already [`MAX_MATERIALS`] materials.
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        let renderer = &mut self.text_renderer;
+        assert!(renderer.materials.len() < MAX_MATERIALS, "textslabs: too many materials, the maximum is {}", MAX_MATERIALS);
+        let id = MaterialId { renderer_id: renderer.renderer_id, i: renderer.materials.len() as u32 };
+        renderer.materials.push(material);
+        renderer.params.materials[id.i as usize] = material.to_gpu();
+        renderer.needs_gpu_sync = true;
+        id
+    }
+
+    /// Change the parameters of a material. Text that uses it is updated without being prepared again.
+    ///
+    /// Panics if `id` was returned by another renderer.
+    pub fn set_material(&mut self, id: MaterialId, material: Material) {
+        let renderer = &mut self.text_renderer;
+        assert_eq!(id.renderer_id, renderer.renderer_id, "textslabs: the material belongs to another renderer");
+        renderer.materials[id.i as usize] = material;
+        renderer.params.materials[id.i as usize] = material.to_gpu();
+        renderer.needs_gpu_sync = true;
+    }
+
+    /// Panics if `id` was returned by another renderer.
+    pub fn material(&self, id: MaterialId) -> Material {
+        assert_eq!(id.renderer_id, self.text_renderer.renderer_id, "textslabs: the material belongs to another renderer");
+        self.text_renderer.materials[id.i as usize]
+    }
+
+    /// Returns true if any material changes over time. In that case, the app should keep redrawing while text with those materials is visible.
+    pub fn has_animated_materials(&self) -> bool {
+        self.text_renderer.materials.iter().any(Material::is_animated)
+    }
+
+    /// Replace the WGSL function used by [`Material::Custom`] and rebuild the render pipeline.
+    ///
+    /// `source` must define a function with this signature, and can define other helper functions before it:
+    ///
+    /// ```wgsl
+    /// fn custom_material(input: MaterialInput) -> vec4<f32>
+    /// ```
+    ///
+    /// `MaterialInput` has these fields:
+    /// - `color: vec4<f32>`: the linear text color, before glyph coverage and fade are applied.
+    /// - `screen_pos: vec2<f32>`: the pixel position, in the coordinates of the box's group.
+    /// - `local_pos: vec2<f32>`: the pixel position relative to the top left corner of the box.
+    /// - `time: f32`: seconds since the renderer was created.
+    /// - `color_a`, `color_b: vec4<f32>`: the material colors, converted to linear.
+    /// - `params: vec4<f32>`: the material parameters.
+    ///
+    /// The returned color is multiplied by the glyph coverage. Passing `None` goes back to the default function, which returns `input.color`.
+    pub fn set_custom_material_shader(&mut self, device: &Device, source: Option<&str>) {
+        let renderer = &mut self.text_renderer;
+        renderer.custom_material_shader = source.map(str::to_string);
+        renderer.pipeline = create_pipeline(
+            device,
+            &renderer.pipeline_layout,
+            renderer.custom_material_shader.as_deref(),
+            renderer.format,
+            renderer.depth_stencil.clone(),
+            renderer.sample_count,
+        );
+    }
+}
+
+impl Text {
+    /// Draw all text with this style using a material added with [`TextRenderer::add_material()`].
+    ///
+    /// The material is only used when the text is prepared into the renderer that it belongs to. When the same `Text` is prepared into more than one renderer, a material can be set for each of them, and setting another material from the same renderer replaces the previous one.
+    pub fn set_style_material(&mut self, handle: &StyleHandle, material: MaterialId) {
+        let materials = &mut self.shared.styles[handle.i as usize].materials;
+        materials.retain(|m| m.renderer_id != material.renderer_id);
+        materials.push(material);
+        self.shared.text_changed = true;
+    }
+
+    /// Go back to the flat style color when the text is prepared into `text_renderer`.
+    pub fn clear_style_material(&mut self, handle: &StyleHandle, text_renderer: &TextRenderer) {
+        let renderer_id = text_renderer.text_renderer.renderer_id;
+        self.shared.styles[handle.i as usize].materials.retain(|m| m.renderer_id != renderer_id);
+        self.shared.text_changed = true;
+    }
+
+    /// The material used for this style when the text is prepared into `text_renderer`.
+    pub fn style_material(&self, handle: &StyleHandle, text_renderer: &TextRenderer) -> Option<MaterialId> {
+        let renderer_id = text_renderer.text_renderer.renderer_id;
+        self.shared.styles[handle.i as usize].materials.iter().copied().find(|m| m.renderer_id == renderer_id)
+    }
+}
+
+impl<'a> TextBoxMut<'a> {
+    pub(crate) fn material(&self, renderer_id: u64) -> Option<MaterialId> {
+        self.shared.styles[self.inner.style.i as usize].materials.iter().copied().find(|m| m.renderer_id == renderer_id)
+    }
+
+    /// Draw byte ranges of the text with materials added with [`TextRenderer::add_material()`], for example a gradient on a single word. In the ranges, these materials replace the style material.
+    ///
+    /// Ranges with materials from a renderer are only drawn with them when the text is prepared into that renderer, so ranges for several renderers can be mixed.
+    ///
+    /// Ranges refer to the current text, so they need to be set again if the text changes.
+    pub fn set_material_ranges(&mut self, ranges: &[(Range<usize>, MaterialId)]) {
+        // Kept sorted and without overlaps for each renderer, so that quads can be looked up with a binary search. Later ranges win, so the overlapping parts are cut out of the earlier ones.
+        let material_ranges = &mut self.inner.material_ranges;
+        material_ranges.clear();
+        for (range, material) in ranges {
+            if range.is_empty() {
+                continue;
+            }
+            let mut tails = Vec::new();
+            material_ranges.retain_mut(|(existing, existing_material)| {
+                if existing_material.renderer_id != material.renderer_id || existing.end <= range.start || existing.start >= range.end {
+                    return true;
+                }
+                if existing.end > range.end {
+                    tails.push((range.end..existing.end, *existing_material));
+                }
+                existing.end = range.start;
+                existing.start < range.start
+            });
+            material_ranges.extend(tails);
+            material_ranges.push((range.clone(), *material));
+        }
+        material_ranges.sort_by_key(|(range, _)| range.start);
+        self.shared.text_changed = true;
+    }
+}
+
+impl ContextlessTextRenderer {
+    /// Set the material bits of the glyph quads of a box that are drawn inside the material ranges. `left` and `top` are the position of the text content, including scrolling.
+    ///
+    /// Each quad is hit tested once to find its byte index, which is then looked up in the sorted ranges.
+    pub(crate) fn set_range_materials(&mut self, quad_storage: &QuadStorage, layout: &Layout<ColorBrush>, ranges: &[(Range<usize>, MaterialId)], left: f32, top: f32) {
+        let ranges: Vec<&(Range<usize>, MaterialId)> = ranges.iter().filter(|(_, material)| material.renderer_id == self.renderer_id).collect();
+        if ranges.is_empty() {
+            return;
+        }
+        let material_mask = 0xff << MATERIAL_SHIFT;
+        for page_range in &quad_storage.pages {
+            if page_range.page_type != AtlasPageType::Mask {
+                continue;
+            }
+            let quads = &mut self.mask_atlas_pages[page_range.page_index as usize].quads;
+            for quad in &mut quads[page_range.quad_start as usize..page_range.quad_end as usize] {
+                // Hit test a bit to the left of the center, so that the result is the start of the cluster and not the end.
+                let x = quad.pos[0] as f32 + quad.dim[0] as f32 * 0.25 - left;
+                let y = quad.pos[1] as f32 + quad.dim[1] as f32 * 0.5 - top;
+                let byte_index = parley::Selection::from_point(layout, x, y).focus().index();
+                let after = ranges.partition_point(|(range, _)| range.start <= byte_index);
+                let Some((range, material)) = after.checked_sub(1).map(|i| ranges[i]) else {
+                    continue;
+                };
+                if range.contains(&byte_index) {
+                    quad.flags = (quad.flags & !material_mask) | ((material.i + 1) << MATERIAL_SHIFT);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use winit::keyboard::{Key, ModifiersState, NamedKey};
+
+use crate::*;
+
+/// The current mode of a text edit with modal editing enabled. See [`TextEditMut::set_modal_editing()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModalMode {
+    /// Keys run motions and operators instead of inserting text.
+    #[default]
+    Normal,
+    /// Keys insert text like in a normal text edit. Escape goes back to normal mode.
+    Insert,
+    /// Motions extend the selection, and operators act on it.
+    Visual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ModalState {
+    pub(crate) mode: ModalMode,
+    operator: Option<Operator>,
+    count: Option<usize>,
+    /// A `g` was pressed, waiting for a second `g`.
+    pending_g: bool,
+}
+
+/// The longest count that is honored, so that typing a large number by mistake doesn't freeze the app.
+const MAX_COUNT: usize = 10_000;
+
+fn motion_for_char(c: &str) -> Option<Motion> {
+    let motion = match c {
+        "h" => Motion::Left,
+        "l" => Motion::Right,
+        "k" => Motion::Up,
+        "j" => Motion::Down,
+        "w" => Motion::WordRight,
+        "b" => Motion::WordLeft,
+        "0" => Motion::LineStart,
+        "$" => Motion::LineEnd,
+        "G" => Motion::TextEnd,
+        _ => return None,
+    };
+    Some(motion)
+}
+
+impl<'a> TextEditMut<'a> {
+    /// Enable or disable vim-style modal editing, with normal, insert and visual modes.
+    ///
+    /// Normal mode supports `h` `j` `k` `l` `w` `b` `0` `$` `gg` `G` motions with counts, the `d`, `c` and `y` operators followed by a motion or doubled to act on the line, `x`, `X`, `p`, `u`, `Ctrl+r`, and `i` `a` `I` `A` `o` `O` to enter insert mode. `v` enters visual mode, where motions extend the selection and `d`, `x`, `c` and `y` act on it. Space and Enter work as `l` and `j`, and Tab is ignored.
+    ///
+    /// Keys go through the layers added with [`Text::add_pre_input_layer()`] first, so a layer can still consume or replace them.
+    ///
+    /// Yanked and deleted text goes through the system clipboard. The edit starts in normal mode.
+    ///
+    /// Requires the `modal` feature.
+    pub fn set_modal_editing(&mut self, enabled: bool) {
+        if enabled != self.inner.modal.is_some() {
+            self.inner.modal = enabled.then(ModalState::default);
+            self.text_box.shared.decorations_changed = true;
+        }
+    }
+
+    /// Switch to a different mode. Does nothing if modal editing is disabled.
+    pub fn set_modal_mode(&mut self, mode: ModalMode) {
+        let Some(modal) = &mut self.inner.modal else {
+            return;
+        };
+        let previous = modal.mode;
+        *modal = ModalState { mode, ..Default::default() };
+        if previous == ModalMode::Visual && mode != ModalMode::Visual {
+            self.text_box.collapse_selection();
+        }
+        self.text_box.shared.decorations_changed = true;
+    }
+
+    /// Handle a key press with the modal editing rules. Returns false if the key should go through the normal handling instead.
+    pub(crate) fn handle_modal_key(&mut self, key: &KeyInput, modifiers: ModifiersState) -> bool {
+        let Some(modal) = &self.inner.modal else {
+            return false;
+        };
+        if !key.pressed || self.inner.disabled || self.is_composing() {
+            return false;
+        }
+        let mode = modal.mode;
+
+        if let Key::Named(NamedKey::Escape) = key.logical_key {
+            if mode == ModalMode::Insert {
+                self.apply_action(&EditAction::Move { motion: Motion::Left, extend: false });
+            }
+            self.set_modal_mode(ModalMode::Normal);
+            return true;
+        }
+
+        if mode == ModalMode::Insert {
+            return false;
+        }
+
+        let mods = self.text_box.shared.key_bindings.mods(modifiers);
+        let c = match &key.logical_key {
+            Key::Character(c) => c.as_str(),
+            // These would insert text. Like in vim, Space and Enter move right and down, and Tab does nothing.
+            Key::Named(NamedKey::Space) if !mods.action => "l",
+            Key::Named(NamedKey::Enter) if !mods.action => "j",
+            Key::Named(NamedKey::Tab) => {
+                if let Some(modal) = &mut self.inner.modal {
+                    modal.operator = None;
+                    modal.count = None;
+                }
+                return true;
+            }
+            // Arrows, Backspace and the like keep working as usual.
+            _ => return false,
+        };
+        if mods.action {
+            if c.eq_ignore_ascii_case("r") {
+                self.apply_action(&EditAction::Redo);
+                return true;
+            }
+            return false;
+        }
+
+        self.handle_modal_char(c, mode);
+        true
+    }
+
+    fn handle_modal_char(&mut self, c: &str, mode: ModalMode) {
+        let Some(modal) = &mut self.inner.modal else {
+            return;
+        };
+
+        // Counts. A leading 0 is the line start motion instead.
+        if let Some(digit) = c.parse::<usize>().ok().filter(|d| *d != 0 || modal.count.is_some()) {
+            modal.count = Some((modal.count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+            return;
+        }
+
+        let count = modal.count.take().unwrap_or(1);
+        let operator = modal.operator;
+
+        if std::mem::take(&mut modal.pending_g) {
+            if c == "g" {
+                self.run_motion(Motion::TextStart, 1, mode == ModalMode::Visual || operator.is_some());
+                self.finish_operator(operator);
+            } else {
+                modal.operator = None;
+            }
+            return;
+        }
+
+        if c == "g" {
+            modal.pending_g = true;
+            modal.count = Some(count);
+            return;
+        }
+
+        if let Some(motion) = motion_for_char(c) {
+            // A count before G means a line number, which we don't track. Go to the end like a plain G.
+            let count = if motion == Motion::TextEnd { 1 } else { count };
+            self.run_motion(motion, count, mode == ModalMode::Visual || operator.is_some());
+            self.finish_operator(operator);
+            return;
+        }
+
+        if mode == ModalMode::Visual {
+            let operator = match c {
+                "d" | "x" => Some(Operator::Delete),
+                "c" => Some(Operator::Change),
+                "y" => Some(Operator::Yank),
+                _ => None,
+            };
+            if operator.is_some() {
+                self.finish_operator(operator);
+            }
+            return;
+        }
+
+        let pressed_operator = match c {
+            "d" => Some(Operator::Delete),
+            "c" => Some(Operator::Change),
+            "y" => Some(Operator::Yank),
+            _ => None,
+        };
+        if let Some(pressed) = pressed_operator {
+            match operator {
+                // dd, cc and yy act on whole lines.
+                Some(pending) if pending == pressed => {
+                    self.select_lines(count, pressed != Operator::Change);
+                    self.finish_operator(Some(pressed));
+                }
+                _ => {
+                    if let Some(modal) = &mut self.inner.modal {
+                        modal.operator = Some(pressed);
+                        modal.count = (count > 1).then_some(count);
+                    }
+                }
+            }
+            return;
+        }
+
+        if let Some(modal) = &mut self.inner.modal {
+            modal.operator = None;
+        }
+
+        match c {
+            "x" => self.repeat_action(&EditAction::Delete, count),
+            "X" => self.repeat_action(&EditAction::Backspace, count),
+            "p" => self.repeat_action(&EditAction::Paste, count),
+            "u" => self.repeat_action(&EditAction::Undo, count),
+            "v" => self.set_modal_mode(ModalMode::Visual),
+            "i" => self.set_modal_mode(ModalMode::Insert),
+            "a" => {
+                self.apply_action(&EditAction::Move { motion: Motion::Right, extend: false });
+                self.set_modal_mode(ModalMode::Insert);
+            }
+            "I" => {
+                self.apply_action(&EditAction::Move { motion: Motion::LineStart, extend: false });
+                self.set_modal_mode(ModalMode::Insert);
+            }
+            "A" => {
+                self.apply_action(&EditAction::Move { motion: Motion::LineEnd, extend: false });
+                self.set_modal_mode(ModalMode::Insert);
+            }
+            "o" => {
+                self.apply_action(&EditAction::Move { motion: Motion::LineEnd, extend: false });
+                self.apply_action(&EditAction::Newline);
+                self.set_modal_mode(ModalMode::Insert);
+            }
+            "O" => {
+                self.apply_action(&EditAction::Move { motion: Motion::LineStart, extend: false });
+                self.apply_action(&EditAction::Newline);
+                self.apply_action(&EditAction::Move { motion: Motion::Left, extend: false });
+                self.set_modal_mode(ModalMode::Insert);
+            }
+            _ => (),
+        }
+    }
+
+    fn run_motion(&mut self, motion: Motion, count: usize, extend: bool) {
+        self.repeat_action(&EditAction::Move { motion, extend }, count);
+    }
+
+    fn repeat_action(&mut self, action: &EditAction, count: usize) {
+        for _ in 0..count {
+            self.apply_action(action);
+        }
+    }
+
+    /// Select `count` lines starting at the cursor's line, including the final line break if `with_newline` is true.
+    fn select_lines(&mut self, count: usize, with_newline: bool) {
+        self.apply_action(&EditAction::Move { motion: Motion::LineStart, extend: false });
+        self.run_motion(Motion::Down, count - 1, true);
+        self.apply_action(&EditAction::Move { motion: Motion::LineEnd, extend: true });
+        if with_newline {
+            self.apply_action(&EditAction::Move { motion: Motion::Right, extend: true });
+        }
+    }
+
+    /// Apply the operator to the current selection, if there is one, and go to the mode that follows it.
+    fn finish_operator(&mut self, operator: Option<Operator>) {
+        let Some(operator) = operator else {
+            return;
+        };
+        match operator {
+            Operator::Delete => self.apply_action(&EditAction::Cut),
+            Operator::Yank => self.apply_action(&EditAction::Copy),
+            Operator::Change => self.apply_action(&EditAction::Cut),
+        }
+        let next_mode = if operator == Operator::Change { ModalMode::Insert } else { ModalMode::Normal };
+        // Leaving visual mode collapses the selection, but a yank in normal mode has to collapse it explicitly.
+        if operator == Operator::Yank {
+            self.text_box.collapse_selection();
+            self.text_box.shared.decorations_changed = true;
+        }
+        self.set_modal_mode(next_mode);
+    }
+}
+
+impl Text {
+    /// Give the focused text edit a chance to handle a key press with its modal editing rules. Returns true if the key was used.
+    pub(crate) fn handle_modal_key(&mut self, focused: AnyBox, key: &KeyInput) -> bool {
+        let AnyBox::TextEdit(i) = focused else {
+            return false;
+        };
+        let modifiers = self.input_state.modifiers.state();
+        let mut text_edit = get_full_text_edit_free_function(&mut self.text_edits, &mut self.shared, i);
+        text_edit.handle_modal_key(key, modifiers)
+    }
+}
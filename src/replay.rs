@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{DeviceId, ElementState, Ime, Modifiers, MouseButton, MouseScrollDelta, TouchPhase, WindowEvent};
+use winit::keyboard::{Key, ModifiersState};
+use winit::platform::modifier_supplement::KeyEventExtModifierSupplement;
+use winit::window::Window;
+
+use crate::*;
+
+/// The parts of a `winit::event::KeyEvent` that text boxes use.
+///
+/// Winit's `KeyEvent` can't be constructed outside of winit, so recorded key presses are stored in this form and handled without going through a `WindowEvent`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyInput {
+    pub logical_key: Key,
+    /// The key that would have been produced without any modifiers held.
+    pub key_without_modifiers: Key,
+    pub pressed: bool,
+    pub repeat: bool,
+}
+
+impl KeyInput {
+    pub fn from_key_event(event: &winit::event::KeyEvent) -> Self {
+        Self {
+            logical_key: event.logical_key.clone(),
+            key_without_modifiers: event.key_without_modifiers(),
+            pressed: event.state.is_pressed(),
+            repeat: event.repeat,
+        }
+    }
+}
+
+/// An input event handled by [`Text`], in a form that can be stored and fed back with [`Text::replay_event()`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RecordedEvent {
+    CursorMoved { x: f64, y: f64 },
+    MouseInput { button: MouseButton, pressed: bool },
+    MouseWheel { delta: MouseScrollDelta },
+    Key { key: KeyInput, is_synthetic: bool },
+    Modifiers(ModifiersState),
+    Ime(Ime),
+    Focused(bool),
+    Resized { width: u32, height: u32 },
+}
+
+impl RecordedEvent {
+    /// Convert a `WindowEvent` into a `RecordedEvent`. Returns `None` for events that don't affect text boxes.
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        let recorded = match event {
+            WindowEvent::CursorMoved { position, .. } => RecordedEvent::CursorMoved { x: position.x, y: position.y },
+            WindowEvent::MouseInput { state, button, .. } => RecordedEvent::MouseInput { button: *button, pressed: state.is_pressed() },
+            WindowEvent::MouseWheel { delta, .. } => RecordedEvent::MouseWheel { delta: *delta },
+            WindowEvent::KeyboardInput { event, is_synthetic, .. } => RecordedEvent::Key {
+                key: KeyInput::from_key_event(event),
+                is_synthetic: *is_synthetic,
+            },
+            WindowEvent::ModifiersChanged(modifiers) => RecordedEvent::Modifiers(modifiers.state()),
+            WindowEvent::Ime(ime) => RecordedEvent::Ime(ime.clone()),
+            WindowEvent::Focused(focused) => RecordedEvent::Focused(*focused),
+            WindowEvent::Resized(size) => RecordedEvent::Resized { width: size.width, height: size.height },
+            _ => return None,
+        };
+        Some(recorded)
+    }
+
+    /// Convert back to a `WindowEvent`. Returns `None` for key presses, which can't be turned back into winit events.
+    fn to_window_event(&self) -> Option<WindowEvent> {
+        // Safety: the dummy id is only compared against other ids, and text boxes never look at it.
+        let device_id = unsafe { DeviceId::dummy() };
+        let event = match self {
+            RecordedEvent::CursorMoved { x, y } => WindowEvent::CursorMoved { device_id, position: PhysicalPosition::new(*x, *y) },
+            RecordedEvent::MouseInput { button, pressed } => WindowEvent::MouseInput {
+                device_id,
+                state: if *pressed { ElementState::Pressed } else { ElementState::Released },
+                button: *button,
+            },
+            RecordedEvent::MouseWheel { delta } => WindowEvent::MouseWheel { device_id, delta: *delta, phase: TouchPhase::Moved },
+            RecordedEvent::Key { .. } => return None,
+            RecordedEvent::Modifiers(state) => WindowEvent::ModifiersChanged(Modifiers::from(*state)),
+            RecordedEvent::Ime(ime) => WindowEvent::Ime(ime.clone()),
+            RecordedEvent::Focused(focused) => WindowEvent::Focused(*focused),
+            RecordedEvent::Resized { width, height } => WindowEvent::Resized(PhysicalSize::new(*width, *height)),
+        };
+        Some(event)
+    }
+}
+
+/// A sequence of input events captured with [`Text::start_recording()`].
+///
+/// With the `serde` feature, recordings can be serialized, so that they can be collected from users and replayed in tests.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EventRecording {
+    /// The events, with the time since the start of the recording at which they were handled.
+    pub events: Vec<(Duration, RecordedEvent)>,
+}
+
+impl Text {
+    /// Start recording the events passed to [`Text::handle_event()`] and [`Text::handle_event_with_topmost()`]. Any previous recording is discarded.
+    ///
+    /// Times are taken from the clock set with [`Text::set_time_source()`].
+    pub fn start_recording(&mut self) {
+        self.recording = Some((self.shared.now(), EventRecording::default()));
+    }
+
+    /// Stop recording and return the events that were recorded since [`Text::start_recording()`].
+    pub fn stop_recording(&mut self) -> EventRecording {
+        self.recording.take().map(|(_, recording)| recording).unwrap_or_default()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub(crate) fn record_event(&mut self, event: &WindowEvent) {
+        if self.recording.is_some() {
+            if let Some(recorded) = RecordedEvent::from_window_event(event) {
+                self.push_recorded_event(recorded);
+            }
+        }
+    }
+
+    fn push_recorded_event(&mut self, recorded: RecordedEvent) {
+        let now = self.shared.now();
+        if let Some((start, recording)) = &mut self.recording {
+            recording.events.push((now.saturating_duration_since(*start), recorded));
+        }
+    }
+
+    /// Handle a recorded event as if it came from the window.
+    ///
+    /// Events are always handled as with [`Text::handle_event()`], so clicks that were occluded when using [`Text::handle_event_with_topmost()`] are not reproduced. For timing-sensitive sequences like double clicks or key repeat throttling, use [`Text::set_time_source()`] to replay the recorded times.
+    pub fn replay_event(&mut self, event: &RecordedEvent, window: &Window) {
+        match event {
+            RecordedEvent::Key { key, is_synthetic } => self.handle_key_input(key, *is_synthetic),
+            _ => {
+                if let Some(window_event) = event.to_window_event() {
+                    self.handle_event(&window_event, window);
+                }
+            }
+        }
+    }
+
+    /// Handle all the events in a recording, in order, without waiting between them.
+    pub fn replay(&mut self, recording: &EventRecording, window: &Window) {
+        for (_time, event) in &recording.events {
+            self.replay_event(event, window);
+        }
+    }
+
+    /// Handle a key press that doesn't come from a `WindowEvent`. This follows the same path as a `WindowEvent::KeyboardInput` passed to [`Text::handle_event()`].
+    pub fn handle_key_input(&mut self, key: &KeyInput, is_synthetic: bool) {
+        profile_span!("Text::handle_key_input");
+        if self.recording.is_some() {
+            self.push_recorded_event(RecordedEvent::Key { key: key.clone(), is_synthetic });
+        }
+        self.shared.current_event_number += 1;
+        self.forget_static_label_focus();
+
+        if !self.should_handle_key_event(key.repeat, is_synthetic) {
+            return;
+        }
+
+        if let Some(focused) = self.focused {
+            self.shared.event_consumed = true;
+            let action = match self.run_pre_input_layers(focused, None, Some(key)) {
+                PreLayerOutcome::Consume => return,
+                PreLayerOutcome::Replace(action) => {
+                    self.apply_edit_action(focused, &action);
+                    Some(action)
+                }
+                PreLayerOutcome::Continue(_) if self.handle_modal_key_if_enabled(focused, Some(key)) => None,
+                PreLayerOutcome::Continue(_) => {
+                    self.shared.performed_action = None;
+                    match focused {
+                        AnyBox::TextEdit(i) => {
+                            let mut text_edit = get_full_text_edit_free_function(&mut self.text_edits, &mut self.shared, i);
+                            text_edit.handle_key_event(key, &self.input_state);
+                        },
+                        AnyBox::TextBox(i) => {
+                            let mut text_box = get_full_text_box_free_function(&mut self.text_boxes, &mut self.shared, i);
+                            text_box.handle_key_event(key, &self.input_state);
+                        },
+                    }
+                    self.shared.performed_action.take()
+                }
+            };
+            self.after_focused_event(focused);
+            self.run_post_input_layers(focused, None, Some(key), action);
+
+            #[cfg(feature = "accessibility")] {
+                if self.need_rerender() {
+                    self.push_ak_update_for_focused(focused);
+                }
+            }
+        }
+    }
+}
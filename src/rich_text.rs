@@ -0,0 +1,186 @@
+use crate::*;
+
+/// Function called for every run of text when converting pasted HTML to plain text.
+///
+/// It receives the decoded text of the run and the names of the tags that enclose it (outermost first), and returns the text that should be inserted. This can be used to map some formatting to plain text, for example wrapping `<b>` runs in `**`.
+pub type HtmlSpanHook = fn(text: &str, open_tags: &[&str]) -> String;
+
+/// Convert a piece of text to an HTML fragment that carries the given style.
+pub fn text_to_html(text: &str, style: &TextStyle2) -> String {
+    let [r, g, b, a] = style.brush.0;
+
+    let mut css = format!(
+        "color: rgba({}, {}, {}, {:.3}); font-size: {}px; font-weight: {};",
+        r, g, b, a as f32 / 255.0, style.font_size, style.font_weight.value()
+    );
+    if !matches!(style.font_style, FontStyle::Normal) {
+        css.push_str(" font-style: italic;");
+    }
+
+    let mut html = format!("<span style=\"{}\">", css);
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\n' => html.push_str("<br>"),
+            c => html.push(c),
+        }
+    }
+    html.push_str("</span>");
+    html
+}
+
+/// Convert an HTML fragment to plain text, dropping all markup.
+///
+/// Line breaks are inserted for `<br>` and block-level tags. The contents of `<script>` and `<style>` are skipped.
+pub fn html_to_plain_text(html: &str, hook: Option<HtmlSpanHook>) -> String {
+    let mut result = String::with_capacity(html.len());
+    let mut open_tags: Vec<&str> = Vec::new();
+    let mut skip_depth = 0;
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = match comment.find("-->") {
+                Some(end) => &comment[end + 3..],
+                None => "",
+            };
+            continue;
+        }
+
+        if rest.starts_with('<') {
+            let end = rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+            let tag = rest[1..end].trim_end_matches('>');
+            rest = &rest[end..];
+
+            let closing = tag.starts_with('/');
+            let name = tag
+                .trim_start_matches('/')
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .unwrap_or("");
+            let self_closing = tag.ends_with('/') || is_void_tag(name);
+
+            if is_block_tag(name) && !result.is_empty() && !result.ends_with('\n') && skip_depth == 0 {
+                result.push('\n');
+            }
+
+            if closing {
+                if let Some(pos) = open_tags.iter().rposition(|t| t.eq_ignore_ascii_case(name)) {
+                    open_tags.truncate(pos);
+                }
+                if is_skipped_tag(name) {
+                    skip_depth = skip_depth.saturating_sub(1);
+                }
+            } else if !self_closing {
+                open_tags.push(name);
+                if is_skipped_tag(name) {
+                    skip_depth += 1;
+                }
+            }
+            continue;
+        }
+
+        let end = rest.find('<').unwrap_or(rest.len());
+        let run = &rest[..end];
+        rest = &rest[end..];
+
+        if skip_depth > 0 {
+            continue;
+        }
+
+        let decoded = decode_entities(&collapse_whitespace(run));
+        if decoded.is_empty() {
+            continue;
+        }
+        match hook {
+            Some(hook) => result.push_str(&hook(&decoded, &open_tags)),
+            None => result.push_str(&decoded),
+        }
+    }
+
+    result.trim_end_matches('\n').to_string()
+}
+
+fn is_void_tag(name: &str) -> bool {
+    const VOID: &[&str] = &["br", "hr", "img", "meta", "link", "input", "wbr", "col", "area", "base", "source"];
+    VOID.iter().any(|t| t.eq_ignore_ascii_case(name))
+}
+
+fn is_block_tag(name: &str) -> bool {
+    const BLOCK: &[&str] = &[
+        "br", "p", "div", "li", "tr", "h1", "h2", "h3", "h4", "h5", "h6", "blockquote", "pre", "ul", "ol", "table", "hr",
+    ];
+    BLOCK.iter().any(|t| t.eq_ignore_ascii_case(name))
+}
+
+fn is_skipped_tag(name: &str) -> bool {
+    name.eq_ignore_ascii_case("script") || name.eq_ignore_ascii_case("style") || name.eq_ignore_ascii_case("head")
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut last_was_space = false;
+    for c in s.chars() {
+        if c.is_whitespace() {
+            if !last_was_space {
+                result.push(' ');
+            }
+            last_was_space = true;
+        } else {
+            result.push(c);
+            last_was_space = false;
+        }
+    }
+    result
+}
+
+fn decode_entities(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(amp) = rest.find('&') {
+        result.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let Some(semi) = rest[..floor_char_boundary(rest, 12)].find(';') else {
+            result.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let entity = &rest[1..semi];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => {
+                if let Some(hex) = entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                    u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse::<u32>().ok().and_then(char::from_u32)
+                } else {
+                    None
+                }
+            }
+        };
+
+        match decoded {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
@@ -0,0 +1,115 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::*;
+
+/// Why a character was reported by [`find_suspicious_chars()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SuspiciousCharKind {
+    /// A bidirectional embedding, override, isolate or mark, which can make text display in a different order than it's stored in. This is the trick behind "Trojan Source" attacks on code review.
+    BidiControl,
+    /// A zero width space, word joiner or byte order mark, or a zero width joiner or non-joiner next to ASCII text or whitespace, where it has no visible effect.
+    ZeroWidth,
+    /// A control character other than tab, line feed and carriage return.
+    Control,
+}
+
+/// An invisible character that can hide or disguise what a text contains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SuspiciousChar {
+    /// Byte range of the character.
+    pub range: Range<usize>,
+    pub char: char,
+    pub kind: SuspiciousCharKind,
+}
+
+/// What a text edit does with suspicious characters in pasted and loaded text. Set with [`TextEditMut::set_suspicious_char_policy()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SuspiciousCharPolicy {
+    /// Text is inserted as it is. Suspicious characters are still reported by [`TextEdit::suspicious_char_report()`].
+    #[default]
+    Allow,
+    /// Text is inserted as it is, and suspicious characters are marked with a bar in the [`TextEditStyle::suspicious_char_color`] of the edit's style.
+    Highlight,
+    /// Suspicious characters are removed from pasted and loaded text.
+    Strip,
+}
+
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Joiners are needed inside emoji sequences and in some scripts, but not next to ASCII text or whitespace.
+fn is_plausible_joiner_neighbor(c: Option<char>) -> bool {
+    c.is_some_and(|c| !c.is_ascii() && !c.is_whitespace())
+}
+
+fn suspicious_kind(c: char, prev: Option<char>, next: Option<char>) -> Option<SuspiciousCharKind> {
+    match c {
+        c if is_bidi_control(c) => Some(SuspiciousCharKind::BidiControl),
+        '\u{200B}' | '\u{2060}' | '\u{FEFF}' => Some(SuspiciousCharKind::ZeroWidth),
+        '\u{200C}' | '\u{200D}' => {
+            if is_plausible_joiner_neighbor(prev) && is_plausible_joiner_neighbor(next) {
+                None
+            } else {
+                Some(SuspiciousCharKind::ZeroWidth)
+            }
+        }
+        '\t' | '\n' | '\r' => None,
+        c if c.is_control() => Some(SuspiciousCharKind::Control),
+        _ => None,
+    }
+}
+
+/// Find the invisible characters in `text` that can hide or disguise its contents, such as bidi overrides and misplaced zero width joiners.
+pub fn find_suspicious_chars(text: &str) -> Vec<SuspiciousChar> {
+    let mut found = Vec::new();
+    let mut prev = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let next = chars.peek().map(|(_, c)| *c);
+        if let Some(kind) = suspicious_kind(c, prev, next) {
+            found.push(SuspiciousChar { range: index..index + c.len_utf8(), char: c, kind });
+        }
+        prev = Some(c);
+    }
+    found
+}
+
+/// Remove the characters found by [`find_suspicious_chars()`] from `text`. Returns the text unchanged if there are none.
+pub fn strip_suspicious_chars(text: &str) -> Cow<'_, str> {
+    let found = find_suspicious_chars(text);
+    if found.is_empty() {
+        return Cow::Borrowed(text);
+    }
+    let mut stripped = String::with_capacity(text.len());
+    let mut last_end = 0;
+    for suspicious in &found {
+        stripped.push_str(&text[last_end..suspicious.range.start]);
+        last_end = suspicious.range.end;
+    }
+    stripped.push_str(&text[last_end..]);
+    Cow::Owned(stripped)
+}
+
+impl<'a> TextEditMut<'a> {
+    /// Choose what happens to suspicious characters in pasted text and in text set with [`TextEditMut::set_text()`] and its variants. See [`find_suspicious_chars()`] for what counts as suspicious.
+    pub fn set_suspicious_char_policy(&mut self, policy: SuspiciousCharPolicy) {
+        if policy != self.inner.suspicious_char_policy {
+            self.inner.suspicious_char_policy = policy;
+            self.text_box.shared.text_changed = true;
+        }
+    }
+
+    /// Update the report for text that is about to be inserted, and return the text to insert instead if it needs to be stripped.
+    pub(crate) fn sanitize_incoming(&mut self, text: &str) -> Option<String> {
+        self.inner.suspicious_char_report = find_suspicious_chars(text);
+        if self.inner.suspicious_char_policy != SuspiciousCharPolicy::Strip || self.inner.suspicious_char_report.is_empty() {
+            return None;
+        }
+        Some(strip_suspicious_chars(text).into_owned())
+    }
+}
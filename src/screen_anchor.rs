@@ -0,0 +1,194 @@
+use crate::*;
+
+/// A point of a rectangle, used by [`ScreenAnchor`] both for the point of the box that is attached and for the point of the screen that it's attached to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// The point as fractions of the rectangle's width and height, from `(0.0, 0.0)` for the top-left corner to `(1.0, 1.0)` for the bottom-right one.
+    pub fn fractions(self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+/// Keeps a box attached to a point of the screen. Set with [`TextBoxMut::set_screen_anchor()`].
+///
+/// The `pivot` point of the box is placed at `screen_point`, given as fractions of the screen size, and then moved by `offset` pixels. The position is recalculated when the window is resized, so HUD text stays in its corner without the app moving it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScreenAnchor {
+    /// The point of the box that is attached, relative to the box size set with [`TextBoxMut::set_size()`].
+    pub pivot: Anchor,
+    /// The point of the screen, as fractions of its width and height.
+    pub screen_point: (f32, f32),
+    /// Offset in pixels, applied after placing the box. Positive values go right and down.
+    pub offset: (f64, f64),
+}
+
+impl ScreenAnchor {
+    /// Attach the `anchor` point of the box to the same point of the screen, for example the bottom-right corner of the box to the bottom-right corner of the screen. The offset usually points inwards, like `(-10.0, -10.0)` for a margin from the bottom-right corner.
+    pub fn corner(anchor: Anchor, offset: (f64, f64)) -> Self {
+        Self { pivot: anchor, screen_point: anchor.fractions(), offset }
+    }
+
+    /// Attach the `pivot` point of the box to a point of the screen given as fractions of its size, for example `(0.5, 0.25)` for the horizontal center, a quarter of the way down.
+    pub fn relative(screen_point: (f32, f32), pivot: Anchor) -> Self {
+        Self { pivot, screen_point, offset: (0.0, 0.0) }
+    }
+
+    /// The position of the top-left corner of a box of the given size.
+    pub(crate) fn resolve(&self, box_size: (f32, f32), screen_size: (f32, f32)) -> (f64, f64) {
+        let (pivot_x, pivot_y) = self.pivot.fractions();
+        let x = screen_size.0 as f64 * self.screen_point.0 as f64 + self.offset.0 - box_size.0 as f64 * pivot_x as f64;
+        let y = screen_size.1 as f64 * self.screen_point.1 as f64 + self.offset.1 - box_size.1 as f64 * pivot_y as f64;
+        (x.round(), y.round())
+    }
+}
+
+/// A box width that follows the width of the window. Set with [`TextBoxMut::set_relative_width()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RelativeWidth {
+    /// A fraction of the screen width, like `0.5` for half of it.
+    Fraction(f32),
+    /// From the left edge of the box to the right edge of the screen, minus `margin` pixels.
+    FillRemaining { margin: f32 },
+}
+
+impl RelativeWidth {
+    pub(crate) fn resolve(&self, left: f64, screen_width: f32) -> f32 {
+        let width = match *self {
+            RelativeWidth::Fraction(fraction) => screen_width * fraction,
+            RelativeWidth::FillRemaining { margin } => (screen_width as f64 - left) as f32 - margin,
+        };
+        width.max(0.0).round()
+    }
+}
+
+impl<'a> TextBoxMut<'a> {
+    /// Make the width of the box follow the width of the window, or keep the current width with `None`.
+    ///
+    /// On every `WindowEvent::Resized`, the width and the wrapping width are recalculated and the box is laid out again, so wrapped paragraphs reflow without the app resizing them. The height set with [`TextBoxMut::set_size()`] is kept, and calling [`TextBoxMut::set_size()`] goes back to a fixed width.
+    ///
+    /// If the box also has a [`ScreenAnchor`], the width is calculated first. [`RelativeWidth::FillRemaining`] uses the position of the box before anchoring, so it works best with anchors on the left side.
+    pub fn set_relative_width(&mut self, width: Option<RelativeWidth>) {
+        self.inner.relative_width = width;
+        if width.is_some() {
+            self.apply_relative_width();
+            self.apply_screen_anchor();
+        }
+    }
+
+    pub fn relative_width(&self) -> Option<RelativeWidth> {
+        self.inner.relative_width
+    }
+
+    pub(crate) fn apply_relative_width(&mut self) {
+        if apply_relative_width(self.inner, self.shared.screen_size) {
+            self.shared.text_changed = true;
+        }
+    }
+
+    /// Attach the box to a point of the screen, or go back to the position set with [`TextBoxMut::set_pos()`] with `None`.
+    ///
+    /// The position is in the screen pixels set by `WindowEvent::Resized`, and it's updated on every resize and when the box size changes. Calling [`TextBoxMut::set_pos()`] or [`TextBoxMut::set_position_baseline()`] removes the anchor. Boxes in a group are placed in the group's coordinates.
+    pub fn set_screen_anchor(&mut self, anchor: Option<ScreenAnchor>) {
+        self.inner.screen_anchor = anchor;
+        if anchor.is_some() {
+            self.inner.baseline_anchor = None;
+            self.apply_screen_anchor();
+        }
+    }
+
+    pub fn screen_anchor(&self) -> Option<ScreenAnchor> {
+        self.inner.screen_anchor
+    }
+
+    pub(crate) fn apply_screen_anchor(&mut self) {
+        if apply_screen_anchor(self.inner, self.shared.screen_size) {
+            self.shared.text_changed = true;
+        }
+    }
+}
+
+impl<'a> TextEditMut<'a> {
+    /// Attach the text edit to a point of the screen. See [`TextBoxMut::set_screen_anchor()`].
+    pub fn set_screen_anchor(&mut self, anchor: Option<ScreenAnchor>) {
+        self.text_box.set_screen_anchor(anchor);
+    }
+
+    pub fn screen_anchor(&self) -> Option<ScreenAnchor> {
+        self.text_box.screen_anchor()
+    }
+
+    /// Make the width of the text edit follow the width of the window. See [`TextBoxMut::set_relative_width()`].
+    pub fn set_relative_width(&mut self, width: Option<RelativeWidth>) {
+        self.text_box.set_relative_width(width);
+    }
+
+    pub fn relative_width(&self) -> Option<RelativeWidth> {
+        self.text_box.relative_width()
+    }
+}
+
+/// Resize the box to its relative width, if it has one. Returns true if the width changed.
+pub(crate) fn apply_relative_width(text_box: &mut TextBoxInner, screen_size: (f32, f32)) -> bool {
+    let Some(relative_width) = text_box.relative_width else {
+        return false;
+    };
+    let width = relative_width.resolve(text_box.left, screen_size.0);
+    if text_box.width == width && text_box.max_advance == width {
+        return false;
+    }
+    text_box.width = width;
+    text_box.max_advance = width;
+    text_box.needs_relayout = true;
+    true
+}
+
+/// Move the box to the position given by its screen anchor, if it has one. Returns true if the position changed.
+pub(crate) fn apply_screen_anchor(text_box: &mut TextBoxInner, screen_size: (f32, f32)) -> bool {
+    let Some(anchor) = text_box.screen_anchor else {
+        return false;
+    };
+    let pos = anchor.resolve((text_box.width, text_box.height), screen_size);
+    if (text_box.left, text_box.top) == pos {
+        return false;
+    }
+    (text_box.left, text_box.top) = pos;
+    true
+}
+
+impl Text {
+    /// Resize the boxes that have a [`RelativeWidth`] and reposition the ones that have a [`ScreenAnchor`] after the screen size changed.
+    pub(crate) fn apply_screen_anchors(&mut self) {
+        let screen_size = self.shared.screen_size;
+        for (_, text_box) in self.text_boxes.iter_mut() {
+            apply_relative_width(text_box, screen_size);
+            apply_screen_anchor(text_box, screen_size);
+        }
+        for (_, (_, text_box)) in self.text_edits.iter_mut() {
+            apply_relative_width(text_box, screen_size);
+            apply_screen_anchor(text_box, screen_size);
+        }
+    }
+}
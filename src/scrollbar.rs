@@ -0,0 +1,198 @@
+use winit::event::{MouseButton, WindowEvent};
+
+use crate::*;
+
+/// Appearance of the built-in scrollbars of text edits. See [`TextEditStyle::scrollbar`].
+///
+/// Edits get a vertical scrollbar along their right edge when the text overflows vertically, and a horizontal one along their bottom edge when it overflows horizontally, like in single-line edits or in multi-line edits with lines that can't be wrapped. When both are shown, they leave the bottom-right corner free. The bars are drawn over the text, inside the box, and only when the text overflows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScrollbarStyle {
+    /// Thickness of the bar, in pixels.
+    pub width: f32,
+    /// The thumb doesn't get shorter than this, even for very long texts.
+    pub min_thumb_length: f32,
+    pub thumb_color: ColorBrush,
+    pub track_color: ColorBrush,
+}
+
+impl Default for ScrollbarStyle {
+    fn default() -> Self {
+        Self {
+            width: 8.0,
+            min_thumb_length: 20.0,
+            thumb_color: ColorBrush([140, 140, 140, 200]),
+            track_color: ColorBrush([0, 0, 0, 40]),
+        }
+    }
+}
+
+/// Rectangles of a scrollbar, relative to the box position.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ScrollbarGeometry {
+    pub(crate) direction: ScrollDirection,
+    pub(crate) track: Rect,
+    pub(crate) thumb: Rect,
+    pub(crate) max_scroll: f32,
+}
+
+impl ScrollbarGeometry {
+    fn along(&self, point: (f64, f64)) -> f64 {
+        match self.direction {
+            ScrollDirection::Horizontal => point.0,
+            ScrollDirection::Vertical => point.1,
+        }
+    }
+
+    fn thumb_start(&self) -> f64 {
+        match self.direction {
+            ScrollDirection::Horizontal => self.thumb.x0,
+            ScrollDirection::Vertical => self.thumb.y0,
+        }
+    }
+
+    fn thumb_length(&self) -> f64 {
+        match self.direction {
+            ScrollDirection::Horizontal => self.thumb.width(),
+            ScrollDirection::Vertical => self.thumb.height(),
+        }
+    }
+
+    fn track_length(&self) -> f64 {
+        match self.direction {
+            ScrollDirection::Horizontal => self.track.width(),
+            ScrollDirection::Vertical => self.track.height(),
+        }
+    }
+}
+
+/// A scrollbar thumb being dragged with the mouse.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ScrollbarDrag {
+    direction: ScrollDirection,
+    /// Distance from the start of the thumb to the point where it was grabbed.
+    grab_offset: f64,
+}
+
+fn rect_contains(rect: &Rect, point: (f64, f64)) -> bool {
+    point.0 >= rect.x0 && point.0 < rect.x1 && point.1 >= rect.y0 && point.1 < rect.y1
+}
+
+/// How far a text edit can be scrolled horizontally. In multi-line edits, the whitespace at the end of wrapped lines doesn't count.
+fn max_horizontal_overflow(text_edit: &TextEditInner, text_box: &TextBoxInner) -> f32 {
+    if text_edit.single_line {
+        max_horizontal_scroll(text_box)
+    } else {
+        (text_box.layout.width() - text_box.max_advance).max(0.0).round()
+    }
+}
+
+impl<'a> TextEditMut<'a> {
+    /// Returns the scrollbar of the edit in `direction`, or `None` if scrollbars are disabled in its style or the text fits in that direction.
+    ///
+    /// The layout should be up to date.
+    pub(crate) fn scrollbar_geometry(&self, direction: ScrollDirection) -> Option<ScrollbarGeometry> {
+        let style = self.text_edit_style().scrollbar?;
+        let inner = &self.text_box.inner;
+        let (width, height) = (inner.max_advance as f64, inner.height as f64);
+        let bar = style.width as f64;
+
+        // Same bounds as wheel scrolling.
+        let max_horizontal = max_horizontal_overflow(self.inner, inner);
+        let max_vertical = if self.inner.single_line { 0.0 } else { max_vertical_scroll(self.inner, inner) };
+        // With both bars, each one stops short of the corner.
+        let (scroll, max_scroll, view, track_length) = match direction {
+            ScrollDirection::Horizontal => {
+                if max_horizontal <= 0.0 {
+                    return None;
+                }
+                let track_length = if max_vertical > 0.0 { width - bar } else { width };
+                (inner.scroll_offset.0, max_horizontal, width, track_length)
+            }
+            ScrollDirection::Vertical => {
+                if max_vertical <= 0.0 {
+                    return None;
+                }
+                let track_length = if max_horizontal > 0.0 { height - bar } else { height };
+                (inner.scroll_offset.1, max_vertical, height, track_length)
+            }
+        };
+
+        let content = view + max_scroll as f64;
+        let thumb_length = (track_length * view / content).max(style.min_thumb_length as f64).min(track_length);
+        let thumb_start = (scroll / max_scroll).clamp(0.0, 1.0) as f64 * (track_length - thumb_length);
+
+        let (track, thumb) = match direction {
+            ScrollDirection::Horizontal => (
+                Rect { x0: 0.0, y0: height - bar, x1: track_length, y1: height },
+                Rect { x0: thumb_start, y0: height - bar, x1: thumb_start + thumb_length, y1: height },
+            ),
+            ScrollDirection::Vertical => (
+                Rect { x0: width - bar, y0: 0.0, x1: width, y1: track_length },
+                Rect { x0: width - bar, y0: thumb_start, x1: width, y1: thumb_start + thumb_length },
+            ),
+        };
+        Some(ScrollbarGeometry { direction, track, thumb, max_scroll })
+    }
+
+    /// Returns the scrollbars of the edit that are shown, vertical first.
+    pub(crate) fn scrollbar_geometries(&self) -> impl Iterator<Item = ScrollbarGeometry> + '_ {
+        [ScrollDirection::Vertical, ScrollDirection::Horizontal].into_iter().filter_map(|direction| self.scrollbar_geometry(direction))
+    }
+
+    /// Handle clicks and drags on the scrollbars. Returns true if the event was used by a scrollbar.
+    pub(crate) fn handle_scrollbar_event(&mut self, event: &WindowEvent, input_state: &TextInputState) -> bool {
+        let cursor_pos = (
+            input_state.mouse.cursor_pos.0 - self.text_box.inner.left,
+            input_state.mouse.cursor_pos.1 - self.text_box.inner.top,
+        );
+
+        match event {
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                if !state.is_pressed() {
+                    return self.inner.scrollbar_drag.take().is_some();
+                }
+                self.refresh_layout();
+                let Some(geometry) = self.scrollbar_geometries().find(|geometry| rect_contains(&geometry.track, cursor_pos)) else {
+                    return false;
+                };
+
+                let grab_offset = if rect_contains(&geometry.thumb, cursor_pos) {
+                    geometry.along(cursor_pos) - geometry.thumb_start()
+                } else {
+                    // Clicking the track jumps there, with the thumb centered under the mouse.
+                    let grab_offset = geometry.thumb_length() * 0.5;
+                    self.drag_scrollbar_to(&geometry, geometry.along(cursor_pos) - grab_offset);
+                    grab_offset
+                };
+                self.inner.scrollbar_drag = Some(ScrollbarDrag { direction: geometry.direction, grab_offset });
+                true
+            }
+            WindowEvent::CursorMoved { .. } => {
+                let Some(drag) = self.inner.scrollbar_drag else {
+                    return false;
+                };
+                self.refresh_layout();
+                if let Some(geometry) = self.scrollbar_geometry(drag.direction) {
+                    self.drag_scrollbar_to(&geometry, geometry.along(cursor_pos) - drag.grab_offset);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn drag_scrollbar_to(&mut self, geometry: &ScrollbarGeometry, thumb_start: f64) {
+        let free_length = geometry.track_length() - geometry.thumb_length();
+        let fraction = if free_length > 0.0 { (thumb_start / free_length).clamp(0.0, 1.0) } else { 0.0 };
+        let new_scroll = (fraction as f32 * geometry.max_scroll).round();
+
+        let scroll_offset = &mut self.text_box.inner.scroll_offset;
+        let old_scroll = match geometry.direction {
+            ScrollDirection::Horizontal => std::mem::replace(&mut scroll_offset.0, new_scroll),
+            ScrollDirection::Vertical => std::mem::replace(&mut scroll_offset.1, new_scroll),
+        };
+        if old_scroll != new_scroll {
+            self.text_box.shared.scrolled = true;
+        }
+    }
+}
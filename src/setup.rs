@@ -1,6 +1,8 @@
 use crate::*;
 
 pub(crate) const INITIAL_BUFFER_SIZE: u64 = 4096;
+/// Room for a few cursors. The cursor buffer only grows if there are more.
+const INITIAL_CURSOR_BUFFER_SIZE: u64 = 8 * std::mem::size_of::<Quad>() as u64;
 
 
 const ATLAS_BIND_GROUP_LAYOUT: BindGroupLayoutDescriptor = wgpu::BindGroupLayoutDescriptor {
@@ -29,13 +31,17 @@ const ATLAS_BIND_GROUP_LAYOUT: BindGroupLayoutDescriptor = wgpu::BindGroupLayout
 pub struct TextRendererParams {
     /// Size of texture atlas pages used for glyph caching.
     pub atlas_page_size: AtlasPageSize,
+    /// Number of samples per pixel of the render target, for drawing directly into a multisampled target. The default is 1.
+    pub sample_count: u32,
+    /// A cache to share with other renderers, from [`TextRenderer::glyph_cache()`] or [`GlyphCache::new()`]. If it's `None`, the renderer gets a new one. When it's set, `atlas_page_size` is ignored in favor of the cache's page size.
+    pub glyph_cache: Option<GlyphCache>,
 }
 impl Default for TextRendererParams {
     fn default() -> Self {
         // 2048 is guaranteed to work everywhere that webgpu supports, and it seems both small enough that it's fine to allocate it upfront even if a smaller one would have been fine, and big enough that even on gpus that could hold 8k textures, I don't feel too bad about using multiple 2k pages instead of a single big 8k one
         // Ideally you'd still with small pages and grow them until the max texture dim, but having cache eviction, multiple pages, AND page growing seems a bit too much for now
         let atlas_page_size = AtlasPageSize::DownlevelWrbgl2Max; // 2048
-        Self { atlas_page_size }
+        Self { atlas_page_size, sample_count: 1, glyph_cache: None }
     }
 }
 /// Determines the size of texture atlas pages for glyph storage.
@@ -52,7 +58,7 @@ pub enum AtlasPageSize {
     WgpuMax,
 }
 impl AtlasPageSize {
-    fn size(self, device: &Device) -> u32 {
+    pub(crate) fn size(self, device: &Device) -> u32 {
         match self {
             AtlasPageSize::Flat(i) => i,
             AtlasPageSize::DownlevelWrbgl2Max => Limits::downlevel_defaults().max_texture_dimension_2d,
@@ -72,6 +78,129 @@ fn create_vertex_buffer(device: &Device, size: u64) -> Buffer {
     })
 }
 
+/// The shader source, with the `custom_material` function replaced if there is a custom one. See [`TextRenderer::set_custom_material_shader()`].
+fn shader_source(custom_material: Option<&str>) -> Cow<'static, str> {
+    let source = include_str!("shader.wgsl");
+    let Some(custom_material) = custom_material else {
+        return Cow::Borrowed(source);
+    };
+    let start = source.find(CUSTOM_MATERIAL_START).expect("marker in shader.wgsl");
+    let end = source.find(CUSTOM_MATERIAL_END).expect("marker in shader.wgsl") + CUSTOM_MATERIAL_END.len();
+    Cow::Owned(format!("{}{}{}", &source[..start], custom_material, &source[end..]))
+}
+
+const CUSTOM_MATERIAL_START: &str = "// custom_material start";
+const CUSTOM_MATERIAL_END: &str = "// custom_material end";
+
+pub(crate) fn create_pipeline(
+    device: &Device,
+    pipeline_layout: &PipelineLayout,
+    custom_material: Option<&str>,
+    format: TextureFormat,
+    depth_stencil: Option<DepthStencilState>,
+    sample_count: u32,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("shader"),
+        source: ShaderSource::Wgsl(shader_source(custom_material)),
+    });
+
+    let vertex_buffer_layout = wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<Quad>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &wgpu::vertex_attr_array![
+            0 => Sint32x2,
+            1 => Uint32,
+            2 => Uint32,
+            3 => Uint32,
+            4 => Float32,
+            5 => Uint32,
+            6 => Sint16x4,
+            7 => Sint16x2,
+            8 => Uint32,
+        ],
+    };
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("textslabs pipeline"),
+        layout: Some(pipeline_layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[vertex_buffer_layout],
+            compilation_options: PipelineCompilationOptions::default(),
+        },
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(ColorTargetState {
+                format,
+                blend: Some(BlendState::ALPHA_BLENDING),
+                write_mask: ColorWrites::default(),
+            })],
+            compilation_options: PipelineCompilationOptions::default(),
+        }),
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleStrip,
+            ..Default::default()
+        },
+        depth_stencil,
+        multisample: MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+fn create_gpu_atlas_page(device: &Device, layout: &BindGroupLayout, sampler: &Sampler, atlas_size: u32, format: TextureFormat) -> GpuAtlasPage {
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("atlas"),
+        size: Extent3d {
+            width: atlas_size,
+            height: atlas_size,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let texture_view = texture.create_view(&TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&texture_view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(sampler),
+            },
+        ],
+        label: Some("atlas bind group"),
+    });
+
+    GpuAtlasPage {
+        texture,
+        bind_group,
+    }
+}
+
+fn create_cursor_buffer(device: &Device, size: u64) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some("cursor vertex buffer"),
+        size,
+        usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
 impl ContextlessTextRenderer {
     pub fn new_with_params(
         device: &Device,
@@ -80,27 +209,13 @@ impl ContextlessTextRenderer {
         depth_stencil: Option<DepthStencilState>,
         params: TextRendererParams,
     ) -> Self {
-        let _srgb = format.is_srgb();
-        // todo put this in the uniform and use it
-        
-        let atlas_size = params.atlas_page_size.size(device);
-
-        let mask_texture = device.create_texture(&TextureDescriptor {
-            label: Some("atlas"),
-            size: Extent3d {
-                width: atlas_size,
-                height: atlas_size,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::R8Unorm,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-        let mask_texture_view = mask_texture.create_view(&TextureViewDescriptor::default());
+        let depth_stencil_format = depth_stencil.as_ref().map(|depth_stencil| depth_stencil.format);
 
+        let glyph_cache = match params.glyph_cache {
+            Some(glyph_cache) => glyph_cache,
+            None => GlyphCache::with_atlas_size(params.atlas_page_size.size(device)),
+        };
+        let atlas_size = glyph_cache.atlas_size();
 
         let sampler = device.create_sampler(&SamplerDescriptor {
             label: Some("sampler"),
@@ -112,29 +227,16 @@ impl ContextlessTextRenderer {
             ..Default::default()
         });
 
-        let shader = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("shader"),
-            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
-        });
-
-        let vertex_buffer_layout = wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Quad>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &wgpu::vertex_attr_array![
-                0 => Sint32x2,
-                1 => Uint32,
-                2 => Uint32,
-                3 => Uint32,
-                4 => Float32,
-                5 => Uint32,
-                6 => Sint16x4,
-            ],
-        };
-
         let params = Params {
             screen_resolution_width: 0.0,
             screen_resolution_height: 0.0,
-            _pad: [0, 0],
+            time: 0.0,
+            brightness: 1.0,
+            encode_srgb: needs_srgb_encoding(format) as u32,
+            _pad: [0; 3],
+            group_transforms: [[0.0, 0.0, 1.0, 0.0]; MAX_GROUPS],
+            world_transforms: [[[0.0; 4]; 4]; MAX_GROUPS],
+            materials: [GpuMaterial::zeroed(); MAX_MATERIALS],
         };
 
         let params_buffer = device.create_buffer(&BufferDescriptor {
@@ -147,7 +249,8 @@ impl ContextlessTextRenderer {
         let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[BindGroupLayoutEntry {
                 binding: 0,
-                visibility: ShaderStages::VERTEX,
+                // Materials are read in the fragment shader.
+                visibility: ShaderStages::VERTEX.union(ShaderStages::FRAGMENT),
                 ty: BindingType::Buffer {
                     ty: BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -168,76 +271,15 @@ impl ContextlessTextRenderer {
         });
 
         let atlas_bind_group_layout = device.create_bind_group_layout(&ATLAS_BIND_GROUP_LAYOUT);
-        let mask_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            layout: &atlas_bind_group_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&mask_texture_view),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(&sampler),
-                },
-            ],
-            label: Some("atlas bind group"),
-        });
 
-        let glyph_cache = LruCache::unbounded_with_hasher(BuildHasherDefault::<FxHasher>::default());
-
-        let mask_atlas_pages = vec![AtlasPage::<GrayImage> {
-            image: GrayImage::from_pixel(atlas_size, atlas_size, Luma([0])),
-            packer: BucketedAtlasAllocator::new(size2(atlas_size as i32, atlas_size as i32)),
-            quads: Vec::<Quad>::with_capacity(300),
-            gpu: Some(GpuAtlasPage {
-                texture: mask_texture,
-                bind_group: mask_bind_group,
-            }),
-            quad_count_before_render: 0,
-        }];
-
-        let color_texture = device.create_texture(&TextureDescriptor {
-            label: Some("atlas"),
-            size: Extent3d {
-                width: atlas_size,
-                height: atlas_size,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba8Unorm,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-        let color_texture_view = color_texture.create_view(&TextureViewDescriptor::default());
+        let run_cache = LruCache::with_hasher(
+            std::num::NonZeroUsize::new(RUN_CACHE_CAPACITY).unwrap(),
+            BuildHasherDefault::<FxHasher>::default(),
+        );
 
-
-        let color_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            layout: &atlas_bind_group_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&color_texture_view),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(&sampler),
-                },
-            ],
-            label: Some("atlas bind group"),
-        });
-
-        let color_atlas_pages = vec![AtlasPage::<RgbaImage> {
-            image: RgbaImage::from_pixel(atlas_size, atlas_size, Rgba([0, 0, 0, 0])),
-            packer: BucketedAtlasAllocator::new(size2(atlas_size as i32, atlas_size as i32)),
-            quads: Vec::<Quad>::with_capacity(300),
-            gpu: Some(GpuAtlasPage {
-                texture: color_texture,
-                bind_group: color_bind_group,
-            }),
-            quad_count_before_render: 0,
-        }];
+        // The first pages are created upfront, because decorations use the first mask page's bind group.
+        let mask_atlas_pages = vec![AtlasPage::new(Some(create_gpu_atlas_page(device, &atlas_bind_group_layout, &sampler, atlas_size, TextureFormat::R8Unorm)))];
+        let color_atlas_pages = vec![AtlasPage::new(Some(create_gpu_atlas_page(device, &atlas_bind_group_layout, &sampler, atlas_size, TextureFormat::Rgba8Unorm)))];
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: None,
@@ -245,65 +287,102 @@ impl ContextlessTextRenderer {
             push_constant_ranges: &[],
         });
 
-        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("textslabs pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[vertex_buffer_layout],
-                compilation_options: PipelineCompilationOptions::default(),
-            },
-            fragment: Some(FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(ColorTargetState {
-                    // todo: is this the format that needs to be the same as outside?
-                    format: TextureFormat::Bgra8UnormSrgb,
-                    blend: Some(BlendState::ALPHA_BLENDING),
-                    write_mask: ColorWrites::default(),
-                })],
-                compilation_options: PipelineCompilationOptions::default(),
-            }),
-            primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleStrip,
-                ..Default::default()
-            },
-            depth_stencil,
-            multisample: MultisampleState::default(),
-            multiview: None,
-            cache: None,
-        });
+        let sample_count = params.sample_count.max(1);
+        let pipeline = create_pipeline(device, &pipeline_layout, None, format, depth_stencil.clone(), sample_count);
 
         let tmp_image = Image::new();
-        let frame = 1;
+        let renderer_id = next_renderer_id();
+        let frame = glyph_cache.lock().begin_frame(renderer_id);
         
         let vertex_buffer = create_vertex_buffer(device, INITIAL_BUFFER_SIZE);
+        let cursor_buffer = create_cursor_buffer(device, INITIAL_CURSOR_BUFFER_SIZE);
         
         Self {
             frame,
+            renderer_id,
+            last_prepared: None,
             atlas_size,
             tmp_image,
             mask_atlas_pages,
             color_atlas_pages,
             decorations: Vec::with_capacity(50),
+            cursor_quads: Vec::with_capacity(1),
             pipeline,
+            pipeline_layout,
+            depth_stencil,
+            custom_material_shader: None,
+            materials: Vec::new(),
+            start_time: None,
+            time_source: std::time::Instant::now,
             atlas_bind_group_layout,
             sampler,
             params,
             params_buffer,
             params_bind_group,
             glyph_cache,
-            last_frame_evicted: 0,
+            run_cache,
+            seen_evictions: 0,
             // cached_scaler: None,
             vertex_buffer,
             needs_gpu_sync: true,
+            cursor_buffer,
+            cursor_needs_gpu_sync: true,
+            depth_stencil_format,
+            format,
+            sample_count,
+            hdr_brightness: 1.0,
+            clip_mode: ClipMode::default(),
+            target_size: None,
+            depth_sorting: false,
+            draw_batches: Vec::new(),
+            decoration_batches: Vec::new(),
+            cursor_depth: 0.0,
+            glyphs_rasterized: 0,
         }
     }
+
+    /// Update the color conversion in the params for the current format and brightness.
+    pub(crate) fn update_output_params(&mut self) {
+        self.params.encode_srgb = needs_srgb_encoding(self.format) as u32;
+        self.params.brightness = if is_hdr_format(self.format) { self.hdr_brightness } else { 1.0 };
+        self.needs_gpu_sync = true;
+    }
+}
+
+/// Floating point formats, where colors are linear and can go above 1.0.
+pub(crate) fn is_hdr_format(format: TextureFormat) -> bool {
+    matches!(format, TextureFormat::Rgba16Float | TextureFormat::Rgba32Float)
+}
+
+/// The shader works with linear colors. sRGB targets encode them in hardware, and float targets store them as they are, but other targets need them encoded in the shader.
+fn needs_srgb_encoding(format: TextureFormat) -> bool {
+    !format.is_srgb() && !is_hdr_format(format)
 }
 
 impl ContextlessTextRenderer {
     pub fn gpu_load(&mut self, device: &Device, queue: &Queue) {
+        // Animated materials need the new time every frame, even if nothing else changed.
+        if self.materials.iter().any(Material::is_animated) {
+            let now = (self.time_source)();
+            let start_time = *self.start_time.get_or_insert(now);
+            self.params.time = now.saturating_duration_since(start_time).as_secs_f32();
+            if !self.needs_gpu_sync {
+                let bytes: &[u8] = bytemuck::cast_slice(std::slice::from_ref(&self.params));
+                queue.write_buffer(&self.params_buffer, 0, bytes);
+            }
+        }
+
+        if self.cursor_needs_gpu_sync {
+            self.cursor_needs_gpu_sync = false;
+            let bytes: &[u8] = bytemuck::cast_slice(&self.cursor_quads);
+            if self.cursor_buffer.size() < bytes.len() as u64 {
+                self.cursor_buffer = create_cursor_buffer(device, bytes.len() as u64 * 2);
+            }
+            if !bytes.is_empty() {
+                queue.write_buffer(&self.cursor_buffer, 0, bytes);
+            }
+        }
+
         if !self.needs_gpu_sync {
             return;
         }
@@ -351,128 +430,82 @@ impl ContextlessTextRenderer {
             queue.write_buffer(&self.vertex_buffer, buffer_offset, bytes);
         }
 
-        // Handle mask atlas pages
-        for page in &mut self.mask_atlas_pages {
-            if page.gpu.is_none() {
-                let texture = device.create_texture(&TextureDescriptor {
-                    label: Some("atlas"),
-                    size: Extent3d {
-                        width: self.atlas_size,
-                        height: self.atlas_size,
-                        depth_or_array_layers: 1,
-                    },
-                    mip_level_count: 1,
-                    sample_count: 1,
-                    dimension: TextureDimension::D2,
-                    format: TextureFormat::R8Unorm,
-                    usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-                    view_formats: &[],
-                });
-                let texture_view = texture.create_view(&TextureViewDescriptor::default());
-
-                let bind_group = device.create_bind_group(&BindGroupDescriptor {
-                    layout: &self.atlas_bind_group_layout,
-                    entries: &[
-                        BindGroupEntry {
-                            binding: 0,
-                            resource: BindingResource::TextureView(&texture_view),
-                        },
-                        BindGroupEntry {
-                            binding: 1,
-                            resource: BindingResource::Sampler(&self.sampler),
-                        },
-                    ],
-                    label: Some("atlas bind group"),
-                });
-        
-                page.gpu = Some(GpuAtlasPage {
-                    texture,
-                    bind_group,
-                })
+        // Upload the pages that changed in the glyph cache since the last time, possibly because of another renderer.
+        let cache = self.glyph_cache.lock();
+        while self.mask_atlas_pages.len() < cache.mask_pages.len() {
+            self.mask_atlas_pages.push(AtlasPage::new(None));
+        }
+        while self.color_atlas_pages.len() < cache.color_pages.len() {
+            self.color_atlas_pages.push(AtlasPage::new(None));
+        }
+
+        for (page, cache_page) in self.mask_atlas_pages.iter_mut().zip(&cache.mask_pages) {
+            if page.uploaded_version == cache_page.version {
+                continue;
             }
+            let gpu = page.gpu.get_or_insert_with(|| {
+                create_gpu_atlas_page(device, &self.atlas_bind_group_layout, &self.sampler, self.atlas_size, TextureFormat::R8Unorm)
+            });
 
             queue.write_texture(
                 ImageCopyTexture {
-                    texture: &page.gpu.as_ref().unwrap().texture,
+                    texture: &gpu.texture,
                     mip_level: 0,
                     origin: Origin3d { x: 0, y: 0, z: 0 },
                     aspect: TextureAspect::All,
                 },
-                &page.image.as_raw(),
+                &cache_page.image.as_raw(),
                 ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: Some(page.image.width()),
+                    bytes_per_row: Some(cache_page.image.width()),
                     rows_per_image: None,
                 },
                 Extent3d {
-                    width: page.image.width(),
-                    height: page.image.height(),
+                    width: cache_page.image.width(),
+                    height: cache_page.image.height(),
                     depth_or_array_layers: 1,
                 },
             );
+            page.uploaded_version = cache_page.version;
         }
 
-        // Handle color atlas pages
-        for page in &mut self.color_atlas_pages {
-            if page.gpu.is_none() {
-                let texture = device.create_texture(&TextureDescriptor {
-                    label: Some("atlas"),
-                    size: Extent3d {
-                        width: self.atlas_size,
-                        height: self.atlas_size,
-                        depth_or_array_layers: 1,
-                    },
-                    mip_level_count: 1,
-                    sample_count: 1,
-                    dimension: TextureDimension::D2,
-                    format: TextureFormat::Rgba8Unorm,
-                    usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-                    view_formats: &[],
-                });
-                let texture_view = texture.create_view(&TextureViewDescriptor::default());
-
-                let bind_group = device.create_bind_group(&BindGroupDescriptor {
-                    layout: &self.atlas_bind_group_layout,
-                    entries: &[
-                        BindGroupEntry {
-                            binding: 0,
-                            resource: BindingResource::TextureView(&texture_view),
-                        },
-                        BindGroupEntry {
-                            binding: 1,
-                            resource: BindingResource::Sampler(&self.sampler),
-                        },
-                    ],
-                    label: Some("atlas bind group"),
-                });
-        
-                page.gpu = Some(GpuAtlasPage {
-                    texture,
-                    bind_group,
-                })
+        for (page, cache_page) in self.color_atlas_pages.iter_mut().zip(&cache.color_pages) {
+            if page.uploaded_version == cache_page.version {
+                continue;
             }
+            let gpu = page.gpu.get_or_insert_with(|| {
+                create_gpu_atlas_page(device, &self.atlas_bind_group_layout, &self.sampler, self.atlas_size, TextureFormat::Rgba8Unorm)
+            });
     
             queue.write_texture(
                 ImageCopyTexture {
-                    texture: &page.gpu.as_ref().unwrap().texture,
+                    texture: &gpu.texture,
                     mip_level: 0,
                     origin: Origin3d { x: 0, y: 0, z: 0 },
                     aspect: TextureAspect::All,
                 },
-                &page.image.as_raw(),
+                &cache_page.image.as_raw(),
                 ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: Some(page.image.width() * 4),
+                    bytes_per_row: Some(cache_page.image.width() * 4),
                     rows_per_image: None,
                 },
                 Extent3d {
-                    width: page.image.width(),
-                    height: page.image.height(),
+                    width: cache_page.image.width(),
+                    height: cache_page.image.height(),
                     depth_or_array_layers: 1,
                 },
             );
+            page.uploaded_version = cache_page.version;
         }
 
         self.needs_gpu_sync = false;
     }
+}
+
+impl Drop for ContextlessTextRenderer {
+    fn drop(&mut self) {
+        // Glyphs that only this renderer was using can be evicted now.
+        self.glyph_cache.lock().remove_renderer(self.renderer_id);
+    }
 }
\ No newline at end of file
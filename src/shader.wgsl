@@ -7,6 +7,8 @@ struct VertexInput {
     @location(4) depth: f32,
     @location(5) flags: u32,
     @location(6) clip_rect: vec4<i32>,
+    @location(7) origin: vec2<i32>,
+    @location(8) fade: u32,
 }
 
 struct VertexOutput {
@@ -18,11 +20,49 @@ struct VertexOutput {
     @location(4) @interpolate(flat) quad_size: vec2<f32>,
     @location(5) @interpolate(flat) clip_rect: vec4<f32>,
     @location(6) screen_pos: vec2<f32>,
+    @location(7) local_pos: vec2<f32>,
+    @location(8) @interpolate(flat) fade: u32,
+};
+
+struct Material {
+    kind: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+    color_a: vec4<f32>,
+    color_b: vec4<f32>,
+    params: vec4<f32>,
+};
+
+const MATERIAL_SHIMMER: u32 = 1u;
+const MATERIAL_CUSTOM: u32 = 2u;
+const MATERIAL_LINEAR_GRADIENT: u32 = 3u;
+const MATERIAL_RADIAL_GRADIENT: u32 = 4u;
+
+struct MaterialInput {
+    color: vec4<f32>,
+    screen_pos: vec2<f32>,
+    local_pos: vec2<f32>,
+    time: f32,
+    color_a: vec4<f32>,
+    color_b: vec4<f32>,
+    params: vec4<f32>,
 };
 
 struct Params {
     screen_resolution: vec2<f32>,
-    _pad: vec2<u32>,
+    time: f32,
+    // Multiplier for the output color, above 1.0 only on HDR targets
+    brightness: f32,
+    // 1 if the target isn't sRGB and the output has to be encoded here
+    encode_srgb: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+    // translation.x, translation.y, scale, mode (0 = screen space, 1 = world space, 2 = billboard)
+    group_transforms: array<vec4<f32>, 128>,
+    world_transforms: array<mat4x4<f32>, 128>,
+    materials: array<Material, 32>,
 };
 
 @group(0) @binding(0)
@@ -42,6 +82,23 @@ fn srgb_to_linear(c: f32) -> f32 {
     }
 }
 
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        return c * 12.92;
+    } else {
+        return 1.055 * pow(c, 1.0 / 2.4) - 0.055;
+    }
+}
+
+// Convert a linear color for the target format.
+fn output_color(c: vec4<f32>) -> vec4<f32> {
+    let rgb = c.rgb * params.brightness;
+    if params.encode_srgb == 1u {
+        return vec4f(linear_to_srgb(rgb.r), linear_to_srgb(rgb.g), linear_to_srgb(rgb.b), c.a);
+    }
+    return vec4f(rgb, c.a);
+}
+
 fn split(u: u32) -> vec2<f32> {
     return vec2f(vec2u(
          u & 0x0000ffffu,
@@ -57,6 +114,59 @@ fn get_fade_enabled(flags: u32) -> bool {
     return (flags & (1u << 4u)) != 0u;
 }
 
+fn get_group(flags: u32) -> u32 {
+    return (flags >> 16u) & 0xffu;
+}
+
+// Material index plus one, or zero for no material.
+fn get_material(flags: u32) -> u32 {
+    return (flags >> 8u) & 0xffu;
+}
+
+fn srgb_to_linear4(c: vec4<f32>) -> vec4<f32> {
+    return vec4f(srgb_to_linear(c.r), srgb_to_linear(c.g), srgb_to_linear(c.b), c.a);
+}
+
+// custom_material start
+fn custom_material(input: MaterialInput) -> vec4<f32> {
+    return input.color;
+}
+// custom_material end
+
+fn apply_material(material: Material, color: vec4<f32>, screen_pos: vec2<f32>, local_pos: vec2<f32>) -> vec4<f32> {
+    let color_a = srgb_to_linear4(material.color_a);
+    if material.kind == MATERIAL_SHIMMER {
+        // A diagonal band, repeating every few band widths.
+        let band_width = max(material.params.x, 1.0);
+        let period = band_width * 4.0;
+        let x = screen_pos.x + screen_pos.y * 0.5 - params.time * material.params.y;
+        let distance = abs(fract(x / period) - 0.5) * period;
+        let band = 1.0 - smoothstep(0.0, band_width * 0.5, distance);
+        return vec4f(mix(color.rgb, color_a.rgb, band * color_a.a), color.a);
+    } else if material.kind == MATERIAL_LINEAR_GRADIENT {
+        let start = material.params.xy;
+        let direction = material.params.zw - start;
+        let t = clamp(dot(local_pos - start, direction) / max(dot(direction, direction), 0.0001), 0.0, 1.0);
+        let gradient = mix(color_a, srgb_to_linear4(material.color_b), t);
+        return vec4f(gradient.rgb, gradient.a * color.a);
+    } else if material.kind == MATERIAL_RADIAL_GRADIENT {
+        let t = clamp(length(local_pos - material.params.xy) / max(material.params.z, 0.0001), 0.0, 1.0);
+        let gradient = mix(color_a, srgb_to_linear4(material.color_b), t);
+        return vec4f(gradient.rgb, gradient.a * color.a);
+    } else if material.kind == MATERIAL_CUSTOM {
+        var input: MaterialInput;
+        input.color = color;
+        input.screen_pos = screen_pos;
+        input.local_pos = local_pos;
+        input.time = params.time;
+        input.color_a = color_a;
+        input.color_b = srgb_to_linear4(material.color_b);
+        input.params = material.params;
+        return custom_material(input);
+    }
+    return color;
+}
+
 
 @vertex
 fn vs_main(input: VertexInput) -> VertexOutput {
@@ -101,13 +211,28 @@ fn vs_main(input: VertexInput) -> VertexOutput {
 
     // Use clipped position and dimensions
     let clipped_pos = vec2f(clipped_x0, clipped_y0) + clipped_dim * coords;
+
+    // Clipping happens in the box's coordinates, then the group transform moves the result on screen
+    let group = get_group(input.flags);
+    let transform = params.group_transforms[group];
+    let transformed_pos = clipped_pos * transform.z + transform.xy;
+    let mode = u32(transform.w);
     
-    vert_output.position = vec4f(
-        2.0 * (clipped_pos / params.screen_resolution) - 1.0,
-        input.depth,
-        1.0,
-    );
-    vert_output.position.y = -vert_output.position.y;
+    if mode == 1u {
+        vert_output.position = params.world_transforms[group] * vec4f(transformed_pos, 0.0, 1.0);
+    } else if mode == 2u {
+        // Billboards keep their pixel size around the projected anchor point
+        let anchor = params.world_transforms[group] * vec4f(0.0, 0.0, 0.0, 1.0);
+        let offset = 2.0 * transformed_pos / params.screen_resolution * anchor.w;
+        vert_output.position = anchor + vec4f(offset.x, -offset.y, 0.0, 0.0);
+    } else {
+        vert_output.position = vec4f(
+            2.0 * (transformed_pos / params.screen_resolution) - 1.0,
+            input.depth,
+            1.0,
+        );
+        vert_output.position.y = -vert_output.position.y;
+    }
 
     vert_output.color = vec4<f32>(
         f32((input.color & 0xff000000u) >> 24u) / 255.0,
@@ -121,23 +246,40 @@ fn vs_main(input: VertexInput) -> VertexOutput {
     vert_output.quad_size = clipped_dim;
     vert_output.clip_rect = clip_rect;
     vert_output.screen_pos = clipped_pos;
+    vert_output.local_pos = clipped_pos - vec2f(input.origin);
+    vert_output.fade = input.fade;
 
     return vert_output;
 }
 
-fn calculate_fade_alpha(screen_pos: vec2<f32>, clip_rect: vec4<f32>) -> f32 {
-    let fade_distance = 15.0;
-    
-    // Calculate distance to each edge of the clip rect
-    let dist_to_left = screen_pos.x - clip_rect.x;
-    let dist_to_right = clip_rect.z - screen_pos.x;
-    let dist_to_top = screen_pos.y - clip_rect.y;
-    let dist_to_bottom = clip_rect.w - screen_pos.y;
+// `fade` is packed by FadeoutStyle::to_gpu(): the length in bits 0-11, the edges that don't fade in bits 12-15 and the curve in bits 16-19. Zero is the default: 15 pixels on all edges, linear.
+fn calculate_fade_alpha(screen_pos: vec2<f32>, clip_rect: vec4<f32>, fade: u32) -> f32 {
+    var fade_distance = f32(fade & 0xfffu);
+    if fade_distance == 0.0 {
+        fade_distance = 15.0;
+    }
+    let disabled_edges = (fade >> 12u) & 0xfu;
+    let curve = (fade >> 16u) & 0xfu;
+
+    // Calculate distance to each edge of the clip rect that fades
+    let big = 1e9;
+    let dist_to_left = select(screen_pos.x - clip_rect.x, big, (disabled_edges & 1u) != 0u);
+    let dist_to_right = select(clip_rect.z - screen_pos.x, big, (disabled_edges & 2u) != 0u);
+    let dist_to_top = select(screen_pos.y - clip_rect.y, big, (disabled_edges & 4u) != 0u);
+    let dist_to_bottom = select(clip_rect.w - screen_pos.y, big, (disabled_edges & 8u) != 0u);
     
     // Calculate alpha based on minimum distance to any clip edge
     let min_dist = min(min(dist_to_left, dist_to_right), min(dist_to_top, dist_to_bottom));
-    
-    return clamp(min_dist / fade_distance, 0.0, 1.0);
+    let t = clamp(min_dist / fade_distance, 0.0, 1.0);
+
+    if curve == 1u {
+        return smoothstep(0.0, 1.0, t);
+    } else if curve == 2u {
+        return t * t;
+    } else if curve == 3u {
+        return 1.0 - (1.0 - t) * (1.0 - t);
+    }
+    return t;
 }
 
 @fragment
@@ -146,7 +288,7 @@ fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
     let fade_enabled = get_fade_enabled(input.flags);
     var fade_alpha = 1.0;
     if fade_enabled {
-        fade_alpha = calculate_fade_alpha(input.screen_pos, input.clip_rect);
+        fade_alpha = calculate_fade_alpha(input.screen_pos, input.clip_rect, input.fade);
     }
     
     if content_type == 1 {
@@ -159,20 +301,20 @@ fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
         );
         var result = vec4<f32>(input.color * color);
         result.a *= fade_alpha;
-        return result;
+        return output_color(result);
     
     } else if content_type == 0 {
         var glyph_alpha = textureSampleLevel(mask_atlas_texture, atlas_sampler, input.uv, 0.0).r;
-        var color = vec3f(
-            srgb_to_linear(input.color.rgb.r),
-            srgb_to_linear(input.color.rgb.g),
-            srgb_to_linear(input.color.rgb.b),
-        );
-        return vec4<f32>(color, input.color.a * glyph_alpha * fade_alpha);
+        var color = srgb_to_linear4(input.color);
+        let material = get_material(input.flags);
+        if material != 0u {
+            color = apply_material(params.materials[material - 1u], color, input.screen_pos, input.local_pos);
+        }
+        return output_color(vec4<f32>(color.rgb, color.a * glyph_alpha * fade_alpha));
     
     } else {
         var result = vec4f(input.color);
         result.a *= fade_alpha;
-        return result;
+        return output_color(result);
     }
 }
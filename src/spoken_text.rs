@@ -0,0 +1,156 @@
+use crate::*;
+
+/// The kind of box, as a screen reader would announce it. See [`SpokenText`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpokenRole {
+    Label,
+    TextInput,
+    MultilineTextInput,
+    PasswordInput,
+}
+
+/// A description of a box for screen readers and other accessibility bridges, as returned by [`Text::spoken_text_for()`].
+///
+/// This doesn't depend on the `accessibility` feature, so that engines with their own accessibility integration can announce the same things that AccessKit would expose.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpokenText {
+    pub role: SpokenRole,
+    /// The text of the box. For an empty edit with a placeholder, this is the placeholder. For password edits, every character is replaced with a bullet.
+    pub text: String,
+    pub is_placeholder: bool,
+    pub disabled: bool,
+    /// The caret and selection, if the box has keyboard focus.
+    pub caret: Option<CaretDescription>,
+}
+
+/// The state of the caret and selection of a box, in terms that can be read out when they change. See [`Text::caret_description_for()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaretDescription {
+    /// Byte offset of the caret.
+    pub index: usize,
+    /// Line of the caret, counting hard line breaks only, starting from 0.
+    pub line: usize,
+    /// Characters between the start of the line and the caret.
+    pub column: usize,
+    /// The word that the caret is in or touching, if any.
+    pub word_at_caret: Option<String>,
+    /// The selected text, if the selection isn't collapsed.
+    pub selected_text: Option<String>,
+}
+
+const PASSWORD_BULLET: char = '\u{2022}';
+
+impl CaretDescription {
+    fn new(text: &str, selection: Selection, masked: bool) -> Self {
+        let index = floor_char_boundary(text, selection.focus().index());
+        let before = &text[..index];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        let selected_text = (!selection.is_collapsed()).then(|| {
+            let range = selection.text_range();
+            let selected = &text[floor_char_boundary(text, range.start)..floor_char_boundary(text, range.end)];
+            mask(selected, masked)
+        });
+
+        Self {
+            index,
+            line: before.matches('\n').count(),
+            column: before[line_start..].chars().count(),
+            // Reading out words would leak a password.
+            word_at_caret: if masked { None } else { word_at(text, index).map(str::to_string) },
+            selected_text,
+        }
+    }
+}
+
+fn mask(text: &str, masked: bool) -> String {
+    if masked {
+        text.chars().map(|_| PASSWORD_BULLET).collect()
+    } else {
+        text.to_string()
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The run of word characters around `index`, if there is one.
+fn word_at(text: &str, index: usize) -> Option<&str> {
+    let start = text[..index].char_indices().rev()
+        .take_while(|(_, c)| is_word_char(*c))
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(index);
+    let end = text[index..].char_indices()
+        .find(|(_, c)| !is_word_char(*c))
+        .map(|(i, _)| index + i)
+        .unwrap_or(text.len());
+    (start < end).then(|| &text[start..end])
+}
+
+impl Text {
+    /// Describe a box for screen readers: its role, its text, and the caret and selection if it's focused.
+    pub fn spoken_text_for<T: IntoAnyBox>(&mut self, handle: &T) -> SpokenText {
+        self.check_handle(handle);
+        let any_box = handle.into_anybox();
+        let caret = self.caret_description_for(&any_box);
+        match any_box {
+            AnyBox::TextEdit(i) => {
+                let text_edit = self.get_full_text_edit_at(i);
+                let masked = text_edit.ime_purpose() == ImePurpose::Password;
+                let is_placeholder = text_edit.inner.showing_placeholder;
+                let role = if masked {
+                    SpokenRole::PasswordInput
+                } else if text_edit.single_line() {
+                    SpokenRole::TextInput
+                } else {
+                    SpokenRole::MultilineTextInput
+                };
+                SpokenText {
+                    role,
+                    // The placeholder isn't secret, so it's not masked.
+                    text: mask(text_edit.text_box.text_inner(), masked && !is_placeholder),
+                    is_placeholder,
+                    disabled: text_edit.disabled(),
+                    caret,
+                }
+            }
+            AnyBox::TextBox(i) => {
+                let text_box = self.get_full_text_box_at(i);
+                SpokenText {
+                    role: SpokenRole::Label,
+                    text: text_box.text_inner().to_string(),
+                    is_placeholder: false,
+                    disabled: false,
+                    caret,
+                }
+            }
+        }
+    }
+
+    /// Describe the caret and selection of a box, or return `None` if it doesn't have keyboard focus.
+    ///
+    /// Comparing the result before and after handling an event tells what changed, for example to announce the new word after a word movement or the selected text after a selection change.
+    pub fn caret_description_for<T: IntoAnyBox>(&mut self, handle: &T) -> Option<CaretDescription> {
+        self.check_handle(handle);
+        let any_box = handle.into_anybox();
+        if self.focused != Some(any_box) {
+            return None;
+        }
+        match any_box {
+            AnyBox::TextEdit(i) => {
+                let text_edit = self.get_full_text_edit_at(i);
+                if text_edit.inner.showing_placeholder {
+                    return Some(CaretDescription { index: 0, line: 0, column: 0, word_at_caret: None, selected_text: None });
+                }
+                let masked = text_edit.ime_purpose() == ImePurpose::Password;
+                Some(CaretDescription::new(text_edit.text_box.text_inner(), text_edit.text_box.selection(), masked))
+            }
+            AnyBox::TextBox(i) => {
+                let text_box = self.get_full_text_box_at(i);
+                Some(CaretDescription::new(text_box.text_inner(), text_box.selection(), false))
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use parley::{Affinity, Cursor, Layout, Selection};
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+/// The saved configuration of a text box. See [`Text::save_state()`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextBoxState {
+    pub text: String,
+    pub pos: (f64, f64),
+    pub size: (f32, f32),
+    pub depth: f32,
+    /// Index of the style used by the box. Styles aren't saved, so they have to be added again in the same order before restoring.
+    pub style: u32,
+    pub scroll_offset: (f32, f32),
+    /// Anchor and focus of the selection, as byte offsets.
+    pub selection: (usize, usize),
+    pub selectable: bool,
+}
+
+/// The saved configuration of a text edit. See [`Text::save_state()`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextEditState {
+    /// The text box part of the edit. If the placeholder was showing, the text is empty.
+    pub text_box: TextBoxState,
+    pub single_line: bool,
+    pub newline_mode: NewlineMode,
+    pub disabled: bool,
+    pub placeholder: Option<String>,
+}
+
+/// All the text boxes and text edits in a [`Text`], as returned by [`Text::save_state()`].
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct TextState {
+    pub text_boxes: Vec<TextBoxState>,
+    pub text_edits: Vec<TextEditState>,
+}
+
+impl TextBoxState {
+    fn from_inner(inner: &TextBoxInner, text: &str) -> Self {
+        let selection = inner.selection.selection;
+        Self {
+            text: text.to_string(),
+            pos: (inner.left, inner.top),
+            size: (inner.width, inner.height),
+            depth: inner.depth,
+            style: inner.style.i,
+            scroll_offset: inner.scroll_offset,
+            selection: (selection.anchor().index(), selection.focus().index()),
+            selectable: inner.selectable,
+        }
+    }
+}
+
+impl Text {
+    /// Save the configuration of all text boxes and text edits, so that it can be serialized and restored later with [`Text::restore_state()`].
+    ///
+    /// Only what's needed to recreate the scene is saved: undo history, styles, spans and other decorations are not included.
+    pub fn save_state(&self) -> TextState {
+        let text_boxes = self.text_boxes.iter()
+            .map(|(_, text_box)| TextBoxState::from_inner(text_box, &text_box.text))
+            .collect();
+
+        let text_edits = self.text_edits.iter()
+            .map(|(_, (text_edit, text_box))| {
+                let text = if text_edit.showing_placeholder { "" } else { text_box.text.as_ref() };
+                TextEditState {
+                    text_box: TextBoxState::from_inner(text_box, text),
+                    single_line: text_edit.single_line,
+                    newline_mode: text_edit.newline_mode,
+                    disabled: text_edit.disabled,
+                    placeholder: text_edit.placeholder_text.as_ref().map(|p| p.to_string()),
+                }
+            })
+            .collect();
+
+        TextState { text_boxes, text_edits }
+    }
+
+    /// Recreate the text boxes and text edits from a [`TextState`] saved with [`Text::save_state()`].
+    ///
+    /// The existing boxes are left as they are. The new boxes are added, and their handles are returned in the same order as in the state. Boxes that referred to a style that doesn't exist anymore use the default style.
+    pub fn restore_state(&mut self, state: &TextState) -> (Vec<TextBoxHandle>, Vec<TextEditHandle>) {
+        let mut text_box_handles = Vec::with_capacity(state.text_boxes.len());
+        for saved in &state.text_boxes {
+            let handle = self.add_text_box(saved.text.clone(), saved.pos, saved.size, saved.depth);
+            let style = self.restored_style(saved.style);
+            let mut text_box = self.get_text_box_mut(&handle);
+            text_box.set_style(&style);
+            text_box.set_selectable(saved.selectable);
+            text_box.set_scroll_offset(saved.scroll_offset);
+            text_box.refresh_layout();
+            text_box.set_selection(saved_selection(&text_box.inner.layout, saved));
+            text_box_handles.push(handle);
+        }
+
+        let mut text_edit_handles = Vec::with_capacity(state.text_edits.len());
+        for saved in &state.text_edits {
+            let saved_box = &saved.text_box;
+            let handle = self.add_text_edit(saved_box.text.clone(), saved_box.pos, saved_box.size, saved_box.depth);
+            let style = self.restored_style(saved_box.style);
+            let mut text_edit = self.get_text_edit_mut(&handle);
+            text_edit.set_style(&style);
+            text_edit.set_single_line(saved.single_line);
+            text_edit.set_newline_mode(saved.newline_mode);
+            text_edit.set_disabled(saved.disabled);
+            if let Some(placeholder) = &saved.placeholder {
+                text_edit.set_placeholder(placeholder.clone());
+            }
+            text_edit.text_box.set_selectable(saved_box.selectable);
+            text_edit.set_scroll_offset(saved_box.scroll_offset);
+            text_edit.refresh_layout();
+            if !text_edit.inner.showing_placeholder {
+                let selection = saved_selection(&text_edit.text_box.inner.layout, saved_box);
+                text_edit.text_box.set_selection(selection);
+            }
+            text_edit_handles.push(handle);
+        }
+
+        (text_box_handles, text_edit_handles)
+    }
+
+    fn restored_style(&self, i: u32) -> StyleHandle {
+        if self.shared.styles.contains(i as usize) {
+            StyleHandle { i }
+        } else {
+            DEFAULT_STYLE_HANDLE
+        }
+    }
+}
+
+fn saved_selection(layout: &Layout<ColorBrush>, saved: &TextBoxState) -> Selection {
+    let len = saved.text.len();
+    let anchor = floor_char_boundary(&saved.text, saved.selection.0.min(len));
+    let focus = floor_char_boundary(&saved.text, saved.selection.1.min(len));
+    Selection::new(
+        Cursor::from_byte_index(layout, anchor, Affinity::Downstream),
+        Cursor::from_byte_index(layout, focus, Affinity::Downstream),
+    )
+}
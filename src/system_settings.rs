@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use crate::*;
+
+/// User interface preferences that usually come from the operating system. Set with [`Text::set_system_settings()`].
+///
+/// The defaults match what the library did before these settings existed. [`SystemSettings::detect()`] reads them from the system where possible, which is currently only on Linux desktops like GNOME, and returns the defaults elsewhere. Hosts that have their own settings UI can build them manually.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemSettings {
+    /// How long the cursor stays visible, and then hidden, while blinking. `None` disables blinking.
+    pub cursor_blink_interval: Option<Duration>,
+    /// Disable smooth scrolling animations, elastic overscroll and fade-out clipping.
+    pub reduce_motion: bool,
+    /// Colors that replace the style colors, for high contrast modes. `None` uses the style colors.
+    pub high_contrast: Option<HighContrastPalette>,
+}
+
+impl Default for SystemSettings {
+    fn default() -> Self {
+        Self {
+            cursor_blink_interval: Some(Duration::from_millis(500)),
+            reduce_motion: false,
+            high_contrast: None,
+        }
+    }
+}
+
+/// Colors used when [`SystemSettings::high_contrast`] is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HighContrastPalette {
+    /// Color of all text, including placeholders and disabled edits.
+    pub text_color: ColorBrush,
+    pub selection_color: ColorBrush,
+    pub cursor_color: ColorBrush,
+}
+
+impl Default for HighContrastPalette {
+    fn default() -> Self {
+        Self {
+            text_color: ColorBrush([255, 255, 255, 255]),
+            selection_color: ColorBrush([255, 255, 0, 160]),
+            cursor_color: ColorBrush([255, 255, 0, 255]),
+        }
+    }
+}
+
+impl SystemSettings {
+    /// Read the settings from the system.
+    ///
+    /// This is currently only implemented on Linux desktops that expose their settings through `gsettings`, like GNOME. On other systems, and for any setting that can't be read, the default is used.
+    pub fn detect() -> Self {
+        #[allow(unused_mut)]
+        let mut settings = Self::default();
+
+        #[cfg(target_os = "linux")] {
+            if gsetting("org.gnome.desktop.interface", "cursor-blink").as_deref() == Some("false") {
+                settings.cursor_blink_interval = None;
+            } else if let Some(millis) = gsetting("org.gnome.desktop.interface", "cursor-blink-time").and_then(|value| value.parse::<u64>().ok()) {
+                // GNOME stores the length of a full on-off cycle.
+                settings.cursor_blink_interval = Some(Duration::from_millis((millis / 2).max(1)));
+            }
+            if gsetting("org.gnome.desktop.interface", "enable-animations").as_deref() == Some("false") {
+                settings.reduce_motion = true;
+            }
+            if gsetting("org.gnome.desktop.a11y.interface", "high-contrast").as_deref() == Some("true") {
+                settings.high_contrast = Some(HighContrastPalette::default());
+            }
+        }
+
+        settings
+    }
+}
+
+/// Returns the value of a `gsettings` key, without the quotes around strings. Returns `None` if `gsettings` isn't available or the key doesn't exist.
+#[cfg(target_os = "linux")]
+fn gsetting(schema: &str, key: &str) -> Option<String> {
+    let output = std::process::Command::new("gsettings").args(["get", schema, key]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let value = String::from_utf8(output.stdout).ok()?;
+    // Numbers are printed with a type prefix, like "uint32 1200".
+    let value = value.trim().trim_matches('\'');
+    Some(value.rsplit(' ').next().unwrap_or(value).to_string())
+}
+
+impl Text {
+    /// Replace the current [`SystemSettings`]. All boxes are laid out again, so that a change of the high contrast palette is applied.
+    pub fn set_system_settings(&mut self, settings: SystemSettings) {
+        if self.shared.system_settings == settings {
+            return;
+        }
+        self.shared.system_settings = settings;
+        self.invalidate_all_layouts();
+        self.reset_cursor_blink();
+    }
+
+    pub fn system_settings(&self) -> &SystemSettings {
+        &self.shared.system_settings
+    }
+}
@@ -0,0 +1,163 @@
+//! Helpers for writing regression tests for text UIs.
+//!
+//! System fonts differ between machines, so deterministic tests should first call [`use_test_fonts()`] with fonts that are checked into the test's repository. Then, layouts can be compared against golden files with [`layout_snapshot()`] and [`assert_golden_snapshot()`], and rendered output with [`render_box_to_image()`] and [`assert_golden_image()`].
+//!
+//! Golden files are only written when the tests run with the `TEXTSLABS_UPDATE_GOLDEN` environment variable set, both to create new ones and to overwrite existing ones after an intended change. Without it, a missing golden file is a test failure, so that a file that was forgotten or deleted doesn't make the test pass silently.
+
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use parley::fontique::{Blob, Collection, CollectionOptions, SourceCache};
+use parley::FontContext;
+
+use crate::*;
+
+const UPDATE_GOLDEN_VAR: &str = "TEXTSLABS_UPDATE_GOLDEN";
+
+/// Replace the fonts used for layout on the current thread with the given font files, disabling system fonts.
+///
+/// Returns the family names of the registered fonts, so that they can be used in a [`TextStyle2`]'s font stack. Only layouts built on the calling thread are affected, so asynchronous layout should be left disabled in tests.
+pub fn use_test_fonts(fonts: &[&[u8]]) -> Vec<String> {
+    with_text_cx(|_layout_cx, font_cx| {
+        *font_cx = FontContext {
+            collection: Collection::new(CollectionOptions {
+                shared: false,
+                system_fonts: false,
+            }),
+            source_cache: SourceCache::default(),
+        };
+
+        let mut families = Vec::new();
+        for font in fonts {
+            let registered = font_cx.collection.register_fonts(Blob::new(Arc::new(font.to_vec())), None);
+            for (family_id, _) in registered {
+                if let Some(name) = font_cx.collection.family_name(family_id) {
+                    if !families.iter().any(|family| family == name) {
+                        families.push(name.to_string());
+                    }
+                }
+            }
+        }
+        families
+    })
+}
+
+/// Describe the geometry of a layout as text: lines with their text ranges and metrics, and the glyph runs inside them.
+///
+/// Positions are rounded to two decimals, so that the snapshot is stable across small floating point differences.
+pub fn layout_snapshot(layout: &Layout<ColorBrush>) -> String {
+    let mut out = String::new();
+    writeln!(out, "layout: width {:.2}, height {:.2}, lines {}", layout.full_width(), layout.height(), layout.len()).ok();
+
+    for (i, line) in layout.lines().enumerate() {
+        let metrics = line.metrics();
+        writeln!(out, "line {}: text {:?}, baseline {:.2}, ascent {:.2}, descent {:.2}, advance {:.2}",
+            i, line.text_range(), metrics.baseline, metrics.ascent, metrics.descent, metrics.advance
+        ).ok();
+
+        for item in line.items() {
+            match item {
+                PositionedLayoutItem::GlyphRun(glyph_run) => {
+                    writeln!(out, "  run: text {:?}, offset {:.2}, advance {:.2}, glyphs {}",
+                        glyph_run.run().text_range(), glyph_run.offset(), glyph_run.advance(), glyph_run.glyphs().count()
+                    ).ok();
+                }
+                PositionedLayoutItem::InlineBox(inline_box) => {
+                    writeln!(out, "  inline box {}: x {:.2}, y {:.2}, width {:.2}, height {:.2}",
+                        inline_box.id, inline_box.x, inline_box.y, inline_box.width, inline_box.height
+                    ).ok();
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Compare a snapshot, like the one returned by [`layout_snapshot()`], with the contents of a golden file.
+///
+/// # Panics
+///
+/// Panics if the file doesn't exist or its contents are different, unless the update variable is set.
+pub fn assert_golden_snapshot(actual: &str, golden_path: impl AsRef<Path>) {
+    let golden_path = golden_path.as_ref();
+    if should_write_golden(golden_path) {
+        write_golden(golden_path, actual.as_bytes());
+        return;
+    }
+
+    let expected = std::fs::read_to_string(golden_path)
+        .unwrap_or_else(|e| panic!("Failed to read golden file {}: {}", golden_path.display(), e));
+    if expected == actual {
+        return;
+    }
+
+    let first_difference = expected.lines().zip(actual.lines())
+        .position(|(expected, actual)| expected != actual)
+        .unwrap_or(expected.lines().count().min(actual.lines().count()));
+    panic!(
+        "Snapshot doesn't match golden file {} (first difference at line {}).\n--- expected\n{}\n--- actual\n{}\nSet {} to update it.",
+        golden_path.display(), first_difference + 1, expected, actual, UPDATE_GOLDEN_VAR
+    );
+}
+
+/// Compare an image, like the one returned by [`render_box_to_image()`], with a golden PNG file.
+///
+/// Each channel of each pixel can differ by up to `tolerance`, to allow for small rasterization differences.
+///
+/// # Panics
+///
+/// Panics if the file doesn't exist, or if the images have different sizes or any pixel differs by more than `tolerance`, unless the update variable is set. In the second case, the actual image is saved next to the golden file with an `.actual.png` extension.
+pub fn assert_golden_image(actual: &RgbaImage, golden_path: impl AsRef<Path>, tolerance: u8) {
+    let golden_path = golden_path.as_ref();
+    if should_write_golden(golden_path) {
+        if let Some(parent) = golden_path.parent() {
+            std::fs::create_dir_all(parent).ok();
+        }
+        actual.save(golden_path)
+            .unwrap_or_else(|e| panic!("Failed to write golden image {}: {}", golden_path.display(), e));
+        return;
+    }
+
+    let expected = image::open(golden_path)
+        .unwrap_or_else(|e| panic!("Failed to read golden image {}: {}", golden_path.display(), e))
+        .to_rgba8();
+
+    let mismatch = if expected.dimensions() != actual.dimensions() {
+        Some(format!("size is {:?} instead of {:?}", actual.dimensions(), expected.dimensions()))
+    } else {
+        let different_pixels = expected.pixels().zip(actual.pixels())
+            .filter(|(expected, actual)| {
+                expected.0.iter().zip(actual.0.iter()).any(|(e, a)| e.abs_diff(*a) > tolerance)
+            })
+            .count();
+        (different_pixels > 0).then(|| format!("{} pixels differ by more than {}", different_pixels, tolerance))
+    };
+
+    if let Some(mismatch) = mismatch {
+        let actual_path = golden_path.with_extension("actual.png");
+        actual.save(&actual_path).ok();
+        panic!(
+            "Image doesn't match golden file {}: {}. The actual image was saved to {}. Set {} to update it.",
+            golden_path.display(), mismatch, actual_path.display(), UPDATE_GOLDEN_VAR
+        );
+    }
+}
+
+fn should_write_golden(golden_path: &Path) -> bool {
+    if std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        return true;
+    }
+    if !golden_path.exists() {
+        panic!("Golden file {} doesn't exist. Set {} to create it.", golden_path.display(), UPDATE_GOLDEN_VAR);
+    }
+    false
+}
+
+fn write_golden(golden_path: &Path, contents: &[u8]) {
+    if let Some(parent) = golden_path.parent() {
+        std::fs::create_dir_all(parent).ok();
+    }
+    std::fs::write(golden_path, contents)
+        .unwrap_or_else(|e| panic!("Failed to write golden file {}: {}", golden_path.display(), e));
+}
@@ -1,23 +1,33 @@
 use crate::*;
 #[cfg(feature = "accessibility")]
 use accesskit::{NodeId, TreeUpdate};
+use rustc_hash::FxHashMap;
 use slab::Slab;
 #[cfg(feature = "accessibility")]
 use std::collections::HashMap;
 use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, Instant};
-use winit::{event::{Modifiers, MouseButton, WindowEvent}, window::Window};
+use winit::{event::{Modifiers, MouseButton, TouchPhase, WindowEvent}, window::{CursorIcon, Window}};
 use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 const MULTICLICK_DELAY: f64 = 0.4;
 const MULTICLICK_TOLERANCE_SQUARED: f64 = 26.0;
+const NO_FONT_SIZE_LIMITS: (f32, f32) = (0.0, f32::INFINITY);
+
+/// Gives each [`Text`] an id, so that a [`TextRenderer`] can tell which scene it was last prepared for.
+static NEXT_SCENE_ID: AtomicU64 = AtomicU64::new(1);
 
 #[derive(Debug)]
 pub(crate) struct StyleInner {
     pub(crate) text_style: TextStyle2,
     pub(crate) text_edit_style: TextEditStyle,
     pub(crate) version: u64,
+    /// Set with [`Text::set_font_size_limits()`].
+    pub(crate) font_size_limits: (f32, f32),
+    /// Set with [`Text::set_style_material()`], at most one for each renderer.
+    pub(crate) materials: Vec<MaterialId>,
 }
 
 /// Centralized struct that holds collections of [`TextBox`]es, [`TextEdit`]s, [`TextStyle2`]s.
@@ -30,16 +40,21 @@ pub struct Text {
     pub(crate) shared: Shared,
 
     pub(crate) style_version_id_counter: u64,
+    pub(crate) box_generation_counter: u64,
 
     pub(crate) input_state: TextInputState,
 
     pub(crate) focused: Option<AnyBox>,
     pub(crate) mouse_hit_stack: Vec<(AnyBox, f32)>,
+    /// Reused buffer for preparing boxes back to front when the renderer has depth sorting enabled.
+    pub(crate) depth_order: Vec<(AnyBox, f32)>,
     
     pub(crate) using_frame_based_visibility: bool,
     pub(crate) decorations_changed: bool,
     
     pub(crate) scrolled_moved_indices: Vec<AnyBox>,
+    /// Whole pixel moves from [`Text::translate_boxes()`] that weren't applied to the quads yet.
+    pub(crate) translated_boxes: Vec<(AnyBox, (i32, i32))>,
     pub(crate) scroll_animations: Vec<ScrollAnimation>,
 
     pub(crate) current_visibility_frame: u64,
@@ -47,9 +62,31 @@ pub struct Text {
     pub(crate) cursor_currently_blinked_out: bool,
     
     pub(crate) cursor_blink_timer: Option<CursorBlinkWaker>,
+    pub(crate) window_focused: bool,
+    pub(crate) key_repeat: KeyRepeat,
+    pub(crate) last_handled_repeat: Option<Instant>,
+    pub(crate) ime_disabled_by_focus: bool,
+    pub(crate) virtual_keyboard_callback: Option<fn(VirtualKeyboardRequest)>,
+    pub(crate) escape_callback: Option<fn(AnyBox, EscapeOutcome)>,
+    pub(crate) focus_tracing: Option<fn(&FocusTrace)>,
+    pub(crate) virtual_keyboard_update_needed: bool,
+    pub(crate) virtual_keyboard_shown: bool,
+    pub(crate) recording: Option<(Instant, EventRecording)>,
+    pub(crate) viewport: Option<parley::Rect>,
+    pub(crate) hovered: Option<AnyBox>,
+    pub(crate) manage_cursor_icon: bool,
+    pub(crate) text_cursor_icon_shown: bool,
+    pub(crate) pre_input_layers: Vec<PreInputLayer>,
+    pub(crate) post_input_layers: Vec<PostInputLayer>,
+    /// Boxes added with [`Text::add_text_box_keyed()`].
+    pub(crate) keyed_text_boxes: FxHashMap<u64, WeakTextBoxHandle>,
+    /// Unique for each `Text`. See [`Text::prepare_all()`] for how it's used with multiple renderers.
+    pub(crate) scene_id: u64,
+    /// Incremented by every [`Text::prepare_all()`] that found something to change.
+    pub(crate) scene_revision: u64,
+    /// The `renderer_id` of the renderer that last did a full prepare. The quad ranges in the boxes' `quad_storage` point into that renderer, so the cheap updates can only be applied to it.
+    pub(crate) quads_prepared_for: Option<u64>,
     
-    pub(crate) screen_width: f32,
-    pub(crate) screen_height: f32,
 
     pub(crate) slot_for_text_box_mut: Option<TextBoxMut<'static>>,
 
@@ -73,6 +110,116 @@ pub struct Shared {
     pub(crate) current_event_number: u64,
     #[cfg(feature = "accessibility")]
     pub(crate) node_id_generator: fn() -> NodeId,
+    pub(crate) paste_filter: Option<PasteFilter>,
+    pub(crate) history_merge_policy: HistoryMergePolicy,
+    pub(crate) async_layout_threshold: Option<usize>,
+    pub(crate) layout_progress_callback: Option<fn(LayoutProgress)>,
+    pub(crate) key_bindings: KeyBindings,
+    pub(crate) ime_update_needed: bool,
+    pub(crate) obscured_area: Option<parley::Rect>,
+    pub(crate) perf_stats: TextPerfStats,
+    pub(crate) time_source: fn() -> Instant,
+    pub(crate) groups: Slab<GroupInner>,
+    pub(crate) screen_size: (f32, f32),
+    pub(crate) group_transforms_changed: bool,
+    pub(crate) text_scale: f32,
+    pub(crate) system_settings: SystemSettings,
+    pub(crate) label_layouts: Option<LabelLayoutCache>,
+    pub(crate) glyph_animations_running: bool,
+    /// Set when Escape is handled by a text edit, and passed to the escape callback after the event.
+    pub(crate) escape_outcome: Option<EscapeOutcome>,
+    /// Set when the built-in key handling performs an action, and passed to the post input layers after the event.
+    pub(crate) performed_action: Option<EditAction>,
+    pub(crate) debug_overlay: Option<DebugOverlay>,
+    /// Counts the frames drawn with [`Text::prepare_all()`] or [`Text::render_to_image()`].
+    pub(crate) frames_prepared: u64,
+}
+
+impl Shared {
+    pub(crate) fn now(&self) -> Instant {
+        (self.time_source)()
+    }
+}
+
+/// How much work the text subsystem did for a frame, returned by [`Text::prepare_all()`].
+/// 
+/// Layout counters include layouts rebuilt since the previous `prepare_all()`, for example while handling events.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TextPerfStats {
+    /// Total time spent rebuilding layouts.
+    pub layout_time: Duration,
+    /// Number of layout rebuilds.
+    pub boxes_relaid_out: usize,
+    /// Time spent in `prepare_all()` itself, including any layouts rebuilt inside it.
+    pub prepare_time: Duration,
+    /// Number of quads prepared, including decorations. When nothing changed, this is the count from the last frame that was prepared.
+    pub quads: usize,
+    /// Number of glyphs rasterized and added to the atlas. These are uploaded to the GPU in the next [`TextRenderer::gpu_load()`].
+    pub glyphs_rasterized: usize,
+}
+
+/// Font metrics of a style, returned by [`Text::style_metrics()`]. All values are in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct StyleMetrics {
+    /// The font size after the text scale and the font size limits.
+    pub font_size: f32,
+    /// Distance from the baseline to the top of the font's tallest glyphs, positive upwards.
+    pub ascent: f32,
+    /// Distance from the baseline to the bottom of the font's lowest glyphs, positive downwards.
+    pub descent: f32,
+    /// Extra space that the font recommends between lines.
+    pub line_gap: f32,
+    /// Height of a line with the style's line height.
+    pub line_height: f32,
+    /// Distance from the top of a line to its baseline.
+    pub baseline: f32,
+    /// Height of capital letters above the baseline, or 0 if the font doesn't say.
+    pub cap_height: f32,
+    /// Height of lowercase letters like "x" above the baseline, or 0 if the font doesn't say.
+    pub x_height: f32,
+}
+
+/// Passed to the callback set with [`Text::set_virtual_keyboard_callback()`] when a text edit gains or loses focus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VirtualKeyboardRequest {
+    /// A text edit was focused. `rect` is its area in screen coordinates, so that the app can keep it clear of the keyboard.
+    Show { rect: parley::Rect },
+    /// The focused text edit lost focus.
+    Hide,
+}
+
+/// Passed to the callback set with [`Text::set_focus_tracing()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FocusTrace<'a> {
+    /// The left mouse button was pressed. `hits` are the boxes under the cursor with their depths, in no particular order, and `topmost` is the one that received the press.
+    ///
+    /// Presses handled by [`Text::handle_event_with_topmost()`] don't do any hit testing, so `hits` is empty and `topmost` is the box chosen by the caller.
+    Press { position: (f64, f64), hits: &'a [(AnyBox, f32)], topmost: Option<AnyBox> },
+    /// The focus moved from one box to another, for any reason.
+    FocusChanged { from: Option<AnyBox>, to: Option<AnyBox> },
+}
+
+/// Controls how repeated and synthetic key presses are handled. Set with [`Text::set_key_repeat()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyRepeat {
+    /// Whether key presses generated by holding a key down are handled. If false, holding a key only acts once.
+    pub honor_repeats: bool,
+    /// Whether synthetic key events are handled. Winit sends these when the window gains focus while keys are held down.
+    pub honor_synthetic: bool,
+    /// Minimum time between two handled repeats of a key. Repeats that arrive faster than this are dropped.
+    /// 
+    /// `None` leaves the repeat rate to the OS settings.
+    pub throttle: Option<Duration>,
+}
+
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        Self {
+            honor_repeats: true,
+            honor_synthetic: true,
+            throttle: None,
+        }
+    }
 }
 
 /// Handle for a text edit box.
@@ -80,9 +227,12 @@ pub struct Shared {
 /// Obtained when creating a text edit box with [`Text::add_text_edit()`].
 /// 
 /// Use with [`Text::get_text_edit()`] to get a reference to the corresponding [`TextEdit`]. 
+///
+/// The handle is valid until it's passed to [`Text::remove_text_edit()`] or [`Text::clear()`] is called. Using a handle that outlived a [`Text::clear()`] panics, even if a text edit added afterwards took the same slot. Keep a [`WeakTextEditHandle`] instead where the text edit can go away.
 #[derive(Debug, Clone)]
 pub struct TextEditHandle {
     pub(crate) i: u32,
+    pub(crate) generation: u64,
 }
 
 /// Handle for a text box.
@@ -90,9 +240,12 @@ pub struct TextEditHandle {
 /// Obtained when creating a text box with [`Text::add_text_box()`].
 /// 
 /// Use with [`Text::get_text_box()`] to get a reference to the corresponding [`TextBox`].
+///
+/// The handle is valid until it's passed to [`Text::remove_text_box()`] or [`Text::clear()`] is called. Using a handle that outlived a [`Text::clear()`] panics, even if a text box added afterwards took the same slot. Keep a [`WeakTextBoxHandle`] instead where the text box can go away.
 #[derive(Debug)]
 pub struct TextBoxHandle {
     pub(crate) i: u32,
+    pub(crate) generation: u64,
 }
 
 
@@ -173,16 +326,28 @@ pub enum AnyBox {
 // todo: you can use this to clone a handle basically
 pub trait IntoAnyBox {
     fn into_anybox(&self) -> AnyBox;
+
+    /// The generation of the box when the handle was created, for handles that can tell a removed box from a newer one in the same slot.
+    #[doc(hidden)]
+    fn generation(&self) -> Option<u64> {
+        None
+    }
 }
 impl IntoAnyBox for TextBoxHandle {
     fn into_anybox(&self) -> AnyBox {
         AnyBox::TextBox(self.i)
     }
+    fn generation(&self) -> Option<u64> {
+        Some(self.generation)
+    }
 }
 impl IntoAnyBox for TextEditHandle {
     fn into_anybox(&self) -> AnyBox {
         AnyBox::TextEdit(self.i)
     }
+    fn generation(&self) -> Option<u64> {
+        Some(self.generation)
+    }
 }
 impl IntoAnyBox for AnyBox {
     fn into_anybox(&self) -> AnyBox {
@@ -226,6 +391,9 @@ pub(crate) const DEFAULT_STYLE_I: usize = 0;
 /// Pre-defined handle for the default text style.
 pub const DEFAULT_STYLE_HANDLE: StyleHandle = StyleHandle { i: DEFAULT_STYLE_I as u32 };
 
+/// Number of layouts kept by [`Text::set_label_interning()`].
+pub const LABEL_LAYOUT_CACHE_CAPACITY: usize = 256;
+
 impl Text {
     /// Create a new Text instance.
     /// 
@@ -251,6 +419,8 @@ impl Text {
             text_style: original_default_style(),
             text_edit_style: TextEditStyle::default(),
             version: 0,
+            font_size_limits: NO_FONT_SIZE_LIMITS,
+            materials: Vec::new(),
         });
         debug_assert!(i == DEFAULT_STYLE_I);
 
@@ -260,20 +430,41 @@ impl Text {
             text_boxes: Slab::with_capacity(10),
             text_edits: Slab::with_capacity(10),
             style_version_id_counter: 0,
+            box_generation_counter: 0,
             input_state: TextInputState::new(),
             focused: None,
             mouse_hit_stack: Vec::with_capacity(6),
+            depth_order: Vec::new(),
             decorations_changed: true,
             scrolled_moved_indices: Vec::new(),
+            translated_boxes: Vec::new(),
             scroll_animations: Vec::new(),
             current_visibility_frame: 1,
             using_frame_based_visibility: false,
             cursor_blink_start: None,
             cursor_currently_blinked_out: false,
             cursor_blink_timer,
+            window_focused: true,
+            key_repeat: KeyRepeat::default(),
+            last_handled_repeat: None,
+            ime_disabled_by_focus: false,
+            virtual_keyboard_callback: None,
+            escape_callback: None,
+            focus_tracing: None,
+            virtual_keyboard_update_needed: false,
+            virtual_keyboard_shown: false,
+            recording: None,
+            viewport: None,
+            hovered: None,
+            manage_cursor_icon: true,
+            text_cursor_icon_shown: false,
+            pre_input_layers: Vec::new(),
+            post_input_layers: Vec::new(),
+            keyed_text_boxes: FxHashMap::default(),
+            scene_id: NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed),
+            scene_revision: 0,
+            quads_prepared_for: None,
 
-            screen_width: 800.0,
-            screen_height: 600.0,
 
             slot_for_text_box_mut: None,
 
@@ -289,6 +480,26 @@ impl Text {
                 #[cfg(feature = "accessibility")]
                 accesskit_focus_update: (Some(NodeId(0)), 0),
                 current_event_number: 1,
+                paste_filter: None,
+                history_merge_policy: HistoryMergePolicy::default(),
+                async_layout_threshold: None,
+                layout_progress_callback: None,
+                key_bindings: KeyBindings::default(),
+                ime_update_needed: false,
+                obscured_area: None,
+                perf_stats: TextPerfStats::default(),
+                time_source: Instant::now,
+                groups: Slab::new(),
+                group_transforms_changed: false,
+                screen_size: (800.0, 600.0),
+                text_scale: 1.0,
+                system_settings: SystemSettings::default(),
+                label_layouts: None,
+                glyph_animations_running: false,
+                escape_outcome: None,
+                performed_action: None,
+                debug_overlay: None,
+                frames_prepared: 0,
                 #[cfg(feature = "accessibility")]
                 node_id_generator: crate::accessibility::next_node_id,
                 #[cfg(feature = "accessibility")]
@@ -302,6 +513,11 @@ impl Text {
     }
 
 
+    fn new_box_generation(&mut self) -> u64 {
+        self.box_generation_counter += 1;
+        self.box_generation_counter
+    }
+
     pub(crate) fn new_style_version(&mut self) -> u64 {
         self.style_version_id_counter += 1;
         self.style_version_id_counter
@@ -319,9 +535,24 @@ impl Text {
         let mut text_box = TextBoxInner::new(text, pos, size, depth);
         text_box.last_frame_touched = self.current_visibility_frame;
         text_box.style_version = self.shared.styles[text_box.style.i as usize].version;
+        let generation = self.new_box_generation();
+        text_box.generation = generation;
         let i = self.text_boxes.insert(text_box) as u32;
         self.shared.text_changed = true;
-        TextBoxHandle { i }
+        TextBoxHandle { i, generation }
+    }
+
+    /// Add a text box in static label mode and return a handle. See [`TextBoxMut::set_static_label()`].
+    /// 
+    /// Like boxes added with [`Text::add_text_box()`], it must be manually removed by calling [`Text::remove_text_box()`].
+    #[must_use]
+    pub fn add_static_label(&mut self, text: impl Into<Cow<'static, str>>, pos: (f64, f64), size: (f32, f32), depth: f32) -> TextBoxHandle {
+        let handle = self.add_text_box(text, pos, size, depth);
+        let text_box = &mut self.text_boxes[handle.i as usize];
+        text_box.static_label = true;
+        text_box.selectable_before_static = text_box.selectable;
+        text_box.selectable = false;
+        handle
     }
 
     /// Add a text edit and return a handle.
@@ -329,14 +560,18 @@ impl Text {
     /// The handle can be used with [`Text::get_text_edit()`] to get a reference to the [`TextEdit`] that was added.
     /// 
     /// The [`TextEdit`] must be manually removed by calling [`Text::remove_text_edit()`].
+    /// 
+    /// `text` can be a `String`, a `&'static str`, or a `Cow<'static, str>`. A `&'static str` isn't copied until the text is edited.
     #[must_use]
-    pub fn add_text_edit(&mut self, text: String, pos: (f64, f64), size: (f32, f32), depth: f32) -> TextEditHandle {
+    pub fn add_text_edit(&mut self, text: impl Into<Cow<'static, str>>, pos: (f64, f64), size: (f32, f32), depth: f32) -> TextEditHandle {
         let (text_edit, mut text_box) = TextEditInner::new(text, pos, size, depth);
         text_box.last_frame_touched = self.current_visibility_frame;
         text_box.style_version = self.shared.styles[text_box.style.i as usize].version;
+        let generation = self.new_box_generation();
+        text_box.generation = generation;
         let i = self.text_edits.insert((text_edit, text_box)) as u32;
         self.shared.text_changed = true;
-        TextEditHandle { i }
+        TextEditHandle { i, generation }
     }
 
 
@@ -347,8 +582,9 @@ impl Text {
     /// `handle` is the handle that was returned when first creating the text edit with [`Text::add_text_edit()`] or similar functions.
     ///    
     /// This is a fast lookup operation that does not require any hashing.
+    /// 
+    /// Getting the edit doesn't mark anything as changed by itself. Each setter marks only what it affects, so reading through the mutable accessor doesn't cause the scene to be prepared again.
     pub fn get_text_edit_mut(&mut self, handle: &TextEditHandle) -> TextEditMut {
-        self.shared.text_changed = true;
         self.get_full_text_edit(handle)
     }
 
@@ -358,6 +594,7 @@ impl Text {
     ///    
     /// This is a fast lookup operation that does not require any hashing.
     pub fn get_text_edit(&mut self, handle: &TextEditHandle) -> TextEdit {
+        self.check_handle(handle);
         let (text_edit_inner, text_box_inner) = self.text_edits.get_mut(handle.i as usize).unwrap();
         let text_box = TextBox { inner: text_box_inner, shared: &mut self.shared };
         TextEdit { inner: text_edit_inner, text_box }
@@ -371,6 +608,8 @@ impl Text {
             text_style,
             text_edit_style,
             version: new_version,
+            font_size_limits: NO_FONT_SIZE_LIMITS,
+            materials: Vec::new(),
         }) as u32;
         StyleHandle { i }
     }
@@ -395,6 +634,111 @@ impl Text {
         &mut self.shared.styles[handle.i as usize].text_edit_style
     }
 
+    /// Keep the font size of a style between `min` and `max` after the global scale from [`Text::set_text_scale()`] is applied.
+    ///
+    /// The whole layout is scaled by the same factor, so font sizes set in rich text spans keep their proportions. The per-box zoom from [`TextBoxMut::set_zoom()`] is applied on top of the clamped size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min` is greater than `max`.
+    pub fn set_font_size_limits(&mut self, handle: &StyleHandle, min: f32, max: f32) {
+        assert!(min <= max, "Minimum font size {} is greater than the maximum {}", min, max);
+        self.shared.styles[handle.i as usize].font_size_limits = (min, max);
+        self.shared.styles[handle.i as usize].version = self.new_style_version();
+        self.shared.text_changed = true;
+    }
+
+    pub fn font_size_limits(&self, handle: &StyleHandle) -> (f32, f32) {
+        self.shared.styles[handle.i as usize].font_size_limits
+    }
+
+    /// Resolved font metrics of a style, for aligning text with icons, drawing a custom cursor or following a baseline grid.
+    ///
+    /// The metrics come from the font that the style resolves to for Latin text, at the size that boxes using the style are laid out with: after [`Text::set_text_scale()`] and [`Text::set_font_size_limits()`], but without any per-box zoom.
+    pub fn style_metrics(&self, handle: &StyleHandle) -> StyleMetrics {
+        let style = &self.shared.styles[handle.i as usize];
+        let font_size = style.text_style.font_size;
+        let mut scale = self.shared.text_scale;
+        if font_size > 0.0 {
+            let (min, max) = style.font_size_limits;
+            scale = (font_size * scale).clamp(min, max) / font_size;
+        }
+
+        let spans = LayoutSpans { wrap_spans: &[], inline_boxes: &[], image_replacements: &[], folds: &[] };
+        let layout = build_layout("x", &style.text_style, spans, None, true, f32::MAX, Alignment::default(), scale);
+
+        let mut metrics = StyleMetrics { font_size: font_size * scale, ..Default::default() };
+        let Some(line) = layout.lines().next() else {
+            return metrics;
+        };
+        let line_metrics = line.metrics();
+        metrics.ascent = line_metrics.ascent;
+        metrics.descent = line_metrics.descent;
+        metrics.line_height = line_metrics.line_height;
+        metrics.baseline = line_metrics.baseline;
+
+        for item in line.items() {
+            let PositionedLayoutItem::GlyphRun(glyph_run) = item else {
+                continue;
+            };
+            let run = glyph_run.run();
+            let font = run.font();
+            if let Some(font_ref) = FontRef::from_index(font.data.as_ref(), font.index as usize) {
+                let font_metrics = font_ref.metrics(run.normalized_coords()).scale(run.font_size());
+                // Parley's leading is the extra space from the style's line height, not the font's line gap.
+                metrics.line_gap = font_metrics.leading;
+                metrics.cap_height = font_metrics.cap_height;
+                metrics.x_height = font_metrics.x_height;
+            }
+            break;
+        }
+        metrics
+    }
+
+    /// Scale the text of all boxes by `scale`, for example to follow a "large text" accessibility setting. All boxes are laid out again.
+    ///
+    /// Unlike changing the font size of each style, this also scales the font sizes set in rich text spans. See also [`Text::set_font_size_limits()`].
+    pub fn set_text_scale(&mut self, scale: f32) {
+        if self.shared.text_scale == scale {
+            return;
+        }
+        self.shared.text_scale = scale;
+        self.invalidate_all_layouts();
+    }
+
+    /// Make every box relayout before it's used again.
+    pub(crate) fn invalidate_all_layouts(&mut self) {
+        // Changing the versions makes every box using the styles relayout.
+        for (_, style) in self.shared.styles.iter_mut() {
+            self.style_version_id_counter += 1;
+            style.version = self.style_version_id_counter;
+        }
+        self.shared.text_changed = true;
+    }
+
+    pub fn text_scale(&self) -> f32 {
+        self.shared.text_scale
+    }
+
+    /// Share layouts between identical labels, like thousands of "Delete" buttons, instead of laying each one out separately.
+    ///
+    /// A box shares its layout when it was created from a `&'static str` (see [`TextBox::is_borrowed()`]), has no spans, and has the same style, width and alignment as a box that was laid out before. The most recently used [`LABEL_LAYOUT_CACHE_CAPACITY`] layouts are kept.
+    ///
+    /// Disabled by default.
+    pub fn set_label_interning(&mut self, enabled: bool) {
+        if enabled == self.shared.label_layouts.is_some() {
+            return;
+        }
+        self.shared.label_layouts = enabled.then(|| LabelLayoutCache::with_hasher(
+            std::num::NonZeroUsize::new(LABEL_LAYOUT_CACHE_CAPACITY).unwrap(),
+            BuildHasherDefault::<FxHasher>::default(),
+        ));
+    }
+
+    pub fn label_interning(&self) -> bool {
+        self.shared.label_layouts.is_some()
+    }
+
     pub fn get_default_text_style(&self) -> &TextStyle2 {
         self.get_text_style(&DEFAULT_STYLE_HANDLE)
     }
@@ -431,7 +775,12 @@ impl Text {
     /// 
     /// Part of the "declarative" interface.  
     pub fn refresh_text_box(&mut self, handle: &TextBoxHandle) {
-        if let Some(text_box) = self.text_boxes.get_mut(handle.i as usize) {
+        self.check_handle(handle);
+        self.refresh_text_box_at(handle.i);
+    }
+
+    pub(crate) fn refresh_text_box_at(&mut self, i: u32) {
+        if let Some(text_box) = self.text_boxes.get_mut(i as usize) {
             text_box.last_frame_touched = self.current_visibility_frame;
         }
     }
@@ -441,15 +790,28 @@ impl Text {
     /// 
     /// Part of the "declarative" interface.
     pub fn refresh_text_edit(&mut self, handle: &TextEditHandle) {
+        self.check_handle(handle);
         if let Some((_text_edit, text_box)) = self.text_edits.get_mut(handle.i as usize) {
             text_box.last_frame_touched = self.current_visibility_frame;
         }
     }
 
 
+    /// Rebuild the layout of a text box if it's out of date, without preparing it for rendering.
+    /// 
+    /// This is normally done by [`Text::prepare_all()`]. It's useful for measuring layout performance separately, or in programs that only need the layout.
+    pub fn refresh_text_box_layout(&mut self, handle: &TextBoxHandle) {
+        self.get_text_box_mut(handle).refresh_layout();
+    }
+
+    /// Rebuild the layout of a text edit if it's out of date, without preparing it for rendering. See [`Text::refresh_text_box_layout()`].
+    pub fn refresh_text_edit_layout(&mut self, handle: &TextEditHandle) {
+        self.get_text_edit_mut(handle).refresh_layout();
+    }
+
     /// Remove all text boxes that were made outdated by [`Text::advance_frame_and_hide_boxes()`], were not refreshed with [`Text::refresh_text_box()`], and were not set to remain as hidden with [`TextBox::set_can_hide()`].
     /// 
-    /// Because [`Text::remove_old_nodes()`] mass-removes text boxes without consuming their handles, the handles become "dangling" and should not be reused. Using them in functions like [`Text::get_text_box()`] or [`Text::remove_text_box()`] will cause panics or incorrect results.
+    /// Because [`Text::remove_old_nodes()`] mass-removes text boxes without consuming their handles, the handles become "dangling" and should not be reused. Using them in functions like [`Text::get_text_box()`] or [`Text::remove_text_box()`] panics.
     /// 
     /// Only use this function if the structs holding the handles are managed in a way where you can be confident that the handles won't be kept around and reused.
     /// 
@@ -457,6 +819,8 @@ impl Text {
     /// 
     /// [`Text::remove_old_nodes()`] is the only function that breaks the "no dangling handles" promise. If you use imperative [`Text::remove_text_box()`] calls and avoid `remove_old_nodes()`, then there is no way for the handle system to break.
     /// 
+    /// Long-lived references to boxes that might be removed here should use weak handles, see [`Text::downgrade_text_edit()`] and [`Text::is_alive()`].
+    /// 
 
     pub fn remove_old_nodes(&mut self) {
         // Clear focus if the focused text box will be removed
@@ -479,7 +843,7 @@ impl Text {
             };
             
             if should_clear_focus {
-                self.focused = None;
+                self.lose_focus();
             }
         }
 
@@ -498,23 +862,29 @@ impl Text {
     /// 
     /// `handle` is the handle that was returned when first creating the text box with [`Text::add_text_box()`].
     pub fn remove_text_box(&mut self, handle: TextBoxHandle) {
+        self.check_handle(&handle);
+        self.remove_text_box_at(handle.i);
+        std::mem::forget(handle);
+    }
+
+    pub(crate) fn remove_text_box_at(&mut self, i: u32) {
         self.shared.text_changed = true;
-        if let Some(AnyBox::TextBox(i)) = self.focused {
-            if i == handle.i {
-                self.focused = None;
-            }
+        if self.focused == Some(AnyBox::TextBox(i)) {
+            self.lose_focus();
+        }
+        if self.hovered == Some(AnyBox::TextBox(i)) {
+            self.hovered = None;
         }
         
         // Remove from accessibility mapping if it exists
         #[cfg(feature = "accessibility")]
-        if let Some(text_box) = self.text_boxes.get(handle.i as usize) {
+        if let Some(text_box) = self.text_boxes.get(i as usize) {
             if let Some(accesskit_id) = text_box.accesskit_id {
                 self.accesskit_id_to_text_handle_map.remove(&accesskit_id);
             }
         }
         
-        self.text_boxes.remove(handle.i as usize);
-        std::mem::forget(handle);
+        self.text_boxes.remove(i as usize);
     }
 
 
@@ -522,12 +892,16 @@ impl Text {
     /// 
     /// `handle` is the handle that was returned when first creating the text edit with [`Text::add_text_edit()`] or similar functions.
     pub fn remove_text_edit(&mut self, handle: TextEditHandle) {
+        self.check_handle(&handle);
         self.shared.text_changed = true;
         if let Some(AnyBox::TextEdit(i)) = self.focused {
             if i == handle.i {
-                self.focused = None;
+                self.lose_focus();
             }
         }
+        if self.hovered == Some(AnyBox::TextEdit(handle.i)) {
+            self.hovered = None;
+        }
         
         // Remove from accessibility mapping if it exists
         #[cfg(feature = "accessibility")]
@@ -548,19 +922,107 @@ impl Text {
         self.shared.styles.remove(handle.i as usize);
     }
 
-    pub fn prepare_all(&mut self, text_renderer: &mut TextRenderer) {
-        text_renderer.update_resolution(self.screen_width, self.screen_height);
+    /// Remove all styles that aren't used by any text box or text edit, except for the default style. Styles set for labels with [`Text::link_label()`] count as used. Returns the number of styles removed.
+    ///
+    /// This is meant for long-running apps that create many short-lived styles, for example one per notification, and don't want to track them. Handles to the removed styles become invalid, so a style that isn't used right now but will be needed later has to be added again.
+    pub fn collect_unused_styles(&mut self) -> usize {
+        let mut used = vec![false; self.shared.styles.capacity()];
+        used[DEFAULT_STYLE_I] = true;
+        for (_, text_box) in self.text_boxes.iter() {
+            used[text_box.style.i as usize] = true;
+            // Labels switch between these styles when their linked edit changes state.
+            if let Some(link) = &text_box.label_link {
+                let styles = [Some(link.normal), link.styles.focused, link.styles.error];
+                for style in styles.into_iter().flatten() {
+                    used[style.i as usize] = true;
+                }
+            }
+        }
+        for (_, (_text_edit, text_box)) in self.text_edits.iter() {
+            used[text_box.style.i as usize] = true;
+        }
+
+        let len_before = self.shared.styles.len();
+        self.shared.styles.retain(|i, _| used[i]);
+        len_before - self.shared.styles.len()
+    }
+
+    /// Remove all text boxes, text edits and styles, except for the default style, in one call. Useful for apps that rebuild the whole scene when navigating between screens.
+    ///
+    /// Focus, hover state and scroll animations are reset, and everything is removed from the [`TextRenderer`] on the next [`Text::prepare_all()`]. Groups and settings are kept.
+    ///
+    /// All outstanding [`TextBoxHandle`]s, [`TextEditHandle`]s and [`StyleHandle`]s, except for [`DEFAULT_STYLE_HANDLE`], become invalid. They should be dropped with `std::mem::forget()`. Using a box handle afterwards panics, even if a box added later took the same slot, and [`Text::is_alive()`] returns false for it. Weak handles from [`Text::downgrade_text_edit()`] and [`Text::downgrade_text_box()`] are safe: box generations keep increasing across a clear, so they will no longer upgrade.
+    pub fn clear(&mut self) {
+        self.refocus(None);
+        self.hovered = None;
+        self.mouse_hit_stack.clear();
+        self.scrolled_moved_indices.clear();
+        self.translated_boxes.clear();
+        self.scroll_animations.clear();
+
+        self.text_boxes.clear();
+        self.text_edits.clear();
+        self.keyed_text_boxes.clear();
+        self.shared.styles.retain(|i, _| i == DEFAULT_STYLE_I);
+        if let Some(label_layouts) = &mut self.shared.label_layouts {
+            label_layouts.clear();
+        }
+
+        #[cfg(feature = "accessibility")]
+        self.accesskit_id_to_text_handle_map.clear();
+
+        self.shared.text_changed = true;
+        self.decorations_changed = true;
+    }
+
+    /// Prepare all visible text boxes and text edits for rendering.
+    /// 
+    /// Returns a [`TextPerfStats`] describing the work that was done, which can be shown in a profiler or ignored.
+    ///
+    /// The same `Text` can be prepared for more than one [`TextRenderer`], for example to show it in several windows. Each renderer remembers which scene and which revision of it it was last prepared for: a renderer that missed some changes, because they were prepared for another renderer, is prepared again from scratch, while renderers that are already up to date only get the cheap updates. The cheap updates move quads in place, so they're only used for the renderer that did the last full prepare: when several renderers are prepared in turn, each of them is prepared from scratch. When [`Text::need_rerender()`] returns true, all renderers should be prepared and redrawn.
+    pub fn prepare_all(&mut self, text_renderer: &mut TextRenderer) -> TextPerfStats {
+        profile_span!("Text::prepare_all");
+        let prepare_start = Instant::now();
+        text_renderer.text_renderer.glyphs_rasterized = 0;
+        text_renderer.text_renderer.time_source = self.shared.time_source;
+        text_renderer.update_resolution(self.shared.screen_size.0, self.shared.screen_size.1);
+        text_renderer.text_renderer.update_group_transforms(&self.shared.groups);
+        self.shared.group_transforms_changed = false;
+
+        self.poll_pending_layouts();
+        self.update_linked_labels();
+        if self.shared.scrolled {
+            self.collect_scroll_moved_boxes();
+        }
+
+        // Changes made through the handles outside of events, like remote cursor updates, only set the shared flag.
+        if self.shared.decorations_changed {
+            self.decorations_changed = true;
+        }
+        // The red tint of the debug overlay has to go away on the frame after a relayout, even if nothing else changed.
+        if self.shared.debug_overlay.is_some_and(|overlay| overlay.dirty_flags) {
+            self.decorations_changed = true;
+        }
+
+        // Checked before a stale renderer forces a full prepare, so that renderers that are up to date don't become stale because of it.
+        let mut scene_changed = self.shared.text_changed || self.shared.decorations_changed || self.decorations_changed || !self.scrolled_moved_indices.is_empty();
+        let renderer_id = text_renderer.text_renderer.renderer_id;
+        if text_renderer.text_renderer.last_prepared != Some((self.scene_id, self.scene_revision)) || self.quads_prepared_for != Some(renderer_id) {
+            self.shared.text_changed = true;
+        }
         
         if ! self.shared.text_changed && self.using_frame_based_visibility {
             // see if any text boxes were just hidden
             for (_i, (_text_edit, text_box)) in self.text_edits.iter_mut() {
                 if text_box.last_frame_touched == self.current_visibility_frame - 1 {
                     self.shared.text_changed = true;
+                    scene_changed = true;
                 }
             }
             for (_i, text_box) in self.text_boxes.iter_mut() {
                 if text_box.last_frame_touched == self.current_visibility_frame - 1 {
                     self.shared.text_changed = true;
+                    scene_changed = true;
                 }
 
             }
@@ -569,25 +1031,71 @@ impl Text {
         
         // decorations
         let (show_cursor, blink_changed) = self.cursor_blinked_out(true);
+        scene_changed |= blink_changed;
+        // A blink alone only replaces the cursor, without touching the selection and the other decorations.
+        let only_blink_changed = blink_changed && !self.decorations_changed && !self.shared.text_changed && self.scrolled_moved_indices.is_empty();
 
         if self.shared.text_changed {
             text_renderer.clear();
+        } else if only_blink_changed {
+            text_renderer.clear_cursor_only();
         } else if self.decorations_changed || !self.scrolled_moved_indices.is_empty() || blink_changed {
             text_renderer.clear_decorations_only();
         }
 
-        if self.decorations_changed || self.shared.text_changed  || !self.scrolled_moved_indices.is_empty() || blink_changed {
+        let mut decorations_prepared = false;
+        if only_blink_changed {
+            if let (Some(AnyBox::TextEdit(i)), true) = (self.focused, self.window_focused) {
+                let text_edit = self.get_full_text_edit_at(i);
+                text_renderer.prepare_text_box_cursor(&text_edit.text_box, show_cursor);
+            }
+        } else if self.decorations_changed || self.shared.text_changed  || !self.scrolled_moved_indices.is_empty() || blink_changed {
+            decorations_prepared = true;
+            let current_frame = self.current_visibility_frame;
+            for (_, (text_edit, text_box)) in self.text_edits.iter_mut() {
+                if text_box.hidden || text_box.last_frame_touched != current_frame || outside_viewport(self.viewport, &self.shared, text_box) {
+                    continue;
+                }
+                {
+                    let text_edit = get_full_text_edit_free_function_but_for_iterating((&mut *text_edit, &mut *text_box), &mut self.shared);
+                    if !text_edit.inner.remote_cursors.is_empty() || !text_edit.text_box.inner.folds.is_empty() {
+                        text_renderer.prepare_remote_cursor_decorations(&text_edit);
+                        text_renderer.prepare_fold_markers(&text_edit);
+                    }
+                    text_renderer.prepare_suspicious_char_highlights(&text_edit);
+                    text_renderer.prepare_scrollbars(&text_edit);
+                }
+                if !text_box.overlay_rects.is_empty() {
+                    let text_box = get_full_text_box_free_function_but_for_iterating(text_box, &mut self.shared);
+                    text_renderer.prepare_text_box_overlays(&text_box);
+                }
+            }
+            for (_, text_box) in self.text_boxes.iter_mut() {
+                if !text_box.static_label && !text_box.overlay_rects.is_empty() && !text_box.hidden && text_box.last_frame_touched == current_frame && !outside_viewport(self.viewport, &self.shared, text_box) {
+                    let text_box = get_full_text_box_free_function_but_for_iterating(text_box, &mut self.shared);
+                    text_renderer.prepare_text_box_overlays(&text_box);
+                }
+            }
+
             if let Some(focused) = self.focused {
+                let window_focused = self.window_focused;
                 match focused {
                     AnyBox::TextEdit(i) => {
-                        let handle = TextEditHandle { i: i as u32 };
-                        let text_edit = self.get_full_text_edit(&handle);
-                        text_renderer.prepare_text_box_decorations(&text_edit.text_box, show_cursor);
+                        let text_edit = self.get_full_text_edit_at(i);
+                        if window_focused {
+                            text_renderer.prepare_bracket_match_highlight(&text_edit);
+                            text_renderer.prepare_text_box_decorations(&text_edit.text_box, show_cursor);
+                        } else {
+                            text_renderer.prepare_inactive_text_box_decorations(&text_edit.text_box);
+                        }
                     },
                     AnyBox::TextBox(i) => {
-                        let handle = TextBoxHandle { i: i as u32 };
-                        let text_box = self.get_full_text_box(&handle);
-                        text_renderer.prepare_text_box_decorations(&text_box, false);
+                        let text_box = self.get_full_text_box_at(i);
+                        if window_focused {
+                            text_renderer.prepare_text_box_decorations(&text_box, false);
+                        } else {
+                            text_renderer.prepare_inactive_text_box_decorations(&text_box);
+                        }
                     },
                 }
             }
@@ -598,13 +1106,28 @@ impl Text {
             if !self.scrolled_moved_indices.is_empty() {
                 self.handle_scroll_fast_path(text_renderer);
             }
+            for (any_box, (dx, dy)) in self.translated_boxes.drain(..) {
+                let text_box = match any_box {
+                    AnyBox::TextEdit(i) => self.text_edits.get(i as usize).map(|(_, text_box)| text_box),
+                    AnyBox::TextBox(i) => self.text_boxes.get(i as usize),
+                };
+                if let Some(text_box) = text_box {
+                    text_renderer.text_renderer.translate_quads(&text_box.quad_storage, dx, dy);
+                }
+            }
 
         } else {
         // if self.shared.text_changed || !self.scrolled_moved_indices.is_empty(){
+            self.quads_prepared_for = Some(renderer_id);
 
             let current_frame = self.current_visibility_frame;
-            if self.shared.text_changed {
+            if self.shared.text_changed && text_renderer.depth_sorting() {
+                self.prepare_back_to_front(text_renderer);
+            } else if self.shared.text_changed {
                 for (_, text_edit) in self.text_edits.iter_mut() {
+                    if outside_viewport(self.viewport, &self.shared, &text_edit.1) {
+                        continue;
+                    }
                     let mut text_edit = get_full_text_edit_free_function_but_for_iterating((&mut text_edit.0, &mut text_edit.1), &mut self.shared);
                     if !text_edit.hidden() && text_edit.text_box.inner.last_frame_touched == current_frame {
                         text_renderer.prepare_text_edit_layout(&mut text_edit);
@@ -612,6 +1135,9 @@ impl Text {
                 }
 
                 for (_, text_box) in self.text_boxes.iter_mut() {
+                    if outside_viewport(self.viewport, &self.shared, text_box) {
+                        continue;
+                    }
                     let mut text_box = get_full_text_box_free_function_but_for_iterating(text_box, &mut self.shared);
                     if !text_box.hidden() && text_box.inner.last_frame_touched == current_frame {
                         text_renderer.prepare_text_box_layout(&mut text_box);
@@ -620,7 +1146,33 @@ impl Text {
             }
         }
 
+        // After the layouts were refreshed, so that it shows the current ones.
+        if let (Some(overlay), true) = (self.shared.debug_overlay, decorations_prepared) {
+            self.prepare_debug_overlay(text_renderer, overlay);
+        }
+
+        self.animate_glyphs(text_renderer);
+
+        if scene_changed {
+            self.scene_revision += 1;
+        }
+        text_renderer.text_renderer.last_prepared = Some((self.scene_id, self.scene_revision));
+
+        self.end_frame();
+
+        let mut stats = std::mem::take(&mut self.shared.perf_stats);
+        stats.prepare_time = prepare_start.elapsed();
+        stats.quads = text_renderer.quad_count();
+        stats.glyphs_rasterized = text_renderer.text_renderer.glyphs_rasterized;
+        stats
+    }
+
+    /// Reset the dirty flags after the scene was drawn, by [`Text::prepare_all()`] or [`Text::render_to_image()`].
+    pub(crate) fn end_frame(&mut self) {
         self.clear_finished_scroll_animations();
+        self.shared.frames_prepared += 1;
+        // A full prepare already placed the quads at the new positions.
+        self.translated_boxes.clear();
 
         self.shared.text_changed = false;
         self.shared.decorations_changed = false;
@@ -636,6 +1188,50 @@ impl Text {
         }
     }
 
+    /// Move many boxes by the same amount, for example when the host scrolls a panel that contains them.
+    /// 
+    /// Unlike calling [`TextBoxMut::set_pos()`] on each box, this doesn't force the whole scene to be prepared again: the quads that were already prepared for the boxes are moved in place by the next [`Text::prepare_all()`]. Boxes created from handles with [`IntoAnyBox::into_anybox()`] can be mixed freely.
+    /// 
+    /// Quads are placed on whole pixels, so fractional deltas, boxes with a glyph animation and boxes that enter or leave the viewport set with [`Text::set_viewport()`] still go through a full prepare.
+    pub fn translate_boxes(&mut self, boxes: &[AnyBox], dx: f64, dy: f64) {
+        if dx == 0.0 && dy == 0.0 {
+            return;
+        }
+        let whole_pixels = dx.fract() == 0.0 && dy.fract() == 0.0;
+        for &any_box in boxes {
+            let text_box = match any_box {
+                AnyBox::TextEdit(i) => self.text_edits.get_mut(i as usize).map(|(_, text_box)| text_box),
+                AnyBox::TextBox(i) => self.text_boxes.get_mut(i as usize),
+            };
+            let Some(text_box) = text_box else {
+                continue;
+            };
+            let was_outside = outside_viewport(self.viewport, &self.shared, text_box);
+            text_box.left += dx;
+            text_box.top += dy;
+            if let Some(baseline) = &mut text_box.baseline_anchor {
+                *baseline += dy;
+            }
+            let is_outside = outside_viewport(self.viewport, &self.shared, text_box);
+
+            if !whole_pixels || text_box.glyph_animation.is_some() || was_outside != is_outside {
+                self.shared.text_changed = true;
+                continue;
+            }
+            if text_box.hidden || text_box.last_frame_touched != self.current_visibility_frame || is_outside {
+                continue;
+            }
+            match self.translated_boxes.iter_mut().find(|(b, _)| *b == any_box) {
+                Some((_, delta)) => *delta = (delta.0 + dx as i32, delta.1 + dy as i32),
+                None => self.translated_boxes.push((any_box, (dx as i32, dy as i32))),
+            }
+            // Goes through the same path as scrolling, which prepares the decorations again.
+            if !self.scrolled_moved_indices.contains(&any_box) {
+                self.scrolled_moved_indices.push(any_box);
+            }
+        }
+    }
+
     /// Fast path for handling scroll-only changes by moving quads in-place
     fn handle_scroll_fast_path(&mut self, text_renderer: &mut TextRenderer) {
         for any_box in &self.scrolled_moved_indices {
@@ -654,13 +1250,27 @@ impl Text {
         }
     }
 
+    /// Queue the boxes that were scrolled through the mutable accessors, outside of event handling, for the scroll fast path.
+    fn collect_scroll_moved_boxes(&mut self) {
+        for (i, (_, text_box)) in self.text_edits.iter_mut() {
+            if std::mem::take(&mut text_box.scroll_moved) {
+                self.scrolled_moved_indices.push(AnyBox::TextEdit(i as u32));
+            }
+        }
+        for (i, text_box) in self.text_boxes.iter_mut() {
+            if std::mem::take(&mut text_box.scroll_moved) {
+                self.scrolled_moved_indices.push(AnyBox::TextBox(i as u32));
+            }
+        }
+    }
+
     /// Clear scroll indices only for elements that have finished their animations
     fn clear_finished_scroll_animations(&mut self) {
         self.scrolled_moved_indices.retain(|any_box| {
             match any_box {
                 AnyBox::TextEdit(i) => {
                     // Keep in list if any animation is still running for this text edit
-                    self.scroll_animations.iter().any(|anim| anim.handle.i == *i)
+                    self.scroll_animations.iter().any(|anim| anim.i == *i)
                 },
                 AnyBox::TextBox(_i) => {
                     // Text boxes don't have animations, so they can be cleared immediately
@@ -677,14 +1287,27 @@ impl Text {
     /// 
     /// Any events other than `winit::WindowEvent::MouseInput` can use either this method or the occlusion method interchangeably.
     pub fn handle_event(&mut self, event: &WindowEvent, window: &Window) {
+        profile_span!("Text::handle_event");
+        self.record_event(event);
+        self.forget_static_label_focus();
         self.shared.current_event_number += 1;
         
         self.input_state.handle_event(event);
 
         if let WindowEvent::Resized(size) = event {
-            self.screen_width = size.width as f32;
-            self.screen_height = size.height as f32;
-            self.shared.text_changed = true;
+            self.handle_resize(*size);
+        }
+
+        if let WindowEvent::Focused(focused) = event {
+            self.window_focused = *focused;
+            if !*focused {
+                // The release events for anything held down won't reach us while the window is in the background.
+                self.input_state.mouse.pointer_down = false;
+            }
+            // Stops the blinking while the window is in the background, or restarts it.
+            self.reset_cursor_blink();
+            self.decorations_changed = true;
+            self.shared.decorations_changed = true;
         }
 
         // update smooth scrolling animations
@@ -695,17 +1318,36 @@ impl Text {
             }
         }
 
+        let mut label_clicked = false;
         if let WindowEvent::MouseInput { state, button, .. } = event {
             if state.is_pressed() && *button == MouseButton::Left {
-                let new_focus = self.find_topmost_at_pos(self.input_state.mouse.cursor_pos);
+                let mut new_focus = self.find_topmost_at_pos(self.input_state.mouse.cursor_pos);
+                self.trace_press(&self.mouse_hit_stack, new_focus);
                 if new_focus.is_some() {
                     self.shared.event_consumed = true;
                 }
+                if let Some(edit) = new_focus.and_then(|target| self.label_focus_target(target)) {
+                    new_focus = Some(edit);
+                    label_clicked = true;
+                }
                 self.refocus(new_focus);
                 self.handle_click_counting();
             }
         }
 
+        self.update_ime_for_focus(window);
+        self.update_virtual_keyboard(window);
+
+        match event {
+            WindowEvent::CursorMoved { .. } => {
+                let hovered = self.find_topmost_at_pos(self.input_state.mouse.cursor_pos);
+                self.update_hovered(hovered, window);
+            }
+            WindowEvent::CursorLeft { .. } => self.update_hovered(None, window),
+            WindowEvent::MouseInput { .. } => self.update_cursor_icon(window),
+            _ => {}
+        }
+
         if let WindowEvent::MouseWheel { .. } = event {
             let hovered = self.find_topmost_at_pos(self.input_state.mouse.cursor_pos);
             if let Some(hovered_widget) = hovered {
@@ -715,6 +1357,17 @@ impl Text {
             return;
         }
 
+        if let WindowEvent::KeyboardInput { event: key_event, is_synthetic, .. } = event {
+            if !self.should_handle_key_event(key_event.repeat, *is_synthetic) {
+                return;
+            }
+        }
+
+        // Clicking a linked label only focuses its edit, without moving the edit's cursor.
+        if label_clicked {
+            return;
+        }
+
         if let Some(focused) = self.focused {
             self.shared.event_consumed = true;
             self.handle_focused_event(focused, event, window);
@@ -728,17 +1381,61 @@ impl Text {
         }
     }
 
+    pub(crate) fn should_handle_key_event(&mut self, repeat: bool, is_synthetic: bool) -> bool {
+        if is_synthetic && !self.key_repeat.honor_synthetic {
+            return false;
+        }
+        if !repeat {
+            self.last_handled_repeat = None;
+            return true;
+        }
+        if !self.key_repeat.honor_repeats {
+            return false;
+        }
+        if let Some(throttle) = self.key_repeat.throttle {
+            let now = self.shared.now();
+            if let Some(last) = self.last_handled_repeat {
+                if now.duration_since(last) < throttle {
+                    return false;
+                }
+            }
+            self.last_handled_repeat = Some(now);
+        }
+        true
+    }
+
+    /// Set the function used to get the current time, for cursor blinking, multi-click detection, key repeat throttling, scroll animations and the time of animated materials in the [`TextRenderer`]s that this `Text` is prepared into. The default is `Instant::now`.
+    /// 
+    /// Tests and replay systems can use this to drive time deterministically, for example with a function that returns a fixed base `Instant` plus an offset that the test controls.
+    /// 
+    /// Deadlines returned by functions like [`Text::next_redraw_deadline()`] are relative to this clock.
+    pub fn set_time_source(&mut self, time_source: fn() -> Instant) {
+        self.shared.time_source = time_source;
+        // Times taken from the previous clock can't be compared with the new one.
+        self.input_state.mouse.last_click_info = None;
+        self.last_handled_repeat = None;
+        self.reset_cursor_blink();
+    }
+
+    /// Choose the keyboard shortcuts used for navigation and editing. By default, they're chosen based on the platform.
+    pub fn set_key_bindings(&mut self, key_bindings: KeyBindings) {
+        self.shared.key_bindings = key_bindings;
+    }
+
+    /// Configure how repeated and synthetic key presses are handled. See [`KeyRepeat`].
+    pub fn set_key_repeat(&mut self, key_repeat: KeyRepeat) {
+        self.key_repeat = key_repeat;
+    }
+
     #[cfg(feature = "accessibility")]
     fn get_accesskit_id(&mut self, i: AnyBox) -> Option<NodeId> {
         return match i {
             AnyBox::TextEdit(i) => {
-                let handle = TextEditHandle { i: i as u32 };
-                let text_edit = get_full_text_edit_free_function(&mut self.text_edits, &mut self.shared, &handle);
+                let text_edit = get_full_text_edit_free_function(&mut self.text_edits, &mut self.shared, i);
                 text_edit.accesskit_id()
             },
             AnyBox::TextBox(i) => {
-                let handle = TextBoxHandle { i: i as u32 };
-                let text_box = get_full_text_box_free_function(&mut self.text_boxes, &mut self.shared, &handle);
+                let text_box = get_full_text_box_free_function(&mut self.text_boxes, &mut self.shared, i);
                 text_box.accesskit_id()
             },
         }
@@ -776,8 +1473,15 @@ impl Text {
     /// 
     /// If the text box is occluded, this function should still be called with `None`, so that text boxes can defocus.
     pub fn handle_event_with_topmost(&mut self, event: &WindowEvent, window: &Window, topmost_text_box: Option<AnyBox>) {        
+        profile_span!("Text::handle_event_with_topmost");
+        self.record_event(event);
+        self.forget_static_label_focus();
         self.input_state.handle_event(event);
 
+        if let WindowEvent::Resized(size) = event {
+            self.handle_resize(*size);
+        }
+
         // update smooth scrolling animations
         if let WindowEvent::RedrawRequested = event {
             let animation_updated = self.update_smooth_scrolling();
@@ -786,16 +1490,34 @@ impl Text {
             }
         }
 
+        let mut label_clicked = false;
         if let WindowEvent::MouseInput { state, button, .. } = event {
             if state.is_pressed() && *button == MouseButton::Left {
-                if topmost_text_box.is_some() {
+                let mut new_focus = topmost_text_box;
+                // No hit testing happens here, so there are no hits to report.
+                self.trace_press(&[], new_focus);
+                if new_focus.is_some() {
                     self.shared.event_consumed = true;
                 }
-                self.refocus(topmost_text_box);
+                if let Some(edit) = new_focus.and_then(|target| self.label_focus_target(target)) {
+                    new_focus = Some(edit);
+                    label_clicked = true;
+                }
+                self.refocus(new_focus);
                 self.handle_click_counting();
             }
         }
 
+        self.update_ime_for_focus(window);
+        self.update_virtual_keyboard(window);
+
+        match event {
+            WindowEvent::CursorMoved { .. } => self.update_hovered(topmost_text_box, window),
+            WindowEvent::CursorLeft { .. } => self.update_hovered(None, window),
+            WindowEvent::MouseInput { .. } => self.update_cursor_icon(window),
+            _ => {}
+        }
+
         if let WindowEvent::MouseWheel { .. } = event {
             if let Some(hovered_widget) = topmost_text_box {
                 self.shared.event_consumed = true;
@@ -803,6 +1525,10 @@ impl Text {
             }
         }
 
+        if label_clicked {
+            return;
+        }
+
         if let Some(focused) = self.focused {
             self.shared.event_consumed = true;
             self.handle_focused_event(focused, event, window);
@@ -814,11 +1540,20 @@ impl Text {
 
         // Find all text widgets at this position
         for (i, (_text_edit, text_box)) in self.text_edits.iter_mut() {
+            let Some(cursor_pos) = self.shared.screen_to_box(text_box.group, cursor_pos) else {
+                continue;
+            };
             if !text_box.hidden && text_box.last_frame_touched == self.current_visibility_frame && text_box.hit_full_rect(cursor_pos) {
                 self.mouse_hit_stack.push((AnyBox::TextEdit(i as u32), text_box.depth));
             }
         }
         for (i, text_box) in self.text_boxes.iter_mut() {
+            if text_box.static_label {
+                continue;
+            }
+            let Some(cursor_pos) = self.shared.screen_to_box(text_box.group, cursor_pos) else {
+                continue;
+            };
             if !text_box.hidden && text_box.last_frame_touched == self.current_visibility_frame && text_box.hit_bounding_box(cursor_pos) {
                 self.mouse_hit_stack.push((AnyBox::TextBox(i as u32), text_box.depth));
             }
@@ -837,13 +1572,57 @@ impl Text {
         topmost
     }
 
+    /// Store the new screen size and reapply the [`RelativeWidth`]s and [`ScreenAnchor`]s that depend on it.
+    fn handle_resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        self.shared.screen_size = (size.width as f32, size.height as f32);
+        self.apply_screen_anchors();
+        self.shared.text_changed = true;
+    }
+
+    /// Drop the focus and hover of a box that was turned into a static label with [`TextBoxMut::set_static_label()`] while it had them.
+    pub(crate) fn forget_static_label_focus(&mut self) {
+        if let Some(AnyBox::TextBox(i)) = self.focused {
+            if self.text_boxes[i as usize].static_label {
+                self.lose_focus();
+            }
+        }
+        if let Some(AnyBox::TextBox(i)) = self.hovered {
+            if self.text_boxes[i as usize].static_label {
+                self.hovered = None;
+            }
+        }
+    }
+
+    /// Forget the focused box without the usual cleanup, because it was removed or disabled.
+    fn lose_focus(&mut self) {
+        if let (Some(callback), Some(from)) = (self.focus_tracing, self.focused) {
+            callback(&FocusTrace::FocusChanged { from: Some(from), to: None });
+        }
+        if self.focused.is_some() {
+            self.shared.ime_update_needed = true;
+            self.virtual_keyboard_update_needed = true;
+        }
+        self.focused = None;
+    }
+
+    fn trace_press(&self, hits: &[(AnyBox, f32)], topmost: Option<AnyBox>) {
+        if let Some(callback) = self.focus_tracing {
+            callback(&FocusTrace::Press { position: self.input_state.mouse.cursor_pos, hits, topmost });
+        }
+    }
+
     fn refocus(&mut self, new_focus: Option<AnyBox>) {
         let focus_changed = new_focus != self.focused;
         
         if focus_changed {
+            if let Some(callback) = self.focus_tracing {
+                callback(&FocusTrace::FocusChanged { from: self.focused, to: new_focus });
+            }
             if let Some(old_focus) = self.focused {
                 self.remove_focus(old_focus);
             }
+            self.shared.ime_update_needed = true;
+            self.virtual_keyboard_update_needed = true;
         }
 
         self.focused = new_focus;
@@ -861,8 +1640,196 @@ impl Text {
         }
     }
 
+    /// Forward the IME purpose of the focused text edit to the window. This is deferred to event handling because focus can also change in places where we don't have the window.
+    fn update_ime_for_focus(&mut self, window: &Window) {
+        if !std::mem::take(&mut self.shared.ime_update_needed) {
+            return;
+        }
+        let purpose = match self.focused {
+            Some(AnyBox::TextEdit(i)) => self.text_edits.get(i as usize).map(|(text_edit, _)| text_edit.ime_purpose),
+            _ => None,
+        };
+        match purpose {
+            Some(purpose) => {
+                purpose.apply(window);
+                self.ime_disabled_by_focus = !purpose.allows_ime();
+            }
+            None => {
+                // Don't leave IME disabled after a password field loses focus.
+                if std::mem::take(&mut self.ime_disabled_by_focus) {
+                    ImePurpose::Normal.apply(window);
+                }
+            }
+        }
+    }
+
+    /// The virtual keyboard request for the last focus change, if there is one. This is the part of `update_virtual_keyboard()` that doesn't need the window.
+    fn take_virtual_keyboard_request(&mut self) -> Option<VirtualKeyboardRequest> {
+        if !std::mem::take(&mut self.virtual_keyboard_update_needed) {
+            return None;
+        }
+        let request = match self.focused_text_edit_rect() {
+            Some(rect) => VirtualKeyboardRequest::Show { rect },
+            None if self.virtual_keyboard_shown => VirtualKeyboardRequest::Hide,
+            None => return None,
+        };
+        self.virtual_keyboard_shown = matches!(request, VirtualKeyboardRequest::Show { .. });
+        Some(request)
+    }
+
+    /// Show the virtual keyboard when a text edit gets focused, and hide it when it loses focus.
+    /// 
+    /// On Android, winit shows the soft keyboard when IME is allowed. On other platforms, the host has to do it in the callback set with [`Text::set_virtual_keyboard_callback()`].
+    fn update_virtual_keyboard(&mut self, #[allow(unused_variables)] window: &Window) {
+        let Some(request) = self.take_virtual_keyboard_request() else {
+            return;
+        };
+
+        #[cfg(target_os = "android")]
+        window.set_ime_allowed(self.virtual_keyboard_shown);
+
+        if let Some(callback) = self.virtual_keyboard_callback {
+            callback(request);
+        }
+    }
+
+    /// Returns the text box or text edit under the mouse cursor, as of the last `CursorMoved` event.
+    /// 
+    /// Like focus, hovering follows [`Text::handle_event_with_topmost()`] when it's used, so occluded boxes aren't hovered.
+    pub fn hovered(&self) -> Option<AnyBox> {
+        self.hovered
+    }
+
+    /// Returns the hovered box and the value of the meta range under the mouse cursor, if there is one. See [`TextBoxMut::set_meta_ranges()`].
+    /// 
+    /// This can be called after handling a `CursorMoved` event to show or hide a tooltip.
+    pub fn hovered_meta(&mut self) -> Option<(AnyBox, u64)> {
+        let hovered = self.hovered?;
+        let (x, y) = self.input_state.mouse.cursor_pos;
+        let meta = match hovered {
+            AnyBox::TextEdit(i) => {
+                let mut text_edit = self.get_full_text_edit_at(i);
+                text_edit.refresh_layout();
+                text_edit.meta_at_point(x, y)
+            }
+            AnyBox::TextBox(i) => {
+                let mut text_box = self.get_full_text_box_at(i);
+                text_box.refresh_layout();
+                text_box.meta_at_point(x, y)
+            }
+        };
+        meta.map(|meta| (hovered, meta))
+    }
+
+    /// Choose whether `Text` sets the window's cursor icon: a text cursor over selectable text boxes and enabled text edits, and the default arrow elsewhere. This is enabled by default.
+    /// 
+    /// Apps that manage the cursor icon themselves should disable this, and can use [`Text::hovered()`] to decide which icon to show.
+    pub fn set_manage_cursor_icon(&mut self, manage_cursor_icon: bool) {
+        self.manage_cursor_icon = manage_cursor_icon;
+    }
+
+    fn update_hovered(&mut self, hovered: Option<AnyBox>, window: &Window) {
+        self.hovered = hovered;
+        self.update_cursor_icon(window);
+    }
+
+    fn update_cursor_icon(&mut self, window: &Window) {
+        if !self.manage_cursor_icon {
+            return;
+        }
+        // While dragging a selection, the text cursor stays even if the mouse leaves the box.
+        let wants_text_cursor = if self.input_state.mouse.pointer_down && self.focused.is_some() {
+            self.focused.is_some_and(|focused| self.shows_text_cursor(focused))
+        } else {
+            self.hovered.is_some_and(|hovered| self.shows_text_cursor(hovered))
+        };
+
+        if wants_text_cursor != self.text_cursor_icon_shown {
+            self.text_cursor_icon_shown = wants_text_cursor;
+            window.set_cursor(if wants_text_cursor { CursorIcon::Text } else { CursorIcon::Default });
+        }
+    }
+
+    fn shows_text_cursor(&self, any_box: AnyBox) -> bool {
+        match any_box {
+            AnyBox::TextEdit(i) => self.text_edits.get(i as usize).is_some_and(|(text_edit, _)| !text_edit.disabled),
+            AnyBox::TextBox(i) => self.text_boxes.get(i as usize).is_some_and(|text_box| text_box.selectable),
+        }
+    }
+
+    /// Set a function that's called when a text edit gains or loses focus, so that the app can show or hide an on-screen keyboard on platforms where winit doesn't do it.
+    /// 
+    /// The callback is only called from [`Text::handle_event()`] and [`Text::handle_event_with_topmost()`]. If the focus is changed with [`Text::set_focus()`], it's called on the next event.
+    pub fn set_virtual_keyboard_callback(&mut self, callback: Option<fn(VirtualKeyboardRequest)>) {
+        self.virtual_keyboard_callback = callback;
+    }
+
+    /// Set a function that's called with every mouse press and every focus change, to find out why a box does or doesn't get focus. See [`FocusTrace`].
+    ///
+    /// ```ignore
+    /// text.set_focus_tracing(Some(|trace| println!("{trace:?}")));
+    /// ```
+    pub fn set_focus_tracing(&mut self, callback: Option<fn(&FocusTrace)>) {
+        self.focus_tracing = callback;
+    }
+
+    /// The boxes that were under the cursor the last time a hit test ran, with their depths. Hit tests run on mouse presses and cursor movement, and in [`Text::find_topmost_text_box()`].
+    pub fn last_hit_stack(&self) -> &[(AnyBox, f32)] {
+        &self.mouse_hit_stack
+    }
+
+    /// Set a function that's called when Escape is pressed in the focused text edit, with what it did. See [`EscapeOutcome`] and [`TextEditMut::set_clear_on_escape()`].
+    pub fn set_escape_callback(&mut self, callback: Option<fn(AnyBox, EscapeOutcome)>) {
+        self.escape_callback = callback;
+    }
+
+    /// Set an area of the screen that's covered by something else, like a virtual keyboard, in screen coordinates.
+    /// 
+    /// The focused text edit scrolls to keep the cursor above this area. Pass `None` when the area is gone.
+    pub fn set_obscured_area(&mut self, area: Option<parley::Rect>) {
+        self.shared.obscured_area = area;
+        if let Some(AnyBox::TextEdit(i)) = self.focused {
+            let did_scroll = self.get_full_text_edit_at(i).update_scroll_to_cursor();
+            if did_scroll {
+                self.decorations_changed = true;
+                self.scrolled_moved_indices.push(AnyBox::TextEdit(i));
+            }
+        }
+    }
+
+    /// Set the area of the screen where text is visible, in screen coordinates. Boxes that are entirely outside of it are skipped by [`Text::prepare_all()`], so they don't cost anything to draw.
+    /// 
+    /// This is useful for long documents made of many boxes, where only a few are on screen at a time. The transforms of groups are taken into account. Pass `None` to draw all boxes.
+    pub fn set_viewport(&mut self, viewport: Option<parley::Rect>) {
+        if self.viewport != viewport {
+            self.viewport = viewport;
+            self.shared.text_changed = true;
+        }
+    }
+
+    pub fn viewport(&self) -> Option<parley::Rect> {
+        self.viewport
+    }
+
+    /// Returns the area of the focused text edit in screen coordinates, or `None` if no text edit is focused.
+    /// 
+    /// Apps can use this to avoid covering the text edit with a virtual keyboard.
+    pub fn focused_text_edit_rect(&self) -> Option<parley::Rect> {
+        let Some(AnyBox::TextEdit(i)) = self.focused else {
+            return None;
+        };
+        let (_, text_box) = self.text_edits.get(i as usize)?;
+        let rect = parley::Rect {
+            x0: text_box.left,
+            y0: text_box.top,
+            x1: text_box.left + text_box.max_advance as f64,
+            y1: text_box.top + text_box.height as f64,
+        };
+        Some(self.shared.box_rect_to_screen(text_box.group, rect))
+    }
+
     fn handle_click_counting(&mut self) {
-        let now = Instant::now();
+        let now = self.shared.now();
         let current_pos = self.input_state.mouse.cursor_pos;
         
         if let Some(last_info) = self.input_state.mouse.last_click_info.take() {
@@ -893,14 +1860,13 @@ impl Text {
     fn remove_focus(&mut self, old_focus: AnyBox) {
         match old_focus {
             AnyBox::TextEdit(i) => {
-                let handle = TextEditHandle { i: i as u32 };
-                let mut text_edit = self.get_full_text_edit(&handle);
+                let mut text_edit = self.get_full_text_edit_at(i);
+                text_edit.interrupt_composition();
                 text_edit.text_box.reset_selection();
                 text_edit.inner.show_cursor = false;
             },
             AnyBox::TextBox(i) => {
-                let handle = TextBoxHandle { i: i as u32 };
-                let mut text_box = self.get_full_text_box(&handle);
+                let mut text_box = self.get_full_text_box_at(i);
                 text_box.reset_selection();
             },
         }
@@ -911,25 +1877,70 @@ impl Text {
         if let WindowEvent::MouseWheel { .. } = event {
             match hovered {
                 AnyBox::TextEdit(i) => {
-                    let handle = TextEditHandle { i: i as u32 };
-                    let did_scroll = self.handle_text_edit_scroll_event(&handle, event, window);
+                    let did_scroll = self.handle_text_edit_scroll_event(i, event, window);
                     if did_scroll {
                         self.decorations_changed = true;
                         self.scrolled_moved_indices.push(AnyBox::TextEdit(i));
                     }
                 },
-                AnyBox::TextBox(_) => {}
+                AnyBox::TextBox(i) => {
+                    let did_scroll = self.handle_text_box_scroll_event(i, event);
+                    if did_scroll {
+                        self.decorations_changed = true;
+                        self.scrolled_moved_indices.push(AnyBox::TextBox(i));
+                    }
+                }
             }
         }
     }
 
     fn handle_focused_event(&mut self, focused: AnyBox, event: &WindowEvent, window: &Window) {
+        let key = match event {
+            WindowEvent::KeyboardInput { event: key_event, .. } => Some(KeyInput::from_key_event(key_event)),
+            _ => None,
+        };
+        let action = match self.run_pre_input_layers(focused, Some(event), key.as_ref()) {
+            PreLayerOutcome::Consume => return,
+            PreLayerOutcome::Replace(action) => {
+                self.apply_edit_action(focused, &action);
+                Some(action)
+            }
+            PreLayerOutcome::Continue(_) if self.handle_modal_key_if_enabled(focused, key.as_ref()) => None,
+            PreLayerOutcome::Continue(_) => {
+                self.shared.performed_action = None;
+                self.handle_focused_event_builtin(focused, event, window);
+                self.shared.performed_action.take()
+            }
+        };
+        self.after_focused_event(focused);
+        self.run_post_input_layers(focused, Some(event), key.as_ref(), action);
+    }
+
+    fn handle_focused_event_builtin(&mut self, focused: AnyBox, event: &WindowEvent, window: &Window) {
+        // Boxes in a transformed group see the mouse in their own coordinates.
+        let transformed = self.transformed_input(self.any_box_group(focused), event);
+        let (event, input_state) = match &transformed {
+            Some((event, input_state)) => (event, input_state),
+            None => (event, &self.input_state),
+        };
+
         match focused {
             AnyBox::TextEdit(i) => {
-                let handle = TextEditHandle { i: i as u32 };
-                let mut text_edit = get_full_text_edit_free_function(&mut self.text_edits, &mut self.shared, &handle);
+                let mut text_edit = get_full_text_edit_free_function(&mut self.text_edits, &mut self.shared, i);
+
+                text_edit.handle_event(event, window, input_state);
+            },
+            AnyBox::TextBox(i) => {
+                let mut text_box = get_full_text_box_free_function(&mut self.text_boxes, &mut self.shared, i);
+
+                text_box.handle_event(event, window, input_state);
+            },
+        }
+    }
 
-                text_edit.handle_event(event, window, &self.input_state);
+    pub(crate) fn after_focused_event(&mut self, focused: AnyBox) {
+        match focused {
+            AnyBox::TextEdit(_) => {
                 if self.shared.text_changed {
                     self.reset_cursor_blink();
                 }
@@ -937,43 +1948,70 @@ impl Text {
                     self.decorations_changed = true;
                     self.reset_cursor_blink();
                 }
-                if !self.shared.text_changed && self.shared.scrolled {
-                    self.scrolled_moved_indices.push(AnyBox::TextEdit(i));
-                }
             },
-            AnyBox::TextBox(i) => {
-                let handle = TextBoxHandle { i: i as u32 };
-                let mut text_box = get_full_text_box_free_function(&mut self.text_boxes, &mut self.shared, &handle);
-
-                text_box.handle_event(event, window, &self.input_state);
+            AnyBox::TextBox(_) => {
                 if self.shared.decorations_changed {
                     self.decorations_changed = true;
                 }
-                if !self.shared.text_changed && self.shared.scrolled {
-                    self.scrolled_moved_indices.push(AnyBox::TextBox(i));
-                }
             },
         }
+        if !self.shared.text_changed && self.shared.scrolled {
+            self.scrolled_moved_indices.push(focused);
+        }
+        if let Some(outcome) = self.shared.escape_outcome.take() {
+            if let Some(callback) = self.escape_callback {
+                callback(focused, outcome);
+            }
+        }
     }
 
     /// Set the disabled state of a text edit box.
     /// 
     /// When disabled, the text edit will not respond to events and will be rendered with greyed out text.
     pub fn set_text_edit_disabled(&mut self, handle: &TextEditHandle, disabled: bool) {
+        self.check_handle(handle);
         let text_edit_inner = &mut self.text_edits[handle.i as usize].0;
         text_edit_inner.disabled = disabled;
         if disabled {
             if let Some(AnyBox::TextEdit(e)) = self.focused {
                 if e == handle.i {
-                    self.get_full_text_edit(&handle).text_box.reset_selection();
-                    self.focused = None;
+                    self.get_full_text_edit(handle).text_box.reset_selection();
+                    self.lose_focus();
                 }
             }
         }
 
     }
 
-    /// Returns whether any text was changed in the last frame.
+    /// Add an overlay rect to a text box or text edit.
+    /// 
+    /// Overlay rects are drawn in the same pass as selections and cursors, so they can be used for search match highlights, diff gutters, collaborative cursors and similar things without setting up another pipeline.
+    /// 
+    /// `rect` is in the same coordinates as the box's layout, so it scrolls together with the text.
+    pub fn add_overlay_rect<T: IntoAnyBox>(&mut self, handle: &T, rect: Rect, color: ColorBrush, kind: DecorationKind) {
+        self.check_handle(handle);
+        let overlay = OverlayRect { rect, color, kind };
+        match handle.into_anybox() {
+            AnyBox::TextEdit(i) => self.text_edits[i as usize].1.overlay_rects.push(overlay),
+            AnyBox::TextBox(i) => self.text_boxes[i as usize].overlay_rects.push(overlay),
+        }
+        self.decorations_changed = true;
+        self.shared.decorations_changed = true;
+    }
+
+    /// Remove all overlay rects added to a box with [`Text::add_overlay_rect()`].
+    pub fn clear_overlay_rects<T: IntoAnyBox>(&mut self, handle: &T) {
+        self.check_handle(handle);
+        match handle.into_anybox() {
+            AnyBox::TextEdit(i) => self.text_edits[i as usize].1.overlay_rects.clear(),
+            AnyBox::TextBox(i) => self.text_boxes[i as usize].overlay_rects.clear(),
+        }
+        self.decorations_changed = true;
+        self.shared.decorations_changed = true;
+    }
+
+    /// Returns whether any text or layout was changed in the last frame.
+    #[deprecated(note = "this only covers one kind of change. Use `Text::need_rerender()` to decide whether to redraw, and `TextEdit::revision()` to track edits to the content.")]
     pub fn get_text_changed(&self) -> bool {
         self.shared.text_changed
     }
@@ -992,7 +2030,59 @@ impl Text {
 
     pub fn need_rerender(&mut self) -> bool {
         let (_, blink_changed) = self.cursor_blinked_out(true);
-        self.shared.text_changed || self.shared.decorations_changed || self.shared.scrolled || blink_changed
+        self.shared.text_changed || self.shared.decorations_changed || self.shared.scrolled || self.shared.group_transforms_changed || self.shared.glyph_animations_running || blink_changed || self.layouts_pending()
+    }
+
+    /// Returns a human-readable description of every text box and text edit, with their geometry, style, dirty flags and visibility.
+    /// 
+    /// This is meant for debugging, for example to find out why a box isn't being drawn. The format isn't stable.
+    pub fn debug_dump(&self) -> String {
+        use std::fmt::Write;
+
+        let mut out = String::new();
+        writeln!(out, "Text: frame {}, focused {:?}, {} text edits, {} text boxes, {} styles",
+            self.current_visibility_frame, self.focused, self.text_edits.len(), self.text_boxes.len(), self.shared.styles.len()
+        ).ok();
+        writeln!(out, "  dirty: text_changed {}, decorations_changed {}, scrolled {}, layouts_pending {}",
+            self.shared.text_changed, self.shared.decorations_changed || self.decorations_changed, self.shared.scrolled, self.layouts_pending()
+        ).ok();
+
+        for (i, (text_edit, text_box)) in self.text_edits.iter() {
+            let any_box = AnyBox::TextEdit(i as u32);
+            self.debug_dump_box(&mut out, any_box, text_box);
+            writeln!(out, "    single_line {}, disabled {}, showing_placeholder {}, composing {}",
+                text_edit.single_line, text_edit.disabled, text_edit.showing_placeholder, text_edit.compose.is_some()
+            ).ok();
+        }
+        for (i, text_box) in self.text_boxes.iter() {
+            self.debug_dump_box(&mut out, AnyBox::TextBox(i as u32), text_box);
+        }
+        out
+    }
+
+    fn debug_dump_box(&self, out: &mut String, any_box: AnyBox, text_box: &TextBoxInner) {
+        use std::fmt::Write;
+
+        let focused = if self.focused == Some(any_box) { " (focused)" } else { "" };
+        let visible = text_box.last_frame_touched == self.current_visibility_frame;
+        let style_stale = self.shared.styles.get(text_box.style.i as usize)
+            .map(|style| style.version != text_box.style_version)
+            .unwrap_or(true);
+
+        const PREVIEW_LEN: usize = 40;
+        let preview_end = floor_char_boundary(&text_box.text, PREVIEW_LEN.min(text_box.text.len()));
+        let ellipsis = if preview_end < text_box.text.len() { "..." } else { "" };
+
+        writeln!(out, "  {:?}{}: {:?}{}", any_box, focused, &text_box.text[..preview_end], ellipsis).ok();
+        writeln!(out, "    pos ({}, {}), size ({}, {}), depth {}, scroll {:?}, clip {:?}",
+            text_box.left, text_box.top, text_box.width, text_box.height, text_box.depth, text_box.scroll_offset, text_box.clip_rect
+        ).ok();
+        writeln!(out, "    style {} (stale {}), needs_relayout {}, layout_pending {}",
+            text_box.style.i, style_stale, text_box.needs_relayout, text_box.pending_layout.is_some()
+        ).ok();
+        writeln!(out, "    last_frame_touched {} (visible {}), hidden {}, can_hide {}",
+            text_box.last_frame_touched, visible, text_box.hidden, text_box.can_hide
+        ).ok();
     }
 
     /// Get a mutable reference to a text box wrapped with its style.
@@ -1001,8 +2091,7 @@ impl Text {
     /// 
     /// This is a fast lookup operation that does not require any hashing.
     pub fn get_text_box_mut(&mut self, handle: &TextBoxHandle) -> TextBoxMut {
-        let text_box_inner = &mut self.text_boxes[handle.i as usize];
-        TextBoxMut { inner: text_box_inner, shared: &mut self.shared }
+        self.get_full_text_box(handle)
     }
 
     /// If we did it this way, we could return a real reference to the fake struct, instead of the fake struct. It would be a much better interface. We could get rid of the TextBox/TextBoxMut split and use normal mutability of reference, just like if we were returning a real reference to a real inner struct.
@@ -1015,6 +2104,7 @@ impl Text {
     #[allow(dead_code)]
     pub(crate) fn get_text_box_mut_but_epic<'a>(&'a mut self, handle: &TextBoxHandle) -> &'a mut TextBoxMut<'a> {
         // SAFETY: since this function borrows the whole Text struct, there's no way to call any functions that would invalidate the references.
+        self.check_handle(handle);
         unsafe {
             let text_box_inner = &mut self.text_boxes[handle.i as usize];
 
@@ -1042,31 +2132,66 @@ impl Text {
     /// 
     /// This is a fast lookup operation that does not require any hashing.
     pub fn get_text_box(&self, handle: &TextBoxHandle) -> TextBox {
+        self.check_handle(handle);
         let text_box_inner = &self.text_boxes[handle.i as usize];
         TextBox { inner: text_box_inner, shared: &self.shared }
     }
 
-    pub(crate) fn get_full_text_box(&mut self, i: &TextBoxHandle) -> TextBoxMut<'_> {
+    pub(crate) fn get_full_text_box(&mut self, handle: &TextBoxHandle) -> TextBoxMut<'_> {
+        self.check_handle(handle);
+        get_full_text_box_free_function(&mut self.text_boxes, &mut self.shared, handle.i)
+    }
+
+    pub(crate) fn get_full_text_edit(&mut self, handle: &TextEditHandle) -> TextEditMut<'_> {
+        self.check_handle(handle);
+        get_full_text_edit_free_function(&mut self.text_edits, &mut self.shared, handle.i)
+    }
+
+    /// Like [`Text::get_full_text_box()`], but for code that keeps the slot index of a box instead of its handle, like keyed boxes.
+    pub(crate) fn get_full_text_box_at(&mut self, i: u32) -> TextBoxMut<'_> {
         get_full_text_box_free_function(&mut self.text_boxes, &mut self.shared, i)
     }
 
-    pub(crate) fn get_full_text_edit(&mut self, i: &TextEditHandle) -> TextEditMut<'_> {
+    /// Like [`Text::get_full_text_edit()`], but for code that keeps the slot index of an edit instead of its handle, like the focus.
+    pub(crate) fn get_full_text_edit_at(&mut self, i: u32) -> TextEditMut<'_> {
         get_full_text_edit_free_function(&mut self.text_edits, &mut self.shared, i)
     }
 
+    /// Panic if `handle` is a strong handle whose box was removed, for example by [`Text::clear()`] or [`Text::remove_old_nodes()`], even if a newer box took its slot.
+    pub(crate) fn check_handle<T: IntoAnyBox>(&self, handle: &T) {
+        let Some(generation) = handle.generation() else {
+            return;
+        };
+        let any_box = handle.into_anybox();
+        let current = match any_box {
+            AnyBox::TextEdit(i) => self.text_edits.get(i as usize).map(|(_, text_box)| text_box.generation),
+            AnyBox::TextBox(i) => self.text_boxes.get(i as usize).map(|text_box| text_box.generation),
+        };
+        assert!(
+            current == Some(generation),
+            "Handle for {any_box:?} was used after its box was removed. \
+            Handles become invalid when Text::clear() or Text::remove_old_nodes() removes their box."
+        );
+    }
+
     /// Add a scroll animation for a text edit
-    pub(crate) fn add_scroll_animation(&mut self, handle: TextEditHandle, start_offset: f32, target_offset: f32, duration: std::time::Duration, direction: ScrollDirection) {
-        // Remove any existing animation for this handle and direction
-        self.scroll_animations.retain(|anim| !(anim.handle.i == handle.i && anim.direction == direction));
+    pub(crate) fn add_scroll_animation(&mut self, i: u32, start_offset: f32, target_offset: f32, duration: std::time::Duration, direction: ScrollDirection) {
+        self.add_scroll_animation_after(i, start_offset, target_offset, Duration::ZERO, duration, direction);
+    }
+
+    /// Like [`Text::add_scroll_animation()`], but the offset stays at `start_offset` until `delay` has passed.
+    pub(crate) fn add_scroll_animation_after(&mut self, i: u32, start_offset: f32, target_offset: f32, delay: Duration, duration: Duration, direction: ScrollDirection) {
+        // Remove any existing animation for this edit and direction
+        self.scroll_animations.retain(|anim| !(anim.i == i && anim.direction == direction));
         self.shared.scrolled = true;
         
         let animation = ScrollAnimation {
             start_offset,
             target_offset,
-            start_time: std::time::Instant::now(),
+            start_time: self.shared.now() + delay,
             duration,
             direction,
-            handle,
+            i,
         };
         
         self.scroll_animations.push(animation);
@@ -1074,14 +2199,13 @@ impl Text {
 
     /// Get the maximum remaining animation duration, if any animations are running.
     fn get_max_animation_duration(&self) -> Option<Duration> {
-        let now = Instant::now();
+        let now = self.shared.now();
         let mut max_remaining = Duration::ZERO;
         let mut has_animations = false;
         
         for animation in &self.scroll_animations {
-            let elapsed = now.duration_since(animation.start_time);
-            if elapsed < animation.duration {
-                let remaining = animation.duration - elapsed;
+            let remaining = (animation.start_time + animation.duration).saturating_duration_since(now);
+            if !remaining.is_zero() {
                 if remaining > max_remaining {
                     max_remaining = remaining;
                 }
@@ -1100,15 +2224,15 @@ impl Text {
     /// Returns true if any text edit animations were updated and require redrawing.
     fn update_smooth_scrolling(&mut self) -> bool {
         let mut needs_redraw = false;
+        let now = self.shared.now();
         
         // Update all active animations
         let mut i = 0;
         while i < self.scroll_animations.len() {
             let animation = &self.scroll_animations[i];
-            let handle = TextEditHandle { i: animation.handle.i };
             
-            if let Some((_text_edit_inner, text_box_inner)) = self.text_edits.get_mut(handle.i as usize) {
-                let current_offset = animation.get_current_offset();
+            if let Some((_text_edit_inner, text_box_inner)) = self.text_edits.get_mut(animation.i as usize) {
+                let current_offset = animation.get_current_offset_at(now);
                 
                 match animation.direction {
                     ScrollDirection::Horizontal => {
@@ -1119,7 +2243,7 @@ impl Text {
                     }
                 }
                 
-                if animation.is_finished() {
+                if animation.is_finished_at(now) {
                     self.scroll_animations.remove(i);
                     // Don't increment i since we removed an element
                 } else {
@@ -1136,45 +2260,68 @@ impl Text {
         needs_redraw
     }
 
-    fn handle_text_edit_scroll_event(&mut self, handle: &TextEditHandle, event: &WindowEvent, _window: &Window) -> bool {
+    /// Horizontal wheel scrolling for single-line text boxes with auto clip. Returns true if the box scrolled.
+    fn handle_text_box_scroll_event(&mut self, i: u32, event: &WindowEvent) -> bool {
+        let WindowEvent::MouseWheel { delta, .. } = event else {
+            return false;
+        };
+        let shift_held = self.input_state.modifiers.state().shift_key();
+        let Some(text_box_inner) = self.text_boxes.get_mut(i as usize) else {
+            return false;
+        };
+        if !text_box_inner.single_line || !text_box_inner.auto_clip {
+            return false;
+        }
+
+        let scroll_amount = horizontal_wheel_amount(delta, shift_held);
+        if scroll_amount == 0.0 {
+            return false;
+        }
+        let current_scroll = text_box_inner.scroll_offset.0;
+        let max_scroll = (text_box_inner.layout.full_width() - text_box_inner.max_advance).max(0.0).round();
+        let target_scroll = (current_scroll - scroll_amount).clamp(0.0, max_scroll).round();
+        if (target_scroll - current_scroll).abs() > 0.1 {
+            text_box_inner.scroll_offset.0 = target_scroll;
+            self.shared.scrolled = true;
+            return true;
+        }
+        false
+    }
+
+    fn handle_text_edit_scroll_event(&mut self, i: u32, event: &WindowEvent, _window: &Window) -> bool {
         let mut did_scroll = false;
 
-        if let WindowEvent::MouseWheel { delta, .. } = event {
+        if let WindowEvent::MouseWheel { delta, phase, .. } = event {
             let shift_held = self.input_state.modifiers.state().shift_key();
+
+            let zoom_held = self.shared.key_bindings.mods(self.input_state.modifiers.state()).action;
+            if zoom_held && self.text_edits.get(i as usize).is_some_and(|(text_edit, _)| text_edit.zoom_shortcuts) {
+                let steps = match delta {
+                    winit::event::MouseScrollDelta::LineDelta(_x, y) => *y,
+                    winit::event::MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 50.0,
+                };
+                if steps != 0.0 {
+                    self.get_full_text_edit_at(i).zoom_by(ZOOM_STEP.powf(steps));
+                }
+                return false;
+            }
             
-            if let Some((text_edit_inner, text_box_inner)) = self.text_edits.get_mut(handle.i as usize) {
+            if let Some((text_edit_inner, text_box_inner)) = self.text_edits.get_mut(i as usize) {
                 if text_edit_inner.single_line {
                     // Single-line horizontal scrolling
-                    let scroll_amount = match delta {
-                        winit::event::MouseScrollDelta::LineDelta(x, y) => {
-                            if shift_held {
-                                y * 120.0
-                            } else {
-                                x * 120.0
-                            }
-                        },
-                        winit::event::MouseScrollDelta::PixelDelta(pos) => {
-                            if shift_held {
-                                pos.y as f32 
-                            } else {
-                                pos.x as f32
-                            }
-                        },
-                    };
+                    let scroll_amount = horizontal_wheel_amount(delta, shift_held);
                     
                     if scroll_amount != 0.0 {
                         let current_scroll = text_box_inner.scroll_offset.0;
                         let target_scroll = current_scroll - scroll_amount;
                         
-                        let total_text_width = text_box_inner.layout.full_width();
-                        let text_width = text_box_inner.max_advance;
-                        let max_scroll = (total_text_width - text_width).max(0.0).round() + crate::text_edit::CURSOR_WIDTH;
+                        let max_scroll = max_horizontal_scroll(text_box_inner);
                         let clamped_target = target_scroll.clamp(0.0, max_scroll).round();
                         
                         if (clamped_target - current_scroll).abs() > 0.1 {
-                            if should_use_animation(delta, shift_held) {
+                            if should_use_animation(delta, shift_held) && !self.shared.system_settings.reduce_motion {
                                 let animation_duration = std::time::Duration::from_millis(200);
-                                self.add_scroll_animation(handle.clone(), current_scroll, clamped_target, animation_duration, ScrollDirection::Horizontal);
+                                self.add_scroll_animation(i, current_scroll, clamped_target, animation_duration, ScrollDirection::Horizontal);
                             } else {
                                 text_box_inner.scroll_offset.0 = clamped_target;
                             }
@@ -1188,19 +2335,36 @@ impl Text {
                         winit::event::MouseScrollDelta::PixelDelta(pos) => pos.y as f32,
                     };
                     
-                    if scroll_amount != 0.0 {
-                        let current_scroll = text_box_inner.scroll_offset.1;
+                    let current_scroll = text_box_inner.scroll_offset.1;
+                    let max_scroll = max_vertical_scroll(text_edit_inner, text_box_inner);
+                    let elastic = text_edit_inner.scroll_bounds.overscroll == Overscroll::Elastic
+                        && !should_use_animation(delta, true)
+                        && !self.shared.system_settings.reduce_motion;
+
+                    if elastic {
+                        let new_scroll = elastic_scroll(current_scroll, scroll_amount, max_scroll, text_box_inner.height);
+                        if (new_scroll - current_scroll).abs() > 0.1 {
+                            text_box_inner.scroll_offset.1 = new_scroll;
+                            did_scroll = true;
+                        }
+
+                        // Spring back once the gesture ends, or when it pauses on platforms that don't report the end.
+                        let clamped = new_scroll.clamp(0.0, max_scroll);
+                        if clamped != new_scroll {
+                            let delay = if *phase == TouchPhase::Ended { Duration::ZERO } else { ELASTIC_SPRING_BACK_DELAY };
+                            self.add_scroll_animation_after(i, new_scroll, clamped, delay, ELASTIC_SPRING_BACK_DURATION, ScrollDirection::Vertical);
+                            did_scroll = true;
+                        } else {
+                            self.scroll_animations.retain(|anim| !(anim.i == i && anim.direction == ScrollDirection::Vertical));
+                        }
+                    } else if scroll_amount != 0.0 {
                         let target_scroll = current_scroll - scroll_amount;
-                        
-                        let total_text_height = text_box_inner.layout.height();
-                        let text_height = text_box_inner.height;
-                        let max_scroll = (total_text_height - text_height).max(0.0).round();
                         let clamped_target = target_scroll.clamp(0.0, max_scroll).round();
                         
                         if (clamped_target - current_scroll).abs() > 0.1 {
-                            if should_use_animation(delta, true) {
+                            if should_use_animation(delta, true) && !self.shared.system_settings.reduce_motion {
                                 let animation_duration = std::time::Duration::from_millis(200);
-                                self.add_scroll_animation(handle.clone(), current_scroll, clamped_target, animation_duration, ScrollDirection::Vertical);
+                                self.add_scroll_animation(i, current_scroll, clamped_target, animation_duration, ScrollDirection::Vertical);
                             } else {
                                 text_box_inner.scroll_offset.1 = clamped_target;
                             }
@@ -1217,9 +2381,12 @@ impl Text {
     // result: (currently blinked, changed).
     pub(crate) fn cursor_blinked_out(&mut self, update: bool) -> (bool, bool) {
         if let Some(start_time) = self.cursor_blink_start {
-            let elapsed = Instant::now().duration_since(start_time);
-            let blink_period = Duration::from_millis(CURSOR_BLINK_TIME_MILLIS);
-            let blinked_out = (elapsed.as_millis() / blink_period.as_millis()) % 2 == 0;
+            let Some(blink_period) = self.shared.system_settings.cursor_blink_interval else {
+                // Blinking is disabled, so the cursor stays visible.
+                return (true, false);
+            };
+            let elapsed = self.shared.now().duration_since(start_time);
+            let blinked_out = (elapsed.as_nanos() / blink_period.as_nanos().max(1)) % 2 == 0;
             let changed = blinked_out != self.cursor_currently_blinked_out;
             if update {
                 self.cursor_currently_blinked_out = blinked_out;
@@ -1235,28 +2402,59 @@ impl Text {
     /// Returns `None` if cursor blinking should not be blinking.
     pub fn time_until_next_cursor_blink(&self) -> Option<Duration> {
         if let Some(start_time) = self.cursor_blink_start {
-            let elapsed = Instant::now().duration_since(start_time);
-            let blink_period = Duration::from_millis(CURSOR_BLINK_TIME_MILLIS);
-            let elapsed_in_current_cycle = elapsed.as_millis() % blink_period.as_millis();
-            let time_until_next_blink = blink_period.as_millis() - elapsed_in_current_cycle;
-            Some(Duration::from_millis(time_until_next_blink as u64))
+            let blink_period = self.shared.system_settings.cursor_blink_interval?;
+            let elapsed = self.shared.now().duration_since(start_time);
+            let blink_period = blink_period.as_nanos().max(1);
+            let elapsed_in_current_cycle = elapsed.as_nanos() % blink_period;
+            let time_until_next_blink = blink_period - elapsed_in_current_cycle;
+            Some(Duration::from_nanos(time_until_next_blink as u64))
         } else {
             None
         }
     }
 
+    /// Returns the next time when the text will need to be redrawn, taking into account cursor blinking, smooth scrolling animations and layouts that are being built in the background.
+    /// 
+    /// Apps using winit's `ControlFlow::WaitUntil` can pass this deadline directly instead of querying every source individually. Returns `None` if nothing is scheduled. A deadline in the past or equal to now means that the text should be redrawn as soon as possible, for example because an animation is running.
+    pub fn next_redraw_deadline(&self) -> Option<Instant> {
+        let now = self.shared.now();
+        let mut deadline: Option<Instant> = None;
+
+        if let Some(until_blink) = self.time_until_next_cursor_blink() {
+            deadline = earliest(deadline, now + until_blink);
+        }
+        if self.get_max_animation_duration().is_some() {
+            deadline = earliest(deadline, now);
+        }
+        if self.layouts_pending() {
+            deadline = earliest(deadline, now + Duration::from_millis(ASYNC_LAYOUT_POLL_INTERVAL_MILLIS));
+        }
+
+        deadline
+    }
+
     // If the cursor needs to be blinking, reset it. Otherwise, stop it.
-    fn reset_cursor_blink(&mut self) {
+    pub(crate) fn reset_cursor_blink(&mut self) {
+        if !self.window_focused {
+            self.cursor_blink_start = None;
+            if let Some(timer) = &self.cursor_blink_timer {
+                timer.stop_waker();
+            }
+            return;
+        }
+
         if let Some(AnyBox::TextEdit(i)) = self.focused {
-            let handle = TextEditHandle { i: i as u32 };
-            let text_edit = self.get_full_text_edit(&handle);
+            let text_edit = self.get_full_text_edit_at(i);
             if text_edit.text_box.selection().is_collapsed() {
                 
-                self.cursor_blink_start = Some(Instant::now());
+                self.cursor_blink_start = Some(self.shared.now());
                 self.decorations_changed = true;
                 
                 if let Some(timer) = &self.cursor_blink_timer {
-                    timer.start_waker();
+                    match self.shared.system_settings.cursor_blink_interval {
+                        Some(interval) => timer.start_waker(interval),
+                        None => timer.stop_waker(),
+                    }
                 }
 
                 return;             
@@ -1271,6 +2469,7 @@ impl Text {
     }
     
     pub fn set_focus<T: IntoAnyBox>(&mut self, handle: &T) {
+        self.check_handle(handle);
         let handle: AnyBox = (*handle).into_anybox();
         self.refocus(Some(handle));
     }
@@ -1278,6 +2477,7 @@ impl Text {
     /// Update the AccessKit node ID mapping for a text box
     #[cfg(feature = "accessibility")]
     pub fn set_text_box_accesskit_id(&mut self, handle: &TextBoxHandle, accesskit_id: NodeId) {
+        self.check_handle(handle);
         let any_box = handle.into_anybox();
         self.accesskit_id_to_text_handle_map.insert(accesskit_id, any_box);
         self.get_text_box_mut(handle).set_accesskit_id(accesskit_id);
@@ -1286,6 +2486,7 @@ impl Text {
     /// Update the AccessKit node ID mapping for a text edit
     #[cfg(feature = "accessibility")]
     pub fn set_text_edit_accesskit_id(&mut self, handle: &TextEditHandle, accesskit_id: NodeId) {
+        self.check_handle(handle);
         let any_box = handle.into_anybox();
         self.accesskit_id_to_text_handle_map.insert(accesskit_id, any_box);
         self.get_text_edit_mut(handle).set_accesskit_id(accesskit_id);
@@ -1325,6 +2526,87 @@ impl Text {
         self.shared.node_id_generator = generator;
     }
 
+    /// Build the layouts of texts longer than `threshold` bytes on a background thread.
+    /// 
+    /// When a box gets a long text for the first time, the first `threshold` bytes are laid out right away, so that the start of the text can be shown and the frame isn't blocked. When the text of a box that's already laid out changes, for example on every keystroke, the previous layout stays on screen instead. The full layout is swapped in during a later [`Text::prepare_all()`] once it's ready.
+    /// 
+    /// Only drawing uses the background layouts. Editing, event handling and functions that query the layout, like [`TextBoxMut::wrap_points()`], always work on a layout that matches the current text: they lay it out synchronously, or wait for the pending layout to arrive. Jobs that are replaced by a newer one before the worker gets to them are skipped.
+    /// 
+    /// Pass `None` to always lay out synchronously, which is the default.
+    pub fn set_async_layout_threshold(&mut self, threshold: Option<usize>) {
+        self.shared.async_layout_threshold = threshold;
+    }
+
+    /// Set a function that's called when a partial layout is shown and when a full layout built in the background is swapped in. See [`Text::set_async_layout_threshold()`].
+    pub fn set_layout_progress_callback(&mut self, callback: Option<fn(LayoutProgress)>) {
+        self.shared.layout_progress_callback = callback;
+    }
+
+    /// Returns true if any box is waiting for a layout that's being built in the background.
+    /// 
+    /// While this is true, [`Text::need_rerender()`] also returns true, so that the finished layouts get picked up.
+    pub fn layouts_pending(&self) -> bool {
+        self.text_boxes.iter().any(|(_, text_box)| text_box.pending_layout.is_some())
+            || self.text_edits.iter().any(|(_, (_, text_box))| text_box.pending_layout.is_some())
+    }
+
+    /// Like [`Text::prepare_all()`], but layouts that need to be rebuilt are built on a background thread instead of blocking the frame.
+    /// 
+    /// Until a box's new layout is ready, it keeps being drawn with its previous layout. Finished layouts are swapped in by the next call to `prepare_async()` or `prepare_all()`. The returned [`AsyncPrepare`] tells if any layouts are still pending, in which case another frame should be rendered later.
+    /// 
+    /// The focused text edit is always laid out synchronously, so that editing and cursor movement keep working on an up-to-date layout.
+    pub fn prepare_async(&mut self, text_renderer: &mut TextRenderer) -> AsyncPrepare {
+        let focused = self.focused;
+        for (i, text_box) in self.text_boxes.iter_mut() {
+            if text_box.hidden || focused == Some(AnyBox::TextBox(i as u32)) {
+                continue;
+            }
+            get_full_text_box_free_function_but_for_iterating(text_box, &mut self.shared).refresh_layout_in_background();
+        }
+        for (i, (text_edit, text_box)) in self.text_edits.iter_mut() {
+            if text_box.hidden || focused == Some(AnyBox::TextEdit(i as u32)) {
+                continue;
+            }
+            get_full_text_edit_free_function_but_for_iterating((text_edit, text_box), &mut self.shared).refresh_layout_in_background();
+        }
+
+        self.prepare_all(text_renderer);
+
+        let pending_layouts = self.text_boxes.iter().filter(|(_, text_box)| text_box.pending_layout.is_some()).count()
+            + self.text_edits.iter().filter(|(_, (_, text_box))| text_box.pending_layout.is_some()).count();
+        AsyncPrepare { pending_layouts }
+    }
+
+    pub(crate) fn poll_pending_layouts(&mut self) {
+        for (_, text_box) in self.text_boxes.iter_mut() {
+            if text_box.pending_layout.is_some() {
+                get_full_text_box_free_function_but_for_iterating(text_box, &mut self.shared).poll_pending_layout();
+            }
+        }
+        for (_, (text_edit, text_box)) in self.text_edits.iter_mut() {
+            if text_box.pending_layout.is_some() {
+                get_full_text_edit_free_function_but_for_iterating((text_edit, text_box), &mut self.shared).text_box.poll_pending_layout();
+            }
+        }
+    }
+
+    /// Set a filter that's applied to pasted text in all text edits, unless they have their own filter set with [`TextEditMut::set_paste_filter()`].
+    /// 
+    /// See [`PasteFilter`].
+    pub fn set_paste_filter(&mut self, filter: Option<PasteFilter>) {
+        self.shared.paste_filter = filter;
+    }
+
+    /// Set which consecutive edits are merged into a single undo step, for all text edits that don't have their own policy set with [`TextEditMut::set_history_merge_policy()`].
+    pub fn set_history_merge_policy(&mut self, policy: HistoryMergePolicy) {
+        self.shared.history_merge_policy = policy;
+    }
+
+    /// Returns false if the window lost keyboard focus, according to the last `WindowEvent::Focused` event passed to [`Text::handle_event()`].
+    pub fn window_focused(&self) -> bool {
+        self.window_focused
+    }
+
     pub fn focus(&self) -> Option<AnyBox> {
         self.focused
     }
@@ -1401,13 +2683,11 @@ impl Text {
     fn push_ak_update_for_focused(&mut self, focused: AnyBox) {
         match focused {
             AnyBox::TextEdit(i) => {
-                let handle = TextEditHandle { i };
-                let mut text_edit = self.get_text_edit_mut(&handle);
+                let mut text_edit = self.get_full_text_edit_at(i);
                 text_edit.push_accesskit_update_to_self();
             },
             AnyBox::TextBox(i) => {
-                let handle = TextBoxHandle { i };
-                let mut text_box = self.get_text_box_mut(&handle);
+                let mut text_box = self.get_full_text_box_at(i);
                 text_box.push_accesskit_update_to_self();
             },
         }
@@ -1418,22 +2698,26 @@ impl Text {
 pub(crate) fn get_full_text_box_free_function<'a>(
     text_boxes: &'a mut Slab<TextBoxInner>,
     shared: &'a mut Shared,
-    i: &TextBoxHandle,
+    i: u32,
 ) -> TextBoxMut<'a> {
-    let text_box_inner = &mut text_boxes[i.i as usize];
+    let text_box_inner = &mut text_boxes[i as usize];
     TextBoxMut { inner: text_box_inner, shared }
 }
 
 pub(crate) fn get_full_text_edit_free_function<'a>(
     text_edits: &'a mut Slab<(TextEditInner, TextBoxInner)>,
     shared: &'a mut Shared,
-    i: &TextEditHandle,
+    i: u32,
 ) -> TextEditMut<'a> {
-    let (text_edit_inner, text_box_inner) = text_edits.get_mut(i.i as usize).unwrap();
+    let (text_edit_inner, text_box_inner) = text_edits.get_mut(i as usize).unwrap();
     let text_box = TextBoxMut { inner: text_box_inner, shared };
     TextEditMut { inner: text_edit_inner, text_box }
 }
 
+fn earliest(deadline: Option<Instant>, candidate: Instant) -> Option<Instant> {
+    Some(deadline.map_or(candidate, |deadline| deadline.min(candidate)))
+}
+
 pub(crate) fn get_full_text_edit_free_function_but_for_iterating<'a>(
     text_edit: (&'a mut TextEditInner, &'a mut TextBoxInner),
     shared: &'a mut Shared,
@@ -1450,6 +2734,47 @@ pub(crate) fn get_full_text_box_free_function_but_for_iterating<'a>(
     TextBoxMut { inner: text_box_inner, shared }
 }
 
+impl Text {
+    /// Prepare the visible boxes from the highest depth to the lowest, for [`TextRenderer::set_depth_sorting()`].
+    fn prepare_back_to_front(&mut self, text_renderer: &mut TextRenderer) {
+        let depth_order = self.take_depth_order();
+        for &(any_box, _) in &depth_order {
+            match any_box {
+                AnyBox::TextEdit(i) => {
+                    let (text_edit, text_box) = &mut self.text_edits[i as usize];
+                    let mut text_edit = get_full_text_edit_free_function_but_for_iterating((text_edit, text_box), &mut self.shared);
+                    text_renderer.prepare_text_edit_layout(&mut text_edit);
+                }
+                AnyBox::TextBox(i) => {
+                    let mut text_box = get_full_text_box_free_function_but_for_iterating(&mut self.text_boxes[i as usize], &mut self.shared);
+                    text_renderer.prepare_text_box_layout(&mut text_box);
+                }
+            }
+        }
+        self.depth_order = depth_order;
+    }
+
+    /// The boxes that are drawn this frame, from the highest depth to the lowest. The buffer is borrowed from `self.depth_order`, and should be put back there after use.
+    pub(crate) fn take_depth_order(&mut self) -> Vec<(AnyBox, f32)> {
+        let current_frame = self.current_visibility_frame;
+        let mut depth_order = std::mem::take(&mut self.depth_order);
+        depth_order.clear();
+        for (i, (_, text_box)) in self.text_edits.iter() {
+            if !text_box.hidden && text_box.last_frame_touched == current_frame && !outside_viewport(self.viewport, &self.shared, text_box) {
+                depth_order.push((AnyBox::TextEdit(i as u32), text_box.depth));
+            }
+        }
+        for (i, text_box) in self.text_boxes.iter() {
+            if !text_box.hidden && text_box.last_frame_touched == current_frame && !outside_viewport(self.viewport, &self.shared, text_box) {
+                depth_order.push((AnyBox::TextBox(i as u32), text_box.depth));
+            }
+        }
+        // Stable, so that boxes at the same depth keep the usual order.
+        depth_order.sort_by(|a, b| b.1.total_cmp(&a.1));
+        depth_order
+    }
+}
+
 /// Move quads in atlas pages to reflect new scroll position
 fn move_quads_for_scroll(text_renderer: &mut TextRenderer, quad_storage: &mut QuadStorage, current_offset: (f32, f32)) {
     let delta_x = current_offset.0 - quad_storage.last_offset.0;
@@ -1490,12 +2815,11 @@ fn move_quads_for_scroll(text_renderer: &mut TextRenderer, quad_storage: &mut Qu
     quad_storage.last_offset.1 += delta_y_rounded;
 }
 
-// todo: get this from system settings.
-const CURSOR_BLINK_TIME_MILLIS: u64 = 500;
+const ASYNC_LAYOUT_POLL_INTERVAL_MILLIS: u64 = 16;
 
 #[derive(Debug)]
 enum WakerCommand {
-    Start,
+    Start(Duration),
     Stop,
     Exit,
 }
@@ -1516,14 +2840,14 @@ impl CursorBlinkWaker {
         let (command_sender, command_receiver) = mpsc::channel();
         
         thread::spawn(move || {
-            let mut is_running = false;
+            let mut running_interval = None;
             
             loop {
-                if is_running {
+                if let Some(interval) = running_interval {
                     // While running, wait for either a command or timeout
-                    match command_receiver.recv_timeout(Duration::from_millis(CURSOR_BLINK_TIME_MILLIS)) {
-                        Ok(WakerCommand::Start) => {}
-                        Ok(WakerCommand::Stop) => is_running = false,
+                    match command_receiver.recv_timeout(interval) {
+                        Ok(WakerCommand::Start(interval)) => running_interval = Some(interval),
+                        Ok(WakerCommand::Stop) => running_interval = None,
                         Ok(WakerCommand::Exit) => return,
                         Err(mpsc::RecvTimeoutError::Timeout) => {
                             // Timeout occurred, request redraw directly
@@ -1539,7 +2863,7 @@ impl CursorBlinkWaker {
                 } else {
                     // While stopped, wait indefinitely for a command
                     match command_receiver.recv() {
-                        Ok(WakerCommand::Start) => is_running = true,
+                        Ok(WakerCommand::Start(interval)) => running_interval = Some(interval),
                         Ok(WakerCommand::Stop) => {}
                         Ok(WakerCommand::Exit) => return,
                         Err(_) => return,
@@ -1553,11 +2877,90 @@ impl CursorBlinkWaker {
         }
     }
         
-    fn start_waker(&self) {
-        let _ = self.command_sender.send(WakerCommand::Start);
+    fn start_waker(&self, interval: Duration) {
+        let _ = self.command_sender.send(WakerCommand::Start(interval));
     }
     
     fn stop_waker(&self) {
         let _ = self.command_sender.send(WakerCommand::Stop);
     }
-}
\ No newline at end of file
+}
+
+/// How far a wheel event scrolls a single-line box horizontally. Shift turns a vertical wheel into a horizontal one.
+fn horizontal_wheel_amount(delta: &winit::event::MouseScrollDelta, shift_held: bool) -> f32 {
+    match delta {
+        winit::event::MouseScrollDelta::LineDelta(x, y) => {
+            if shift_held {
+                y * 120.0
+            } else {
+                x * 120.0
+            }
+        },
+        winit::event::MouseScrollDelta::PixelDelta(pos) => {
+            if shift_held {
+                pos.y as f32 
+            } else {
+                pos.x as f32
+            }
+        },
+    }
+}
+
+/// Whether a box is entirely outside the viewport set with [`Text::set_viewport()`], so that it can be skipped when preparing.
+pub(crate) fn outside_viewport(viewport: Option<parley::Rect>, shared: &Shared, text_box: &TextBoxInner) -> bool {
+    let Some(viewport) = viewport else {
+        return false;
+    };
+    // Without an up to date layout, we don't know how far the text reaches.
+    if text_box.needs_relayout {
+        return false;
+    }
+
+    let content_left = text_box.left - text_box.scroll_offset.0 as f64;
+    let content_top = text_box.top - text_box.scroll_offset.1 as f64;
+    let bounds = parley::Rect {
+        x0: text_box.left.min(content_left),
+        y0: text_box.top.min(content_top),
+        x1: (text_box.left + text_box.max_advance as f64).max(content_left + text_box.layout.full_width() as f64),
+        y1: (text_box.top + text_box.height as f64).max(content_top + text_box.layout.height() as f64),
+    };
+    let bounds = shared.box_rect_to_screen(text_box.group, bounds);
+
+    bounds.x1 <= viewport.x0 || bounds.x0 >= viewport.x1 || bounds.y1 <= viewport.y0 || bounds.y0 >= viewport.y1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removing_the_focused_text_edit_hides_the_virtual_keyboard() {
+        let mut text = Text::new_without_auto_wakeup();
+        let handle = text.add_text_edit("", (0.0, 0.0), (200.0, 40.0), 0.0);
+        text.set_focus(&handle);
+        assert!(matches!(text.take_virtual_keyboard_request(), Some(VirtualKeyboardRequest::Show { .. })));
+        text.shared.ime_update_needed = false;
+
+        text.remove_text_edit(handle);
+        assert!(matches!(text.take_virtual_keyboard_request(), Some(VirtualKeyboardRequest::Hide)));
+        assert!(text.shared.ime_update_needed);
+    }
+
+    #[test]
+    fn a_handle_from_before_clear_doesnt_reach_the_box_in_its_slot() {
+        let mut text = Text::new_without_auto_wakeup();
+        let stale = text.add_text_box("old", (0.0, 0.0), (200.0, 40.0), 0.0);
+        text.clear();
+        let new = text.add_text_box("new", (0.0, 0.0), (200.0, 40.0), 0.0);
+        assert_eq!(stale.i, new.i);
+        assert!(!text.is_alive(&stale));
+        assert!(text.is_alive(&new));
+
+        let lookup = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            text.get_text_box(&stale).text().to_string()
+        }));
+        assert!(lookup.is_err());
+        std::mem::forget(stale);
+        text.remove_text_box(new);
+    }
+}
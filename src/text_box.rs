@@ -13,9 +13,109 @@ use parley::{Affinity, Alignment, Selection};
 
 use crate::*;
 use smallvec::SmallVec;
+use unicode_segmentation::GraphemeCursor;
+use std::ops::Range;
 
 const X_TOLERANCE: f64 = 35.0;
 
+/// How an overlay rect added with [`Text::add_overlay_rect()`] is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecorationKind {
+    /// Fill the whole rect, like a selection highlight.
+    Fill,
+    /// Draw a thin bar along the bottom edge of the rect.
+    Underline,
+    /// Draw a thin border around the rect.
+    Outline,
+}
+
+/// An extra decoration rect drawn on top of a text box in the decorations pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlayRect {
+    /// The rect, in the same coordinates as the box's layout. It moves together with the text when the box scrolls.
+    pub rect: Rect,
+    pub color: ColorBrush,
+    pub kind: DecorationKind,
+}
+
+/// Controls how a range of text inside a text box is allowed to wrap.
+/// 
+/// Set with [`TextBoxMut::set_wrap_spans()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapSpan {
+    /// The range is kept on a single line and never broken, even if it's wider than the box. In that case it overflows. Useful for inline code spans and dates.
+    NonBreaking,
+    /// The range isn't wrapped at spaces, but it can still be broken according to the style's `overflow_wrap` if it doesn't fit on a line by itself.
+    NoWrap,
+}
+
+/// How text fades out near the edges of its clip rectangle, when fadeout clipping is enabled with [`TextBoxMut::set_fadeout_clipping()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FadeoutStyle {
+    /// Distance in pixels over which the text fades, from fully transparent at the edge to fully opaque.
+    pub length: f32,
+    /// Which edges of the clip rectangle fade. The other edges cut the text off sharply.
+    pub edges: FadeEdges,
+    pub curve: FadeCurve,
+}
+
+impl Default for FadeoutStyle {
+    fn default() -> Self {
+        Self { length: 15.0, edges: FadeEdges::ALL, curve: FadeCurve::Linear }
+    }
+}
+
+impl FadeoutStyle {
+    /// Pack the style into the `fade` field of the quads. Zero is the default style, so that quads that are never set still fade as before.
+    pub(crate) fn to_gpu(&self) -> u32 {
+        if *self == Self::default() {
+            return 0;
+        }
+        // Lengths round to at least one pixel, because zero means the default length.
+        let length = (self.length.round() as u32).clamp(1, 0xfff);
+        let disabled_edges = (!self.edges.left as u32)
+            | (!self.edges.right as u32) << 1
+            | (!self.edges.top as u32) << 2
+            | (!self.edges.bottom as u32) << 3;
+        length | disabled_edges << 12 | (self.curve as u32) << 16
+    }
+}
+
+/// The edges of a clip rectangle that fade, in a [`FadeoutStyle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FadeEdges {
+    pub left: bool,
+    pub right: bool,
+    pub top: bool,
+    pub bottom: bool,
+}
+
+impl FadeEdges {
+    pub const ALL: Self = Self { left: true, right: true, top: true, bottom: true };
+    pub const HORIZONTAL: Self = Self { left: true, right: true, top: false, bottom: false };
+    pub const VERTICAL: Self = Self { left: false, right: false, top: true, bottom: true };
+}
+
+/// How the opacity changes across the fade of a [`FadeoutStyle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    /// Starts and ends gently (smoothstep).
+    Smooth,
+    /// Stays transparent for longer near the edge.
+    EaseIn,
+    /// Becomes opaque quickly after the edge.
+    EaseOut,
+}
+
+/// How much of the text of a box is drawn. Set with [`TextBoxMut::set_revealed_clusters()`] or [`TextBoxMut::set_reveal_progress()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Reveal {
+    Clusters(usize),
+    Progress(f32),
+}
+
 pub(crate) struct TextBoxInner {
     pub(crate) text: Cow<'static, str>,
     pub(crate) style: StyleHandle,
@@ -28,10 +128,22 @@ pub(crate) struct TextBoxInner {
     pub(crate) accesskit_id: Option<accesskit::NodeId>,
 
     pub(crate) needs_relayout: bool,
+    /// The value of `Shared::frames_prepared` when the layout was last rebuilt, for the debug overlay.
+    pub(crate) relaid_out_at: u64,
     pub(crate) left: f64,
     pub(crate) top: f64,
     pub(crate) max_advance: f32,
     pub(crate) depth: f32,
+    pub(crate) group: Option<GroupHandle>,
+    /// Set by `set_position_baseline()`: the box is moved vertically after each relayout so that its first baseline stays at this y.
+    pub(crate) baseline_anchor: Option<f64>,
+    /// Set by `set_screen_anchor()`: the box is moved when the window is resized or the box size changes.
+    pub(crate) screen_anchor: Option<ScreenAnchor>,
+    /// Set by `set_relative_width()`: the width is recalculated when the window is resized.
+    pub(crate) relative_width: Option<RelativeWidth>,
+    pub(crate) zoom: f32,
+    /// Set when the box is added, so that weak handles can tell it apart from a later box in the same slot.
+    pub(crate) generation: u64,
     pub(crate) selection: SelectionState,
     pub(crate) width: f32,
     pub(crate) height: f32, 
@@ -39,10 +151,37 @@ pub(crate) struct TextBoxInner {
     pub(crate) scale: f32,
     pub(crate) clip_rect: Option<parley::Rect>,
     pub(crate) fadeout_clipping: bool,
+    /// `None` uses the default, or the [`TextEditStyle::fadeout`] for text edits.
+    pub(crate) fadeout_style: Option<FadeoutStyle>,
     pub(crate) auto_clip: bool,
+    /// Set by `TextBoxMut::set_single_line()`. Text edits use `TextEditInner::single_line` instead.
+    pub(crate) single_line: bool,
     pub(crate) scroll_offset: (f32, f32),
+    /// Set when the scroll offset changes without a full prepare, so that the next [`Text::prepare_all()`] moves the box's quads.
+    pub(crate) scroll_moved: bool,
     
     pub(crate) selectable: bool,
+    pub(crate) line_navigation: LineNavigation,
+    pub(crate) cluster_movement: ClusterMovement,
+    pub(crate) word_rules: Option<WordRules>,
+    pub(crate) column_selection: Option<ColumnSelection>,
+    /// Set by [`TextBoxMut::set_static_label()`].
+    pub(crate) static_label: bool,
+    /// The value of `selectable` before the box was turned into a static label, restored when it's turned back.
+    pub(crate) selectable_before_static: bool,
+
+    pub(crate) wrap_spans: Vec<(Range<usize>, WrapSpan)>,
+    pub(crate) inline_boxes: Vec<InlineBox>,
+    pub(crate) image_replacements: Vec<(Range<usize>, ImageId)>,
+    pub(crate) meta_ranges: Vec<(Range<usize>, u64)>,
+    pub(crate) material_ranges: Vec<(Range<usize>, MaterialId)>,
+    pub(crate) glyph_animation: Option<GlyphAnimationState>,
+    pub(crate) reveal: Option<Reveal>,
+    pub(crate) overlay_rects: Vec<OverlayRect>,
+    /// Set by [`Text::link_label()`].
+    pub(crate) label_link: Option<LabelLink>,
+    pub(crate) folds: Vec<Range<usize>>,
+    pub(crate) pending_layout: Option<PendingLayout>,
 
     pub(crate) hidden: bool,
     pub(crate) last_frame_touched: u64,
@@ -121,6 +260,17 @@ pub(crate) fn with_text_cx<R>(f: impl FnOnce(&mut LayoutContext<ColorBrush>, &mu
     res
 }
 
+/// Build an unwrapped layout for a small piece of text that isn't part of any box's text, like a remote cursor's name label.
+pub(crate) fn build_label_layout(text: &str, style: &TextStyle2) -> Layout<ColorBrush> {
+    with_text_cx(|layout_cx, font_cx| {
+        let mut builder = layout_cx.tree_builder(font_cx, 1.0, true, style);
+        builder.push_text(text);
+        let (mut layout, _) = builder.build();
+        layout.break_all_lines(None);
+        layout
+    })
+}
+
 thread_local! {
     static CLIPBOARD: RefCell<Clipboard> = RefCell::new(Clipboard::new().unwrap());
 }
@@ -140,6 +290,163 @@ pub(crate) fn original_default_style() -> TextStyle2 {
 }
 
 
+/// Which set of keyboard shortcuts is used for navigation and editing. Set with [`Text::set_key_bindings()`].
+/// 
+/// The default is chosen based on the platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyBindings {
+    /// Ctrl is used for shortcuts and word movement, and Alt for sub-word movement.
+    Standard,
+    /// Cmd is used for shortcuts, Cmd+Arrows move to line and document boundaries, Option+Arrows move by words, Ctrl+Option+Arrows move by sub-words, and Ctrl+A/Ctrl+E move to the start and end of the line.
+    MacOS,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        if cfg!(target_os = "macos") {
+            KeyBindings::MacOS
+        } else {
+            KeyBindings::Standard
+        }
+    }
+}
+
+/// The modifier keys that are currently held, interpreted according to the [`KeyBindings`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KeyMods {
+    pub(crate) shift: bool,
+    /// Modifier for shortcuts like copy and paste.
+    pub(crate) action: bool,
+    /// Modifier for moving and deleting by words.
+    pub(crate) word: bool,
+    /// Modifier for moving and deleting by sub-words, stopping at `camelCase` humps and underscores.
+    pub(crate) sub_word: bool,
+    /// Modifier for moving to line boundaries with Left/Right and document boundaries with Up/Down.
+    pub(crate) line: bool,
+    /// Ctrl on its own, used for the emacs-style bindings on macOS.
+    pub(crate) emacs: bool,
+}
+
+impl KeyBindings {
+    pub(crate) fn mods(self, state: winit::keyboard::ModifiersState) -> KeyMods {
+        match self {
+            KeyBindings::Standard => KeyMods {
+                shift: state.shift_key(),
+                action: state.control_key(),
+                word: state.control_key(),
+                sub_word: state.alt_key() && !state.control_key(),
+                line: false,
+                emacs: false,
+            },
+            KeyBindings::MacOS => KeyMods {
+                shift: state.shift_key(),
+                action: state.super_key(),
+                word: state.alt_key() && !state.control_key(),
+                sub_word: state.alt_key() && state.control_key() && !state.super_key(),
+                line: state.super_key(),
+                emacs: state.control_key() && !state.super_key() && !state.alt_key(),
+            },
+        }
+    }
+}
+
+/// Which lines Home/End and the Up/Down arrow keys move along. Set with [`TextEditMut::set_line_navigation()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineNavigation {
+    /// Move along the lines as they are displayed, including the ones created by soft wrapping.
+    #[default]
+    Visual,
+    /// Move along lines separated by hard line breaks, ignoring soft wrapping.
+    Logical,
+}
+
+/// What the Left/Right arrow keys, Backspace and Delete move over. Set with [`TextEditMut::set_cluster_movement()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClusterMovement {
+    /// Move over the clusters of the layout in visual order. Backspace deletes a single character, except for emoji and line breaks.
+    #[default]
+    Visual,
+    /// Move over extended grapheme clusters as defined by Unicode, in logical order, so that complex emoji like ZWJ sequences and letters with combining marks are always moved over and deleted in one step.
+    Grapheme,
+}
+
+/// A point where a line was soft-wrapped, as returned by [`TextBoxMut::wrap_points()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WrapPoint {
+    /// Byte offset where the continuation line starts.
+    pub index: usize,
+    /// Top of the continuation line, relative to the box position and accounting for scroll.
+    pub y: f32,
+    pub height: f32,
+}
+
+/// A laid out line of a box, as returned by [`TextBoxMut::lines()`].
+///
+/// Soft wrapped lines are reported separately, so these can be used to align gutters or markers with the lines as they are displayed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineInfo {
+    /// Byte range of the text in the line, including any trailing whitespace or newline.
+    pub text_range: Range<usize>,
+    /// Whether the line is the continuation of a soft wrapped one.
+    pub is_wrapped: bool,
+    /// Baseline of the line, relative to the box position and accounting for scroll.
+    pub baseline: f32,
+    pub height: f32,
+    /// Bounding rectangle of the line, relative to the box position and accounting for scroll.
+    pub rect: Rect,
+}
+
+/// A rectangular selection made by dragging with Alt held. The corners are in layout coordinates, so the covered lines are found again after every relayout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ColumnSelection {
+    pub(crate) anchor: (f32, f32),
+    pub(crate) focus: (f32, f32),
+}
+
+/// The part of each line that falls between the horizontal edges of a column selection, for the lines that it touches vertically. Lines shorter than the left edge get an empty range at their end.
+pub(crate) fn column_ranges(layout: &Layout<ColorBrush>, column: ColumnSelection) -> Vec<Range<usize>> {
+    let (x0, x1) = (column.anchor.0.min(column.focus.0), column.anchor.0.max(column.focus.0));
+    let (y0, y1) = (column.anchor.1.min(column.focus.1), column.anchor.1.max(column.focus.1));
+
+    let mut ranges = Vec::new();
+    for line in layout.lines() {
+        let geometry = Cursor::from_byte_index(layout, line.text_range().start, Affinity::Downstream).geometry(layout, 1.0);
+        if (geometry.y1 as f32) < y0 || (geometry.y0 as f32) > y1 {
+            continue;
+        }
+        let y = ((geometry.y0 + geometry.y1) / 2.0) as f32;
+        let start = Cursor::from_point(layout, x0, y).index();
+        let end = Cursor::from_point(layout, x1, y).index();
+        ranges.push(start.min(end)..start.max(end));
+    }
+    ranges
+}
+
+/// Whether a key keeps the column selection alive: typing, deleting, copying and cutting apply to every line of the column, and modifier keys on their own do nothing. Any other key clears the column selection first.
+pub(crate) fn is_column_selection_key(key: &KeyInput, mods: &KeyMods) -> bool {
+    if mods.action {
+        return matches!(&key.key_without_modifiers, Key::Character(c) if c == "c" || c == "x");
+    }
+    match &key.logical_key {
+        Key::Named(NamedKey::Shift | NamedKey::Alt | NamedKey::Control | NamedKey::Super | NamedKey::Meta) => true,
+        Key::Named(NamedKey::Backspace | NamedKey::Delete | NamedKey::Space) => true,
+        Key::Character(_) => !mods.emacs,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LogicalMovement {
+    LineStart,
+    LineEnd,
+    Up,
+    Down,
+    PreviousGrapheme,
+    NextGrapheme,
+    PreviousWord(WordRules),
+    NextWord(WordRules),
+}
+
 // todo: this struct is now useless.
 pub(crate) struct SelectionState {
     pub selection: Selection,
@@ -154,6 +461,49 @@ impl SelectionState {
     fn shift_click_extension(&mut self, layout: &Layout<ColorBrush>, x: f32, y: f32) {
         self.selection = self.selection.shift_click_extension(layout, x, y);
     }
+
+    /// Move the cursor along logical lines, i.e. lines separated by hard line breaks. If `extend` is true, only the focus point is moved.
+    pub(crate) fn move_logical(&mut self, layout: &Layout<ColorBrush>, text: &str, movement: LogicalMovement, extend: bool) {
+        let index = self.selection.focus().index().min(text.len());
+        let line_start = |i: usize| text[..i].rfind('\n').map(|n| n + 1).unwrap_or(0);
+        let line_end = |i: usize| text[i..].find('\n').map(|n| i + n).unwrap_or(text.len());
+
+        let start = line_start(index);
+        let column = index - start;
+        let target = match movement {
+            LogicalMovement::LineStart => start,
+            LogicalMovement::LineEnd => line_end(index),
+            LogicalMovement::PreviousGrapheme => previous_grapheme_boundary(text, index),
+            LogicalMovement::NextGrapheme => next_grapheme_boundary(text, index),
+            LogicalMovement::PreviousWord(rules) => rules.previous_boundary(text, index, rules.sub_words),
+            LogicalMovement::NextWord(rules) => rules.next_boundary(text, index, rules.sub_words),
+            LogicalMovement::Up => {
+                if start == 0 {
+                    0
+                } else {
+                    let prev_start = line_start(start - 1);
+                    (prev_start + column).min(start - 1)
+                }
+            }
+            LogicalMovement::Down => {
+                let end = line_end(index);
+                if end == text.len() {
+                    end
+                } else {
+                    let next_start = end + 1;
+                    (next_start + column).min(line_end(next_start))
+                }
+            }
+        };
+        let target = floor_char_boundary(text, target);
+
+        let focus = Cursor::from_byte_index(layout, target, Affinity::Downstream);
+        self.selection = if extend {
+            Selection::new(self.selection.anchor(), focus)
+        } else {
+            focus.into()
+        };
+    }
 }
 
 impl TextBoxInner {
@@ -167,12 +517,36 @@ impl TextBoxInner {
             #[cfg(feature = "accessibility")]
             accesskit_id: None,
             selectable: true,
+            line_navigation: LineNavigation::Visual,
+            cluster_movement: ClusterMovement::Visual,
+            word_rules: None,
+            column_selection: None,
+            static_label: false,
+            selectable_before_static: true,
+            wrap_spans: Vec::new(),
+            inline_boxes: Vec::new(),
+            image_replacements: Vec::new(),
+            meta_ranges: Vec::new(),
+            material_ranges: Vec::new(),
+            glyph_animation: None,
+            reveal: None,
+            overlay_rects: Vec::new(),
+            label_link: None,
+            folds: Vec::new(),
+            pending_layout: None,
             needs_relayout: true,
+            relaid_out_at: 0,
             left: pos.0,
             top: pos.1,
             max_advance: size.0,
             height: size.1,
             depth,
+            group: None,
+            baseline_anchor: None,
+            screen_anchor: None,
+            relative_width: None,
+            zoom: 1.0,
+            generation: 0,
             selection: SelectionState::new(),
             style: DEFAULT_STYLE_HANDLE,
             width: size.0, 
@@ -180,8 +554,11 @@ impl TextBoxInner {
             scale: Default::default(),
             clip_rect: None,
             fadeout_clipping: false,
+            fadeout_style: None,
             auto_clip: false,
+            single_line: false,
             scroll_offset: (0.0, 0.0),
+            scroll_moved: false,
             hidden: false,
             last_frame_touched: 0,
             can_hide: false,
@@ -250,10 +627,27 @@ impl_for_textbox_and_textboxmut! {
         self.inner.hidden
     }
 
+    pub fn static_label(&self) -> bool {
+        self.inner.static_label
+    }
+
     pub fn depth(&self) -> f32 {
         self.inner.depth
     }
 
+    pub fn zoom(&self) -> f32 {
+        self.inner.zoom
+    }
+
+    /// Returns true if the text is still the `&'static str` that the box was created with, or that was set with [`TextBoxMut::set_static()`]. The text is only copied the first time it's changed, for example with [`TextBoxMut::text_mut()`].
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self.inner.text, Cow::Borrowed(_))
+    }
+
+    pub fn group(&self) -> Option<GroupHandle> {
+        self.inner.group
+    }
+
     pub fn text(self) -> &'a str {
         &self.inner.text
     }
@@ -270,10 +664,32 @@ impl_for_textbox_and_textboxmut! {
         self.inner.fadeout_clipping
     }
 
+    pub fn fadeout_style(&self) -> Option<FadeoutStyle> {
+        self.inner.fadeout_style
+    }
+
     pub fn auto_clip(&self) -> bool {
         self.inner.auto_clip
     }
 
+    /// Get the byte ranges covered by the column selection, one for each line from top to bottom. Returns an empty vector if there is no column selection.
+    /// 
+    /// A column selection is made by dragging with Alt held (Option on macOS). Lines that are shorter than the left edge of the column have an empty range at their end.
+    pub fn column_selection_ranges(&self) -> Vec<Range<usize>> {
+        match self.inner.column_selection {
+            Some(column) => column_ranges(&self.inner.layout, column),
+            None => Vec::new(),
+        }
+    }
+
+    /// Get the text in the column selection, with the part from each line joined by newlines, as it's copied to the clipboard.
+    pub fn column_selected_text(&self) -> Option<String> {
+        self.inner.column_selection?;
+        let ranges = self.column_selection_ranges();
+        let parts: Vec<&str> = ranges.iter().filter_map(|range| self.inner.text.get(range.clone())).collect();
+        Some(parts.join("\n"))
+    }
+
     pub fn selected_text(&self) -> Option<&str> {
         if !self.inner.selection.selection.is_collapsed() {
             self.inner.text.get(self.inner.selection.selection.text_range())
@@ -286,6 +702,19 @@ impl_for_textbox_and_textboxmut! {
         self.inner.selection.selection
     }
 
+    /// Returns the value of the meta range set with [`TextBoxMut::set_meta_ranges()`] whose text is drawn at the point (`x`, `y`), in window coordinates. If ranges overlap, the first one wins.
+    /// 
+    /// The layout should be up to date.
+    pub fn meta_at_point(&self, x: f64, y: f64) -> Option<u64> {
+        if self.inner.meta_ranges.is_empty() {
+            return None;
+        }
+        let (x, y) = self.shared.screen_to_box(self.inner.group, (x, y))?;
+        let x = x - self.inner.left + self.inner.scroll_offset.0 as f64;
+        let y = y - self.inner.top + self.inner.scroll_offset.1 as f64;
+        meta_at_layout_point(&self.inner.layout, &self.inner.text, &self.inner.meta_ranges, x, y)
+    }
+
     pub fn scroll_offset(&self) -> (f32, f32) {
         self.inner.scroll_offset
     }
@@ -341,6 +770,34 @@ impl_for_textbox_and_textboxmut! {
     pub fn selectable(&self) -> bool {
         self.inner.selectable
     }
+
+    pub fn wrap_spans(&self) -> &[(Range<usize>, WrapSpan)] {
+        &self.inner.wrap_spans
+    }
+
+    pub fn line_navigation(&self) -> LineNavigation {
+        self.inner.line_navigation
+    }
+
+    pub fn cluster_movement(&self) -> ClusterMovement {
+        self.inner.cluster_movement
+    }
+
+    pub fn word_rules(&self) -> Option<WordRules> {
+        self.inner.word_rules
+    }
+
+    pub fn inline_boxes(&self) -> &[InlineBox] {
+        &self.inner.inline_boxes
+    }
+
+    pub fn image_replacements(&self) -> &[(Range<usize>, ImageId)] {
+        &self.inner.image_replacements
+    }
+
+    pub fn overlay_rects(&self) -> &[OverlayRect] {
+        &self.inner.overlay_rects
+    }
 }
 
 impl<'a> TextBox<'a> {
@@ -385,10 +842,11 @@ impl<'a> TextBoxMut<'a> {
     }
 
     pub(crate) fn handle_event(&mut self, event: &WindowEvent, _window: &Window, input_state: &TextInputState) {
-        if self.inner.hidden {
+        if self.inner.hidden || self.inner.static_label {
             return;
         }
         
+        self.refresh_layout();
         let initial_selection = self.inner.selection.selection;
         
         let did_scroll = self.handle_event_no_edit(event, input_state, false);
@@ -396,6 +854,13 @@ impl<'a> TextBoxMut<'a> {
             self.shared.scrolled = true;
         }
 
+        // Text edits handle keys together with editing, in `TextEditMut::handle_key_input_editable()`.
+        if let WindowEvent::KeyboardInput { event, .. } = event {
+            if event.state.is_pressed() && self.inner.selectable {
+                self.handle_key_input(&KeyInput::from_key_event(event), input_state);
+            }
+        }
+
         // Handle mouse wheel scrolling for multi-line text boxes with auto_clip
         if let WindowEvent::MouseWheel { delta, .. } = event {
             if self.inner.auto_clip {
@@ -430,6 +895,89 @@ impl<'a> TextBoxMut<'a> {
         }
     }
 
+    /// Same as [`TextBoxMut::handle_event()`] for a key press that doesn't come from a `WindowEvent`.
+    pub(crate) fn handle_key_event(&mut self, key: &KeyInput, input_state: &TextInputState) {
+        if self.inner.hidden {
+            return;
+        }
+        if !self.inner.selectable {
+            self.reset_selection();
+            return;
+        }
+        self.refresh_layout();
+
+        let initial_selection = self.inner.selection.selection;
+        if key.pressed {
+            self.handle_key_input(key, input_state);
+        }
+
+        if selection_decorations_changed(initial_selection, self.inner.selection.selection, false, false, false) {
+            self.shared.decorations_changed = true;
+        }
+    }
+
+    /// Perform an [`EditAction`] as if the keys bound to it were pressed. Non-editable boxes only handle movements, [`EditAction::SelectAll`] and [`EditAction::Copy`].
+    pub fn apply_action(&mut self, action: &EditAction) {
+        if self.inner.hidden || !self.inner.selectable {
+            return;
+        }
+        self.refresh_layout();
+
+        let initial_selection = self.inner.selection.selection;
+        self.apply_action_no_edit(action);
+
+        if selection_decorations_changed(initial_selection, self.inner.selection.selection, false, false, false) {
+            self.shared.decorations_changed = true;
+        }
+    }
+
+    fn apply_action_no_edit(&mut self, action: &EditAction) {
+        match action {
+            EditAction::Move { motion, extend } => self.apply_motion(*motion, *extend),
+            EditAction::SelectAll => self.select_all(),
+            EditAction::Copy => self.copy_selection(),
+            _ => (),
+        }
+    }
+
+    pub(crate) fn apply_motion(&mut self, motion: Motion, extend: bool) {
+        if !extend {
+            match motion {
+                Motion::Left => self.move_left(),
+                Motion::Right => self.move_right(),
+                Motion::WordLeft => self.move_word_left(),
+                Motion::WordRight => self.move_word_right(),
+                Motion::SubWordLeft => self.move_sub_word_left(),
+                Motion::SubWordRight => self.move_sub_word_right(),
+                Motion::Up => self.move_up(),
+                Motion::Down => self.move_down(),
+                Motion::LineStart => self.move_to_line_start(),
+                Motion::LineEnd => self.move_to_line_end(),
+                Motion::TextStart => self.move_to_text_start(),
+                Motion::TextEnd => self.move_to_text_end(),
+            }
+            return;
+        }
+
+        let logical = self.inner.line_navigation == LineNavigation::Logical;
+        match motion {
+            Motion::Left => self.select_left(),
+            Motion::Right => self.select_right(),
+            Motion::WordLeft => self.select_word_left(),
+            Motion::WordRight => self.select_word_right(),
+            Motion::SubWordLeft => self.select_sub_word_left(),
+            Motion::SubWordRight => self.select_sub_word_right(),
+            Motion::Up if logical => self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::Up, true),
+            Motion::Up => self.inner.selection.select_up(&self.inner.layout),
+            Motion::Down if logical => self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::Down, true),
+            Motion::Down => self.inner.selection.select_down(&self.inner.layout),
+            Motion::LineStart => self.select_to_line_start(),
+            Motion::LineEnd => self.select_to_line_end(),
+            Motion::TextStart => self.inner.selection.select_to_text_start(&self.inner.layout),
+            Motion::TextEnd => self.inner.selection.select_to_text_end(&self.inner.layout),
+        }
+    }
+
     /// The output bool says if the text box scrolled as a result of a selection drag.
     pub(crate) fn handle_event_no_edit(&mut self, event: &WindowEvent, input_state: &TextInputState, enable_auto_scroll: bool) -> bool {
         if self.inner.hidden {
@@ -506,11 +1054,17 @@ impl<'a> TextBoxMut<'a> {
                         cursor_pos.0 - left + new_scroll_x,
                         cursor_pos.1 - top + new_scroll_y,
                     );
-                    self.inner.selection.extend_selection_to_point(
-                        &self.inner.layout,
-                        cursor_pos.0,
-                        cursor_pos.1,
-                    );
+                    if let Some(column) = &mut self.inner.column_selection {
+                        column.focus = cursor_pos;
+                        self.inner.selection.move_to_point(&self.inner.layout, cursor_pos.0, cursor_pos.1);
+                        self.shared.decorations_changed = true;
+                    } else {
+                        self.inner.selection.extend_selection_to_point(
+                            &self.inner.layout,
+                            cursor_pos.0,
+                            cursor_pos.1,
+                        );
+                    }
                 }
             }
             WindowEvent::MouseInput { state, button, .. } => {
@@ -522,9 +1076,10 @@ impl<'a> TextBoxMut<'a> {
                     );
 
                     if state.is_pressed() {
+                        self.clear_column_selection();
                         let click_count = input_state.mouse.click_count;
                         match click_count {
-                            2 => self.inner.selection.select_word_at_point(&self.inner.layout, cursor_pos.0, cursor_pos.1),
+                            2 => self.inner.selection.select_word_at_point(&self.inner.layout, &self.inner.text, self.inner.word_rules, cursor_pos.0, cursor_pos.1),
                             3 => self.inner.selection.select_line_at_point(&self.inner.layout, cursor_pos.0, cursor_pos.1),
                             _ => {
                                 if shift {
@@ -533,6 +1088,10 @@ impl<'a> TextBoxMut<'a> {
                                         cursor_pos.0,
                                         cursor_pos.1,
                                     )
+                                } else if input_state.modifiers.state().alt_key() {
+                                    // Start a column selection, which grows as the mouse is dragged.
+                                    self.inner.column_selection = Some(ColumnSelection { anchor: cursor_pos, focus: cursor_pos });
+                                    self.inner.selection.move_to_point(&self.inner.layout, cursor_pos.0, cursor_pos.1)
                                 } else {
                                     self.inner.selection.move_to_point(&self.inner.layout, cursor_pos.0, cursor_pos.1)
                                 }
@@ -541,88 +1100,66 @@ impl<'a> TextBoxMut<'a> {
                     }
                 }
             }
-            WindowEvent::KeyboardInput { event, .. } => {
-                if !event.state.is_pressed() {
-                    return did_scroll;
-                }
-                let mods_state = input_state.modifiers.state();
-                let shift = mods_state.shift_key();
-                let action_mod = if cfg!(target_os = "macos") {
-                    mods_state.super_key()
-                } else {
-                    mods_state.control_key()
-                };
-
-                if shift {
-                    match &event.logical_key {
-                        Key::Named(NamedKey::ArrowLeft) => {
-                            if action_mod {
-                                self.inner.selection.select_word_left(&self.inner.layout);
-                            } else {
-                                self.inner.selection.select_left(&self.inner.layout);
-                            }
-                        }
-                        Key::Named(NamedKey::ArrowRight) => {
-                            if action_mod {
-                                self.inner.selection.select_word_right(&self.inner.layout);
-                            } else {
-                                self.inner.selection.select_right(&self.inner.layout);
-                            }
-                        }
-                        Key::Named(NamedKey::ArrowUp) => {
-                            self.inner.selection.select_up(&self.inner.layout);
-                        }
-                        Key::Named(NamedKey::ArrowDown) => {
-                            self.inner.selection.select_down(&self.inner.layout);
-                        }
-                        Key::Named(NamedKey::Home) => {
-                            if action_mod {
-                                self.inner.selection.select_to_text_start(&self.inner.layout);
-                            } else {
-                                self.inner.selection.select_to_line_start(&self.inner.layout);
-                            }
-                        }
-                        Key::Named(NamedKey::End) => {
-                            if action_mod {
-                                self.inner.selection.select_to_text_end(&self.inner.layout);
-                            } else {
-                                self.inner.selection.select_to_line_end(&self.inner.layout);
-                            }
-                        }
-                        _ => (),
-                    }
-                }
-
-                #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
-                if action_mod {
-                    match event.key_without_modifiers() {
-                        Key::Character(c) => {
-                            match c.as_str() {
-                                "c" if !shift => {
-                                    with_clipboard(|cb| {
-                                        if let Some(text) = self.selected_text() {
-                                            cb.set_text(text.to_owned()).ok();
-                                        }
-                                    })
-                                }
-                                "a" => self.select_all(),
-                                _ => (),
-                            }
-                        }
-                        _ => (),
-                    };
-                }
-            }
             _ => {}
         }
         
         did_scroll
     }
 
+    /// Handle a key press for keyboard selection and copying. The caller checks that the box is visible and selectable.
+    pub(crate) fn handle_key_input(&mut self, key: &KeyInput, input_state: &TextInputState) {
+        let mods = self.shared.key_bindings.mods(input_state.modifiers.state());
+        if self.inner.column_selection.is_some() && !is_column_selection_key(key, &mods) {
+            self.clear_column_selection();
+        }
+
+        let action = match EditAction::from_key(key, input_state.modifiers.state(), self.shared.key_bindings) {
+            // Without a cursor, only movements that extend the selection do something.
+            Some(action @ EditAction::Move { extend: true, .. }) => action,
+            Some(action @ (EditAction::SelectAll | EditAction::Copy)) => action,
+            _ => return,
+        };
+        self.apply_action_no_edit(&action);
+        self.shared.performed_action = Some(action);
+    }
+
+    /// Copy the selected text to the clipboard, if there's a selection.
+    pub(crate) fn copy_selection(&self) {
+        if let Some(text) = self.column_selected_text() {
+            with_clipboard(|cb| {
+                cb.set_text(text).ok();
+            });
+            return;
+        }
+        if let Some(text) = self.selected_text() {
+            with_clipboard(|cb| {
+                cb.set_text(text.to_owned()).ok();
+            })
+        }
+    }
+
+    /// Remove the column selection, if there is one. The normal selection is left as it is.
+    pub fn clear_column_selection(&mut self) {
+        if self.inner.column_selection.take().is_some() {
+            self.shared.decorations_changed = true;
+        }
+    }
+
     pub(crate) fn reset_selection(&mut self) {
         self.set_selection(self.inner.selection.selection.collapse());
     }
 
+    /// Replace the text of the box. If it's the same as the current text, nothing is marked as changed and the layout is kept.
+    pub fn set_text(&mut self, text: impl Into<Cow<'static, str>>) {
+        let text = text.into();
+        if *self.inner.text != *text {
+            self.inner.text = text;
+            self.inner.needs_relayout = true;
+            self.shared.text_changed = true;
+        }
+    }
+
+    /// Get the text for editing in place. This always marks the box for relayout, even if the text isn't modified. To replace the text, [`TextBoxMut::set_text()`] avoids the relayout when the new text is the same.
     pub fn text_mut(&mut self) -> &mut String {
         self.inner.needs_relayout = true;
         self.shared.text_changed = true;
@@ -645,10 +1182,44 @@ impl<'a> TextBoxMut<'a> {
     }
 
     pub fn set_pos(&mut self, pos: (f64, f64)) {
+        if (self.inner.left, self.inner.top) == pos && self.inner.baseline_anchor.is_none() && self.inner.screen_anchor.is_none() {
+            return;
+        }
         (self.inner.left, self.inner.top) = pos;
+        self.inner.baseline_anchor = None;
+        self.inner.screen_anchor = None;
         self.shared.text_changed = true;
     }
 
+    /// Position the box so that the baseline of its first line is at `baseline_y`, instead of placing its top-left corner.
+    ///
+    /// The vertical position is updated whenever the layout changes, so the baseline stays in place when the text or the style change. Calling [`TextBoxMut::set_pos()`] goes back to top-left positioning.
+    pub fn set_position_baseline(&mut self, x: f64, baseline_y: f64) {
+        self.inner.left = x;
+        self.inner.baseline_anchor = Some(baseline_y);
+        self.inner.screen_anchor = None;
+        self.apply_baseline_anchor();
+        self.shared.text_changed = true;
+    }
+
+    /// Get the baseline of the first line, relative to the top of the box.
+    pub fn first_baseline(&mut self) -> f32 {
+        self.refresh_layout();
+        first_baseline(&self.inner.layout)
+    }
+
+    /// Get the baseline of the last line, relative to the top of the box.
+    pub fn last_baseline(&mut self) -> f32 {
+        self.refresh_layout();
+        self.inner.layout.lines().last().map(|line| line.metrics().baseline).unwrap_or(0.0)
+    }
+
+    pub(crate) fn apply_baseline_anchor(&mut self) {
+        if let Some(baseline_y) = self.inner.baseline_anchor {
+            self.inner.top = baseline_y - first_baseline(&self.inner.layout) as f64;
+        }
+    }
+
     pub fn can_hide(&self) -> bool {
         self.inner.can_hide
     }
@@ -670,10 +1241,46 @@ impl<'a> TextBoxMut<'a> {
     }
 
     pub fn set_depth(&mut self, depth: f32) {
+        if self.inner.depth == depth {
+            return;
+        }
         self.inner.depth = depth;
         self.shared.text_changed = true;
     }
 
+    /// Scale the font sizes of the box by `zoom`. The box keeps its size, and the text is wrapped again at the larger or smaller size.
+    ///
+    /// Unlike a group transform, this changes the layout, so the text stays sharp at any zoom.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        if self.inner.zoom != zoom {
+            self.inner.zoom = zoom;
+            self.inner.needs_relayout = true;
+            self.shared.text_changed = true;
+        }
+    }
+
+    fn high_contrast_text_color(&self) -> Option<ColorBrush> {
+        self.shared.system_settings.high_contrast.map(|palette| palette.text_color)
+    }
+
+    /// The scale passed to the layout builder: the global text scale, clamped by the style's font size limits, times the box zoom.
+    pub(crate) fn layout_scale(&self) -> f32 {
+        let style = &self.shared.styles[self.inner.style.i as usize];
+        let font_size = style.text_style.font_size;
+        let mut scale = self.shared.text_scale;
+        if font_size > 0.0 {
+            let (min, max) = style.font_size_limits;
+            scale = (font_size * scale).clamp(min, max) / font_size;
+        }
+        scale * self.inner.zoom
+    }
+
+    /// Put the box in a group created with [`Text::add_group()`], or remove it from its group with `None`.
+    pub fn set_group(&mut self, group: Option<GroupHandle>) {
+        self.inner.group = group;
+        self.shared.text_changed = true;
+    }
+
     pub fn set_clip_rect(&mut self, clip_rect: Option<parley::Rect>) {
         self.inner.clip_rect = clip_rect;
         self.shared.text_changed = true;
@@ -684,12 +1291,22 @@ impl<'a> TextBoxMut<'a> {
         self.shared.text_changed = true;
     }
 
+    /// Set the length, edges and curve of the fade used with [`TextBoxMut::set_fadeout_clipping()`], or go back to the default with `None`.
+    pub fn set_fadeout_style(&mut self, style: Option<FadeoutStyle>) {
+        self.inner.fadeout_style = style;
+        self.shared.text_changed = true;
+    }
+
     pub fn set_scroll_offset(&mut self, offset: (f32, f32)) {
         self.inner.scroll_offset = offset;
         self.shared.text_changed = true;
     }
 
     pub fn set_style(&mut self, style: &StyleHandle) {
+        // Setting the same style again, as declarative code does every frame, keeps the layout.
+        if self.inner.style.i == style.i && !self.style_version_changed() {
+            return;
+        }
         self.inner.style = style.sneak_clone();
         self.inner.style_version = self.style_version();
         self.inner.needs_relayout = true;
@@ -717,38 +1334,183 @@ impl<'a> TextBoxMut<'a> {
         &mut self,
         color_override: Option<ColorBrush>,
         single_line: bool,
+        allow_async: bool,
     ) {
-        with_text_cx(|layout_cx, font_cx| {
-            let mut builder = layout_cx.tree_builder(font_cx, 1.0, true, self.style());
+        profile_span!("rebuild_layout");
+        let start = std::time::Instant::now();
+        self.rebuild_layout_untimed(color_override, single_line, allow_async);
+        self.shared.perf_stats.layout_time += start.elapsed();
+        self.shared.perf_stats.boxes_relaid_out += 1;
+        self.inner.relaid_out_at = self.shared.frames_prepared;
+    }
+
+    fn rebuild_layout_untimed(&mut self, color_override: Option<ColorBrush>, single_line: bool, allow_async: bool) {
+        let color_override = self.high_contrast_text_color().or(color_override);
+        if let (Some(threshold), true) = (self.shared.async_layout_threshold, allow_async) {
+            if self.inner.text.len() > threshold {
+                self.rebuild_layout_async(color_override, single_line, threshold);
+                return;
+            }
+        }
+        self.inner.pending_layout = None;
 
-            if let Some(color_override) = color_override {
-                builder.push_style_modification_span(&[
-                    StyleProperty::Brush(color_override)
-                ]);
+        let scale = self.layout_scale();
+        let key = self.label_layout_key(color_override, single_line, scale);
+        let cached = key.as_ref().and_then(|key| self.shared.label_layouts.as_mut()?.get(key).cloned());
+        if let Some(layout) = cached {
+            self.inner.layout = layout;
+        } else {
+            let style = &self.shared.styles[self.inner.style.i as usize].text_style;
+            self.inner.layout = build_layout(
+                &self.inner.text,
+                style,
+                LayoutSpans::from_inner(&self.inner),
+                color_override,
+                single_line,
+                self.inner.max_advance,
+                self.inner.alignment,
+                scale,
+            );
+            if let (Some(key), Some(cache)) = (key, &mut self.shared.label_layouts) {
+                cache.put(key, self.inner.layout.clone());
             }
+        }
+        self.inner.needs_relayout = false;
+        self.apply_baseline_anchor();
 
-            builder.push_text(&self.inner.text);
+        // todo: does this do anything?
+        self.inner.selection.selection = self.inner.selection.selection.refresh(&self.inner.layout);
+    }
 
-            let (mut layout, _) = builder.build();
+    /// Returns the key for sharing the layout with identical labels, or `None` if label interning is disabled or the box can't share its layout.
+    ///
+    /// Only boxes that borrow their text and don't have any spans are shared.
+    fn label_layout_key(&self, color_override: Option<ColorBrush>, single_line: bool, scale: f32) -> Option<LabelLayoutKey> {
+        self.shared.label_layouts.as_ref()?;
+        let Cow::Borrowed(text) = self.inner.text else {
+            return None;
+        };
+        if !LayoutSpans::from_inner(&self.inner).is_empty() {
+            return None;
+        }
+        Some(LabelLayoutKey {
+            text,
+            style: self.inner.style.i,
+            style_version: self.style_version(),
+            max_advance: self.inner.max_advance.to_bits(),
+            alignment: self.inner.alignment as u8,
+            scale: scale.to_bits(),
+            single_line,
+            color_override: color_override.map(|color| color.0),
+        })
+    }
+
+    /// Send the full text to the layout worker thread. If the box doesn't have a layout yet, the first `threshold` bytes are laid out right away so that the start of the text can be shown. Otherwise, the previous layout stays on screen until the new one arrives.
+    fn rebuild_layout_async(&mut self, color_override: Option<ColorBrush>, single_line: bool, threshold: usize) {
+        let has_layout = self.inner.layout.lines().next().is_some();
+        if !has_layout {
+            let prefix_len = floor_char_boundary(&self.inner.text, threshold);
+            let scale = self.layout_scale();
+            let style = &self.shared.styles[self.inner.style.i as usize].text_style;
+            self.inner.layout = build_layout(
+                &self.inner.text[..prefix_len],
+                style,
+                LayoutSpans::from_inner(&self.inner),
+                color_override,
+                single_line,
+                self.inner.max_advance,
+                self.inner.alignment,
+                scale,
+            );
+            // The selection isn't refreshed against the partial layout, so that it's not clamped to the prefix. It's refreshed when the full layout is swapped in.
+            self.apply_baseline_anchor();
 
-            if ! single_line {
-                layout.break_all_lines(Some(self.inner.max_advance));
-                layout.align(
-                    Some(self.inner.max_advance),
-                    self.inner.alignment,
-                    AlignmentOptions::default(),
-                );
-            } else {
-                layout.break_all_lines(None);
+            if let Some(callback) = self.shared.layout_progress_callback {
+                callback(LayoutProgress { laid_out_bytes: prefix_len, total_bytes: self.inner.text.len() });
             }
+        }
 
-            self.inner.layout = layout;
-            self.inner.needs_relayout = false;
-            
-            // todo: does this do anything?
-            self.inner.selection.selection = self.inner.selection.selection.refresh(&self.inner.layout);
+        self.send_layout_job(color_override, single_line);
+    }
+
+    /// Send the full layout to the worker thread. The current layout stays as it is until the new one is swapped in by [`TextBoxMut::poll_pending_layout()`].
+    pub(crate) fn send_layout_job(&mut self, color_override: Option<ColorBrush>, single_line: bool) {
+        let color_override = self.high_contrast_text_color().or(color_override);
+        let job = LayoutJob {
+            text: self.inner.text.to_string(),
+            style: self.shared.styles[self.inner.style.i as usize].text_style.clone(),
+            spans: LayoutSpans::from_inner(&self.inner).to_owned_spans(),
+            color_override,
+            single_line,
+            max_advance: self.inner.max_advance,
+            alignment: self.inner.alignment,
+            scale: self.layout_scale(),
+        };
+        // Dropping the previous pending layout (if any) discards its result.
+        self.inner.pending_layout = Some(spawn_layout_job(job));
+        self.inner.needs_relayout = false;
+    }
 
-        });
+    /// Like `refresh_layout()`, but the layout is built on the worker thread.
+    pub(crate) fn refresh_layout_in_background(&mut self) -> bool {
+        self.poll_pending_layout();
+        if self.inner.needs_relayout || self.style_version_changed() {
+            if self.style_version_changed() {
+                self.inner.style_version = self.style_version();
+            }
+            self.send_layout_job(None, self.inner.single_line);
+            return true;
+        }
+        false
+    }
+
+    /// If a layout is being built in the background and it's ready, swap it in. Returns true if the layout changed.
+    pub(crate) fn poll_pending_layout(&mut self) -> bool {
+        let Some(pending) = &self.inner.pending_layout else {
+            return false;
+        };
+        let result = pending.try_take();
+        self.take_pending_result(result)
+    }
+
+    /// If a layout is being built in the background, block until it's ready and swap it in. If the worker went away instead, the box is marked for a synchronous relayout.
+    fn wait_for_pending_layout(&mut self) {
+        let Some(pending) = &self.inner.pending_layout else {
+            return;
+        };
+        let result = pending.wait();
+        if result.is_err() {
+            self.inner.needs_relayout = true;
+        }
+        self.take_pending_result(result);
+    }
+
+    fn take_pending_result(&mut self, result: Result<Option<Layout<ColorBrush>>, ()>) -> bool {
+        match result {
+            Ok(Some(layout)) => {
+                let total_bytes = self.inner.pending_layout.as_ref().map_or(0, |pending| pending.total_bytes);
+                self.inner.pending_layout = None;
+                self.inner.layout = layout;
+                self.inner.selection.selection = self.inner.selection.selection.refresh(&self.inner.layout);
+                self.apply_baseline_anchor();
+                self.shared.text_changed = true;
+
+                if let Some(callback) = self.shared.layout_progress_callback {
+                    callback(LayoutProgress { laid_out_bytes: total_bytes, total_bytes });
+                }
+                true
+            }
+            Ok(None) => false,
+            Err(()) => {
+                self.inner.pending_layout = None;
+                false
+            }
+        }
+    }
+
+    /// Returns true if the full layout for the current text is still being built in the background.
+    pub fn layout_pending(&self) -> bool {
+        self.inner.pending_layout.is_some()
     }
 
 
@@ -764,17 +1526,21 @@ impl<'a> TextBoxMut<'a> {
     pub fn set_static(&mut self, text: &'static str) {
         self.inner.needs_relayout = true;
         self.inner.text = Cow::Borrowed(text);
+        self.shared.text_changed = true;
     }
 
     /// Set the width of the layout.
     pub fn set_size(&mut self, size: (f32, f32)) {
+        self.inner.relative_width = None;
         let relayout = (self.inner.width != size.0) || (self.inner.height != size.1) || (self.inner.max_advance != size.0);
         self.inner.width = size.0;
         self.inner.height = size.1;
         self.inner.max_advance = size.0;
         if relayout {
             self.inner.needs_relayout = true;
+            self.shared.text_changed = true;
         }
+        self.apply_screen_anchor();
     }
 
     /// Set the alignment of the layout.
@@ -906,6 +1672,10 @@ impl<'a> TextBoxMut<'a> {
 
     /// Move the cursor to the start of the physical line.
     pub(crate) fn move_to_line_start(&mut self) {
+        if self.inner.line_navigation == LineNavigation::Logical {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::LineStart, false);
+            return;
+        }
         self.set_selection(self.inner.selection.selection.line_start(&self.inner.layout, false));
     }
 
@@ -920,21 +1690,56 @@ impl<'a> TextBoxMut<'a> {
 
     /// Move the cursor to the end of the physical line.
     pub(crate) fn move_to_line_end(&mut self) {
+        if self.inner.line_navigation == LineNavigation::Logical {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::LineEnd, false);
+            return;
+        }
         self.set_selection(self.inner.selection.selection.line_end(&self.inner.layout, false));
     }
 
+    /// Move the selection focus point to the start of the line, respecting the line navigation mode.
+    pub(crate) fn select_to_line_start(&mut self) {
+        if self.inner.line_navigation == LineNavigation::Logical {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::LineStart, true);
+        } else {
+            self.inner.selection.select_to_line_start(&self.inner.layout);
+        }
+    }
+
+    /// Move the selection focus point to the end of the line, respecting the line navigation mode.
+    pub(crate) fn select_to_line_end(&mut self) {
+        if self.inner.line_navigation == LineNavigation::Logical {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::LineEnd, true);
+        } else {
+            self.inner.selection.select_to_line_end(&self.inner.layout);
+        }
+    }
+
     /// Move up to the closest physical cluster boundary on the previous line, preserving the horizontal position for repeated movements.
     pub(crate) fn move_up(&mut self) {
+        if self.inner.line_navigation == LineNavigation::Logical {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::Up, false);
+            return;
+        }
         self.set_selection(self.inner.selection.selection.previous_line(&self.inner.layout, false));
     }
 
     /// Move down to the closest physical cluster boundary on the next line, preserving the horizontal position for repeated movements.
     pub(crate) fn move_down(&mut self) {
+        if self.inner.line_navigation == LineNavigation::Logical {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::Down, false);
+            return;
+        }
         self.set_selection(self.inner.selection.selection.next_line(&self.inner.layout, false));
     }
 
     /// Move to the next cluster left in visual order.
     pub(crate) fn move_left(&mut self) {
+        // A selection still collapses to its visual edge.
+        if self.inner.cluster_movement == ClusterMovement::Grapheme && self.inner.selection.selection.is_collapsed() {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::PreviousGrapheme, false);
+            return;
+        }
         self.set_selection(
             self.inner.selection
                 .selection
@@ -944,11 +1749,37 @@ impl<'a> TextBoxMut<'a> {
 
     /// Move to the next cluster right in visual order.
     pub(crate) fn move_right(&mut self) {
+        if self.inner.cluster_movement == ClusterMovement::Grapheme && self.inner.selection.selection.is_collapsed() {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::NextGrapheme, false);
+            return;
+        }
         self.set_selection(self.inner.selection.selection.next_visual(&self.inner.layout, false));
     }
 
-    /// Move to the next word boundary left.
+    /// Move the selection focus point one cluster left, or one grapheme back with [`ClusterMovement::Grapheme`].
+    pub(crate) fn select_left(&mut self) {
+        if self.inner.cluster_movement == ClusterMovement::Grapheme {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::PreviousGrapheme, true);
+        } else {
+            self.inner.selection.select_left(&self.inner.layout);
+        }
+    }
+
+    /// Move the selection focus point one cluster right, or one grapheme forward with [`ClusterMovement::Grapheme`].
+    pub(crate) fn select_right(&mut self) {
+        if self.inner.cluster_movement == ClusterMovement::Grapheme {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::NextGrapheme, true);
+        } else {
+            self.inner.selection.select_right(&self.inner.layout);
+        }
+    }
+
+    /// Move to the next word boundary left, or to the previous one in logical order with custom [`WordRules`].
     pub(crate) fn move_word_left(&mut self) {
+        if let Some(rules) = self.inner.word_rules {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::PreviousWord(rules), false);
+            return;
+        }
         self.set_selection(
             self.inner.selection
                 .selection
@@ -957,8 +1788,12 @@ impl<'a> TextBoxMut<'a> {
     }
 
 
-    /// Move to the next word boundary right.
+    /// Move to the next word boundary right, or to the next one in logical order with custom [`WordRules`].
     pub(crate) fn move_word_right(&mut self) {
+        if let Some(rules) = self.inner.word_rules {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::NextWord(rules), false);
+            return;
+        }
         self.set_selection(
             self.inner.selection
                 .selection
@@ -966,6 +1801,53 @@ impl<'a> TextBoxMut<'a> {
         );
     }
 
+    /// The word rules used for sub-word movement: the box's custom rules if it has any, with sub-words turned on.
+    pub(crate) fn sub_word_rules(&self) -> WordRules {
+        WordRules { sub_words: true, ..self.inner.word_rules.unwrap_or_default() }
+    }
+
+    /// Move to the previous sub-word boundary, stopping at case changes and underscores.
+    pub(crate) fn move_sub_word_left(&mut self) {
+        let rules = self.sub_word_rules();
+        self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::PreviousWord(rules), false);
+    }
+
+    /// Move to the next sub-word boundary, stopping at case changes and underscores.
+    pub(crate) fn move_sub_word_right(&mut self) {
+        let rules = self.sub_word_rules();
+        self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::NextWord(rules), false);
+    }
+
+    /// Move the selection focus point to the previous sub-word boundary.
+    pub(crate) fn select_sub_word_left(&mut self) {
+        let rules = self.sub_word_rules();
+        self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::PreviousWord(rules), true);
+    }
+
+    /// Move the selection focus point to the next sub-word boundary.
+    pub(crate) fn select_sub_word_right(&mut self) {
+        let rules = self.sub_word_rules();
+        self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::NextWord(rules), true);
+    }
+
+    /// Move the selection focus point to the next word boundary left.
+    pub(crate) fn select_word_left(&mut self) {
+        if let Some(rules) = self.inner.word_rules {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::PreviousWord(rules), true);
+        } else {
+            self.inner.selection.select_word_left(&self.inner.layout);
+        }
+    }
+
+    /// Move the selection focus point to the next word boundary right.
+    pub(crate) fn select_word_right(&mut self) {
+        if let Some(rules) = self.inner.word_rules {
+            self.inner.selection.move_logical(&self.inner.layout, &self.inner.text, LogicalMovement::NextWord(rules), true);
+        } else {
+            self.inner.selection.select_word_right(&self.inner.layout);
+        }
+    }
+
     /// Select the whole text.
     pub(crate) fn select_all(&mut self) {
         self.set_selection(
@@ -992,17 +1874,290 @@ impl<'a> TextBoxMut<'a> {
         &self.inner.layout
     }
 
+    /// Bring the layout up to date with the text. When this returns, the layout always matches the current text, so it's safe to use for editing and geometry queries.
     pub(crate) fn refresh_layout(&mut self) {
+        self.refresh_layout_with(None, self.inner.single_line, false);
+    }
+
+    /// Like `refresh_layout()`, but texts over the async layout threshold are laid out on the worker thread, and the previous layout is kept until the new one arrives. Only for drawing.
+    pub(crate) fn refresh_layout_for_drawing(&mut self) {
+        self.refresh_layout_with(None, self.inner.single_line, true);
+    }
+
+    pub(crate) fn refresh_layout_with(&mut self, color_override: Option<ColorBrush>, single_line: bool, allow_async: bool) {
+        // A pending layout for the current text is waited for, but one that's about to be replaced isn't.
+        if allow_async || self.inner.needs_relayout {
+            self.poll_pending_layout();
+        } else {
+            self.wait_for_pending_layout();
+        }
         if self.inner.needs_relayout || self.style_version_changed() {
             if self.style_version_changed() {
                 self.inner.style_version = self.style_version();
             }
-            self.rebuild_layout(None, false);
+            self.rebuild_layout(color_override, single_line, allow_async);
+        }
+    }
+
+    /// Keep the text on a single line instead of wrapping it at the box width. Explicit newlines still start new lines.
+    /// 
+    /// With [`TextBoxMut::set_auto_clip()`], text that doesn't fit can be scrolled horizontally with the mouse wheel, using shift+wheel on mice that only have a vertical wheel.
+    pub fn set_single_line(&mut self, single_line: bool) {
+        if self.inner.single_line != single_line {
+            self.inner.single_line = single_line;
+            self.inner.scroll_offset.0 = 0.0;
+            self.inner.needs_relayout = true;
+            self.shared.text_changed = true;
+        }
+    }
+
+    pub fn single_line(&self) -> bool {
+        self.inner.single_line
+    }
+
+    /// Turn the box into a static label: it can't be selected, hovered or focused, it doesn't receive events, and it's skipped by the per-frame checks for mouse hits, decorations and overlays.
+    /// 
+    /// This is meant for scenes with thousands of labels that are only ever drawn. The text, style and position can still be changed as usual. See also [`Text::add_static_label()`].
+    ///
+    /// If the box is focused or hovered, it loses focus and hover at the next event. Turning the static label mode off again restores the previous [`TextBoxMut::set_selectable()`] value.
+    pub fn set_static_label(&mut self, static_label: bool) {
+        if self.inner.static_label == static_label {
+            return;
+        }
+        self.inner.static_label = static_label;
+        if static_label {
+            self.inner.selectable_before_static = self.inner.selectable;
+            self.inner.selectable = false;
+            self.inner.column_selection = None;
+            self.reset_selection();
+            self.shared.decorations_changed = true;
+        } else {
+            self.inner.selectable = self.inner.selectable_before_static;
         }
     }
 
     pub fn set_selectable(&mut self, selectable: bool) {
-        self.inner.selectable = selectable;
+        if self.inner.selectable != selectable {
+            self.inner.selectable = selectable;
+            self.shared.decorations_changed = true;
+        }
+    }
+
+    /// Use custom [`WordRules`] for word movement and double click selection, or `None` for the default Unicode word boundaries.
+    pub fn set_word_rules(&mut self, rules: Option<WordRules>) {
+        self.inner.word_rules = rules;
+    }
+
+    /// Mark byte ranges of the text as non-breaking or no-wrap.
+    /// 
+    /// The ranges are respected when the text is broken into lines. Ranges are in bytes and refer to the current text, so they need to be set again if the text changes.
+    pub fn set_wrap_spans(&mut self, spans: &[(Range<usize>, WrapSpan)]) {
+        self.inner.wrap_spans.clear();
+        self.inner.wrap_spans.extend_from_slice(spans);
+        self.inner.needs_relayout = true;
+        self.shared.text_changed = true;
+    }
+
+    /// Reserve inline rectangles inside the text flow.
+    /// 
+    /// Each [`InlineBox`] is placed at its byte `index` in the text, and takes up `width` x `height` pixels in the layout. Nothing is rendered for them: use [`TextBoxMut::inline_box_positions()`] to find out where they ended up, and draw icons, images or widgets there.
+    pub fn set_inline_boxes(&mut self, inline_boxes: &[InlineBox]) {
+        self.inner.inline_boxes.clear();
+        self.inner.inline_boxes.extend_from_slice(inline_boxes);
+        self.inner.needs_relayout = true;
+        self.shared.text_changed = true;
+    }
+
+    /// Replace byte ranges of the text with images registered with [`TextRenderer::register_image()`].
+    /// 
+    /// The text in each range still takes up space in the layout, but it's not drawn. Instead, the renderer draws the image at the start of the range, vertically centered on its line. This can be used for `:shortcode:` emoji, custom badges, and similar things.
+    /// 
+    /// Images are drawn at their original pixel size.
+    pub fn set_image_replacements(&mut self, replacements: &[(Range<usize>, ImageId)]) {
+        self.inner.image_replacements.clear();
+        self.inner.image_replacements.extend_from_slice(replacements);
+        self.inner.needs_relayout = true;
+        self.shared.text_changed = true;
+    }
+
+    /// Attach app-defined values to byte ranges of the text, for example to identify diagnostics, links or defined terms.
+    /// 
+    /// Nothing is drawn for them. Use [`TextBoxMut::meta_at_point()`] or [`Text::hovered_meta()`] to find out which value is under the mouse, for example to show a tooltip. Ranges refer to the current text, so they need to be set again if the text is replaced. In a [`TextEdit`], edits, undo and redo shift the ranges to keep them on the same text.
+    pub fn set_meta_ranges(&mut self, ranges: &[(Range<usize>, u64)]) {
+        self.inner.meta_ranges.clear();
+        self.inner.meta_ranges.extend_from_slice(ranges);
+    }
+
+    /// Draw only the first `count` clusters of the text, in logical order, or all of it with `None`.
+    /// 
+    /// The layout isn't rebuilt, so this can be called every frame to reveal dialogue text progressively. The hidden text still takes up its space, so the visible text doesn't move or rewrap as more of it appears.
+    pub fn set_revealed_clusters(&mut self, count: Option<usize>) {
+        self.set_reveal(count.map(Reveal::Clusters));
+    }
+
+    /// Draw only a fraction of the clusters of the text, from `0.0` for nothing to `1.0` for all of it. See [`TextBoxMut::set_revealed_clusters()`].
+    pub fn set_reveal_progress(&mut self, progress: f32) {
+        self.set_reveal(Some(Reveal::Progress(progress.clamp(0.0, 1.0))));
+    }
+
+    fn set_reveal(&mut self, reveal: Option<Reveal>) {
+        if self.inner.reveal != reveal {
+            self.inner.reveal = reveal;
+            self.shared.text_changed = true;
+        }
+    }
+
+    /// Number of clusters in the text, to use with [`TextBoxMut::set_revealed_clusters()`]. A cluster is the smallest piece of text that can be drawn on its own, like a character, or a letter and its accents.
+    pub fn cluster_count(&mut self) -> usize {
+        self.refresh_layout();
+        cluster_starts(&self.inner.layout).len()
+    }
+
+    /// The byte index where the hidden part of the text starts, or `None` if the whole text is drawn.
+    pub fn revealed_bytes(&mut self) -> Option<usize> {
+        self.refresh_layout();
+        revealed_byte_index(&self.inner.layout, self.inner.reveal, self.inner.text.len())
+    }
+
+    /// Get the points where the text was soft-wrapped, i.e. the start of every line that continues the previous one without a hard line break.
+    /// 
+    /// This can be used to mark continuation lines in a gutter.
+    pub fn wrap_points(&mut self) -> Vec<WrapPoint> {
+        self.refresh_layout();
+        let text: &str = &self.inner.text;
+        let layout = &self.inner.layout;
+        let scroll_y = self.inner.scroll_offset.1;
+
+        let mut points = Vec::new();
+        let mut prev_end = None;
+        for line in layout.lines() {
+            let range = line.text_range();
+            if let Some(prev_end) = prev_end {
+                if prev_end > 0 && !text[..prev_end].ends_with('\n') {
+                    let geometry = Cursor::from_byte_index(layout, range.start, Affinity::Downstream).geometry(layout, 1.0);
+                    points.push(WrapPoint {
+                        index: range.start,
+                        y: geometry.y0 as f32 - scroll_y,
+                        height: geometry.height() as f32,
+                    });
+                }
+            }
+            prev_end = Some(range.end);
+        }
+        points
+    }
+
+    pub fn line_count(&mut self) -> usize {
+        self.refresh_layout();
+        self.inner.layout.len()
+    }
+
+    /// Get the geometry of each laid out line, in display order.
+    pub fn lines(&mut self) -> Vec<LineInfo> {
+        self.refresh_layout();
+        let text: &str = &self.inner.text;
+        let layout = &self.inner.layout;
+        let (scroll_x, scroll_y) = self.inner.scroll_offset;
+
+        let mut lines = Vec::with_capacity(layout.len());
+        let mut prev_end = None;
+        for line in layout.lines() {
+            let range = line.text_range();
+            let metrics = line.metrics();
+            let is_wrapped = match prev_end {
+                Some(prev_end) => prev_end > 0 && !text[..prev_end].ends_with('\n'),
+                None => false,
+            };
+            prev_end = Some(range.end);
+
+            let geometry = Cursor::from_byte_index(layout, range.start, Affinity::Downstream).geometry(layout, 1.0);
+            let x0 = (metrics.offset - scroll_x) as f64;
+            let y0 = geometry.y0 - scroll_y as f64;
+            lines.push(LineInfo {
+                text_range: range,
+                is_wrapped,
+                baseline: metrics.baseline - scroll_y,
+                height: geometry.height() as f32,
+                rect: Rect { x0, y0, x1: x0 + metrics.advance as f64, y1: y0 + geometry.height() },
+            });
+        }
+        lines
+    }
+
+    /// Get the size of the laid out text, which can be larger than the box if the text overflows.
+    pub fn content_size(&mut self) -> (f32, f32) {
+        self.refresh_layout();
+        (self.inner.layout.full_width(), self.inner.layout.height())
+    }
+
+    /// Returns true if the laid out text doesn't fit in the box size, either horizontally or vertically.
+    pub fn is_overflowing(&mut self) -> bool {
+        let (width, height) = self.content_size();
+        // Allow for rounding in the layout.
+        width > self.inner.width + 0.5 || height > self.inner.height + 0.5
+    }
+
+    /// Get the byte range of the text that is at least partly visible, given the scroll offset and clipping.
+    ///
+    /// Lines that are only partly visible are included. If the box isn't clipped, the whole text is visible.
+    pub fn visible_byte_range(&mut self) -> Range<usize> {
+        self.refresh_layout();
+        let Some(visible) = self.effective_clip_rect() else {
+            return 0..self.inner.text.len();
+        };
+        let layout = &self.inner.layout;
+
+        let mut range: Option<Range<usize>> = None;
+        let mut last_line_y = 0.0;
+        for line in layout.lines() {
+            let line_range = line.text_range();
+            let geometry = Cursor::from_byte_index(layout, line_range.start, Affinity::Downstream).geometry(layout, 1.0);
+            let (line_top, line_bottom) = (geometry.y0, geometry.y1);
+            if line_bottom <= visible.y0 || line_top >= visible.y1 {
+                continue;
+            }
+            match &mut range {
+                None => {
+                    // Trim the part of the first line that is scrolled out horizontally.
+                    let y = (line_top + line_bottom) * 0.5;
+                    let left = Selection::from_point(layout, visible.x0 as f32, y as f32).focus().index();
+                    let right = Selection::from_point(layout, visible.x1 as f32, y as f32).focus().index();
+                    range = Some(left.min(right).max(line_range.start)..line_range.end);
+                }
+                Some(range) => range.end = line_range.end,
+            }
+            last_line_y = (line_top + line_bottom) * 0.5;
+        }
+
+        let Some(mut range) = range else {
+            return 0..0;
+        };
+        // Same for the last line.
+        let left = Selection::from_point(layout, visible.x0 as f32, last_line_y as f32).focus().index();
+        let right = Selection::from_point(layout, visible.x1 as f32, last_line_y as f32).focus().index();
+        range.end = left.max(right).min(range.end).max(range.start);
+        range
+    }
+
+    /// Get the laid-out positions of the inline boxes set with [`TextBoxMut::set_inline_boxes()`].
+    /// 
+    /// The positions are in window coordinates, accounting for the box position and scroll offset.
+    pub fn inline_box_positions(&mut self) -> Vec<PositionedInlineBox> {
+        self.refresh_layout();
+        let (left, top) = self.pos();
+        let (scroll_x, scroll_y) = self.inner.scroll_offset;
+
+        let mut result = Vec::with_capacity(self.inner.inline_boxes.len());
+        for line in self.inner.layout.lines() {
+            for item in line.items() {
+                if let PositionedLayoutItem::InlineBox(mut inline_box) = item {
+                    inline_box.x += left as f32 - scroll_x;
+                    inline_box.y += top as f32 - scroll_y;
+                    result.push(inline_box);
+                }
+            }
+        }
+        result
     }
     
     #[cfg(feature = "accessibility")]
@@ -1027,6 +2182,308 @@ impl<'a> TextBoxMut<'a> {
 
 pub use parley::Rect;
 
+/// The parts of a text box that affect how its text is split into spans when building the layout.
+#[derive(Clone, Copy)]
+pub(crate) struct LayoutSpans<'a> {
+    pub(crate) wrap_spans: &'a [(Range<usize>, WrapSpan)],
+    pub(crate) inline_boxes: &'a [InlineBox],
+    pub(crate) image_replacements: &'a [(Range<usize>, ImageId)],
+    pub(crate) folds: &'a [Range<usize>],
+}
+
+/// Start byte indices of all the clusters in the layout, in logical order.
+fn cluster_starts(layout: &Layout<ColorBrush>) -> Vec<usize> {
+    let mut starts = Vec::new();
+    for line in layout.lines() {
+        for item in line.items() {
+            if let PositionedLayoutItem::GlyphRun(glyph_run) = item {
+                starts.extend(glyph_run.run().clusters().map(|cluster| cluster.text_range().start));
+            }
+        }
+    }
+    starts.sort_unstable();
+    starts.dedup();
+    starts
+}
+
+/// The byte index where the hidden part of the text starts. Text before it is drawn normally.
+pub(crate) fn revealed_byte_index(layout: &Layout<ColorBrush>, reveal: Option<Reveal>, text_len: usize) -> Option<usize> {
+    let reveal = reveal?;
+    let starts = cluster_starts(layout);
+    let count = match reveal {
+        Reveal::Clusters(count) => count,
+        Reveal::Progress(progress) => (starts.len() as f32 * progress).round() as usize,
+    };
+    Some(starts.get(count).copied().unwrap_or(text_len))
+}
+
+impl<'a> LayoutSpans<'a> {
+    pub(crate) fn from_inner(inner: &'a TextBoxInner) -> Self {
+        Self {
+            wrap_spans: &inner.wrap_spans,
+            inline_boxes: &inner.inline_boxes,
+            image_replacements: &inner.image_replacements,
+            folds: &inner.folds,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.wrap_spans.is_empty() && self.inline_boxes.is_empty() && self.image_replacements.is_empty() && self.folds.is_empty()
+    }
+
+    pub(crate) fn to_owned_spans(&self) -> OwnedLayoutSpans {
+        OwnedLayoutSpans {
+            wrap_spans: self.wrap_spans.to_vec(),
+            inline_boxes: self.inline_boxes.to_vec(),
+            image_replacements: self.image_replacements.to_vec(),
+            folds: self.folds.to_vec(),
+        }
+    }
+}
+
+pub(crate) struct OwnedLayoutSpans {
+    wrap_spans: Vec<(Range<usize>, WrapSpan)>,
+    inline_boxes: Vec<InlineBox>,
+    image_replacements: Vec<(Range<usize>, ImageId)>,
+    folds: Vec<Range<usize>>,
+}
+
+impl OwnedLayoutSpans {
+    pub(crate) fn as_refs(&self) -> LayoutSpans<'_> {
+        LayoutSpans {
+            wrap_spans: &self.wrap_spans,
+            inline_boxes: &self.inline_boxes,
+            image_replacements: &self.image_replacements,
+            folds: &self.folds,
+        }
+    }
+}
+
+/// Identifies a layout that can be shared between labels. See [`Text::set_label_interning()`].
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct LabelLayoutKey {
+    text: &'static str,
+    style: u32,
+    style_version: u64,
+    max_advance: u32,
+    alignment: u8,
+    scale: u32,
+    single_line: bool,
+    color_override: Option<[u8; 4]>,
+}
+
+pub(crate) type LabelLayoutCache = LruCache<LabelLayoutKey, Layout<ColorBrush>, BuildHasherDefault<FxHasher>>;
+
+pub(crate) fn build_layout(
+    text: &str,
+    style: &TextStyle2,
+    spans: LayoutSpans,
+    color_override: Option<ColorBrush>,
+    single_line: bool,
+    max_advance: f32,
+    alignment: Alignment,
+    scale: f32,
+) -> Layout<ColorBrush> {
+    with_text_cx(|layout_cx, font_cx| {
+        let mut builder = layout_cx.tree_builder(font_cx, scale, true, style);
+
+        if let Some(color_override) = color_override {
+            builder.push_style_modification_span(&[
+                StyleProperty::Brush(color_override)
+            ]);
+        }
+
+        push_text_with_spans(&mut builder, text, spans);
+
+        let (mut layout, _) = builder.build();
+
+        if ! single_line {
+            layout.break_all_lines(Some(max_advance));
+            layout.align(
+                Some(max_advance),
+                alignment,
+                AlignmentOptions::default(),
+            );
+        } else {
+            layout.break_all_lines(None);
+            // Single-line text is aligned only when it fits. When it overflows, it starts at the left edge and scrolls, which is the default for AlignmentOptions. Room is left for the cursor at the end, so that right-aligned text doesn't scroll as soon as the cursor reaches the end.
+            layout.align(
+                Some((max_advance - CURSOR_WIDTH).max(0.0)),
+                alignment,
+                AlignmentOptions::default(),
+            );
+        }
+        layout
+    })
+}
+
+/// Push the box's text into the builder, splitting it at the boundaries of any range-based spans so that each segment can get its own style properties.
+fn push_text_with_spans(builder: &mut TreeBuilder<'_, ColorBrush>, text: &str, inner: LayoutSpans) {
+    if inner.is_empty() {
+        builder.push_text(text);
+        return;
+    }
+
+    let mut boundaries: SmallVec<[usize; 16]> = SmallVec::new();
+    boundaries.push(0);
+    boundaries.push(text.len());
+    for (range, _) in inner.wrap_spans {
+        boundaries.push(floor_char_boundary(text, range.start));
+        boundaries.push(floor_char_boundary(text, range.end));
+    }
+    for inline_box in inner.inline_boxes {
+        boundaries.push(floor_char_boundary(text, inline_box.index));
+    }
+    for (range, _) in inner.image_replacements {
+        boundaries.push(floor_char_boundary(text, range.start));
+        boundaries.push(floor_char_boundary(text, range.end));
+    }
+    for range in inner.folds {
+        boundaries.push(floor_char_boundary(text, range.start));
+        boundaries.push(floor_char_boundary(text, range.end));
+    }
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut props: SmallVec<[StyleProperty<'static, ColorBrush>; 6]> = SmallVec::new();
+    for window in boundaries.windows(2) {
+        let (start, end) = (window[0], window[1]);
+
+        push_inline_boxes_at(builder, text, inner.inline_boxes, start);
+
+        props.clear();
+        for (range, wrap) in inner.wrap_spans {
+            if range.start <= start && end <= range.end {
+                props.push(StyleProperty::TextWrapMode(TextWrapMode::NoWrap));
+                if *wrap == WrapSpan::NonBreaking {
+                    props.push(StyleProperty::OverflowWrap(OverflowWrap::Normal));
+                }
+            }
+        }
+        // Replaced text is laid out normally, but made invisible. The renderer skips fully transparent runs.
+        if inner.image_replacements.iter().any(|(range, _)| range.start <= start && end <= range.end) {
+            props.push(StyleProperty::Brush(ColorBrush([0, 0, 0, 0])));
+        }
+        // Folded text stays in the layout so that byte offsets don't change, but it takes no space.
+        if inner.folds.iter().any(|range| range.start <= start && end <= range.end) {
+            props.push(StyleProperty::Brush(ColorBrush([0, 0, 0, 0])));
+            props.push(StyleProperty::FontSize(0.0));
+            props.push(StyleProperty::LineHeight(LineHeight::MetricsRelative(0.0)));
+        }
+
+        if props.is_empty() {
+            builder.push_text(&text[start..end]);
+        } else {
+            builder.push_style_modification_span(&props);
+            builder.push_text(&text[start..end]);
+            builder.pop_style_span();
+        }
+    }
+
+    push_inline_boxes_at(builder, text, inner.inline_boxes, text.len());
+}
+
+fn push_inline_boxes_at(builder: &mut TreeBuilder<'_, ColorBrush>, text: &str, inline_boxes: &[InlineBox], index: usize) {
+    for inline_box in inline_boxes {
+        if floor_char_boundary(text, inline_box.index) == index {
+            builder.push_inline_box(inline_box.clone());
+        }
+    }
+}
+
+pub(crate) fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CjkScript {
+    Han,
+    Hiragana,
+    Katakana,
+}
+
+fn cjk_script(c: char) -> Option<CjkScript> {
+    match c as u32 {
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x3134F | 0x3005 | 0x3007 => Some(CjkScript::Han),
+        0x3041..=0x309F => Some(CjkScript::Hiragana),
+        0x30A0..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F => Some(CjkScript::Katakana),
+        _ => None,
+    }
+}
+
+// Loading the dictionaries is slow, so the segmenter is created once per thread.
+#[cfg(feature = "cjk_segmentation")]
+thread_local! {
+    static WORD_SEGMENTER: icu_segmenter::WordSegmenter = icu_segmenter::WordSegmenter::new_dictionary();
+}
+
+/// Narrow down the word `range` that contains the character starting at `index` if it's a run of Chinese or Japanese text. `index` is the character that was clicked on, not the cursor position, which can be after it.
+///
+/// Parley's word boundaries treat a whole run of ideographs and kana as a single word, because there are no spaces to break on. With the `cjk_segmentation` feature, the run is split with a dictionary. Otherwise, it's split where the script changes, so that kanji, hiragana and katakana are selected separately.
+pub(crate) fn cjk_word_range(text: &str, range: Range<usize>, index: usize) -> Range<usize> {
+    let Some(word) = text.get(range.clone()) else {
+        return range;
+    };
+    if !word.chars().any(|c| cjk_script(c).is_some()) {
+        return range;
+    }
+    // The character that was clicked on.
+    let index = floor_char_boundary(text, index.clamp(range.start, range.end)) - range.start;
+    let index = if index == word.len() {
+        word[..index].char_indices().next_back().map_or(0, |(i, _)| i)
+    } else {
+        index
+    };
+
+    #[cfg(feature = "cjk_segmentation")]
+    {
+        WORD_SEGMENTER.with(|segmenter| {
+            let mut start = 0;
+            for end in segmenter.segment_str(word) {
+                if end > index {
+                    return range.start + start..range.start + end;
+                }
+                start = end;
+            }
+            range
+        })
+    }
+
+    #[cfg(not(feature = "cjk_segmentation"))]
+    {
+        let script = word[index..].chars().next().and_then(cjk_script);
+        let start = word[..index]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| cjk_script(c) == script)
+            .last()
+            .map_or(index, |(i, _)| i);
+        let end = word[index..]
+            .char_indices()
+            .find(|&(_, c)| cjk_script(c) != script)
+            .map_or(word.len(), |(i, _)| index + i);
+        range.start + start..range.start + end
+    }
+}
+
+/// The start of the extended grapheme cluster that ends at or contains `index`, or `index` itself at the start of the text.
+pub(crate) fn previous_grapheme_boundary(text: &str, index: usize) -> usize {
+    let index = floor_char_boundary(text, index);
+    let mut cursor = GraphemeCursor::new(index, text.len(), true);
+    cursor.prev_boundary(text, 0).ok().flatten().unwrap_or(index)
+}
+
+/// The end of the extended grapheme cluster that starts at or contains `index`, or `index` itself at the end of the text.
+pub(crate) fn next_grapheme_boundary(text: &str, index: usize) -> usize {
+    let index = floor_char_boundary(text, index);
+    let mut cursor = GraphemeCursor::new(index, text.len(), true);
+    cursor.next_boundary(text, 0).ok().flatten().unwrap_or(index)
+}
+
 pub(crate) trait Ext1 {
     fn hit_bounding_box(&mut self, cursor_pos: (f64, f64)) -> bool;
 }
@@ -1055,8 +2512,28 @@ impl SelectionState {
         self.set_selection(Selection::from_point(layout, x, y));
     }
 
-    pub(crate) fn select_word_at_point(&mut self, layout: &Layout<ColorBrush>, x: f32, y: f32) {
-        self.set_selection(Selection::word_from_point(layout, x, y));
+    /// Select the word at the point, using the custom `rules` if there are any. Runs of Chinese and Japanese text, which have no spaces, are split further with [`cjk_word_range()`].
+    pub(crate) fn select_word_at_point(&mut self, layout: &Layout<ColorBrush>, text: &str, rules: Option<WordRules>, x: f32, y: f32) {
+        let word = Selection::word_from_point(layout, x, y);
+        // The start of the character that was clicked on. A click on the right half of a character puts the cursor after it, with upstream affinity.
+        let focus = Selection::from_point(layout, x, y).focus();
+        let index = match focus.affinity() {
+            Affinity::Upstream => text[..focus.index().min(text.len())].char_indices().next_back().map_or(0, |(i, _)| i),
+            Affinity::Downstream => focus.index(),
+        };
+        let range = match rules {
+            Some(rules) => rules.word_range(text, index),
+            None => word.text_range(),
+        };
+        let range = cjk_word_range(text, range, index);
+        if range == word.text_range() {
+            self.set_selection(word);
+        } else {
+            self.set_selection(Selection::new(
+                Cursor::from_byte_index(layout, range.start, Affinity::Downstream),
+                Cursor::from_byte_index(layout, range.end, Affinity::Upstream),
+            ));
+        }
     }
 
     /// Select the physical line at the point.
@@ -1179,4 +2656,28 @@ fn push_accesskit_update_text_box_free_function(
     }
 }
 
+pub(crate) fn meta_at_layout_point(layout: &Layout<ColorBrush>, text: &str, ranges: &[(Range<usize>, u64)], x: f64, y: f64) -> Option<u64> {
+    for (range, value) in ranges {
+        let start = floor_char_boundary(text, range.start.min(text.len()));
+        let end = floor_char_boundary(text, range.end.min(text.len()));
+        if start >= end {
+            continue;
+        }
+        let selection = Selection::new(
+            Cursor::from_byte_index(layout, start, Affinity::Downstream),
+            Cursor::from_byte_index(layout, end, Affinity::Upstream),
+        );
+        let mut hit = false;
+        selection.geometry_with(layout, |rect, _line_i| {
+            hit |= x >= rect.x0 && x < rect.x1 && y >= rect.y0 && y < rect.y1;
+        });
+        if hit {
+            return Some(*value);
+        }
+    }
+    None
+}
 
+fn first_baseline(layout: &Layout<ColorBrush>) -> f32 {
+    layout.lines().next().map(|line| line.metrics().baseline).unwrap_or(0.0)
+}
@@ -11,6 +11,10 @@ use winit::{
 use accesskit::{Node, NodeId, Rect as AccessRect, Role, TreeUpdate};
 
 pub(crate) const CURSOR_WIDTH: f32 = 3.0;
+pub(crate) const REMOTE_CURSOR_WIDTH: f32 = 2.0;
+pub(crate) const SUSPICIOUS_CHAR_BAR_WIDTH: f32 = 2.0;
+pub(crate) const REMOTE_CURSOR_LABEL_FONT_SIZE: f32 = 12.0;
+pub(crate) const FOLD_MARKER_GAP: f32 = 4.0;
 
 use crate::*;
 
@@ -28,6 +32,7 @@ macro_rules! clear_placeholder {
 
 /// Defines how newlines are entered in a text edit box.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum NewlineMode {
     /// Enter key inserts newlines (default for multi-line)
     Enter,
@@ -45,6 +50,147 @@ impl Default for NewlineMode {
     }
 }
 
+/// What pressing Escape did in the focused text edit. Passed to the callback set with [`Text::set_escape_callback()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscapeOutcome {
+    /// A composition was in progress, and it was cancelled.
+    CompositionCancelled,
+    /// The text was cleared, because the edit is single line and has [`TextEditMut::set_clear_on_escape()`] enabled.
+    Cleared,
+    /// Nothing was cleared. The selection was collapsed, if there was one.
+    ///
+    /// Apps can use this to close a popup or move focus away, without reacting to presses that only dismissed a composition or cleared a search field.
+    Cancelled,
+}
+
+/// A sequence that ends lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LineEnding {
+    /// `\n`, used on Linux, macOS and the web.
+    #[default]
+    Lf,
+    /// `\r\n`, used on Windows.
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    /// Find the line ending used by most lines of `text`, or `None` if it has no line breaks. Ties count as [`LineEnding::Lf`].
+    pub fn detect(text: &str) -> Option<LineEnding> {
+        let line_breaks = text.matches('\n').count();
+        if line_breaks == 0 {
+            return None;
+        }
+        let crlf = text.matches("\r\n").count();
+        Some(if crlf * 2 > line_breaks { LineEnding::CrLf } else { LineEnding::Lf })
+    }
+}
+
+/// Convert all line endings in `text` to `ending`. Returns the text unchanged if it already uses only that ending.
+pub fn normalize_line_endings(text: &str, ending: LineEnding) -> Cow<'_, str> {
+    match ending {
+        LineEnding::Lf if text.contains("\r\n") => Cow::Owned(text.replace("\r\n", "\n")),
+        LineEnding::CrLf if text.matches('\n').count() != text.matches("\r\n").count() => {
+            Cow::Owned(text.replace("\r\n", "\n").replace('\n', "\r\n"))
+        }
+        _ => Cow::Borrowed(text),
+    }
+}
+
+/// How a text edit handles line endings. Set with [`TextEditMut::set_line_ending_policy()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LineEndingPolicy {
+    /// Pasted text is inserted as it is, and Enter inserts `\n`.
+    #[default]
+    Preserve,
+    /// Pasted text is converted to this line ending, and Enter inserts it.
+    Always(LineEnding),
+    /// Like `Always`, with the line ending that's used by most lines of the current text, as found by [`LineEnding::detect()`]. Text without line breaks uses `\n`.
+    Detect,
+}
+
+/// The kind of input a text edit expects, used as a hint for IMEs and virtual keyboards. Set with [`TextEditMut::set_ime_purpose()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImePurpose {
+    /// Regular text input.
+    #[default]
+    Normal,
+    /// A password field. IME is disabled while the field is focused.
+    Password,
+    /// A numeric field. IME is disabled while the field is focused.
+    Number,
+}
+
+impl ImePurpose {
+    /// Returns true if IME composition makes sense for this kind of field.
+    pub fn allows_ime(&self) -> bool {
+        matches!(self, ImePurpose::Normal)
+    }
+
+    pub(crate) fn apply(&self, window: &Window) {
+        // On Android, disallowing IME would also hide the soft keyboard.
+        window.set_ime_allowed(self.allows_ime() || cfg!(target_os = "android"));
+        let winit_purpose = match self {
+            ImePurpose::Normal | ImePurpose::Number => winit::window::ImePurpose::Normal,
+            ImePurpose::Password => winit::window::ImePurpose::Password,
+        };
+        window.set_ime_purpose(winit_purpose);
+    }
+}
+
+/// What started the current IME composition. See [`TextEditMut::composition_kind()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompositionKind {
+    /// A dead key (like ´ on many European layouts) that's waiting for the next key. Interrupting it cancels it.
+    DeadKey,
+    /// A full IME composition. Interrupting it commits the preedit text.
+    Ime,
+}
+
+/// The word around the cursor, as returned by [`TextEditMut::word_under_cursor()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordUnderCursor {
+    /// Byte range of the word in the text edit's text.
+    pub range: Range<usize>,
+    /// Anchor rectangle for the start of the word, in window coordinates.
+    pub rect: Rect,
+}
+
+/// Bracket and quote pairs used by [`TextEditMut::set_auto_pairs()`] in the common case.
+pub const DEFAULT_AUTO_PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')];
+
+/// Bracket pairs used by [`TextEditMut::matching_bracket()`] unless they're changed with [`TextEditMut::set_bracket_pairs()`].
+pub const DEFAULT_BRACKET_PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+
+/// Function that receives pasted text before it's inserted into a text edit.
+/// 
+/// It can return the text unchanged, a transformed version of it (for example with newlines stripped or trimmed to a maximum length), or `None` to reject the paste entirely. Rejected pastes don't create an undo history entry.
+pub type PasteFilter = fn(text: &str) -> Option<String>;
+
+/// A caret and selection belonging to another user, for example from a collaborative editing session.
+/// 
+/// Set with [`TextEditMut::set_remote_cursor()`]. The offsets are byte offsets into the text edit's text, and they're resolved against the current layout every time the decorations are rendered, so they follow relayouts automatically.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteCursor {
+    /// Identifies the cursor. Setting a cursor with the same `id` replaces the old one.
+    pub id: u64,
+    /// Byte offset where the selection starts. Equal to `focus` for a plain caret.
+    pub anchor: usize,
+    /// Byte offset of the caret.
+    pub focus: usize,
+    pub color: ColorBrush,
+    /// Optional name label, drawn above the caret.
+    pub name: Option<String>,
+}
+
 /// A string that may be split into two parts (used for IME composition).
 #[derive(Debug, Clone, Copy)]
 pub struct SplitString<'source>(pub(crate) [&'source str; 2]);
@@ -112,15 +258,44 @@ pub(crate) fn selection_decorations_changed(initial_selection: Selection, new_se
 /// Then, the handle can be used to get a reference to the `TextEdit` with [`Text::get_text_edit()`] or [`Text::get_text_edit_mut()`].
 pub(crate) struct TextEditInner {
     pub(crate) compose: Option<Range<usize>>,
+    /// The selection when the last composition started, and the text that the composition replaced. It's only recorded in the history when the composition is committed, so that the whole composition is a single undo step.
+    pub(crate) compose_origin: Option<(Selection, String)>,
+    pub(crate) compose_kind: Option<CompositionKind>,
+    pub(crate) last_key_was_dead: bool,
+    pub(crate) ime_purpose: ImePurpose,
     pub(crate) show_cursor: bool,
     pub(crate) start_time: Option<Instant>,
     pub(crate) blink_period: Duration,
     pub(crate) history: TextEditHistory,
+    pub(crate) history_merge_policy: Option<HistoryMergePolicy>,
+    /// Incremented on every committed change to the text. See [`TextEdit::revision()`].
+    pub(crate) revision: u64,
     pub(crate) single_line: bool,
     pub(crate) newline_mode: NewlineMode,
+    pub(crate) line_ending_policy: LineEndingPolicy,
     pub(crate) disabled: bool,
     pub(crate) showing_placeholder: bool,
     pub(crate) placeholder_text: Option<Cow<'static, str>>,
+    pub(crate) remote_cursors: Vec<RemoteCursor>,
+    pub(crate) html_paste_hook: Option<HtmlSpanHook>,
+    pub(crate) paste_filter: Option<PasteFilter>,
+    pub(crate) auto_pairs: Vec<(char, char)>,
+    pub(crate) bracket_pairs: Vec<(char, char)>,
+    pub(crate) highlight_matching_brackets: bool,
+    // Byte offsets of closing characters that were inserted automatically, innermost last.
+    pub(crate) auto_closers: Vec<usize>,
+    pub(crate) ghost_text: Option<(usize, String)>,
+    pub(crate) scrollbar_drag: Option<ScrollbarDrag>,
+    pub(crate) scroll_bounds: ScrollBounds,
+    pub(crate) zoom_shortcuts: bool,
+    pub(crate) show_invisibles: bool,
+    pub(crate) suspicious_char_policy: SuspiciousCharPolicy,
+    pub(crate) suspicious_char_report: Vec<SuspiciousChar>,
+    pub(crate) clear_on_escape: bool,
+    /// Set by [`TextEditMut::set_error()`].
+    pub(crate) error: bool,
+    #[cfg(feature = "modal")]
+    pub(crate) modal: Option<ModalState>,
 }
 
 #[derive(Debug, Clone)]
@@ -130,7 +305,7 @@ pub struct ScrollAnimation {
     pub start_time: Instant,
     pub duration: Duration,
     pub direction: ScrollDirection,
-    pub handle: TextEditHandle,
+    pub(crate) i: u32,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -139,21 +314,102 @@ pub enum ScrollDirection {
     Vertical,
 }
 
+/// What happens when a text edit is scrolled past the start or the end of its text. See [`ScrollBounds`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overscroll {
+    /// The scroll offset stops at the bounds.
+    #[default]
+    Clamp,
+    /// Touchpad and touch scrolling can go past the bounds with increasing resistance, and the text springs back when the gesture ends.
+    ///
+    /// Mouse wheel scrolling is still clamped.
+    Elastic,
+}
+
+/// How far a multi-line text edit can be scrolled with the mouse wheel, touchpad and scrollbar. Set with [`TextEditMut::set_scroll_bounds()`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ScrollBounds {
+    pub overscroll: Overscroll,
+    /// Extra space after the end of the text, in pixels. Editors often use the height of the box minus one line, so that the last line can be scrolled up to the top.
+    pub past_end: f32,
+}
+
+/// Zoom factor applied by each zoom shortcut press or wheel step.
+pub(crate) const ZOOM_STEP: f32 = 1.1;
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 4.0;
+
+pub(crate) const ELASTIC_SPRING_BACK_DELAY: Duration = Duration::from_millis(120);
+pub(crate) const ELASTIC_SPRING_BACK_DURATION: Duration = Duration::from_millis(250);
+/// How far past the bounds an elastic scroll can go, as a fraction of the box height.
+const ELASTIC_MAX_OVERSCROLL: f32 = 0.3;
+/// How much of the scroll delta is applied when moving further past the bounds.
+const ELASTIC_RESISTANCE: f32 = 0.35;
+
+/// The largest vertical scroll offset reachable by the user, including [`ScrollBounds::past_end`].
+pub(crate) fn max_vertical_scroll(text_edit: &TextEditInner, text_box: &TextBoxInner) -> f32 {
+    let past_end = text_edit.scroll_bounds.past_end.max(0.0);
+    (text_box.layout.height() + past_end - text_box.height).max(0.0).round()
+}
+
+/// The largest horizontal scroll offset of a single-line edit. There's room for the cursor after the end of the text, but text that fits entirely isn't scrolled at all, so centered and right-aligned text stays where the alignment put it.
+pub(crate) fn max_horizontal_scroll(text_box: &TextBoxInner) -> f32 {
+    let total_text_width = text_box.layout.full_width();
+    let text_width = text_box.max_advance;
+    if total_text_width + CURSOR_WIDTH <= text_width {
+        return 0.0;
+    }
+    (total_text_width - text_width).max(0.0).round() + CURSOR_WIDTH
+}
+
+/// Apply a scroll delta with rubber-banding past `0.0..=max_scroll`.
+pub(crate) fn elastic_scroll(current: f32, scroll_amount: f32, max_scroll: f32, view_height: f32) -> f32 {
+    let moving_out = (current <= 0.0 && scroll_amount > 0.0) || (current >= max_scroll && scroll_amount < 0.0);
+    let scroll_amount = if moving_out { scroll_amount * ELASTIC_RESISTANCE } else { scroll_amount };
+    let limit = view_height * ELASTIC_MAX_OVERSCROLL;
+    (current - scroll_amount).clamp(-limit, max_scroll + limit).round()
+}
+
 impl TextEditInner {
-    pub fn new(text: String, pos: (f64, f64), size: (f32, f32), depth: f32) -> (Self, TextBoxInner) {
+    pub fn new(text: impl Into<Cow<'static, str>>, pos: (f64, f64), size: (f32, f32), depth: f32) -> (Self, TextBoxInner) {
         let mut text_box = TextBoxInner::new(text, pos, size, depth);
         text_box.auto_clip = true;
         let text_edit = Self {
             compose: Default::default(),
+            compose_origin: None,
             show_cursor: true,
             start_time: Default::default(),
             blink_period: Default::default(),
             history: TextEditHistory::new(),
+            history_merge_policy: None,
+            revision: 0,
             single_line: false,
             newline_mode: NewlineMode::default(),
+            line_ending_policy: LineEndingPolicy::default(),
             disabled: false,
             showing_placeholder: false,
             placeholder_text: None,
+            remote_cursors: Vec::new(),
+            html_paste_hook: None,
+            paste_filter: None,
+            auto_pairs: Vec::new(),
+            bracket_pairs: DEFAULT_BRACKET_PAIRS.to_vec(),
+            highlight_matching_brackets: false,
+            auto_closers: Vec::new(),
+            ghost_text: None,
+            scrollbar_drag: None,
+            scroll_bounds: ScrollBounds::default(),
+            zoom_shortcuts: false,
+            show_invisibles: false,
+            suspicious_char_policy: SuspiciousCharPolicy::default(),
+            suspicious_char_report: Vec::new(),
+            clear_on_escape: false,
+            error: false,
+            #[cfg(feature = "modal")]
+            modal: None,
+            compose_kind: None,
+            last_key_was_dead: false,
+            ime_purpose: ImePurpose::Normal,
             // Scroll animations are now managed centrally in Text struct
         };
         (text_edit, text_box)
@@ -162,9 +418,9 @@ impl TextEditInner {
 
 
 impl ScrollAnimation {
-
-    pub fn get_current_offset(&self) -> f32 {
-        let elapsed = self.start_time.elapsed();
+    /// The scroll offset at the time `now`. Times should come from the clock set with [`Text::set_time_source()`], like the start time.
+    pub fn get_current_offset_at(&self, now: Instant) -> f32 {
+        let elapsed = now.saturating_duration_since(self.start_time);
         if elapsed >= self.duration {
             return self.target_offset;
         }
@@ -176,8 +432,8 @@ impl ScrollAnimation {
         self.start_offset + (self.target_offset - self.start_offset) * eased_progress
     }
 
-    pub fn is_finished(&self) -> bool {
-        self.start_time.elapsed() >= self.duration
+    pub fn is_finished_at(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.start_time) >= self.duration
     }
 }
 
@@ -200,6 +456,8 @@ impl<'a> TextEditMut<'a> {
                 // When switching back to multi-line, restore default newline mode
                 self.inner.newline_mode = NewlineMode::Enter;
             }
+            self.text_box.inner.needs_relayout = true;
+            self.text_box.shared.text_changed = true;
         }
     }
 
@@ -210,8 +468,423 @@ impl<'a> TextEditMut<'a> {
         }
     }
 
+    /// Configure how far the edit can be scrolled by the user. See [`ScrollBounds`].
+    pub fn set_scroll_bounds(&mut self, scroll_bounds: ScrollBounds) {
+        self.inner.scroll_bounds = scroll_bounds;
+    }
+
+    pub fn scroll_bounds(&self) -> ScrollBounds {
+        self.inner.scroll_bounds
+    }
+
+    /// Scale the font sizes of the edit. See [`TextBoxMut::set_zoom()`].
+    ///
+    /// The scroll offset is adjusted so that the cursor stays at the same place in the visible area.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        if zoom == self.text_box.inner.zoom {
+            return;
+        }
+        self.refresh_layout();
+        let scroll = self.text_box.scroll_offset();
+        let caret_in_view = self.cursor_geometry(1.0).map(|rect| (rect.x0 as f32 - scroll.0, rect.y0 as f32 - scroll.1));
+
+        self.text_box.set_zoom(zoom);
+        self.refresh_layout();
+
+        let Some((view_x, view_y)) = caret_in_view else {
+            return;
+        };
+        let Some(rect) = self.cursor_geometry(1.0) else {
+            return;
+        };
+        if self.inner.single_line {
+            self.apply_horizontal_scroll(rect.x0 as f32 - view_x);
+        } else {
+            let max_scroll = max_vertical_scroll(self.inner, self.text_box.inner);
+            let new_scroll = (rect.y0 as f32 - view_y).clamp(0.0, max_scroll).round();
+            self.text_box.set_scroll_offset((0.0, new_scroll));
+        }
+        self.text_box.shared.scrolled = true;
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.text_box.zoom()
+    }
+
+    /// Let the user change the zoom with Ctrl+Plus, Ctrl+Minus and Ctrl+0 while the edit is focused, and with Ctrl+wheel while it's hovered. On macOS, Cmd is used instead of Ctrl.
+    ///
+    /// This is disabled by default, so that the shortcuts can be used by the application.
+    pub fn set_zoom_shortcuts(&mut self, enabled: bool) {
+        self.inner.zoom_shortcuts = enabled;
+    }
+
+    /// Multiply the zoom by `factor`, keeping it within the range used by the zoom shortcuts.
+    pub(crate) fn zoom_by(&mut self, factor: f32) {
+        let zoom = (self.zoom() * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.set_zoom(zoom);
+    }
+
     pub fn set_disabled(&mut self, disabled: bool) {
-        self.inner.disabled = disabled;
+        if self.inner.disabled != disabled {
+            self.inner.disabled = disabled;
+            // The text is drawn with a different color.
+            self.text_box.shared.text_changed = true;
+        }
+    }
+
+    /// Copy the selected text to the clipboard as an HTML fragment carrying the text edit's style, with the plain text as a fallback for applications that don't accept HTML.
+    pub fn copy_selection_rich(&mut self) {
+        if self.inner.showing_placeholder {
+            return;
+        }
+        if let Some(text) = self.text_box.selected_text() {
+            let style = &self.text_box.shared.styles[self.text_box.inner.style.i as usize].text_style;
+            let html = text_to_html(text, style);
+            let plain = text.to_owned();
+            with_clipboard(|cb| {
+                cb.set_html(html, Some(plain)).ok();
+            });
+        }
+    }
+
+    /// Insert an HTML fragment at the cursor, or replace the selection with it.
+    /// 
+    /// The HTML is stripped to plain text first, using the hook set with [`TextEditMut::set_html_paste_hook()`] if there is one.
+    pub fn paste_html(&mut self, html: &str) {
+        let text = html_to_plain_text(html, self.inner.html_paste_hook);
+        self.paste(&text);
+    }
+
+    fn cut_selection(&mut self) {
+        if self.inner.showing_placeholder {
+            return;
+        }
+        if self.text_box.selected_text().is_some() {
+            self.text_box.copy_selection();
+            self.inner.auto_closers.clear();
+            self.delete_selection();
+            self.text_box.shared.text_changed = true;
+        }
+    }
+
+    fn paste_from_clipboard(&mut self) {
+        let text = with_clipboard(|cb| cb.get_text().unwrap_or_default());
+        self.paste(&text);
+    }
+
+    /// Cancel the current IME composition, removing the preedit text.
+    /// 
+    /// This only resets the text edit's state. To also reset the system IME, the host can disable and re-enable it with winit's `Window::set_ime_allowed()`.
+    pub fn cancel_composition(&mut self) {
+        self.inner.last_key_was_dead = false;
+        self.clear_compose();
+    }
+
+    /// Returns what started the current composition, or `None` if not composing.
+    pub fn composition_kind(&self) -> Option<CompositionKind> {
+        if self.is_composing() {
+            self.inner.compose_kind
+        } else {
+            None
+        }
+    }
+
+    /// End the composition because something else happened, like a click or a focus change. Dead keys are cancelled and IME compositions are committed.
+    pub(crate) fn interrupt_composition(&mut self) {
+        match self.composition_kind() {
+            Some(CompositionKind::DeadKey) => self.cancel_composition(),
+            Some(CompositionKind::Ime) => {
+                let Some(range) = self.inner.compose.clone() else {
+                    return;
+                };
+                let text = self.text_box.text_inner()[range].to_string();
+                self.cancel_composition();
+                self.insert_or_replace_selection(&text);
+            }
+            None => {}
+        }
+    }
+
+    /// Insert text as if it was typed on the keyboard one character at a time, then update the layout and scroll to the cursor.
+    /// 
+    /// This goes through the same path as keyboard input, including auto-pairing and undo history, without needing winit events.
+    pub fn type_text(&mut self, text: &str) {
+        if self.inner.disabled {
+            return;
+        }
+        clear_placeholder!(self);
+        let mut buf = [0; 4];
+        for c in text.chars() {
+            self.insert_typed(c.encode_utf8(&mut buf));
+        }
+        self.text_box.shared.text_changed = true;
+        self.refresh_layout();
+        self.update_scroll_to_cursor();
+    }
+
+    /// Insert text as if it was pasted from the clipboard, going through the paste filter. Line endings are converted according to the [`LineEndingPolicy`], and suspicious characters are handled according to the [`SuspiciousCharPolicy`].
+    pub fn paste(&mut self, text: &str) {
+        let filter = self.inner.paste_filter.or(self.text_box.shared.paste_filter);
+        let filtered = match filter {
+            Some(filter) => match filter(text) {
+                Some(filtered) => Cow::Owned(filtered),
+                None => return,
+            },
+            None => Cow::Borrowed(text),
+        };
+        let filtered = match self.sanitize_incoming(&filtered) {
+            Some(stripped) => Cow::Owned(stripped),
+            None => filtered,
+        };
+        if self.inner.line_ending_policy == LineEndingPolicy::Preserve {
+            self.replace_selection(&filtered);
+        } else {
+            let normalized = normalize_line_endings(&filtered, self.line_ending());
+            self.replace_selection(&normalized);
+        }
+    }
+
+    /// If enabled, pressing Escape in a single line edit clears its text, after cancelling any composition in progress. Multi-line edits ignore this. The clear can be undone.
+    /// 
+    /// Either way, what Escape did is reported to the callback set with [`Text::set_escape_callback()`].
+    pub fn set_clear_on_escape(&mut self, clear_on_escape: bool) {
+        self.inner.clear_on_escape = clear_on_escape;
+    }
+
+    /// Choose how line endings are handled when pasting and pressing Enter.
+    pub fn set_line_ending_policy(&mut self, policy: LineEndingPolicy) {
+        self.inner.line_ending_policy = policy;
+    }
+
+    /// Fold a range of lines, hiding them from layout and rendering. A marker is drawn at the end of the line before them.
+    /// 
+    /// `lines` are indices of logical lines, i.e. lines separated by hard line breaks. The first line can't be folded, since folds always hang off the end of the previous line.
+    /// 
+    /// The folded text is still part of the text edit, so all byte offsets stay the same. The fold is removed automatically when the cursor enters it, or when the text inside it is edited.
+    pub fn fold(&mut self, lines: Range<usize>) {
+        if self.inner.showing_placeholder {
+            return;
+        }
+        let text = self.text_box.text_inner();
+        let first = lines.start.max(1);
+        if first >= lines.end {
+            return;
+        }
+
+        let line_ends: Vec<usize> = text.match_indices('\n').map(|(i, _)| i).chain(std::iter::once(text.len())).collect();
+        let Some(&start) = line_ends.get(first - 1) else {
+            return;
+        };
+        let end = line_ends[(lines.end - 1).min(line_ends.len() - 1)];
+        if start >= end {
+            return;
+        }
+
+        // Merge with overlapping folds.
+        let mut range = start..end;
+        self.text_box.inner.folds.retain(|fold| {
+            if fold.start <= range.end && range.start <= fold.end {
+                range = range.start.min(fold.start)..range.end.max(fold.end);
+                false
+            } else {
+                true
+            }
+        });
+        self.text_box.inner.folds.push(range);
+        self.text_box.inner.folds.sort_by_key(|fold| fold.start);
+
+        self.text_box.inner.needs_relayout = true;
+        self.text_box.shared.text_changed = true;
+    }
+
+    /// Remove the fold that hides the byte offset `index`, if any.
+    pub fn unfold_at(&mut self, index: usize) {
+        let len = self.text_box.inner.folds.len();
+        self.text_box.inner.folds.retain(|range| !fold_hides_cursor(range, index));
+        if self.text_box.inner.folds.len() != len {
+            self.text_box.inner.needs_relayout = true;
+            self.text_box.shared.text_changed = true;
+        }
+    }
+
+    /// Remove all folds that hide any part of `range`. This can be used to make a search result visible.
+    pub fn reveal(&mut self, range: Range<usize>) {
+        let len = self.text_box.inner.folds.len();
+        self.text_box.inner.folds.retain(|fold| range.end <= fold.start || fold.end < range.start);
+        if self.text_box.inner.folds.len() != len {
+            self.text_box.inner.needs_relayout = true;
+            self.text_box.shared.text_changed = true;
+        }
+    }
+
+    pub fn unfold_all(&mut self) {
+        if !self.text_box.inner.folds.is_empty() {
+            self.text_box.inner.folds.clear();
+            self.text_box.inner.needs_relayout = true;
+            self.text_box.shared.text_changed = true;
+        }
+    }
+
+    /// Update the byte offsets stored on the edit after `range` was replaced with `new_len` bytes of new text.
+    fn shift_ranges(&mut self, range: Range<usize>, new_len: usize) {
+        shift_auto_closers(&mut self.inner.auto_closers, range.clone(), new_len);
+        shift_meta_ranges(&mut self.text_box.inner.meta_ranges, range.clone(), new_len);
+        if shift_fold_ranges(&mut self.text_box.inner.folds, range, new_len) {
+            self.text_box.shared.text_changed = true;
+        }
+    }
+
+    /// Show a dimmed suggestion right after the cursor, without adding it to the text.
+    /// 
+    /// The suggestion can be accepted with Tab or the right arrow key (or with [`TextEditMut::accept_ghost_text()`]), which inserts it as a normal edit. Moving the cursor or editing the text dismisses it.
+    /// 
+    /// The suggestion is drawn on top of whatever comes after the cursor, so it's meant to be used when the cursor is at the end of a line.
+    pub fn set_ghost_text(&mut self, ghost_text: Option<String>) {
+        let cursor = self.text_box.selection().focus().index();
+        self.inner.ghost_text = ghost_text.filter(|text| !text.is_empty()).map(|text| (cursor, text));
+        self.text_box.shared.text_changed = true;
+    }
+
+    /// Draw markers on spaces (`·`), tabs (`→`) and line breaks (`¶`), in the [`TextEditStyle::invisibles_color`] of the edit's style.
+    pub fn set_show_invisibles(&mut self, show: bool) {
+        if show != self.inner.show_invisibles {
+            self.inner.show_invisibles = show;
+            self.text_box.shared.text_changed = true;
+        }
+    }
+
+    /// Insert the current ghost text as a normal edit. Does nothing if there's no ghost text.
+    pub fn accept_ghost_text(&mut self) {
+        if self.is_composing() {
+            return;
+        }
+        if let Some((_, text)) = self.inner.ghost_text.take() {
+            self.inner.auto_closers.clear();
+            self.insert_or_replace_selection(&text);
+            self.text_box.shared.text_changed = true;
+        }
+    }
+
+    /// Set the kind of input this text edit expects.
+    /// 
+    /// When the text edit gets focused, the purpose is forwarded to winit, and IME is enabled or disabled for the window accordingly: password and numeric fields don't use IME.
+    pub fn set_ime_purpose(&mut self, purpose: ImePurpose) {
+        if purpose != self.inner.ime_purpose {
+            self.inner.ime_purpose = purpose;
+            if !purpose.allows_ime() {
+                self.cancel_composition();
+            }
+            self.text_box.shared.ime_update_needed = true;
+        }
+    }
+
+    /// Choose whether Home/End and the Up/Down arrow keys move along visual lines (including the ones created by soft wrapping) or logical lines.
+    pub fn set_line_navigation(&mut self, line_navigation: LineNavigation) {
+        self.text_box.inner.line_navigation = line_navigation;
+    }
+
+    /// Choose whether the Left/Right arrow keys, Backspace and Delete move over the clusters of the layout or over Unicode grapheme clusters. See [`ClusterMovement`].
+    pub fn set_cluster_movement(&mut self, cluster_movement: ClusterMovement) {
+        self.text_box.inner.cluster_movement = cluster_movement;
+    }
+
+    /// Use custom [`WordRules`] for word movement, word deletion and double click selection, or `None` for the default Unicode word boundaries.
+    pub fn set_word_rules(&mut self, rules: Option<WordRules>) {
+        self.text_box.set_word_rules(rules);
+    }
+
+    /// Enable auto-pairing of brackets and quotes.
+    /// 
+    /// When typing the first character of a pair, the second one is inserted automatically after the cursor. Typing the closing character right before an automatically inserted one skips over it, and pressing backspace between an empty pair deletes both characters.
+    /// 
+    /// Pass an empty slice to disable auto-pairing, or [`DEFAULT_AUTO_PAIRS`] for the usual brackets and quotes.
+    pub fn set_auto_pairs(&mut self, pairs: &[(char, char)]) {
+        self.inner.auto_pairs.clear();
+        self.inner.auto_pairs.extend_from_slice(pairs);
+        self.inner.auto_closers.clear();
+    }
+
+    /// Set the bracket pairs used for bracket matching. The default is [`DEFAULT_BRACKET_PAIRS`].
+    pub fn set_bracket_pairs(&mut self, pairs: &[(char, char)]) {
+        self.inner.bracket_pairs.clear();
+        self.inner.bracket_pairs.extend_from_slice(pairs);
+        self.text_box.shared.decorations_changed = true;
+    }
+
+    /// Outline the bracket next to the cursor and its matching bracket while the edit is focused. The outline uses [`TextEditStyle::bracket_match_color`].
+    pub fn set_highlight_matching_brackets(&mut self, highlight: bool) {
+        if self.inner.highlight_matching_brackets != highlight {
+            self.inner.highlight_matching_brackets = highlight;
+            self.text_box.shared.decorations_changed = true;
+        }
+    }
+
+    /// Move the cursor to the bracket that matches the one next to it, as returned by [`TextEditMut::bracket_pair_at_cursor()`]. The cursor ends up right before the matching bracket, so calling this again jumps back.
+    /// 
+    /// Returns false if the cursor isn't next to a bracket with a match. This is also bound to Ctrl+Shift+\\ (Cmd+Shift+\\ on macOS), see [`EditAction::JumpToMatchingBracket`].
+    pub fn jump_to_matching_bracket(&mut self) -> bool {
+        let Some((_, target)) = self.bracket_pair_at_cursor() else {
+            return false;
+        };
+        let initial_selection = self.text_box.selection();
+        self.text_box.set_selection(
+            Cursor::from_byte_index(&self.text_box.inner.layout, target, Affinity::Downstream).into(),
+        );
+        if initial_selection != self.text_box.selection() {
+            self.text_box.shared.decorations_changed = true;
+        }
+        true
+    }
+
+    /// Set which edits are merged into a single undo step in this text edit, overriding the global policy set with [`Text::set_history_merge_policy()`]. `None` goes back to the global policy.
+    pub fn set_history_merge_policy(&mut self, policy: Option<HistoryMergePolicy>) {
+        self.inner.history_merge_policy = policy;
+    }
+
+    /// Set a filter for text pasted into this text edit. This overrides the global filter set with [`Text::set_paste_filter()`].
+    pub fn set_paste_filter(&mut self, filter: Option<PasteFilter>) {
+        self.inner.paste_filter = filter;
+    }
+
+    /// Set a function that decides how each run of text in pasted HTML is converted to plain text. See [`HtmlSpanHook`].
+    pub fn set_html_paste_hook(&mut self, hook: Option<HtmlSpanHook>) {
+        self.inner.html_paste_hook = hook;
+    }
+
+    /// Show another user's caret and selection inside this text edit.
+    /// 
+    /// If a cursor with the same `id` already exists, it's replaced.
+    ///
+    /// Carets and selections are decorations, so moving them is cheap. Cursors with a `name` also move their label, which is rendered as text and makes the whole scene be prepared again.
+    pub fn set_remote_cursor(&mut self, cursor: RemoteCursor) {
+        let mut has_label = cursor.name.is_some();
+        if let Some(existing) = self.inner.remote_cursors.iter_mut().find(|c| c.id == cursor.id) {
+            has_label |= existing.name.is_some();
+            *existing = cursor;
+        } else {
+            self.inner.remote_cursors.push(cursor);
+        }
+        self.remote_cursors_changed(has_label);
+    }
+
+    pub fn remove_remote_cursor(&mut self, id: u64) {
+        let has_label = self.inner.remote_cursors.iter().any(|c| c.id == id && c.name.is_some());
+        self.inner.remote_cursors.retain(|c| c.id != id);
+        self.remote_cursors_changed(has_label);
+    }
+
+    pub fn clear_remote_cursors(&mut self) {
+        let has_label = self.inner.remote_cursors.iter().any(|c| c.name.is_some());
+        self.inner.remote_cursors.clear();
+        self.remote_cursors_changed(has_label);
+    }
+
+    fn remote_cursors_changed(&mut self, label_changed: bool) {
+        self.text_box.shared.decorations_changed = true;
+        // Name labels are rendered as text.
+        if label_changed {
+            self.text_box.shared.text_changed = true;
+        }
     }
 
     #[cfg(feature = "accessibility")]
@@ -228,6 +901,8 @@ impl<'a> TextEditMut<'a> {
         if self.text_box.hidden() {
             return;
         }
+        // Editing must never work on a layout that's still being built in the background.
+        self.refresh_layout();
         
         // Capture initial state for comparison
         let initial_selection = self.text_box.selection();
@@ -235,6 +910,24 @@ impl<'a> TextEditMut<'a> {
         
         let mut scroll_to_cursor = false;
 
+        // Dragging a scrollbar doesn't touch the selection.
+        if self.handle_scrollbar_event(event, input_state) {
+            return;
+        }
+
+        // Clicking while composing ends the composition before the click moves the cursor.
+        if let WindowEvent::MouseInput { state, .. } = event {
+            if state.is_pressed() && self.is_composing() {
+                let kind = self.composition_kind();
+                self.interrupt_composition();
+                if kind == Some(CompositionKind::Ime) {
+                    // Reset the system IME so that it doesn't commit the text that we already resolved.
+                    window.set_ime_allowed(false);
+                    window.set_ime_allowed(true);
+                }
+            }
+        }
+
         if ! self.inner.showing_placeholder {
             let did_scroll = self.text_box.handle_event_no_edit(event, input_state, true);
             if did_scroll {
@@ -243,167 +936,14 @@ impl<'a> TextEditMut<'a> {
         }
 
         match event {
+            WindowEvent::KeyboardInput { event, .. } if event.state.is_pressed() && event.logical_key == Key::Named(NamedKey::Escape) => {
+                self.handle_escape(window);
+            }
             WindowEvent::KeyboardInput { event, .. } if !self.is_composing() => {
                 if !event.state.is_pressed() {
                     return;
                 }
-                #[allow(unused)]
-                let mods_state = input_state.modifiers.state();
-                let shift = mods_state.shift_key();
-                let action_mod = if cfg!(target_os = "macos") {
-                    mods_state.super_key()
-                } else {
-                    mods_state.control_key()
-                };
-
-                // edit action mods
-                if action_mod {
-                    match event.key_without_modifiers() {
-                        Key::Character(c) => {
-                            match c.as_str() {
-                                "x" if !shift => {
-                                    with_clipboard(|cb| {
-                                        if let Some(text) = self.text_box.selected_text() {
-                                            cb.set_text(text.to_owned()).ok();
-                                            self.delete_selection();
-                                            self.text_box.shared.text_changed = true;
-                                        }
-                                    });
-                                }
-                                "v" if !shift => {
-                                    with_clipboard(|cb| {
-                                        let text = cb.get_text().unwrap_or_default();
-                                        self.insert_or_replace_selection(&text);
-                                        self.text_box.shared.text_changed = true;
-                                    });
-                                }
-                                "z" => {
-                                    if shift {
-                                        self.redo();
-                                        self.text_box.shared.text_changed = true;
-                                    } else {
-                                        self.undo();
-                                        self.text_box.shared.text_changed = true;
-                                    }
-                                }
-                                _ => (),
-                            }
-                        }
-                        _ => (),
-                    };
-                }
-
-                match &event.logical_key {
-                    Key::Named(NamedKey::ArrowLeft) => {
-                        if !shift && ! self.inner.showing_placeholder {
-                            scroll_to_cursor = true;
-                            if action_mod {
-                                self.text_box.move_word_left();
-                            } else {
-                                self.text_box.move_left();
-                            }
-                        }
-                    }
-                    Key::Named(NamedKey::ArrowRight) => {
-                        if !shift && ! self.inner.showing_placeholder {
-                            scroll_to_cursor = true;
-                            if action_mod {
-                                self.text_box.move_word_right();
-                            } else {
-                                self.text_box.move_right();
-                            }
-                        }
-                    }
-                    Key::Named(NamedKey::ArrowUp) => {
-                        if !shift && ! self.inner.showing_placeholder {
-                            scroll_to_cursor = true;
-                            if self.inner.single_line {
-                                self.text_box.move_to_text_start();
-                            } else {
-                                self.text_box.move_up();
-                            }
-                        }
-                    }
-                    Key::Named(NamedKey::ArrowDown) => {
-                        if !shift && ! self.inner.showing_placeholder {
-                            scroll_to_cursor = true;
-                            if self.inner.single_line {
-                                self.text_box.move_to_text_end();
-                            } else {
-                                self.text_box.move_down();
-                            }
-                        }
-                    }
-                    Key::Named(NamedKey::Home) => {
-                        if !shift && ! self.inner.showing_placeholder {
-                            scroll_to_cursor = true;
-                            if action_mod {
-                                self.text_box.move_to_text_start();
-                            } else {
-                                self.text_box.move_to_line_start();
-                            }
-                        }
-                    }
-                    Key::Named(NamedKey::End) => {
-                        if !shift && ! self.inner.showing_placeholder {
-                            scroll_to_cursor = true;
-                            if action_mod {
-                                self.text_box.move_to_text_end();
-                            } else {
-                                self.text_box.move_to_line_end();
-                            }
-                        }
-                    }
-                    Key::Named(NamedKey::Delete) => {
-                        if ! self.inner.showing_placeholder {
-                            scroll_to_cursor = true;
-                            if action_mod {
-                                self.delete_word();
-                            } else {
-                                self.delete();
-                            }
-                            self.text_box.shared.text_changed = true;
-                        }
-                    }
-                    Key::Named(NamedKey::Backspace) => {
-                        if ! self.inner.showing_placeholder {
-                            scroll_to_cursor = true;
-                            if action_mod {
-                                self.backdelete_word();
-                            } else {
-                                self.backdelete();
-                            }
-                            self.text_box.shared.text_changed = true;
-                        }
-                    }
-                    Key::Named(NamedKey::Enter) => {
-                        scroll_to_cursor = true;
-                        let newline_mode_matches = match self.inner.newline_mode {
-                            NewlineMode::Enter => !action_mod && !shift,
-                            NewlineMode::ShiftEnter => shift && !action_mod,
-                            NewlineMode::CtrlEnter => action_mod && !shift,
-                            NewlineMode::None => false,
-                        };
-                        
-                        if newline_mode_matches && ! self.inner.single_line {
-                            self.insert_or_replace_selection("\n");
-                            self.text_box.shared.text_changed = true;
-                        }
-                    }
-                    Key::Named(NamedKey::Space) => {
-                        if ! action_mod {
-                            self.insert_or_replace_selection(" ");
-                            self.text_box.shared.text_changed = true;
-                        }
-                    }
-                    Key::Character(s) => {
-                        if ! action_mod {
-                            self.insert_or_replace_selection(&s);
-                            self.text_box.shared.text_changed = true;
-                        }
-                    }
-                    _ => (),
-                }
+                scroll_to_cursor = self.handle_key_input_editable(&KeyInput::from_key_event(event), input_state);
             }
             WindowEvent::Touch(Touch {
                 phase, location, ..
@@ -434,6 +974,7 @@ impl<'a> TextEditMut<'a> {
             }
             WindowEvent::Ime(Ime::Disabled) => {
                 self.clear_compose();
+                self.record_compose_origin();
                 self.text_box.shared.text_changed = true;
             }
             WindowEvent::Ime(Ime::Commit(text)) => {
@@ -441,7 +982,7 @@ impl<'a> TextEditMut<'a> {
                     self.clear_placeholder()
                 }
                 scroll_to_cursor = true;
-                self.insert_or_replace_selection(&text);
+                self.commit_composition(&text);
                 self.text_box.shared.text_changed = true;
             }
             WindowEvent::Ime(Ime::Preedit(text, cursor)) => {
@@ -453,6 +994,13 @@ impl<'a> TextEditMut<'a> {
                 if text.is_empty() {
                     self.clear_compose();
                 } else {
+                    if !self.is_composing() {
+                        self.inner.compose_kind = Some(if self.inner.last_key_was_dead {
+                            CompositionKind::DeadKey
+                        } else {
+                            CompositionKind::Ime
+                        });
+                    }
                     self.set_compose(&text, *cursor);
                     self.set_ime_cursor_area(window);
                 }
@@ -460,8 +1008,235 @@ impl<'a> TextEditMut<'a> {
             _ => {}
         }
 
+        self.finish_event(initial_selection, initial_show_cursor, scroll_to_cursor);
+    }
+
+    /// Same as [`TextEditMut::handle_event()`] for a key press that doesn't come from a `WindowEvent`.
+    pub(crate) fn handle_key_event(&mut self, key: &KeyInput, input_state: &TextInputState) {
+        if self.inner.disabled || self.text_box.hidden() || !key.pressed {
+            return;
+        }
+        // Editing must never work on a layout that's still being built in the background.
+        self.refresh_layout();
+
+        let initial_selection = self.text_box.selection();
+        let initial_show_cursor = self.inner.show_cursor;
+
+        if key.logical_key == Key::Named(NamedKey::Escape) {
+            self.handle_escape_without_window();
+            self.finish_event(initial_selection, initial_show_cursor, false);
+            return;
+        }
+        if self.is_composing() {
+            return;
+        }
+
+        let scroll_to_cursor = self.handle_key_input_editable(key, input_state);
+
+        self.finish_event(initial_selection, initial_show_cursor, scroll_to_cursor);
+    }
+
+    /// Cancel the composition, clear the text or collapse the selection, and report what happened.
+    fn handle_escape(&mut self, window: &Window) {
+        let kind = self.composition_kind();
+        self.handle_escape_without_window();
+        if kind == Some(CompositionKind::Ime) {
+            // Reset the system IME so that it doesn't commit the text that we just cancelled.
+            window.set_ime_allowed(false);
+            window.set_ime_allowed(true);
+        }
+    }
+
+    fn handle_escape_without_window(&mut self) {
+        let outcome = if self.is_composing() {
+            self.cancel_composition();
+            EscapeOutcome::CompositionCancelled
+        } else if self.inner.clear_on_escape && self.inner.single_line && !self.inner.showing_placeholder && !self.text_box.text_inner().is_empty() {
+            self.set_text_with_history(String::new());
+            EscapeOutcome::Cleared
+        } else {
+            self.text_box.collapse_selection();
+            EscapeOutcome::Cancelled
+        };
+        self.text_box.shared.escape_outcome = Some(outcome);
+    }
+
+    /// Handle a key press for selection and editing. Returns true if the view should scroll to the cursor.
+    fn handle_key_input_editable(&mut self, key: &KeyInput, input_state: &TextInputState) -> bool {
+        self.inner.last_key_was_dead = matches!(key.logical_key, Key::Dead(_));
+        let mods = self.text_box.shared.key_bindings.mods(input_state.modifiers.state());
+
+        if self.text_box.inner.column_selection.is_some() && !is_column_selection_key(key, &mods) {
+            self.text_box.clear_column_selection();
+        }
+        if self.text_box.inner.column_selection.is_some() && !self.inner.showing_placeholder {
+            return self.handle_column_key(key, &mods);
+        }
+
+        let action = EditAction::from_key(key, input_state.modifiers.state(), self.text_box.shared.key_bindings);
+        let Some(action) = action.and_then(|action| self.adjust_key_action(action, &mods)) else {
+            return false;
+        };
+        let scroll_to_cursor = self.apply_action_editable(&action);
+        self.text_box.shared.performed_action = Some(action);
+        scroll_to_cursor
+    }
+
+    /// Adjust the action that a key press maps to for the state of this edit. Returns `None` if the edit ignores the key.
+    fn adjust_key_action(&self, action: EditAction, mods: &KeyMods) -> Option<EditAction> {
+        match action {
+            EditAction::ZoomIn | EditAction::ZoomOut | EditAction::ResetZoom if !self.inner.zoom_shortcuts => None,
+            EditAction::Move { motion: Motion::Right, extend: false } if self.inner.ghost_text.is_some() => Some(EditAction::AcceptGhostText),
+            EditAction::AcceptGhostText if self.inner.ghost_text.is_none() => None,
+            EditAction::Newline => {
+                let newline_mode_matches = match self.inner.newline_mode {
+                    NewlineMode::Enter => !mods.action && !mods.shift,
+                    NewlineMode::ShiftEnter => mods.shift && !mods.action,
+                    NewlineMode::CtrlEnter => mods.action && !mods.shift,
+                    NewlineMode::None => false,
+                };
+                newline_mode_matches.then_some(EditAction::Newline)
+            }
+            action => Some(action),
+        }
+    }
+
+    /// Perform an [`EditAction`] as if the keys bound to it were pressed.
+    ///
+    /// This is how replacements returned by input layers are applied, but it can also be called directly, for example from toolbar buttons.
+    pub fn apply_action(&mut self, action: &EditAction) {
+        if self.inner.disabled || self.text_box.hidden() || self.is_composing() {
+            return;
+        }
+
+        let initial_selection = self.text_box.selection();
+        let initial_show_cursor = self.inner.show_cursor;
+
+        self.refresh_layout();
+        let scroll_to_cursor = self.apply_action_editable(action);
+
+        self.finish_event(initial_selection, initial_show_cursor, scroll_to_cursor);
+    }
+
+    /// Returns true if the view should scroll to the cursor.
+    fn apply_action_editable(&mut self, action: &EditAction) -> bool {
+        match action {
+            EditAction::Move { motion, extend } => {
+                if self.inner.showing_placeholder {
+                    return false;
+                }
+                let motion = match motion {
+                    Motion::Up if self.inner.single_line && !extend => Motion::TextStart,
+                    Motion::Down if self.inner.single_line && !extend => Motion::TextEnd,
+                    motion => *motion,
+                };
+                self.text_box.apply_motion(motion, *extend);
+            }
+            EditAction::SelectAll => {
+                if !self.inner.showing_placeholder {
+                    self.text_box.select_all();
+                }
+                return false;
+            }
+            EditAction::Copy => {
+                if !self.inner.showing_placeholder {
+                    self.text_box.copy_selection();
+                }
+                return false;
+            }
+            EditAction::Cut => {
+                if self.inner.showing_placeholder {
+                    return false;
+                }
+                self.cut_selection();
+            }
+            EditAction::Paste => self.paste_from_clipboard(),
+            EditAction::Undo => {
+                self.undo();
+                self.text_box.shared.text_changed = true;
+            }
+            EditAction::Redo => {
+                self.redo();
+                self.text_box.shared.text_changed = true;
+            }
+            EditAction::Insert(s) => {
+                self.insert_typed(s);
+                self.text_box.shared.text_changed = true;
+            }
+            EditAction::Newline => {
+                if !self.inner.single_line {
+                    self.inner.auto_closers.clear();
+                    self.insert_or_replace_selection(self.line_ending().as_str());
+                    self.text_box.shared.text_changed = true;
+                }
+            }
+            EditAction::Backspace | EditAction::BackspaceWord | EditAction::BackspaceSubWord | EditAction::Delete | EditAction::DeleteWord | EditAction::DeleteSubWord => {
+                if self.inner.showing_placeholder {
+                    return false;
+                }
+                match action {
+                    EditAction::Backspace => {
+                        if !self.backdelete_auto_pair() {
+                            self.inner.auto_closers.clear();
+                            self.backdelete();
+                        }
+                    }
+                    EditAction::BackspaceWord => {
+                        self.inner.auto_closers.clear();
+                        self.backdelete_word();
+                    }
+                    EditAction::BackspaceSubWord => {
+                        self.inner.auto_closers.clear();
+                        self.backdelete_sub_word();
+                    }
+                    EditAction::DeleteSubWord => {
+                        self.inner.auto_closers.clear();
+                        self.delete_sub_word();
+                    }
+                    EditAction::Delete => {
+                        self.inner.auto_closers.clear();
+                        self.delete();
+                    }
+                    _ => {
+                        self.inner.auto_closers.clear();
+                        self.delete_word();
+                    }
+                }
+                self.text_box.shared.text_changed = true;
+            }
+            EditAction::JumpToMatchingBracket => {
+                if self.inner.showing_placeholder {
+                    return false;
+                }
+                return self.jump_to_matching_bracket();
+            }
+            EditAction::AcceptGhostText => {
+                if self.inner.ghost_text.is_none() {
+                    return false;
+                }
+                self.accept_ghost_text();
+            }
+            EditAction::ZoomIn => self.zoom_by(ZOOM_STEP),
+            EditAction::ZoomOut => self.zoom_by(1.0 / ZOOM_STEP),
+            EditAction::ResetZoom => self.set_zoom(1.0),
+        }
+        true
+    }
+
+    /// Update the placeholder, folds, ghost text, decorations and scroll position after an event was handled.
+    fn finish_event(&mut self, initial_selection: Selection, initial_show_cursor: bool, scroll_to_cursor: bool) {
         self.restore_placeholder_if_any();
 
+        let focus = self.text_box.selection().focus().index();
+        if self.text_box.inner.folds.iter().any(|range| fold_hides_cursor(range, focus)) {
+            self.unfold_at(focus);
+        }
+
+        // Any other edit or cursor movement dismisses the suggestion.
+        if self.inner.ghost_text.is_some() && self.text_box.selection().text_range() != initial_selection.text_range() {
+            self.set_ghost_text(None);
+        }
+
         if selection_decorations_changed(initial_selection, self.text_box.selection(), initial_show_cursor, self.inner.show_cursor, !self.inner.disabled) {
             self.text_box.shared.decorations_changed = true;
         }
@@ -487,15 +1262,20 @@ impl<'a> TextEditMut<'a> {
 
     /// Insert at cursor, or replace selection.
     fn replace_range_and_record(&mut self, range: Range<usize>, old_selection: Selection, s: &str) {
+        self.record_compose_origin();
         let old_text = &self.text_box.text_inner()[range.clone()];
 
         let new_range_start = range.start;
         let new_range_end = range.start + s.len();
 
+        let policy = self.inner.history_merge_policy.unwrap_or(self.text_box.shared.history_merge_policy);
+        let now = self.text_box.shared.now();
         self.inner.history
-            .record(&old_text, s, old_selection, new_range_start..new_range_end);
+            .record(&old_text, s, old_selection, new_range_start..new_range_end, &policy, now);
 
+        self.shift_ranges(range.clone(), s.len());
         self.text_box.text_mut().replace_range(range, s);
+        self.inner.revision += 1;
         
         if self.inner.single_line {
             self.remove_newlines();
@@ -503,6 +1283,7 @@ impl<'a> TextEditMut<'a> {
     }
 
     fn replace_selection_and_record(&mut self, s: &str) {
+        self.record_compose_origin();
         let old_selection = self.text_box.selection();
 
         let range = self.text_box.selection().text_range();
@@ -511,7 +1292,9 @@ impl<'a> TextEditMut<'a> {
         let new_range_start = range.start;
         let new_range_end = range.start + s.len();
 
-        self.inner.history.record(&old_text, s, old_selection, new_range_start..new_range_end);
+        let policy = self.inner.history_merge_policy.unwrap_or(self.text_box.shared.history_merge_policy);
+        let now = self.text_box.shared.now();
+        self.inner.history.record(&old_text, s, old_selection, new_range_start..new_range_end, &policy, now);
 
         self.replace_selection_inner(s);
     }
@@ -566,7 +1349,14 @@ impl<'a> TextEditMut<'a> {
     pub(crate) fn delete(&mut self) {
         assert!(!self.is_composing());
 
-        if self.text_box.selection().is_collapsed() {
+        if self.text_box.selection().is_collapsed() && self.text_box.inner.cluster_movement == ClusterMovement::Grapheme {
+            let start = self.text_box.selection().focus().index();
+            let end = next_grapheme_boundary(self.text_box.text_inner(), start);
+            if end > start {
+                self.replace_range_and_record(start..end, self.text_box.selection(), "");
+                self.refresh_layout();
+            }
+        } else if self.text_box.selection().is_collapsed() {
             // Upstream cluster range
             if let Some(range) = self
                 .text_box.selection()
@@ -582,33 +1372,248 @@ impl<'a> TextEditMut<'a> {
         } else {
             self.delete_selection();
         }
-    }
+    }
+
+    /// Delete the selection or up to the next word boundary (typical 'ctrl + delete' behavior).
+    pub(crate) fn delete_word(&mut self) {
+        assert!(!self.is_composing());
+
+        if self.text_box.selection().is_collapsed() {
+            let focus = self.text_box.selection().focus();
+            let start = focus.index();
+            let end = match self.text_box.inner.word_rules {
+                Some(rules) => rules.next_boundary(self.text_box.text_inner(), start, rules.sub_words),
+                None => focus.next_logical_word(&self.text_box.layout()).index(),
+            };
+            if self.text_box.text_inner().get(start..end).is_some() {
+                self.replace_range_and_record(start..end, self.text_box.selection(), "");
+                self.refresh_layout();
+                self.text_box.set_selection(
+                    Cursor::from_byte_index(&self.text_box.inner.layout, start, Affinity::Downstream).into(),
+                );
+            }
+        } else {
+            self.delete_selection();
+        }
+    }
+
+    /// Handle a key press while there's a column selection. Only the keys accepted by [`is_column_selection_key()`] get here.
+    fn handle_column_key(&mut self, key: &KeyInput, mods: &KeyMods) -> bool {
+        let Some(column) = self.text_box.inner.column_selection else {
+            return false;
+        };
+        let ranges = column_ranges(&self.text_box.inner.layout, column);
+        if ranges.is_empty() {
+            return false;
+        }
+        let text = self.text_box.text_inner();
+        let any_selected = ranges.iter().any(|range| !range.is_empty());
+
+        let edits: Vec<(Range<usize>, String)> = match &key.logical_key {
+            // Copying was done by the text box already.
+            _ if mods.action && matches!(&key.key_without_modifiers, Key::Character(c) if c == "c") => return false,
+            _ if mods.action => ranges.into_iter().map(|range| (range, String::new())).collect(),
+            Key::Named(NamedKey::Backspace) if !any_selected => ranges
+                .into_iter()
+                .map(|range| match text[..range.start].chars().next_back() {
+                    Some(c) if c != '\n' => (range.start - c.len_utf8()..range.start, String::new()),
+                    _ => (range, String::new()),
+                })
+                .collect(),
+            Key::Named(NamedKey::Delete) if !any_selected => ranges
+                .into_iter()
+                .map(|range| match text[range.end..].chars().next() {
+                    Some(c) if c != '\n' && c != '\r' => (range.start..range.end + c.len_utf8(), String::new()),
+                    _ => (range, String::new()),
+                })
+                .collect(),
+            Key::Named(NamedKey::Backspace | NamedKey::Delete) => ranges.into_iter().map(|range| (range, String::new())).collect(),
+            Key::Named(NamedKey::Space) => ranges.into_iter().map(|range| (range, " ".to_string())).collect(),
+            Key::Character(s) => ranges.into_iter().map(|range| (range, s.to_string())).collect(),
+            _ => return false,
+        };
+
+        if mods.action {
+            self.text_box.copy_selection();
+        }
+        self.replace_column_ranges(edits);
+        true
+    }
+
+    /// Replace a range on each line of the column selection as a single undo step, and shrink the column to a caret after the replacements.
+    fn replace_column_ranges(&mut self, edits: Vec<(Range<usize>, String)>) {
+        let (Some(first), Some(last)) = (edits.first(), edits.last()) else {
+            return;
+        };
+        let (start, end) = (first.0.start, last.0.end);
+
+        let text = self.text_box.text_inner();
+        let mut replacement = String::new();
+        let mut carets = Vec::with_capacity(edits.len());
+        let mut pos = start;
+        for (range, s) in &edits {
+            replacement.push_str(&text[pos..range.start]);
+            replacement.push_str(s);
+            carets.push(start + replacement.len());
+            pos = range.end;
+        }
+        replacement.push_str(&text[pos..end]);
+
+        self.inner.auto_closers.clear();
+        self.replace_range_and_record(start..end, self.text_box.selection(), &replacement);
+        self.refresh_layout();
+
+        let len = self.text_box.text_inner().len();
+        let layout = &self.text_box.inner.layout;
+        let x = Cursor::from_byte_index(layout, carets[0].min(len), Affinity::Downstream).geometry(layout, 1.0).x0 as f32;
+        let focus = Cursor::from_byte_index(layout, carets[carets.len() - 1].min(len), Affinity::Downstream);
+        if let Some(column) = &mut self.text_box.inner.column_selection {
+            column.anchor.0 = x;
+            column.focus.0 = x;
+        }
+        self.text_box.set_selection(focus.into());
+        self.text_box.shared.text_changed = true;
+    }
+
+    /// Delete the selection or up to the next sub-word boundary.
+    pub(crate) fn delete_sub_word(&mut self) {
+        assert!(!self.is_composing());
+
+        if self.text_box.selection().is_collapsed() {
+            let start = self.text_box.selection().focus().index();
+            let end = self.text_box.sub_word_rules().next_boundary(self.text_box.text_inner(), start, true);
+            if end > start {
+                self.replace_range_and_record(start..end, self.text_box.selection(), "");
+                self.refresh_layout();
+                self.text_box.set_selection(
+                    Cursor::from_byte_index(&self.text_box.inner.layout, start, Affinity::Downstream).into(),
+                );
+            }
+        } else {
+            self.delete_selection();
+        }
+    }
+
+    /// Delete the selection or back to the previous sub-word boundary.
+    pub(crate) fn backdelete_sub_word(&mut self) {
+        assert!(!self.is_composing());
+
+        if self.text_box.selection().is_collapsed() {
+            let end = self.text_box.selection().focus().index();
+            let start = self.text_box.sub_word_rules().previous_boundary(self.text_box.text_inner(), end, true);
+            if start < end {
+                self.replace_range_and_record(start..end, self.text_box.selection(), "");
+                self.refresh_layout();
+                self.text_box.set_selection(
+                    Cursor::from_byte_index(&self.text_box.inner.layout, start, Affinity::Downstream).into(),
+                );
+            }
+        } else {
+            self.delete_selection();
+        }
+    }
+
+    /// Insert text typed on the keyboard, handling auto-pairing if it's enabled.
+    fn insert_typed(&mut self, s: &str) {
+        let mut chars = s.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            self.inner.auto_closers.clear();
+            self.insert_or_replace_selection(s);
+            return;
+        };
+
+        if self.inner.auto_pairs.is_empty() || self.inner.showing_placeholder {
+            self.insert_or_replace_selection(s);
+            return;
+        }
 
-    /// Delete the selection or up to the next word boundary (typical 'ctrl + delete' behavior).
-    pub(crate) fn delete_word(&mut self) {
-        assert!(!self.is_composing());
+        let selection = self.text_box.selection();
+        let start = selection.text_range().start;
 
-        if self.text_box.selection().is_collapsed() {
-            let focus = self.text_box.selection().focus();
-            let start = focus.index();
-            let end = focus.next_logical_word(&self.text_box.layout()).index();
-            if self.text_box.text_inner().get(start..end).is_some() {
-                self.replace_range_and_record(start..end, self.text_box.selection(), "");
+        if !selection.is_collapsed() {
+            self.inner.auto_closers.clear();
+        }
+
+        // Typing a closing character over one that was inserted automatically just steps over it.
+        if let Some(&closer) = self.inner.auto_closers.last() {
+            if closer == start && selection.is_collapsed() && self.text_box.text_inner()[closer..].starts_with(c) {
+                self.inner.auto_closers.pop();
                 self.refresh_layout();
                 self.text_box.set_selection(
-                    Cursor::from_byte_index(&self.text_box.inner.layout, start, Affinity::Downstream).into(),
+                    Cursor::from_byte_index(&self.text_box.inner.layout, closer + c.len_utf8(), Affinity::Downstream).into(),
                 );
+                return;
             }
+        }
+
+        // The closers that were already there are shifted by the insertion itself.
+        if let Some(&(_, close)) = self.inner.auto_pairs.iter().find(|(open, _)| *open == c) {
+            let mut pair = String::with_capacity(c.len_utf8() + close.len_utf8());
+            pair.push(c);
+            pair.push(close);
+            self.insert_or_replace_selection(&pair);
+
+            let between = start + c.len_utf8();
+            self.refresh_layout();
+            self.text_box.set_selection(
+                Cursor::from_byte_index(&self.text_box.inner.layout, between, Affinity::Downstream).into(),
+            );
         } else {
-            self.delete_selection();
+            self.insert_or_replace_selection(s);
+        }
+
+        if self.text_box.text_inner()[start..].starts_with(c) && self.inner.auto_pairs.iter().any(|(open, _)| *open == c) {
+            self.inner.auto_closers.push(start + c.len_utf8());
+        }
+    }
+
+    /// If the cursor is between an opening character and a closing character that was inserted automatically, delete both and return `true`.
+    fn backdelete_auto_pair(&mut self) -> bool {
+        let selection = self.text_box.selection();
+        let Some(&closer) = self.inner.auto_closers.last() else {
+            return false;
+        };
+        if !selection.is_collapsed() || selection.focus().index() != closer {
+            return false;
+        }
+
+        let text = self.text_box.text_inner();
+        let Some(open) = text[..closer].chars().next_back() else {
+            return false;
+        };
+        let Some(close) = text[closer..].chars().next() else {
+            return false;
+        };
+        if !self.inner.auto_pairs.contains(&(open, close)) {
+            return false;
         }
+
+        let start = closer - open.len_utf8();
+        let end = closer + close.len_utf8();
+        self.inner.auto_closers.pop();
+        self.replace_range_and_record(start..end, selection, "");
+        self.refresh_layout();
+        self.text_box.set_selection(
+            Cursor::from_byte_index(&self.text_box.inner.layout, start, Affinity::Downstream).into(),
+        );
+        true
     }
 
     /// Delete the selection or the previous cluster (typical ‘backspace’ behavior).
     pub(crate) fn backdelete(&mut self) {
         assert!(!self.is_composing());
 
-        if self.text_box.selection().is_collapsed() {
+        if self.text_box.selection().is_collapsed() && self.text_box.inner.cluster_movement == ClusterMovement::Grapheme {
+            let end = self.text_box.selection().focus().index();
+            let start = previous_grapheme_boundary(self.text_box.text_inner(), end);
+            if start < end {
+                self.replace_range_and_record(start..end, self.text_box.selection(), "");
+                self.refresh_layout();
+                self.text_box.set_selection(
+                    Cursor::from_byte_index(&self.text_box.inner.layout, start, Affinity::Downstream).into(),
+                );
+            }
+        } else if self.text_box.selection().is_collapsed() {
             // Upstream cluster
             if let Some(cluster) = self
                 .text_box.selection()
@@ -650,7 +1655,10 @@ impl<'a> TextEditMut<'a> {
         if self.text_box.selection().is_collapsed() {
             let focus = self.text_box.selection().focus();
             let end = focus.index();
-            let start = focus.previous_logical_word(&self.text_box.layout()).index();
+            let start = match self.text_box.inner.word_rules {
+                Some(rules) => rules.previous_boundary(self.text_box.text_inner(), end, rules.sub_words),
+                None => focus.previous_logical_word(&self.text_box.layout()).index(),
+            };
             if self.text_box.text_inner().get(start..end).is_some() {
                 self.replace_range_and_record(start..end, self.text_box.selection(), "");
                 self.refresh_layout();
@@ -678,10 +1686,20 @@ impl<'a> TextEditMut<'a> {
         debug_assert!(!text.is_empty());
         debug_assert!(cursor.map(|cursor| cursor.1 <= text.len()).unwrap_or(true));
 
-        let start = if let Some(preedit_range) = &self.inner.compose {
+        let start = if let Some(preedit_range) = self.inner.compose.clone() {
+            self.shift_ranges(preedit_range.clone(), text.len());
             self.text_box.text_mut().replace_range(preedit_range.clone(), text);
             preedit_range.start
         } else {
+            self.record_compose_origin();
+            let replaced = self.text_box.text_inner()[self.text_box.selection().text_range()].to_string();
+            // The preedit text doesn't count as an edit, but removing the selected text does.
+            if !replaced.is_empty() {
+                self.inner.revision += 1;
+            }
+            self.inner.compose_origin = Some((self.text_box.selection(), replaced));
+
+            self.shift_ranges(self.text_box.selection().text_range(), text.len());
             let selection_start = self.text_box.selection().text_range().start;
             if self.text_box.selection().is_collapsed() {
                 self.text_box.text_mut()
@@ -716,11 +1734,49 @@ impl<'a> TextEditMut<'a> {
         self.text_box.inner.needs_relayout = true;
     }
 
+    /// Insert the text committed by an IME as a single undo step, together with the selected text that the composition replaced.
+    fn commit_composition(&mut self, text: &str) {
+        // Partial commits and commits that follow typed text aren't merged with the surrounding edits.
+        self.inner.history.stop_merging();
+        if let Some((selection, replaced)) = self.inner.compose_origin.take() {
+            self.clear_placeholder();
+            let start = self.text_box.selection().text_range().start;
+            let policy = self.inner.history_merge_policy.unwrap_or(self.text_box.shared.history_merge_policy);
+            let now = self.text_box.shared.now();
+            self.inner.history.record(&replaced, text, selection, start..start + text.len(), &policy, now);
+            self.replace_selection_inner(text);
+        } else {
+            self.insert_or_replace_selection(text);
+        }
+        self.inner.history.stop_merging();
+    }
+
+    /// If a composition that replaced some selected text ended without a commit, record the deletion of that text. This has to happen before anything else is recorded, so that the history matches the text.
+    fn record_compose_origin(&mut self) {
+        if self.is_composing() {
+            return;
+        }
+        let Some((selection, replaced)) = self.inner.compose_origin.take() else {
+            return;
+        };
+        if replaced.is_empty() {
+            return;
+        }
+        let start = selection.text_range().start;
+        let policy = self.inner.history_merge_policy.unwrap_or(self.text_box.shared.history_merge_policy);
+        let now = self.text_box.shared.now();
+        self.inner.history.stop_merging();
+        // The text was already removed when the composition started, and the revision was changed then.
+        self.inner.history.record(&replaced, "", selection, start..start, &policy, now);
+    }
+
     /// Stop IME composing.
     ///
     /// This removes the IME preedit text.
     pub(crate) fn clear_compose(&mut self) {
+        self.inner.compose_kind = None;
         if let Some(preedit_range) = self.inner.compose.take() {
+            self.shift_ranges(preedit_range.clone(), 0);
             self.text_box.text_mut().replace_range(preedit_range.clone(), "");
             self.inner.show_cursor = true;
 
@@ -769,13 +1825,18 @@ impl<'a> TextEditMut<'a> {
         if self.is_composing() {
             return;
         }
+        self.record_compose_origin();
 
-        if let Some(op) = self.inner.history.undo(self.text_box.text_mut()) {
+        let current_selection = self.text_box.selection();
+        if let Some(op) = self.inner.history.undo(self.text_box.text_mut(), current_selection) {
 
             if ! op.text_to_restore.is_empty() {
                 clear_placeholder!(self);
             }
 
+            shift_fold_ranges(&mut self.text_box.inner.folds, op.range_to_clear.clone(), op.text_to_restore.len());
+            shift_meta_ranges(&mut self.text_box.inner.meta_ranges, op.range_to_clear.clone(), op.text_to_restore.len());
+            self.inner.revision += 1;
             self
                 .text_box.text_mut()
                 .replace_range(op.range_to_clear.clone(), "");
@@ -783,8 +1844,9 @@ impl<'a> TextEditMut<'a> {
                 .text_box.text_mut()
                 .insert_str(op.range_to_clear.start, op.text_to_restore);
 
-            let prev_selection = op.prev_selection;
-            self.text_box.set_selection(prev_selection);
+            if let Some(selection) = op.selection {
+                self.text_box.set_selection(selection);
+            }
             
             if self.inner.single_line {
                 self.remove_newlines();
@@ -798,6 +1860,9 @@ impl<'a> TextEditMut<'a> {
         }
 
         if let Some(op) = self.inner.history.redo() {
+            shift_fold_ranges(&mut self.text_box.inner.folds, op.range_to_clear.clone(), op.text_to_restore.len());
+            shift_meta_ranges(&mut self.text_box.inner.meta_ranges, op.range_to_clear.clone(), op.text_to_restore.len());
+            self.inner.revision += 1;
             self
                 .text_box.text_mut()
                 .replace_range(op.range_to_clear.clone(), "");
@@ -811,9 +1876,13 @@ impl<'a> TextEditMut<'a> {
                 .insert_str(op.range_to_clear.start, op.text_to_restore);
 
             let end = op.range_to_clear.start + op.text_to_restore.len();
+            let selection = op.selection;
 
             self.refresh_layout();
-            self.text_box.inner.selection.selection = Cursor::from_byte_index(&self.text_box.inner.layout, end, Affinity::Upstream).into();
+            self.text_box.inner.selection.selection = match selection {
+                Some(selection) => selection,
+                None => Cursor::from_byte_index(&self.text_box.inner.layout, end, Affinity::Upstream).into(),
+            };
             
             if self.inner.single_line {
                 self.remove_newlines();
@@ -824,6 +1893,8 @@ impl<'a> TextEditMut<'a> {
     pub fn replace_selection_inner(&mut self, s: &str) {
         let range = self.text_box.selection().text_range();
         let start = range.start;
+        self.inner.revision += 1;
+        self.shift_ranges(range.clone(), s.len());
         if self.text_box.selection().is_collapsed() {
             self.text_box.text_mut().insert_str(start, s);
             
@@ -894,6 +1965,29 @@ impl<'a> TextEditMut<'a> {
 }
 
 
+/// Controls which consecutive edits are merged into a single undo step.
+/// 
+/// Set it for all text edits with [`Text::set_history_merge_policy()`], or for a single one with [`TextEditMut::set_history_merge_policy()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryMergePolicy {
+    /// Edits stop being merged into an undo step once it holds this many bytes of inserted or deleted text. With `None`, a word is merged into a single step however long it is.
+    pub max_merged_size: Option<usize>,
+    /// If more than this much time passes between two edits, the second one starts a new undo step. With `None`, timing doesn't matter.
+    pub pause: Option<Duration>,
+    /// Start a new undo step at each word. Typing something other than whitespace after whitespace starts a new step, and so does deleting past whitespace. With `false`, only `max_merged_size`, `pause` and large edits like pastes split steps.
+    pub word_boundaries: bool,
+}
+
+impl Default for HistoryMergePolicy {
+    fn default() -> Self {
+        Self {
+            max_merged_size: None,
+            pause: None,
+            word_boundaries: true,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct TextEditHistory {
     undo_text: String,
@@ -901,6 +1995,7 @@ pub(crate) struct TextEditHistory {
     history: Vec<RecordedOp>,
     current_position: usize,
     can_grow: GrowHint,
+    last_record_time: Option<Instant>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -921,6 +2016,8 @@ struct RecordedOp {
     redo: Option<Ranges>,
     /// State of the selection right before this operation.
     prev_selection: Selection,
+    /// State of the selection right before this operation was undone, so that redoing it puts the selection back.
+    redo_selection: Option<Selection>,
 }
 
 /// Internal Data for an undo or redo operation.
@@ -948,10 +2045,9 @@ struct TextRestore<'a> {
     range_to_clear: Range<usize>,
     /// Text that should be inserted in the place of the cleared range.
     text_to_restore: &'a str,
-    /// The state of selection right before the operation was made.
-    /// Typically, undo operations restore the selection to this stored value,
-    /// while redo operations ignore it and place a collapsed selection at the end of the newly restored text.
-    prev_selection: Selection,
+    /// The selection to restore: for undo, the state right before the operation was made, and for redo, the state right before it was undone.
+    /// `None` for redo operations recorded without one, which place a collapsed selection at the end of the newly restored text.
+    selection: Option<Selection>,
 }
 
 impl TextEditHistory {
@@ -962,6 +2058,7 @@ impl TextEditHistory {
             history: Vec::with_capacity(64),
             current_position: 0,
             can_grow: GrowHint::CannotGrow,
+            last_record_time: None,
         }
     }
 }
@@ -986,6 +2083,7 @@ impl WhitespaceStr for &str {
 }
 
 impl TextEditHistory {
+    /// Edits at least this large, like pastes, aren't merged with the next one.
     const MAX_GROWABLE_SIZE: usize = 20;
 
     #[rustfmt::skip]
@@ -995,6 +2093,8 @@ impl TextEditHistory {
         new_str: &str,
         selection: Selection,
         inserted_range: Range<usize>,
+        policy: &HistoryMergePolicy,
+        now: Instant,
     ) {
         if self.current_position < self.history.len() {
             let undo_trunc = self.history[self.current_position].undo.deleted_range.start;
@@ -1003,22 +2103,29 @@ impl TextEditHistory {
             self.history.truncate(self.current_position);
         }
 
-        if let Some(last) = self.history.last_mut() {
+        let paused = match (policy.pause, self.last_record_time) {
+            (Some(pause), Some(last)) => now.saturating_duration_since(last) > pause,
+            _ => false,
+        };
+        self.last_record_time = Some(now);
+        let step_fits = |step_size: usize| policy.max_merged_size.is_none_or(|max_size| step_size < max_size);
+
+        if let Some(last) = self.history.last_mut().filter(|_| !paused) {
             match self.can_grow {
                 GrowHint::GrowableInsert(size) 
-                    if old_str.is_empty() && size < Self::MAX_GROWABLE_SIZE =>
+                    if old_str.is_empty() && size < Self::MAX_GROWABLE_SIZE && step_fits(last.undo.inserted_range.len()) =>
                         last.undo.inserted_range.end = inserted_range.end,
 
                 GrowHint::GrowableInsertWhitespace(size) 
-                    if old_str.is_empty() && new_str.is_whitespace() && size < Self::MAX_GROWABLE_SIZE =>
+                    if old_str.is_empty() && new_str.is_whitespace() && size < Self::MAX_GROWABLE_SIZE && step_fits(last.undo.inserted_range.len()) =>
                         last.undo.inserted_range.end = inserted_range.end,
 
                 GrowHint::GrowableDelete(size)
-                    if inserted_range.is_empty() && size < Self::MAX_GROWABLE_SIZE =>
+                    if inserted_range.is_empty() && size < Self::MAX_GROWABLE_SIZE && step_fits(last.undo.deleted_range.len()) =>
                         self.merge_delete(old_str, inserted_range),
 
                 GrowHint::GrowableDeleteWhitespace(size)
-                    if inserted_range.is_empty() && old_str.is_whitespace() && size < Self::MAX_GROWABLE_SIZE =>
+                    if inserted_range.is_empty() && old_str.is_whitespace() && size < Self::MAX_GROWABLE_SIZE && step_fits(last.undo.deleted_range.len()) =>
                         self.merge_delete(old_str, inserted_range),
 
                 _ => {
@@ -1029,7 +2136,12 @@ impl TextEditHistory {
             self.push_new(old_str, selection, inserted_range);
         }
 
-        self.set_grow_hint(new_str, old_str);
+        self.set_grow_hint(new_str, old_str, policy.word_boundaries);
+    }
+
+    /// Make sure that the next edit starts a new step instead of being merged into the last one.
+    pub fn stop_merging(&mut self) {
+        self.can_grow = GrowHint::CannotGrow;
     }
 
     pub fn push_new(&mut self, old_str: &str, selection: Selection, inserted_range: Range<usize>) {
@@ -1037,6 +2149,7 @@ impl TextEditHistory {
 
         self.history.push(RecordedOp {
             prev_selection: selection,
+            redo_selection: None,
             undo: Ranges {
                 inserted_range,
                 deleted_range: undo_range,
@@ -1057,20 +2170,20 @@ impl TextEditHistory {
         last.undo.inserted_range = inserted_range.clone();
     }
 
-    fn set_grow_hint(&mut self, new_str: &str, old_str: &str) {
+    fn set_grow_hint(&mut self, new_str: &str, old_str: &str, word_boundaries: bool) {
         let last_op = &self.history.last().unwrap().undo;
 
         self.can_grow = if last_op.is_insert_only() {
             let len = new_str.len();
             match new_str.chars().last() {
-                Some(c) if c.is_whitespace() => GrowHint::GrowableInsertWhitespace(len),
+                Some(c) if c.is_whitespace() && word_boundaries => GrowHint::GrowableInsertWhitespace(len),
                 Some(_) => GrowHint::GrowableInsert(len),
                 None => GrowHint::CannotGrow,
             }
         } else if last_op.is_delete_only() {
             let len = old_str.len();
             match old_str.chars().last() {
-                Some(c) if c.is_whitespace() => GrowHint::GrowableDeleteWhitespace(len),
+                Some(c) if c.is_whitespace() && word_boundaries => GrowHint::GrowableDeleteWhitespace(len),
                 Some(_) => GrowHint::GrowableDelete(len),
                 None => GrowHint::CannotGrow,
             }
@@ -1079,15 +2192,16 @@ impl TextEditHistory {
         };
     }
 
-    fn undo(&mut self, buffer: &String) -> Option<TextRestore<'_>> {
+    fn undo(&mut self, buffer: &String, current_selection: Selection) -> Option<TextRestore<'_>> {
         if self.current_position > 0 {
             self.current_position -= 1;
             let last = &mut self.history[self.current_position];
+            last.redo_selection = Some(current_selection);
 
             // Prepare the undo to return
             let undo_text = last.undo.deleted_range.clone();
             let undo = TextRestore {
-                prev_selection: last.prev_selection,
+                selection: Some(last.prev_selection),
                 range_to_clear: last.undo.inserted_range.clone(),
                 text_to_restore: &self.undo_text[undo_text.clone()],
             };
@@ -1103,7 +2217,6 @@ impl TextEditHistory {
                     deleted_range: redo_range,
                 });
             }
-            // todo: if possible, put a nice prev_selection here so the caller doesn't have to think about it
 
             Some(undo)
         } else {
@@ -1120,13 +2233,34 @@ impl TextEditHistory {
         let old_text = redo.deleted_range;
 
         Some(TextRestore {
-            prev_selection: last.prev_selection,
+            selection: last.redo_selection,
             range_to_clear: redo.inserted_range,
             text_to_restore: &self.redo_text[old_text],
         })
     }
 }
 
+/// Map a byte offset in `old` to the corresponding byte offset in `new`, given the ops of a character diff between the two.
+fn map_offset_through_diff(ops: &[similar::DiffOp], old: &str, new: &str, offset: usize) -> usize {
+    let char_index = old[..floor_char_boundary(old, offset)].chars().count();
+    let mut new_char_index = None;
+    for op in ops {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        if char_index > old_range.end || (char_index == old_range.end && !old_range.is_empty()) {
+            continue;
+        }
+        new_char_index = Some(match tag {
+            similar::DiffTag::Equal => new_range.start + (char_index - old_range.start),
+            _ => new_range.start,
+        });
+        break;
+    }
+    match new_char_index {
+        Some(index) => new.char_indices().nth(index).map_or(new.len(), |(i, _)| i),
+        None => new.len(),
+    }
+}
+
 /// Replace newlines with spaces in-place. This probably doesn't allocate.
 fn remove_newlines_inplace(text: &mut String) -> bool {
     let mut changed = false;
@@ -1211,15 +2345,83 @@ impl_for_textedit_and_texteditmut! {
     }
 }
 
+#[cfg(feature = "modal")]
+impl_for_textedit_and_texteditmut! {
+    /// The current mode, or `None` if modal editing is disabled. See [`TextEditMut::set_modal_editing()`].
+    pub fn modal_mode(&self) -> Option<ModalMode> {
+        self.inner.modal.as_ref().map(|modal| modal.mode)
+    }
+}
+
 impl_for_textedit_and_texteditmut! {
     pub fn is_composing(&self) -> bool {
         self.inner.compose.is_some()
     }
 
+    pub fn line_ending_policy(&self) -> LineEndingPolicy {
+        self.inner.line_ending_policy
+    }
+
+    /// The line ending that Enter inserts, according to the [`LineEndingPolicy`].
+    pub fn line_ending(&self) -> LineEnding {
+        match self.inner.line_ending_policy {
+            LineEndingPolicy::Preserve => LineEnding::Lf,
+            LineEndingPolicy::Always(ending) => ending,
+            LineEndingPolicy::Detect => self.detected_line_ending().unwrap_or_default(),
+        }
+    }
+
+    /// The line ending used by most lines of the current text, or `None` if it has no line breaks.
+    pub fn detected_line_ending(&self) -> Option<LineEnding> {
+        if self.inner.showing_placeholder {
+            return None;
+        }
+        LineEnding::detect(self.text_box.text_inner())
+    }
+
+    pub fn suspicious_char_policy(&self) -> SuspiciousCharPolicy {
+        self.inner.suspicious_char_policy
+    }
+
+    /// The suspicious characters that were found in the last pasted or loaded text. Ranges are relative to that text, before stripping.
+    pub fn suspicious_char_report(&self) -> &[SuspiciousChar] {
+        &self.inner.suspicious_char_report
+    }
+
+    /// The suspicious characters in the current text.
+    pub fn suspicious_chars(&self) -> Vec<SuspiciousChar> {
+        if self.inner.showing_placeholder {
+            return Vec::new();
+        }
+        find_suspicious_chars(self.text_box.text_inner())
+    }
+
+    /// A number that changes every time the text is changed by an edit, an undo or redo, or a call like [`TextEditMut::set_text()`]. Moving the cursor, IME preedit text and placeholders don't change it.
+    /// 
+    /// Store it when the document is saved, then use [`TextEdit::is_modified_since()`] to show an "unsaved changes" indicator or to decide when to autosave.
+    pub fn revision(&self) -> u64 {
+        self.inner.revision
+    }
+
+    /// Returns true if the text was changed after [`TextEdit::revision()`] returned `revision`.
+    /// 
+    /// Undoing back to the saved state still counts as a change.
+    pub fn is_modified_since(&self, revision: u64) -> bool {
+        self.inner.revision != revision
+    }
+
     pub fn single_line(&self) -> bool {
         self.inner.single_line
     }
 
+    pub fn clear_on_escape(&self) -> bool {
+        self.inner.clear_on_escape
+    }
+
+    pub fn has_error(&self) -> bool {
+        self.inner.error
+    }
+
     pub fn newline_mode(&self) -> NewlineMode {
         self.inner.newline_mode
     }
@@ -1234,7 +2436,7 @@ impl_for_textedit_and_texteditmut! {
 
     pub fn next_blink_time(&self) -> Option<Instant> {
         self.inner.start_time.map(|start_time| {
-            let phase = Instant::now().duration_since(start_time);
+            let phase = self.text_box.shared.now().duration_since(start_time);
 
             start_time
                 + Duration::from_nanos(
@@ -1263,6 +2465,10 @@ impl_for_textedit_and_texteditmut! {
     pub fn depth(&self) -> f32 {
         self.text_box.depth()
     }
+
+    pub fn group(&self) -> Option<GroupHandle> {
+        self.text_box.group()
+    }
     
     pub fn clip_rect(&self) -> Option<parley::Rect> {
         self.text_box.clip_rect()
@@ -1272,6 +2478,10 @@ impl_for_textedit_and_texteditmut! {
         self.text_box.fadeout_clipping()
     }
     
+    pub fn fadeout_style(&self) -> Option<FadeoutStyle> {
+        self.text_box.fadeout_style()
+    }
+    
     pub fn auto_clip(&self) -> bool {
         self.text_box.auto_clip()
     }
@@ -1283,6 +2493,31 @@ impl_for_textedit_and_texteditmut! {
     pub fn selection(&self) -> Selection {
         self.text_box.selection()
     }
+
+    pub fn remote_cursors(&self) -> &[RemoteCursor] {
+        &self.inner.remote_cursors
+    }
+
+    pub fn auto_pairs(&self) -> &[(char, char)] {
+        &self.inner.auto_pairs
+    }
+
+    /// Byte ranges of the currently folded text, sorted by position.
+    pub fn folds(&self) -> &[Range<usize>] {
+        &self.text_box.inner.folds
+    }
+
+    pub fn shows_invisibles(&self) -> bool {
+        self.inner.show_invisibles
+    }
+
+    pub fn ghost_text(&self) -> Option<&str> {
+        self.inner.ghost_text.as_ref().map(|(_, text)| text.as_str())
+    }
+
+    pub fn ime_purpose(&self) -> ImePurpose {
+        self.inner.ime_purpose
+    }
 }
 
 /// A text edit with access to both inner data and style.
@@ -1323,12 +2558,28 @@ impl<'a> TextEditMut<'a> {
     }
 
     pub fn raw_text_mut(&mut self) -> &mut String {
+        self.inner.revision += 1;
         self.text_box.text_mut()
     }
 
     pub fn set_pos(&mut self, pos: (f64, f64)) {
         self.text_box.set_pos(pos);
     }
+
+    /// Position the box by the baseline of its first line. See [`TextBoxMut::set_position_baseline()`].
+    pub fn set_position_baseline(&mut self, x: f64, baseline_y: f64) {
+        self.text_box.set_position_baseline(x, baseline_y);
+    }
+
+    pub fn first_baseline(&mut self) -> f32 {
+        self.refresh_layout();
+        self.text_box.first_baseline()
+    }
+
+    pub fn last_baseline(&mut self) -> f32 {
+        self.refresh_layout();
+        self.text_box.last_baseline()
+    }
     
     pub fn set_hidden(&mut self, hidden: bool) {
         self.text_box.set_hidden(hidden);
@@ -1337,6 +2588,24 @@ impl<'a> TextEditMut<'a> {
     pub fn set_depth(&mut self, value: f32) {
         self.text_box.set_depth(value);
     }
+
+    /// Attach app-defined values to byte ranges of the text. See [`TextBoxMut::set_meta_ranges()`].
+    pub fn set_meta_ranges(&mut self, ranges: &[(Range<usize>, u64)]) {
+        self.text_box.set_meta_ranges(ranges);
+    }
+
+    /// See [`TextBoxMut::meta_at_point()`]. The placeholder has no meta ranges.
+    pub fn meta_at_point(&self, x: f64, y: f64) -> Option<u64> {
+        if self.inner.showing_placeholder {
+            return None;
+        }
+        self.text_box.meta_at_point(x, y)
+    }
+
+    /// Put the text edit in a group created with [`Text::add_group()`], or remove it from its group with `None`.
+    pub fn set_group(&mut self, group: Option<GroupHandle>) {
+        self.text_box.set_group(group);
+    }
     
     pub fn set_clip_rect(&mut self, clip_rect: Option<parley::Rect>) {
         self.text_box.set_clip_rect(clip_rect);
@@ -1346,6 +2615,11 @@ impl<'a> TextEditMut<'a> {
         self.text_box.set_fadeout_clipping(fadeout_clipping);
     }
     
+    /// Set the fade for this text edit only, overriding the [`TextEditStyle::fadeout`] of its style. See [`TextBoxMut::set_fadeout_style()`].
+    pub fn set_fadeout_style(&mut self, style: Option<FadeoutStyle>) {
+        self.text_box.set_fadeout_style(style);
+    }
+    
     pub fn set_scroll_offset(&mut self, offset: (f32, f32)) {
         self.text_box.set_scroll_offset(offset);
     }
@@ -1354,13 +2628,13 @@ impl<'a> TextEditMut<'a> {
     /// Returns true if scroll offset was changed
     fn apply_horizontal_scroll(&mut self, new_scroll: f32) -> bool {
         let old_scroll = self.text_box.inner.scroll_offset.0;
-        let total_text_width = self.text_box.inner.layout.full_width();
-        let text_width = self.text_box.inner.max_advance;
-        let max_scroll = (total_text_width - text_width).max(0.0).round() + CURSOR_WIDTH;
+        let max_scroll = max_horizontal_scroll(self.text_box.inner);
         let clamped_scroll = new_scroll.clamp(0.0, max_scroll).round();
         
         if clamped_scroll != old_scroll {
             self.text_box.inner.scroll_offset.0 = clamped_scroll;
+            self.text_box.inner.scroll_moved = true;
+            self.text_box.shared.scrolled = true;
             true
         } else {
             false
@@ -1377,8 +2651,7 @@ impl<'a> TextEditMut<'a> {
                 let cursor_left = cursor_rect.x0 as f32;
                 let cursor_right = cursor_rect.x1 as f32;
                 let current_scroll = self.text_box.scroll_offset().0;
-                let total_text_width = self.text_box.inner.layout.full_width();
-                let max_scroll = (total_text_width - text_width).max(0.0).round() + CURSOR_WIDTH;
+                let max_scroll = max_horizontal_scroll(self.text_box.inner);
                 
                 // Sticky max scroll: if we're at max scroll, try to stay there
                 if current_scroll >= max_scroll {
@@ -1396,7 +2669,7 @@ impl<'a> TextEditMut<'a> {
                 }
             } else {
                 // Vertical scrolling for multi-line edits
-                let text_height = self.text_box.inner.height;
+                let text_height = self.unobscured_height(cursor_rect.height() as f32);
                 let cursor_top = cursor_rect.y0 as f32;
                 let cursor_bottom = cursor_rect.y1 as f32;
                 let current_scroll = self.text_box.scroll_offset().1;
@@ -1435,6 +2708,20 @@ impl<'a> TextEditMut<'a> {
         false
     }
     
+    /// The height of the part of the box that's not covered by the obscured area set with [`Text::set_obscured_area()`], but at least `min_height`.
+    fn unobscured_height(&self, min_height: f32) -> f32 {
+        let inner = &self.text_box.inner;
+        let Some(area) = self.text_box.shared.obscured_area else {
+            return inner.height;
+        };
+        let overlaps_horizontally = area.x0 < inner.left + inner.max_advance as f64 && area.x1 > inner.left;
+        let overlaps_vertically = area.y0 < inner.top + inner.height as f64 && area.y1 > inner.top;
+        if !(overlaps_horizontally && overlaps_vertically) {
+            return inner.height;
+        }
+        ((area.y0 - inner.top) as f32).clamp(min_height.min(inner.height), inner.height)
+    }
+
     pub fn set_style(&mut self, style: &StyleHandle) {
         self.text_box.set_style(style);
     }
@@ -1452,43 +2739,288 @@ impl<'a> TextEditMut<'a> {
         self.refresh_layout();
         self.text_box.selection_geometry()
     }
+
+    /// Get the points where the text was soft-wrapped. See [`TextBoxMut::wrap_points()`].
+    pub fn wrap_points(&mut self) -> Vec<WrapPoint> {
+        self.refresh_layout();
+        self.text_box.wrap_points()
+    }
+
+    pub fn line_count(&mut self) -> usize {
+        self.refresh_layout();
+        self.text_box.line_count()
+    }
+
+    /// Get the geometry of each laid out line. See [`TextBoxMut::lines()`].
+    ///
+    /// While the placeholder is shown, this describes the placeholder's lines.
+    pub fn lines(&mut self) -> Vec<LineInfo> {
+        self.refresh_layout();
+        self.text_box.lines()
+    }
+
+    /// Get the size of the laid out text. See [`TextBoxMut::content_size()`].
+    pub fn content_size(&mut self) -> (f32, f32) {
+        self.refresh_layout();
+        self.text_box.content_size()
+    }
+
+    pub fn is_overflowing(&mut self) -> bool {
+        self.refresh_layout();
+        self.text_box.is_overflowing()
+    }
+
+    /// Get the byte range of the text that is at least partly visible. See [`TextBoxMut::visible_byte_range()`].
+    pub fn visible_byte_range(&mut self) -> Range<usize> {
+        self.refresh_layout();
+        self.text_box.visible_byte_range()
+    }
+
+    /// Get the caret rectangle for the byte offset `index`, in window coordinates.
+    /// 
+    /// This accounts for the box position and its scroll offset, so it can be used to place popups like autocomplete dropdowns right next to a piece of text.
+    pub fn anchor_rect_for_byte(&mut self, index: usize) -> Rect {
+        self.refresh_layout();
+        let index = floor_char_boundary(self.text_box.text_inner(), index);
+        let rect = Cursor::from_byte_index(&self.text_box.inner.layout, index, Affinity::Downstream).geometry(&self.text_box.inner.layout, 1.0);
+
+        let (scroll_x, scroll_y) = self.text_box.scroll_offset();
+        let dx = self.text_box.inner.left - scroll_x as f64;
+        let dy = self.text_box.inner.top - scroll_y as f64;
+        Rect {
+            x0: rect.x0 + dx,
+            y0: rect.y0 + dy,
+            x1: rect.x1 + dx,
+            y1: rect.y1 + dy,
+        }
+    }
+
+    /// Get the byte offset of the bracket that matches the one at `byte`, using the pairs set with [`TextEditMut::set_bracket_pairs()`].
+    /// 
+    /// Returns `None` if there's no bracket at `byte`, or if it isn't balanced. Only brackets of the same pair are counted, so `(` matches the right `)` even if there are unbalanced `[` in between.
+    pub fn matching_bracket(&self, byte: usize) -> Option<usize> {
+        if self.inner.showing_placeholder {
+            return None;
+        }
+        let text = self.text_box.text_inner();
+        let c = text.get(byte..)?.chars().next()?;
+
+        if let Some(&(open, close)) = self.inner.bracket_pairs.iter().find(|(open, close)| *open == c && *close != c) {
+            let mut depth = 0usize;
+            for (i, c) in text[byte..].char_indices() {
+                if c == open {
+                    depth += 1;
+                } else if c == close {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(byte + i);
+                    }
+                }
+            }
+        } else if let Some(&(open, close)) = self.inner.bracket_pairs.iter().find(|(open, close)| *close == c && *open != c) {
+            let mut depth = 0usize;
+            for (i, c) in text[..byte + c.len_utf8()].char_indices().rev() {
+                if c == close {
+                    depth += 1;
+                } else if c == open {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Get the bracket right after the cursor, or right before it, together with its match, as `(bracket, matching_bracket)` byte offsets. Returns `None` if there's a selection.
+    pub fn bracket_pair_at_cursor(&self) -> Option<(usize, usize)> {
+        let selection = self.text_box.selection();
+        if !selection.is_collapsed() {
+            return None;
+        }
+        let index = selection.focus().index();
+        if let Some(found) = self.matching_bracket(index) {
+            return Some((index, found));
+        }
+        let before = self.text_box.text_inner().get(..index)?.char_indices().next_back()?.0;
+        self.matching_bracket(before).map(|found| (before, found))
+    }
+
+    /// Get the word that the cursor is in or right after, for example the token being completed by an autocomplete popup.
+    /// 
+    /// Words are runs of alphanumeric characters and underscores. Returns `None` if there's a selection or if the cursor isn't touching a word.
+    pub fn word_under_cursor(&mut self) -> Option<WordUnderCursor> {
+        if self.inner.showing_placeholder || !self.text_box.selection().is_collapsed() {
+            return None;
+        }
+
+        let text = self.text_box.text_inner();
+        let cursor = self.text_box.selection().focus().index().min(text.len());
+        let rules = self.text_box.inner.word_rules.unwrap_or_default();
+        let is_word_char = |c: char| rules.is_word_char(c);
+
+        let start = text[..cursor]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| is_word_char(*c))
+            .last()
+            .map(|(i, _)| i)
+            .unwrap_or(cursor);
+        let end = text[cursor..]
+            .char_indices()
+            .find(|(_, c)| !is_word_char(*c))
+            .map(|(i, _)| cursor + i)
+            .unwrap_or(text.len());
+
+        if start == end {
+            return None;
+        }
+
+        let rect = self.anchor_rect_for_byte(start);
+        Some(WordUnderCursor { range: start..end, rect })
+    }
     
     pub fn selection_geometry_with(&mut self, f: impl FnMut(Rect, usize)) {
         self.refresh_layout();
         self.text_box.selection_geometry_with(f)
     }
 
+    /// Bring the layout up to date with the text, waiting for a layout that's being built in the background if needed.
     pub fn refresh_layout(&mut self) {
-        let color_override = if self.inner.disabled {
-            Some(self.text_edit_style().disabled_text_color)
-        } else if self.inner.showing_placeholder {
-            Some(self.text_edit_style().placeholder_text_color)
-        } else {
-            None
-        };
+        let color_override = self.color_override();
+        self.text_box.refresh_layout_with(color_override, self.inner.single_line, false);
+    }
+
+    /// Like `refresh_layout()`, but long texts can be laid out on the worker thread. Only for drawing.
+    pub(crate) fn refresh_layout_for_drawing(&mut self) {
+        let color_override = self.color_override();
+        self.text_box.refresh_layout_with(color_override, self.inner.single_line, true);
+    }
+
+    /// Like `refresh_layout()`, but the layout is built on the worker thread. Returns true if a layout job was started.
+    pub(crate) fn refresh_layout_in_background(&mut self) -> bool {
+        self.text_box.poll_pending_layout();
+        let color_override = self.color_override();
 
         if self.text_box.inner.needs_relayout || self.style_version_changed() {
             if self.style_version_changed() {
                 self.text_box.inner.style_version = self.style_version();
             }
-            self.text_box.rebuild_layout(color_override, self.inner.single_line);
+            self.text_box.send_layout_job(color_override, self.inner.single_line);
+            return true;
+        }
+        false
+    }
+
+    fn color_override(&self) -> Option<ColorBrush> {
+        if self.inner.disabled {
+            Some(self.text_edit_style().disabled_text_color)
+        } else if self.inner.showing_placeholder {
+            Some(self.text_edit_style().placeholder_text_color)
+        } else {
+            None
         }
     }
 
     /// Programmatically set the text content of this text edit.
     /// This will replace all text and move the cursor to the end.
+    /// 
+    /// The undo history isn't changed, so undoing right after this can apply old edits to the new text. To make the undo behavior explicit, use [`TextEditMut::set_text_with_history()`] or [`TextEditMut::set_text_and_clear_history()`].
     pub fn set_text(&mut self, new_text: String) {
+        let new_text = self.sanitize_incoming(&new_text).unwrap_or(new_text);
+        self.text_box.inner.folds.clear();
         self.text_box.text_mut().clear();
         self.text_box.text_mut().push_str(&new_text);
+        self.inner.revision += 1;
         self.text_box.inner.needs_relayout = true;
         self.text_box.move_to_text_end();
-        // Clear any composition state
+        // Clear any composition state. The preedit text was replaced along with the rest.
         self.inner.compose = None;
+        self.inner.compose_kind = None;
+        self.inner.compose_origin = None;
         // Not showing placeholder anymore since we have real text
         self.inner.showing_placeholder = false;
         self.text_box.shared.text_changed = true;
     }
 
+    /// Replace all text and move the cursor to the end, recording the change as a single step in the undo history, so that the user can undo it like their own edits.
+    pub fn set_text_with_history(&mut self, new_text: String) {
+        let new_text = self.sanitize_incoming(&new_text).unwrap_or(new_text);
+        // Remove the preedit text, which isn't in the history, and record the deletion of any text that the composition replaced, so that the history matches the text before the replacement.
+        self.clear_compose();
+        self.record_compose_origin();
+        if self.inner.showing_placeholder {
+            self.text_box.text_mut().clear();
+            self.inner.showing_placeholder = false;
+        }
+        self.text_box.inner.folds.clear();
+
+        let old_len = self.text_box.text_inner().len();
+        let selection = self.text_box.selection();
+        // The replacement is its own step, even if it looks like the continuation of some typing.
+        self.inner.history.stop_merging();
+        self.replace_range_and_record(0..old_len, selection, &new_text);
+        self.inner.history.stop_merging();
+        self.text_box.move_to_text_end();
+    }
+
+    /// Replace all text, keeping the cursor, the selection and the scroll position on the same content.
+    /// 
+    /// The old and new text are diffed, and the selection is mapped through the changes, so that reformatting the document (for example with a code formatter or a markdown normalizer) doesn't make the cursor jump. Positions in deleted text move to the start of the replacement. The vertical scroll follows the cursor so that it stays at the same height in the box.
+    /// 
+    /// The change is recorded as a single step in the undo history, like [`TextEditMut::set_text_with_history()`]. Folded ranges are unfolded, since they can't be mapped through the changes.
+    pub fn set_text_preserving_cursor(&mut self, new_text: String) {
+        let new_text = self.sanitize_incoming(&new_text).unwrap_or(new_text);
+        if self.inner.showing_placeholder || self.is_composing() {
+            self.set_text_with_history(new_text);
+            return;
+        }
+        self.refresh_layout();
+        self.text_box.inner.folds.clear();
+
+        let old_text = self.text_box.text_inner().to_string();
+        let selection = self.text_box.selection();
+        let old_caret_y = selection.focus().geometry(&self.text_box.inner.layout, 1.0).y0 as f32;
+
+        let diff = similar::TextDiff::configure()
+            .timeout(std::time::Duration::from_millis(50))
+            .diff_chars(old_text.as_str(), new_text.as_str());
+        let ops = diff.ops();
+        let anchor = map_offset_through_diff(ops, &old_text, &new_text, selection.anchor().index());
+        let focus = map_offset_through_diff(ops, &old_text, &new_text, selection.focus().index());
+
+        self.inner.history.stop_merging();
+        self.replace_range_and_record(0..old_text.len(), selection, &new_text);
+        self.inner.history.stop_merging();
+        self.refresh_layout();
+
+        let layout = &self.text_box.inner.layout;
+        let len = self.text_box.inner.text.len();
+        let anchor = Cursor::from_byte_index(layout, anchor.min(len), Affinity::Downstream);
+        let focus = Cursor::from_byte_index(layout, focus.min(len), Affinity::Downstream);
+        let new_caret_y = focus.geometry(layout, 1.0).y0 as f32;
+        self.text_box.set_selection(Selection::new(anchor, focus));
+
+        let (scroll_x, scroll_y) = self.text_box.inner.scroll_offset;
+        let max_scroll_y = max_vertical_scroll(self.inner, self.text_box.inner);
+        let scroll_y = (scroll_y + new_caret_y - old_caret_y).clamp(0.0, max_scroll_y).round();
+        self.text_box.set_scroll_offset((scroll_x, scroll_y));
+    }
+
+    /// Replace all text and move the cursor to the end, and start over with an empty undo history. This is the usual choice when loading a new document.
+    pub fn set_text_and_clear_history(&mut self, new_text: String) {
+        self.set_text(new_text);
+        self.clear_history();
+    }
+
+    /// Forget all undo and redo steps.
+    pub fn clear_history(&mut self) {
+        self.inner.history = TextEditHistory::new();
+        // The text replaced by a composition would be recorded as a deletion in the new history.
+        self.inner.compose_origin = None;
+    }
+
     /// Set placeholder text that will be shown when the text edit is empty
     pub fn set_placeholder(&mut self, placeholder: impl Into<Cow<'static, str>>) {
         let placeholder_cow = placeholder.into();
@@ -1514,20 +3046,81 @@ impl<'a> TextEditMut<'a> {
 
     pub fn set_ime_cursor_area(&mut self, window: &Window) {
         if let Some(area) = self.cursor_geometry(1.0) {
+            let area = parley::Rect {
+                x0: area.x0 + self.text_box.inner.left,
+                y0: area.y0 + self.text_box.inner.top,
+                x1: area.x1 + self.text_box.inner.left,
+                y1: area.y1 + self.text_box.inner.top,
+            };
+            let area = self.text_box.shared.box_rect_to_screen(self.text_box.inner.group, area);
             // Note: on X11 `set_ime_cursor_area` may cause the exclusion area to be obscured
             // until https://github.com/rust-windowing/winit/pull/3966 is in the Winit release
             // used by this example.
             window.set_ime_cursor_area(
-                winit::dpi::PhysicalPosition::new(
-                    area.x0 + self.text_box.inner.left as f64,
-                    area.y0 + self.text_box.inner.top as f64,
-                ),
+                winit::dpi::PhysicalPosition::new(area.x0, area.y0),
                 winit::dpi::PhysicalSize::new(area.width(), area.height()),
             );
         }
     }
 }
 
+/// A fold hides the newline at its start and everything up to its end, so a cursor at `range.start` is on the visible line before it.
+pub(crate) fn fold_hides_cursor(range: &Range<usize>, index: usize) -> bool {
+    index > range.start && index <= range.end
+}
+
+/// Update fold ranges after `range` was replaced by `new_len` bytes of text. Folds that overlap the edit are removed. Returns true if any fold was removed.
+fn shift_fold_ranges(folds: &mut Vec<Range<usize>>, range: Range<usize>, new_len: usize) -> bool {
+    let len_before = folds.len();
+    folds.retain_mut(|fold| {
+        if fold.end <= range.start {
+            true
+        } else if fold.start >= range.end {
+            fold.start = fold.start - range.len() + new_len;
+            fold.end = fold.end - range.len() + new_len;
+            true
+        } else {
+            false
+        }
+    });
+    folds.len() != len_before
+}
+
+/// Shift the meta ranges after `range` was replaced with `new_len` bytes. Ranges that contain the whole edit grow or shrink with it, ranges that partially overlap it lose the replaced part, and ranges that end up empty are removed.
+fn shift_meta_ranges(meta_ranges: &mut Vec<(Range<usize>, u64)>, range: Range<usize>, new_len: usize) {
+    meta_ranges.retain_mut(|(meta, _)| {
+        if meta.end <= range.start {
+            true
+        } else if meta.start >= range.end {
+            meta.start = meta.start - range.len() + new_len;
+            meta.end = meta.end - range.len() + new_len;
+            true
+        } else if meta.start <= range.start && meta.end >= range.end {
+            meta.end = meta.end - range.len() + new_len;
+            !meta.is_empty()
+        } else {
+            let start = if meta.start < range.start { meta.start } else { range.start + new_len };
+            let end = if meta.end > range.end { meta.end - range.len() + new_len } else { range.start };
+            *meta = start..end;
+            start < end
+        }
+    });
+}
+
+/// Shift the offsets of automatically inserted closing characters after `range` was replaced with `new_len` bytes. Closers inside the replaced range are forgotten.
+fn shift_auto_closers(closers: &mut Vec<usize>, range: Range<usize>, new_len: usize) {
+    closers.retain_mut(|closer| {
+        if *closer < range.start {
+            true
+        } else if *closer >= range.end {
+            *closer = *closer - range.len() + new_len;
+            true
+        } else {
+            false
+        }
+    });
+}
+
 /// Determine if animation should be used based on delta type and which component is being used
 pub(crate) fn should_use_animation(delta: &winit::event::MouseScrollDelta, vertical: bool) -> bool {
     match delta {
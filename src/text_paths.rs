@@ -0,0 +1,86 @@
+use peniko::kurbo::{BezPath, Point};
+use swash::zeno::{Command, PathData};
+
+use crate::*;
+
+impl<'a> TextBox<'a> {
+    /// Get the outlines of the glyphs of the box as vector paths, one per glyph, in the same places where the renderer draws them.
+    ///
+    /// Coordinates are in pixels relative to the top left corner of the box, accounting for scrolling. Glyphs without outlines, like bitmap emoji, are skipped. The box's layout should be up to date: [`TextBoxMut::to_paths()`] updates it first.
+    ///
+    /// This can be used for SVG export, or for effects that need the shape of each letter.
+    pub fn to_paths(&self) -> Vec<BezPath> {
+        let (scroll_x, scroll_y) = self.inner.scroll_offset;
+        layout_to_paths(&self.inner.layout, -scroll_x, -scroll_y)
+    }
+}
+
+impl<'a> TextBoxMut<'a> {
+    /// Get the outlines of the glyphs of the box as vector paths. See [`TextBox::to_paths()`].
+    pub fn to_paths(&mut self) -> Vec<BezPath> {
+        self.refresh_layout();
+        let (scroll_x, scroll_y) = self.inner.scroll_offset;
+        layout_to_paths(&self.inner.layout, -scroll_x, -scroll_y)
+    }
+}
+
+/// Get the outlines of the glyphs of a layout as vector paths, one per glyph, with the layout's top left corner at (`left`, `top`).
+///
+/// See [`TextBox::to_paths()`].
+pub fn layout_to_paths(layout: &Layout<ColorBrush>, left: f32, top: f32) -> Vec<BezPath> {
+    let mut scale_cx = ScaleContext::new();
+    let mut paths = Vec::new();
+
+    for line in layout.lines() {
+        for item in line.items() {
+            let PositionedLayoutItem::GlyphRun(glyph_run) = item else {
+                continue;
+            };
+            // Same as for rendering: transparent runs are hidden text.
+            if glyph_run.style().brush.0[3] == 0 {
+                continue;
+            }
+
+            let run = glyph_run.run();
+            let font = run.font();
+            let font_ref = FontRef::from_index(font.data.as_ref(), font.index as usize).unwrap();
+
+            // Hinting is for pixel grids, and the paths can be drawn at any scale.
+            let mut scaler = scale_cx
+                .builder(font_ref)
+                .size(run.font_size())
+                .hint(false)
+                .normalized_coords(run.normalized_coords())
+                .build();
+
+            let mut run_x = left + glyph_run.offset();
+            let run_y = top + glyph_run.baseline();
+
+            for glyph in glyph_run.glyphs() {
+                let origin_x = (run_x + glyph.x) as f64;
+                let origin_y = (run_y - glyph.y) as f64;
+                run_x += glyph.advance;
+
+                let Some(outline) = scaler.scale_outline(glyph.id) else {
+                    continue;
+                };
+                // Outlines are y-up, with the origin on the baseline.
+                let point = |v: swash::zeno::Vector| Point::new(origin_x + v.x as f64, origin_y - v.y as f64);
+                let mut path = BezPath::new();
+                for command in outline.path().commands() {
+                    match command {
+                        Command::MoveTo(p) => path.move_to(point(p)),
+                        Command::LineTo(p) => path.line_to(point(p)),
+                        Command::QuadTo(c, p) => path.quad_to(point(c), point(p)),
+                        Command::CurveTo(c0, c1, p) => path.curve_to(point(c0), point(c1), point(p)),
+                        Command::Close => path.close_path(),
+                    }
+                }
+                if !path.elements().is_empty() {
+                    paths.push(path);
+                }
+            }
+        }
+    }
+    paths
+}
@@ -1,4 +1,6 @@
 use crate::*;
+use smallvec::SmallVec;
+use std::ops::RangeBounds;
 
 // Content type constants
 const CONTENT_TYPE_MASK: u32 = 0;
@@ -8,6 +10,9 @@ const CONTENT_TYPE_DECORATION: u32 = 2;
 // Flag bits
 const FADE_ENABLED_BIT: u32 = 4;
 
+pub(crate) const DEFAULT_CURSOR_COLOR: u32 = 0xee_ee_ee_ff;
+pub(crate) const DEFAULT_SELECTION_COLOR: u32 = 0x33_33_ff_aa;
+
 fn pack_flags(content_type: u32, fade_enabled: bool) -> u32 {
     content_type | if fade_enabled { 1 << FADE_ENABLED_BIT } else { 0 }
 }
@@ -16,6 +21,10 @@ fn pack_flags(content_type: u32, fade_enabled: bool) -> u32 {
 /// A struct for rendering text and text edit boxes on the GPU.
 /// 
 /// Uses traditional CPU-size rasterizing and a dynamic glyph atlas on the GPU.
+/// 
+/// Glyph runs that repeat across boxes, like the same numbers in many table cells, are looked up in the glyph cache only once: later copies copy the quads of the first one and move them into place. This saves CPU time when preparing, but every copy still has its own quads in the vertex buffer and is drawn like any other text.
+///
+/// A multi-window app can use one renderer per window, and prepare either a separate [`Text`] or the same one for each of them. Fonts and layouts are shared between all `Text`s on the same thread.
 pub struct TextRenderer {
     pub(crate) text_renderer: ContextlessTextRenderer,
     pub(crate) scale_cx: ScaleContext,
@@ -23,14 +32,23 @@ pub struct TextRenderer {
 
 pub(crate) struct ContextlessTextRenderer {
     pub frame: u64,
+    /// Identifies the renderer in the [`GlyphCache`], which might be shared with other renderers.
+    pub(crate) renderer_id: u64,
+    /// The scene id and revision of the [`Text`] that was last prepared into this renderer. See [`Text::prepare_all()`].
+    pub(crate) last_prepared: Option<(u64, u64)>,
     pub tmp_image: Image,
 
-    pub(crate) glyph_cache: LruCache<GlyphKey, Option<StoredGlyph>, BuildHasherDefault<FxHasher>>,
-    pub(crate) mask_atlas_pages: Vec<AtlasPage<GrayImage>>,
-    pub(crate) last_frame_evicted: u64,
+    pub(crate) glyph_cache: GlyphCache,
+    /// Quads of recently prepared glyph runs, so that repeated runs (like the same number in many table cells) can be copied instead of prepared glyph by glyph.
+    pub(crate) run_cache: LruCache<GlyphRunKey, PreparedRun, BuildHasherDefault<FxHasher>>,
+    pub(crate) mask_atlas_pages: Vec<AtlasPage>,
+    /// The glyph cache's eviction count when the run cache was last known to be valid.
+    pub(crate) seen_evictions: u64,
     
-    pub(crate) color_atlas_pages: Vec<AtlasPage<RgbaImage>>,
+    pub(crate) color_atlas_pages: Vec<AtlasPage>,
     pub(crate) decorations: Vec<Quad>,
+    /// Kept apart from the other decorations, so that a blinking cursor can be updated without preparing the selection again.
+    pub(crate) cursor_quads: Vec<Quad>,
     
     pub atlas_bind_group_layout: BindGroupLayout,
     
@@ -40,12 +58,72 @@ pub(crate) struct ContextlessTextRenderer {
     pub params_bind_group: BindGroup,
 
     pub pipeline: RenderPipeline,
+    pub(crate) pipeline_layout: PipelineLayout,
+    pub(crate) depth_stencil: Option<DepthStencilState>,
+    pub(crate) custom_material_shader: Option<String>,
+    pub(crate) materials: Vec<Material>,
+    /// Time zero for animated materials.
+    /// The time of the first frame with animated materials, from `time_source`.
+    pub(crate) start_time: Option<std::time::Instant>,
+    /// The clock set with [`Text::set_time_source()`] on the last [`Text`] prepared into this renderer.
+    pub(crate) time_source: fn() -> std::time::Instant,
     pub atlas_size: u32,
     
     // pub(crate) cached_scaler: Option<CachedScaler>,
     
     pub(crate) vertex_buffer: Buffer,
     pub(crate) needs_gpu_sync: bool,
+    pub(crate) cursor_buffer: Buffer,
+    pub(crate) cursor_needs_gpu_sync: bool,
+    pub(crate) depth_stencil_format: Option<TextureFormat>,
+    /// The format of the render target, which the pipeline is built for.
+    pub(crate) format: TextureFormat,
+    /// Samples per pixel of the render target. See [`TextRendererParams::sample_count`].
+    pub(crate) sample_count: u32,
+    pub(crate) hdr_brightness: f32,
+    pub(crate) clip_mode: ClipMode,
+    /// See [`TextRenderer::set_target_size()`].
+    pub(crate) target_size: Option<(u32, u32)>,
+    /// See [`TextRenderer::set_depth_sorting()`].
+    pub(crate) depth_sorting: bool,
+    /// The quad ranges of each prepared box, in the order they were prepared.
+    pub(crate) draw_batches: Vec<DrawBatch>,
+    /// The decorations of each box, for [`TextRenderer::render_range()`].
+    pub(crate) decoration_batches: Vec<DecorationBatch>,
+    /// The depth of the box that the cursor quads belong to.
+    pub(crate) cursor_depth: f32,
+    /// Glyphs added to the atlas since this was last reset, for [`TextPerfStats`].
+    pub(crate) glyphs_rasterized: usize,
+}
+
+/// How text boxes with a clip rect are clipped. See [`TextRenderer::set_clip_mode()`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ClipMode {
+    /// Every quad carries its clip rect, and fragments outside of it are discarded in the shader.
+    #[default]
+    Shader,
+    /// Quads of clipped boxes are drawn in separate draw calls with a scissor rect, so that the fragment shader doesn't have to test them.
+    Scissor,
+}
+
+/// A range of quads in an atlas page that belongs to a single box.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DrawBatch {
+    pub page_type: AtlasPageType,
+    pub page_index: u16,
+    pub quad_start: u32,
+    pub quad_end: u32,
+    /// Screen space `[x0, y0, x1, y1]` scissor rect, if the box is clipped with [`ClipMode::Scissor`].
+    pub scissor: Option<[i32; 4]>,
+    pub depth: f32,
+}
+
+/// A range of decoration quads that belongs to a single box.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DecorationBatch {
+    pub start: u32,
+    pub end: u32,
+    pub depth: f32,
 }
 
 // pub(crate) struct CachedScaler {
@@ -54,11 +132,12 @@ pub(crate) struct ContextlessTextRenderer {
 //     font_size: f32,
 // }
 
-pub(crate) struct AtlasPage<ImageType> {
+/// The quads that use a page of the [`GlyphCache`], and this renderer's copy of it on the GPU.
+pub(crate) struct AtlasPage {
     pub quads: Vec<Quad>,
-    pub(crate) packer: BucketedAtlasAllocator,
-    pub(crate) image: ImageType,
     pub gpu: Option<GpuAtlasPage>,
+    /// The version of the cache page that was last uploaded to `gpu`.
+    pub(crate) uploaded_version: u64,
     /// Quad count before the current render operation (for tracking ranges)
     pub(crate) quad_count_before_render: u32,
 }
@@ -68,77 +147,96 @@ pub(crate) struct GpuAtlasPage {
     pub bind_group: BindGroup,
 }
 
+impl AtlasPage {
+    pub(crate) fn new(gpu: Option<GpuAtlasPage>) -> Self {
+        Self {
+            quads: Vec::<Quad>::with_capacity(300),
+            gpu,
+            uploaded_version: 0,
+            quad_count_before_render: 0,
+        }
+    }
+}
+
+/// The quads of an atlas page, adding the pages that were created in the glyph cache since the last time.
+fn page_quads(pages: &mut Vec<AtlasPage>, page: usize) -> &mut Vec<Quad> {
+    while pages.len() <= page {
+        pages.push(AtlasPage::new(None));
+    }
+    &mut pages[page].quads
+}
 
-impl ContextlessTextRenderer {
-    // for now, we're evicting both masks and colors at the same time even if only one spills over
-    // separating them would mean that they can't share the same cache and it would make things more complex 
-    fn evict_old_glyphs(&mut self) {
-        self.last_frame_evicted = self.frame;
 
-        while let Some((_key, value)) = self.glyph_cache.peek_lru() {
-            
-            if let Some(stored_glyph) = value {
-                if stored_glyph.frame == self.frame {
-                    break;
-                }
-                
-                let page = stored_glyph.page as usize;
-                match stored_glyph.content_type {
-                    Content::Mask => self.mask_atlas_pages[page].packer.deallocate(stored_glyph.alloc.id),
-                    Content::Color => self.color_atlas_pages[page].packer.deallocate(stored_glyph.alloc.id),
-                    Content::SubpixelMask => unreachable!()
-                }
-            }
-            
-            self.glyph_cache.pop_lru();
+impl ContextlessTextRenderer {
+    pub(crate) fn add_selection_rect(&mut self, rect: parley::Rect, left: f32, top: f32, color: u32, clip_rect: Option<parley::Rect>) {
+        if let Some(quad) = decoration_quad(rect, left, top, color, clip_rect) {
+            self.decorations.push(quad);
         }
     }
 
-    fn needs_evicting(&self, current_frame: u64) -> bool {
-        self.last_frame_evicted != current_frame
+    /// A one pixel wide outline along the inside of `rect`.
+    pub(crate) fn add_outline(&mut self, r: parley::Rect, left: f32, top: f32, color: u32, clip_rect: Option<parley::Rect>) {
+        let edges = [
+            parley::Rect { x0: r.x0, y0: r.y0, x1: r.x1, y1: r.y0 + 1.0 },
+            parley::Rect { x0: r.x0, y0: r.y1 - 1.0, x1: r.x1, y1: r.y1 },
+            parley::Rect { x0: r.x0, y0: r.y0, x1: r.x0 + 1.0, y1: r.y1 },
+            parley::Rect { x0: r.x1 - 1.0, y0: r.y0, x1: r.x1, y1: r.y1 },
+        ];
+        for edge in edges {
+            self.add_selection_rect(edge, left, top, color, clip_rect);
+        }
     }
+}
 
-    fn add_selection_rect(&mut self, rect: parley::Rect, left: f32, top: f32, color: u32, clip_rect: Option<parley::Rect>) {        
-        let left = left as i32;
-        let top = top as i32;
+fn decoration_quad(rect: parley::Rect, left: f32, top: f32, color: u32, clip_rect: Option<parley::Rect>) -> Option<Quad> {
+    let left = left as i32;
+    let top = top as i32;
 
-        let mut x0 = left + rect.x0 as i32;
-        let mut x1 = left + rect.x1 as i32;
-        let mut y0 = top + rect.y0 as i32;
-        let mut y1 = top + rect.y1 as i32;
+    let mut x0 = left + rect.x0 as i32;
+    let mut x1 = left + rect.x1 as i32;
+    let mut y0 = top + rect.y0 as i32;
+    let mut y1 = top + rect.y1 as i32;
 
-        // Apply clipping if clip_rect is provided
-        if let Some(clip) = clip_rect {
-            let clip_x0 = left + clip.x0 as i32;
-            let clip_x1 = left + clip.x1 as i32;
-            let clip_y0 = top + clip.y0 as i32;
-            let clip_y1 = top + clip.y1 as i32;
+    // Apply clipping if clip_rect is provided
+    if let Some(clip) = clip_rect {
+        let clip_x0 = left + clip.x0 as i32;
+        let clip_x1 = left + clip.x1 as i32;
+        let clip_y0 = top + clip.y0 as i32;
+        let clip_y1 = top + clip.y1 as i32;
 
-            x0 = x0.max(clip_x0);
-            x1 = x1.min(clip_x1);
-            y0 = y0.max(clip_y0);
-            y1 = y1.min(clip_y1);
+        x0 = x0.max(clip_x0);
+        x1 = x1.min(clip_x1);
+        y0 = y0.max(clip_y0);
+        y1 = y1.min(clip_y1);
 
-            // If the rectangle is completely clipped out, don't add it
-            if x0 >= x1 || y0 >= y1 {
-                return;
-            }
+        // If the rectangle is completely clipped out, don't add it
+        if x0 >= x1 || y0 >= y1 {
+            return None;
         }
-
-        let quad = Quad {
-            pos: [x0, y0],
-            dim: [(x1 - x0) as u16, (y1 - y0) as u16],
-            color,
-            uv_origin: [0, 0],
-            depth: 0.0,
-            flags: pack_flags(CONTENT_TYPE_DECORATION, false),
-            clip_rect: [0, 0, 32767, 32767], // No clipping for decorations
-        };
-        self.decorations.push(quad);
     }
+
+    Some(Quad {
+        pos: [x0, y0],
+        dim: [(x1 - x0) as u16, (y1 - y0) as u16],
+        color,
+        uv_origin: [0, 0],
+        depth: 0.0,
+        flags: pack_flags(CONTENT_TYPE_DECORATION, false),
+        clip_rect: [0, 0, 32767, 32767], // No clipping for decorations
+        origin: [0, 0],
+        fade: 0,
+    })
 }
 
 
+/// A texture containing the rendered contents of a text box, returned by [`TextRenderer::rasterize_box_to_texture()`].
+pub struct TextureHandle {
+    pub texture: Texture,
+    pub view: TextureView,
+    /// Size of the texture in pixels.
+    pub size: (u32, u32),
+}
+
 /// Key for building a glyph cache
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub(crate) struct GlyphKey {
@@ -159,6 +257,42 @@ pub(crate) struct GlyphKey {
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub(crate) struct SubpixelBin<const N: u8>(pub u8);
 
+pub(crate) const RUN_CACHE_CAPACITY: usize = 1024;
+
+/// Key for the run cache. Two runs with the same key produce the same quads, up to a translation by whole pixels.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct GlyphRunKey {
+    font_id: u64,
+    font_size_bits: u32,
+    color: u32,
+    /// `f32` bits of the fractional part of the run origin, which decides the subpixel bins of the glyphs.
+    origin_fract_bits: [u32; 2],
+    /// Glyph id and `f32` bits of x, y and advance of each glyph.
+    glyphs: SmallVec<[(GlyphId, [u32; 3]); 16]>,
+}
+
+impl GlyphRunKey {
+    fn new(glyph_run: &GlyphRun<'_, ColorBrush>, font_id: u64, font_size: f32, color: u32, origin: (f32, f32)) -> Self {
+        Self {
+            font_id,
+            font_size_bits: font_size.to_bits(),
+            color,
+            origin_fract_bits: [(origin.0 - origin.0.floor()).to_bits(), (origin.1 - origin.1.floor()).to_bits()],
+            glyphs: glyph_run.glyphs()
+                .map(|glyph| (glyph.id, [glyph.x.to_bits(), glyph.y.to_bits(), glyph.advance.to_bits()]))
+                .collect(),
+        }
+    }
+}
+
+/// The quads of a glyph run, relative to the whole-pixel part of its origin.
+#[derive(Debug, Default)]
+pub(crate) struct PreparedRun {
+    quads: Vec<(AtlasPageType, u16, Quad)>,
+    /// Used to keep the glyphs fresh in the glyph cache when the run is reused.
+    glyph_keys: SmallVec<[GlyphKey; 16]>,
+}
+
 fn quantize<const N: u8>(pos: f32) -> (i32, f32, SubpixelBin::<N>) {
     let trunc = pos as i32;
     let fract = pos - trunc as f32;
@@ -193,6 +327,10 @@ pub(crate) struct Quad {
     pub depth: f32,
     pub flags: u32,
     pub clip_rect: [i16; 4], // x, y, width, height in pixels
+    /// Position of the box that the quad belongs to, so that materials can work in the box's coordinates.
+    pub origin: [i16; 2],
+    /// The box's [`FadeoutStyle`], packed by `FadeoutStyle::to_gpu()`.
+    pub fade: u32,
 }
 
 fn make_quad(glyph: &GlyphWithContext, stored_glyph: &StoredGlyph) -> Quad {
@@ -215,6 +353,8 @@ fn make_quad(glyph: &GlyphWithContext, stored_glyph: &StoredGlyph) -> Quad {
         flags: pack_flags(flags, false), // No fade by default
         depth: 0.0,
         clip_rect: [0, 0, 32767, 32767], // No clipping (will be set later)
+        origin: [0, 0],
+        fade: 0,
     };
 }
 
@@ -251,19 +391,46 @@ fn clip_quad(quad: Quad, left: f32, top: f32, clip_rect: Option<parley::Rect>, f
     Some(quad)
 }
 
+/// Clamp a screen space `[x0, y0, x1, y1]` rect to the render target, as the `(x, y, width, height)` that `set_scissor_rect()` takes. Returns `None` if nothing is left, since empty scissor rects aren't allowed.
+fn clamp_scissor(rect: [i32; 4], width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+    let x0 = rect[0].clamp(0, width as i32) as u32;
+    let y0 = rect[1].clamp(0, height as i32) as u32;
+    let x1 = rect[2].clamp(0, width as i32) as u32;
+    let y1 = rect[3].clamp(0, height as i32) as u32;
+    (x1 > x0 && y1 > y0).then(|| (x0, y0, x1 - x0, y1 - y0))
+}
+
+/// Handle for an image registered with [`TextRenderer::register_image()`].
+///
+/// After the image is removed with [`TextRenderer::remove_image()`], the id stays invalid even if its slot is reused for another image, and boxes that still use it draw nothing for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageId {
+    pub(crate) i: u32,
+    pub(crate) generation: u32,
+}
+
+/// A user-provided image stored in the color atlas. Unlike glyphs, images are never evicted.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct StoredImage {
+    pub(crate) page: u16,
+    pub(crate) alloc: Allocation,
+    pub(crate) size: Size2D<i32, UnknownUnit>,
+}
+
 /// A glyph as stored in a glyph atlas.
 #[derive(Clone, Copy, Debug)]
 pub(crate) struct StoredGlyph {
-    content_type: Content,
-    page: u16,
-    frame: u64,
-    alloc: Allocation,
+    pub(crate) content_type: Content,
+    pub(crate) page: u16,
+    /// The last frame in which the glyph was used.
+    pub(crate) frame: u64,
+    pub(crate) alloc: Allocation,
     placement_left: i32,
     placement_top: i32,
     size: Size2D<i32, UnknownUnit>,
 }
 impl StoredGlyph {
-    fn create(alloc: &Allocation, placement: &Placement, page: usize, frame: u64, content_type: Content) -> StoredGlyph {
+    pub(crate) fn create(alloc: &Allocation, placement: &Placement, page: usize, frame: u64, content_type: Content) -> StoredGlyph {
         StoredGlyph {
             content_type,
             page: page as u16,
@@ -276,6 +443,21 @@ impl StoredGlyph {
     }
 }
 
+/// The selection and cursor colors, replaced by the high contrast palette if there is one.
+pub(crate) fn decoration_colors(settings: &SystemSettings, selection_color: u32) -> (u32, u32) {
+    match settings.high_contrast {
+        Some(palette) => (color_to_u32(palette.selection_color), color_to_u32(palette.cursor_color)),
+        None => (selection_color, DEFAULT_CURSOR_COLOR),
+    }
+}
+
+pub(crate) fn color_to_u32(color: ColorBrush) -> u32 {
+      ((color.0[0] as u32) << 24)
+    + ((color.0[1] as u32) << 16)
+    + ((color.0[2] as u32) << 8)
+    + ((color.0[3] as u32) << 0)
+}
+
 /// RGBA color value for text rendering.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct ColorBrush(pub [u8; 4]);
@@ -292,10 +474,25 @@ pub(crate) struct Params {
     pub screen_resolution_width: f32,
     /// The height of the screen in pixels.
     pub screen_resolution_height: f32,
-    pub _pad: [u32; 2],
+    /// Seconds since the renderer was created, for animated materials.
+    pub time: f32,
+    /// Multiplier for the output color. See [`TextRenderer::set_hdr_brightness()`].
+    pub brightness: f32,
+    /// 1 if the target format isn't sRGB, so the shader has to encode the output itself.
+    pub encode_srgb: u32,
+    pub _pad: [u32; 3],
+    /// Translation and scale of each group, as (x, y, scale, unused). Index 0 is the identity.
+    pub group_transforms: [[f32; 4]; MAX_GROUPS],
+    /// Column-major matrix of each group that is placed in a 3D scene.
+    pub world_transforms: [[[f32; 4]; 4]; MAX_GROUPS],
+    /// Materials added with [`TextRenderer::add_material()`]. Quads refer to them by index plus one, so that zero means no material.
+    pub materials: [GpuMaterial; MAX_MATERIALS],
 }
 
 impl TextRenderer {
+    /// Create a renderer with more control over the pipeline than [`TextRenderer::new()`].
+    ///
+    /// `format` is the format of the render target. `depth_stencil` has to match the depth attachment of the render pass that the text is drawn in, if there is one: its `depth_compare` and `depth_write_enabled` decide how text is tested against the rest of the scene, so for example a reverse-Z buffer can use `CompareFunction::GreaterEqual`. For a multisampled target, set [`TextRendererParams::sample_count`].
     pub fn new_with_params(
         device: &Device,
         _queue: &Queue,
@@ -317,6 +514,57 @@ impl TextRenderer {
         self.text_renderer.update_resolution(width, height);
     }
 
+    /// Rebuild everything that lives on the GPU on a new device, for example after the old one was lost, or after the surface was configured with a different format.
+    ///
+    /// The glyph cache, registered images, materials, the custom material shader and the prepared text are kept, so the [`Text`] doesn't need to be prepared again. Everything is uploaded in the next [`TextRenderer::gpu_load()`].
+    ///
+    /// Textures returned by [`TextRenderer::rasterize_box_to_texture()`] belong to the old device and aren't recreated.
+    pub fn recreate(&mut self, device: &Device, queue: &Queue, format: TextureFormat) {
+        let old = &mut self.text_renderer;
+        let params = TextRendererParams {
+            glyph_cache: Some(old.glyph_cache.clone()),
+            sample_count: old.sample_count,
+            ..Default::default()
+        };
+        let mut new = ContextlessTextRenderer::new_with_params(device, queue, format, old.depth_stencil.clone(), params);
+
+        // Keep the old id and frame, so that the glyph cache keeps protecting the glyphs that the prepared quads use. Dropping the old renderer unregisters the id that was made for the new one instead.
+        let new_id = new.renderer_id;
+        new.renderer_id = old.renderer_id;
+        new.frame = old.frame;
+        old.renderer_id = new_id;
+
+        for (i, page) in old.mask_atlas_pages.iter_mut().enumerate() {
+            *page_quads(&mut new.mask_atlas_pages, i) = mem::take(&mut page.quads);
+        }
+        for (i, page) in old.color_atlas_pages.iter_mut().enumerate() {
+            *page_quads(&mut new.color_atlas_pages, i) = mem::take(&mut page.quads);
+        }
+        new.decorations = mem::take(&mut old.decorations);
+        new.cursor_quads = mem::take(&mut old.cursor_quads);
+        new.run_cache = mem::replace(&mut old.run_cache, LruCache::unbounded_with_hasher(BuildHasherDefault::<FxHasher>::default()));
+        new.seen_evictions = old.seen_evictions;
+        new.last_prepared = old.last_prepared;
+        new.params = old.params;
+        new.hdr_brightness = old.hdr_brightness;
+        new.clip_mode = old.clip_mode;
+        new.depth_sorting = old.depth_sorting;
+        new.draw_batches = mem::take(&mut old.draw_batches);
+        new.decoration_batches = mem::take(&mut old.decoration_batches);
+        new.cursor_depth = old.cursor_depth;
+        // The new format might need a different conversion.
+        new.update_output_params();
+        new.materials = mem::take(&mut old.materials);
+        new.start_time = old.start_time;
+        new.time_source = old.time_source;
+        if let Some(source) = old.custom_material_shader.take() {
+            new.pipeline = create_pipeline(device, &new.pipeline_layout, Some(&source), new.format, new.depth_stencil.clone(), new.sample_count);
+            new.custom_material_shader = Some(source);
+        }
+
+        *old = new;
+    }
+
     pub fn clear(&mut self) {
         self.text_renderer.clear();
         self.text_renderer.clear_decorations();
@@ -326,21 +574,41 @@ impl TextRenderer {
         self.text_renderer.clear_decorations();
     }
 
+    /// Remove only the cursor, keeping the selection and the other decorations.
+    pub fn clear_cursor_only(&mut self) {
+        self.text_renderer.clear_cursor();
+    }
+
+    /// Total number of quads currently prepared, including decorations.
+    pub fn quad_count(&self) -> usize {
+        let renderer = &self.text_renderer;
+        renderer.mask_atlas_pages.iter().map(|page| page.quads.len()).sum::<usize>()
+            + renderer.color_atlas_pages.iter().map(|page| page.quads.len()).sum::<usize>()
+            + renderer.decorations.len()
+            + renderer.cursor_quads.len()
+    }
+
+    /// Prepare a layout that isn't part of a [`Text`]. It's drawn at depth 0 as far as [`TextRenderer::render_range()`] and depth sorting are concerned.
     pub fn prepare_layout(&mut self, layout: &Layout<ColorBrush>, left: f32, top: f32, clip_rect: Option<parley::Rect>, fade: bool) {
+        let mut quad_storage = QuadStorage::default();
+        self.capture_quad_ranges_before();
         self.text_renderer.prepare_layout(layout, &mut self.scale_cx, left, top, clip_rect, fade);
         self.text_renderer.needs_gpu_sync = true;
+        self.capture_quad_ranges_after(&mut quad_storage, (0.0, 0.0));
+        self.text_renderer.add_draw_batches(&quad_storage, clip_rect, left, top, !fade, 0.0);
     }
 
     pub fn prepare_text_box_layout(&mut self, text_box: &mut TextBoxMut) {
+        profile_span!("TextRenderer::prepare_text_box_layout");
         if text_box.hidden() {
             return;
         }
-        text_box.refresh_layout();
+        text_box.refresh_layout_for_drawing();
                 
         let (left, top) = text_box.pos();
         let (left, top) = (left as f32, top as f32);
         let clip_rect = text_box.effective_clip_rect();
-        let fade = text_box.fadeout_clipping();
+        let fade = text_box.fadeout_clipping() && !text_box.shared.system_settings.reduce_motion;
 
         let content_left = left - text_box.scroll_offset().0;
         let content_top = top - text_box.scroll_offset().1;
@@ -349,24 +617,43 @@ impl TextRenderer {
         self.capture_quad_ranges_before();
 
         self.text_renderer.prepare_layout(&text_box.inner.layout, &mut self.scale_cx, content_left, content_top, clip_rect, fade);
+        self.text_renderer.prepare_image_replacements(&text_box.inner.layout, &text_box.inner.image_replacements, content_left, content_top, clip_rect, fade);
         self.text_renderer.needs_gpu_sync = true;
         
         // Update quad storage with new ranges
         let scroll_offset = text_box.scroll_offset();
         self.capture_quad_ranges_after(&mut text_box.inner.quad_storage, scroll_offset);
+        let can_scissor = !fade && text_box.inner.group.is_none();
+        self.text_renderer.add_draw_batches(&text_box.inner.quad_storage, clip_rect, content_left, content_top, can_scissor, text_box.inner.depth);
+        self.text_renderer.set_quads_group(&text_box.inner.quad_storage, text_box.inner.group);
+        if fade {
+            let fadeout_style = text_box.inner.fadeout_style.unwrap_or_default();
+            self.text_renderer.set_quads_fade(&text_box.inner.quad_storage, fadeout_style);
+        }
+        self.text_renderer.set_quads_material(&text_box.inner.quad_storage, text_box.material(self.text_renderer.renderer_id), (left, top));
+        if !text_box.inner.material_ranges.is_empty() {
+            self.text_renderer.set_range_materials(&text_box.inner.quad_storage, &text_box.inner.layout, &text_box.inner.material_ranges, content_left, content_top);
+        }
+        if let Some(revealed) = revealed_byte_index(&text_box.inner.layout, text_box.inner.reveal, text_box.inner.text.len()) {
+            self.text_renderer.hide_unrevealed_quads(&text_box.inner.quad_storage, &text_box.inner.layout, &text_box.inner.text, revealed, content_left, content_top);
+        }
+        if let Some(animation) = &mut text_box.inner.glyph_animation {
+            self.text_renderer.capture_glyph_animation_base(animation, &text_box.inner.quad_storage, &text_box.inner.layout, content_left, content_top);
+        }
     }
 
     pub fn prepare_text_edit_layout(&mut self, text_edit: &mut TextEditMut) {
+        profile_span!("TextRenderer::prepare_text_edit_layout");
         if text_edit.hidden() {
             return;
         }
         
-        text_edit.refresh_layout();
+        text_edit.refresh_layout_for_drawing();
 
         let (left, top) = text_edit.pos();
         let (left, top) = (left as f32, top as f32);
         let clip_rect = text_edit.text_box.effective_clip_rect();
-        let fade = text_edit.fadeout_clipping();
+        let fade = text_edit.fadeout_clipping() && !text_edit.text_box.shared.system_settings.reduce_motion;
 
         let content_left = left - text_edit.scroll_offset().0;
         let content_top = top - text_edit.scroll_offset().1;
@@ -375,14 +662,46 @@ impl TextRenderer {
         self.capture_quad_ranges_before();
 
         self.text_renderer.prepare_layout(&text_edit.text_box.inner.layout, &mut self.scale_cx, content_left, content_top, clip_rect, fade);
+        self.text_renderer.prepare_image_replacements(&text_edit.text_box.inner.layout, &text_edit.text_box.inner.image_replacements, content_left, content_top, clip_rect, fade);
+        self.prepare_remote_cursor_labels(text_edit, content_left, content_top, clip_rect);
+        self.prepare_invisibles(text_edit, content_left, content_top, clip_rect, fade);
+        self.prepare_ghost_text(text_edit, content_left, content_top, clip_rect, fade);
         self.text_renderer.needs_gpu_sync = true;
         
         // Update quad storage with new ranges
         let scroll_offset = text_edit.scroll_offset();
         self.capture_quad_ranges_after(&mut text_edit.text_box.inner.quad_storage, scroll_offset);
+        let can_scissor = !fade && text_edit.text_box.inner.group.is_none();
+        self.text_renderer.add_draw_batches(&text_edit.text_box.inner.quad_storage, clip_rect, content_left, content_top, can_scissor, text_edit.text_box.inner.depth);
+        self.text_renderer.set_quads_group(&text_edit.text_box.inner.quad_storage, text_edit.text_box.inner.group);
+        if fade {
+            let fadeout_style = text_edit.text_box.inner.fadeout_style.unwrap_or(text_edit.text_edit_style().fadeout);
+            self.text_renderer.set_quads_fade(&text_edit.text_box.inner.quad_storage, fadeout_style);
+        }
+        self.text_renderer.set_quads_material(&text_edit.text_box.inner.quad_storage, text_edit.text_box.material(self.text_renderer.renderer_id), (left, top));
+        let inner = &mut *text_edit.text_box.inner;
+        if !inner.material_ranges.is_empty() {
+            self.text_renderer.set_range_materials(&inner.quad_storage, &inner.layout, &inner.material_ranges, content_left, content_top);
+        }
+        if let Some(revealed) = revealed_byte_index(&inner.layout, inner.reveal, inner.text.len()) {
+            self.text_renderer.hide_unrevealed_quads(&inner.quad_storage, &inner.layout, &inner.text, revealed, content_left, content_top);
+        }
+        if let Some(animation) = &mut inner.glyph_animation {
+            self.text_renderer.capture_glyph_animation_base(animation, &inner.quad_storage, &inner.layout, content_left, content_top);
+        }
     }
 
     pub fn prepare_text_box_decorations(&mut self, text_box: &TextBoxMut, show_cursor: bool) {
+        self.prepare_text_box_decorations_with_color(text_box, show_cursor, DEFAULT_SELECTION_COLOR);
+    }
+
+    /// Prepare the selection of a box in the window that doesn't have keyboard focus: the selection is drawn in a neutral color and the cursor is hidden.
+    pub fn prepare_inactive_text_box_decorations(&mut self, text_box: &TextBoxMut) {
+        self.prepare_text_box_decorations_with_color(text_box, false, 0x88_88_88_66);
+    }
+
+    fn prepare_text_box_decorations_with_color(&mut self, text_box: &TextBoxMut, show_cursor: bool, selection_color: u32) {
+        let decorations_start = self.text_renderer.decorations.len();
         let (left, top) = text_box.pos();
         let (left, top) = (left as f32, top as f32);
         let clip_rect = text_box.effective_clip_rect();
@@ -390,32 +709,592 @@ impl TextRenderer {
         let content_left = left - text_box.scroll_offset().0;
         let content_top = top - text_box.scroll_offset().1;
 
-        let selection_color = 0x33_33_ff_aa;
-        let cursor_color = 0xee_ee_ee_ff;
+        let (selection_color, cursor_color) = decoration_colors(&text_box.shared.system_settings, selection_color);
+
+        if text_box.inner.column_selection.is_some() {
+            // Each line of a column selection gets its own rectangle, or a thin caret where the column has no width.
+            let layout = &text_box.inner.layout;
+            for range in text_box.column_selection_ranges() {
+                if range.is_empty() {
+                    let caret = parley::Cursor::from_byte_index(layout, range.start, parley::Affinity::Downstream).geometry(layout, 1.5);
+                    self.text_renderer.add_selection_rect(caret, content_left, content_top, cursor_color, clip_rect);
+                } else {
+                    let selection = parley::Selection::new(
+                        parley::Cursor::from_byte_index(layout, range.start, parley::Affinity::Downstream),
+                        parley::Cursor::from_byte_index(layout, range.end, parley::Affinity::Upstream),
+                    );
+                    selection.geometry_with(layout, |rect, _line_i| {
+                        self.text_renderer.add_selection_rect(rect, content_left, content_top, selection_color, clip_rect);
+                    });
+                }
+            }
+            self.text_renderer.finish_box_decorations(decorations_start, text_box.inner.group, text_box.inner.depth);
+            self.text_renderer.needs_gpu_sync = true;
+            return;
+        }
 
         text_box.selection().geometry_with(&text_box.inner.layout, |rect, _line_i| {
             self.text_renderer.add_selection_rect(rect, content_left, content_top, selection_color, clip_rect);
         });
         
-        let show_cursor = show_cursor && text_box.selection().is_collapsed();
-        if show_cursor {
-            let size = CURSOR_WIDTH;
-            let cursor_rect = text_box.selection().focus().geometry(&text_box.inner.layout, size);
-            self.text_renderer.add_selection_rect(cursor_rect, content_left, content_top, cursor_color, clip_rect);
+        self.text_renderer.finish_box_decorations(decorations_start, text_box.inner.group, text_box.inner.depth);
+        self.text_renderer.needs_gpu_sync = true;
+
+        self.prepare_cursor(text_box, show_cursor, cursor_color);
+    }
+
+    /// Prepare only the cursor of a box, for example after the cursor blinked. Removing the old cursor is up to the caller, with [`TextRenderer::clear_cursor_only()`].
+    pub fn prepare_text_box_cursor(&mut self, text_box: &TextBoxMut, show_cursor: bool) {
+        let (_, cursor_color) = decoration_colors(&text_box.shared.system_settings, DEFAULT_SELECTION_COLOR);
+        self.prepare_cursor(text_box, show_cursor, cursor_color);
+    }
+
+    fn prepare_cursor(&mut self, text_box: &TextBoxMut, show_cursor: bool, cursor_color: u32) {
+        if !show_cursor || !text_box.selection().is_collapsed() {
+            return;
         }
+        let (left, top) = text_box.pos();
+        let content_left = left as f32 - text_box.scroll_offset().0;
+        let content_top = top as f32 - text_box.scroll_offset().1;
+
+        let cursor_rect = text_box.selection().focus().geometry(&text_box.inner.layout, CURSOR_WIDTH);
+        if let Some(mut quad) = decoration_quad(cursor_rect, content_left, content_top, cursor_color, text_box.effective_clip_rect()) {
+            if let Some(group) = text_box.inner.group {
+                quad.flags |= group.gpu_index() << GROUP_SHIFT;
+            }
+            self.text_renderer.cursor_quads.push(quad);
+            self.text_renderer.cursor_depth = text_box.inner.depth;
+            self.text_renderer.cursor_needs_gpu_sync = true;
+        }
+    }
+
+    fn prepare_ghost_text(&mut self, text_edit: &TextEditMut, content_left: f32, content_top: f32, clip_rect: Option<parley::Rect>, fade: bool) {
+        let Some((index, ghost)) = &text_edit.inner.ghost_text else {
+            return;
+        };
+        let layout = &text_edit.text_box.inner.layout;
+        let index = floor_char_boundary(text_edit.text_box.text_inner(), *index);
+        let caret = parley::Cursor::from_byte_index(layout, index, parley::Affinity::Downstream).geometry(layout, 0.0);
+
+        let text_style = &text_edit.text_box.shared.styles[text_edit.text_box.inner.style.i as usize];
+        let style = TextStyle2 {
+            brush: text_style.text_edit_style.placeholder_text_color,
+            font_size: text_style.text_style.font_size * text_edit.text_box.layout_scale(),
+            ..text_style.text_style.clone()
+        };
+        let ghost_layout = build_label_layout(ghost, &style);
+
+        // The clip rect is relative to the box content, so shift it into the ghost layout's coordinates.
+        let (dx, dy) = (caret.x0, caret.y0);
+        let clip_rect = clip_rect.map(|r| parley::Rect { x0: r.x0 - dx, y0: r.y0 - dy, x1: r.x1 - dx, y1: r.y1 - dy });
+        self.text_renderer.prepare_layout(&ghost_layout, &mut self.scale_cx, content_left + dx as f32, content_top + dy as f32, clip_rect, fade);
+    }
+
+    fn prepare_invisibles(&mut self, text_edit: &TextEditMut, content_left: f32, content_top: f32, clip_rect: Option<parley::Rect>, fade: bool) {
+        if !text_edit.inner.show_invisibles || text_edit.inner.showing_placeholder {
+            return;
+        }
+        let layout = &text_edit.text_box.inner.layout;
+        let text = text_edit.text_box.text_inner();
+
+        let text_style = &text_edit.text_box.shared.styles[text_edit.text_box.inner.style.i as usize];
+        let style = TextStyle2 {
+            brush: text_style.text_edit_style.invisibles_color,
+            font_size: text_style.text_style.font_size * text_edit.text_box.layout_scale(),
+            ..text_style.text_style.clone()
+        };
+        let space_marker = build_label_layout("·", &style);
+        let tab_marker = build_label_layout("→", &style);
+        let newline_marker = build_label_layout("¶", &style);
+
+        let folds = &text_edit.text_box.inner.folds;
+        // Only lines that are at least partly in view get markers.
+        let visible_lines = layout.lines().map(|line| line.text_range()).filter(|range| match clip_rect {
+            Some(clip) => {
+                let line = parley::Cursor::from_byte_index(layout, range.start, parley::Affinity::Downstream).geometry(layout, 0.0);
+                line.y1 >= clip.y0 && line.y0 <= clip.y1
+            }
+            None => true,
+        });
+
+        for line_range in visible_lines {
+            for (offset, c) in text[line_range.clone()].char_indices() {
+                let index = line_range.start + offset;
+                // Folded text takes no space, so its markers would pile up at the fold.
+                if folds.iter().any(|fold| fold.contains(&index)) {
+                    continue;
+                }
+                let (marker, x, caret) = match c {
+                    ' ' | '\t' => {
+                        let start = parley::Cursor::from_byte_index(layout, index, parley::Affinity::Downstream).geometry(layout, 0.0);
+                        let end = parley::Cursor::from_byte_index(layout, index + 1, parley::Affinity::Upstream).geometry(layout, 0.0);
+                        if c == ' ' {
+                            // Centered on the space.
+                            let x = (start.x0 + end.x0) * 0.5 - space_marker.width() as f64 * 0.5;
+                            (&space_marker, x, start)
+                        } else {
+                            (&tab_marker, start.x0.min(end.x0), start)
+                        }
+                    }
+                    '\n' => {
+                        let caret = parley::Cursor::from_byte_index(layout, index, parley::Affinity::Downstream).geometry(layout, 0.0);
+                        (&newline_marker, caret.x0, caret)
+                    }
+                    _ => continue,
+                };
+
+                // Skip markers that are scrolled out of view.
+                if let Some(clip) = clip_rect {
+                    if caret.y1 < clip.y0 || caret.y0 > clip.y1 || x > clip.x1 || (x + marker.width() as f64) < clip.x0 {
+                        continue;
+                    }
+                }
+
+                // The clip rect is relative to the box content, so shift it into the marker layout's coordinates.
+                let (dx, dy) = (x, caret.y0);
+                let marker_clip = clip_rect.map(|r| parley::Rect { x0: r.x0 - dx, y0: r.y0 - dy, x1: r.x1 - dx, y1: r.y1 - dy });
+                self.text_renderer.prepare_layout(marker, &mut self.scale_cx, content_left + dx as f32, content_top + dy as f32, marker_clip, fade);
+            }
+        }
+    }
+
+    fn prepare_remote_cursor_labels(&mut self, text_edit: &TextEditMut, content_left: f32, content_top: f32, clip_rect: Option<parley::Rect>) {
+        let layout = &text_edit.text_box.inner.layout;
+        let text = text_edit.text_box.text_inner();
+
+        for cursor in &text_edit.inner.remote_cursors {
+            let Some(name) = &cursor.name else {
+                continue;
+            };
+
+            let focus = floor_char_boundary(text, cursor.focus);
+            let caret = parley::Cursor::from_byte_index(layout, focus, parley::Affinity::Downstream).geometry(layout, REMOTE_CURSOR_WIDTH);
+
+            // Don't draw labels for carets that are scrolled out of view.
+            if let Some(clip) = clip_rect {
+                if caret.x1 < clip.x0 || caret.x0 > clip.x1 || caret.y1 < clip.y0 || caret.y0 > clip.y1 {
+                    continue;
+                }
+            }
+
+            let style = TextStyle2 {
+                brush: cursor.color,
+                font_size: REMOTE_CURSOR_LABEL_FONT_SIZE,
+                ..Default::default()
+            };
+            let label = build_label_layout(name, &style);
+            let x = content_left + caret.x0 as f32;
+            let y = content_top + caret.y0 as f32 - label.height();
+            self.text_renderer.prepare_layout(&label, &mut self.scale_cx, x, y, None, false);
+        }
+    }
+
+    pub fn prepare_fold_markers(&mut self, text_edit: &TextEditMut) {
+        let decorations_start = self.text_renderer.decorations.len();
+        let text_box = &text_edit.text_box;
+        if text_box.inner.folds.is_empty() {
+            return;
+        }
+        let (left, top) = text_box.pos();
+        let clip_rect = text_box.effective_clip_rect();
+        let content_left = left as f32 - text_box.scroll_offset().0;
+        let content_top = top as f32 - text_box.scroll_offset().1;
+
+        let layout = &text_box.inner.layout;
+        let text = text_box.text_inner();
+        let color = color_to_u32(text_edit.text_edit_style().placeholder_text_color);
+
+        for fold in &text_box.inner.folds {
+            let start = floor_char_boundary(text, fold.start);
+            let caret = parley::Cursor::from_byte_index(layout, start, parley::Affinity::Downstream).geometry(layout, 0.0);
+            let height = caret.height();
+            let x0 = caret.x1 + FOLD_MARKER_GAP as f64;
+            let marker = parley::Rect {
+                x0,
+                y0: caret.y0 + height * 0.3,
+                x1: x0 + height * 1.2,
+                y1: caret.y1 - height * 0.3,
+            };
+            self.text_renderer.add_selection_rect(marker, content_left, content_top, color, clip_rect);
+        }
+        self.text_renderer.finish_box_decorations(decorations_start, text_box.inner.group, text_box.inner.depth);
+        self.text_renderer.needs_gpu_sync = true;
+    }
+
+    /// Prepare the bars that mark suspicious characters, if the edit uses [`SuspiciousCharPolicy::Highlight`].
+    pub fn prepare_suspicious_char_highlights(&mut self, text_edit: &TextEditMut) {
+        if text_edit.inner.suspicious_char_policy != SuspiciousCharPolicy::Highlight {
+            return;
+        }
+        let found = text_edit.suspicious_chars();
+        if found.is_empty() {
+            return;
+        }
+        let decorations_start = self.text_renderer.decorations.len();
+        let text_box = &text_edit.text_box;
+        let (left, top) = text_box.pos();
+        let clip_rect = text_box.effective_clip_rect();
+        let content_left = left as f32 - text_box.scroll_offset().0;
+        let content_top = top as f32 - text_box.scroll_offset().1;
+
+        let layout = &text_box.inner.layout;
+        let color = color_to_u32(text_edit.text_edit_style().suspicious_char_color);
+
+        // Most of these characters have no width, so they're marked with a bar like a cursor.
+        for suspicious in &found {
+            let cursor = parley::Cursor::from_byte_index(layout, suspicious.range.start, parley::Affinity::Downstream);
+            let bar = cursor.geometry(layout, SUSPICIOUS_CHAR_BAR_WIDTH);
+            self.text_renderer.add_selection_rect(bar, content_left, content_top, color, clip_rect);
+        }
+        self.text_renderer.finish_box_decorations(decorations_start, text_box.inner.group, text_box.inner.depth);
+        self.text_renderer.needs_gpu_sync = true;
+    }
+
+    /// Prepare the outlines around the bracket next to the cursor and its match, if the edit has [`TextEditMut::set_highlight_matching_brackets()`] enabled.
+    pub fn prepare_bracket_match_highlight(&mut self, text_edit: &TextEditMut) {
+        if !text_edit.inner.highlight_matching_brackets {
+            return;
+        }
+        let Some((bracket, matching)) = text_edit.bracket_pair_at_cursor() else {
+            return;
+        };
+        let decorations_start = self.text_renderer.decorations.len();
+        let text_box = &text_edit.text_box;
+        let (left, top) = text_box.pos();
+        let clip_rect = text_box.effective_clip_rect();
+        let content_left = left as f32 - text_box.scroll_offset().0;
+        let content_top = top as f32 - text_box.scroll_offset().1;
+
+        let layout = &text_box.inner.layout;
+        let text = text_box.text_inner();
+        let color = color_to_u32(text_edit.text_edit_style().bracket_match_color);
+
+        for start in [bracket, matching] {
+            let end = start + text[start..].chars().next().map_or(0, char::len_utf8);
+            let selection = parley::Selection::new(
+                parley::Cursor::from_byte_index(layout, start, parley::Affinity::Downstream),
+                parley::Cursor::from_byte_index(layout, end, parley::Affinity::Upstream),
+            );
+            selection.geometry_with(layout, |rect, _line_i| {
+                self.text_renderer.add_outline(rect, content_left, content_top, color, clip_rect);
+            });
+        }
+        self.text_renderer.finish_box_decorations(decorations_start, text_box.inner.group, text_box.inner.depth);
+        self.text_renderer.needs_gpu_sync = true;
+    }
+
+    pub fn prepare_remote_cursor_decorations(&mut self, text_edit: &TextEditMut) {
+        let decorations_start = self.text_renderer.decorations.len();
+        let text_box = &text_edit.text_box;
+        let (left, top) = text_box.pos();
+        let (left, top) = (left as f32, top as f32);
+        let clip_rect = text_box.effective_clip_rect();
+
+        let content_left = left - text_box.scroll_offset().0;
+        let content_top = top - text_box.scroll_offset().1;
+
+        let layout = &text_box.inner.layout;
+        let text = text_box.text_inner();
+
+        for cursor in &text_edit.inner.remote_cursors {
+            let anchor = floor_char_boundary(text, cursor.anchor);
+            let focus = floor_char_boundary(text, cursor.focus);
+            let focus_cursor = parley::Cursor::from_byte_index(layout, focus, parley::Affinity::Downstream);
+
+            let mut selection_color = cursor.color;
+            selection_color.0[3] = 0x55;
+            let selection_color = color_to_u32(selection_color);
+
+            if anchor != focus {
+                let anchor_cursor = parley::Cursor::from_byte_index(layout, anchor, parley::Affinity::Downstream);
+                parley::Selection::new(anchor_cursor, focus_cursor).geometry_with(layout, |rect, _line_i| {
+                    self.text_renderer.add_selection_rect(rect, content_left, content_top, selection_color, clip_rect);
+                });
+            }
+
+            let caret_rect = focus_cursor.geometry(layout, REMOTE_CURSOR_WIDTH);
+            self.text_renderer.add_selection_rect(caret_rect, content_left, content_top, color_to_u32(cursor.color), clip_rect);
+        }
+        self.text_renderer.finish_box_decorations(decorations_start, text_box.inner.group, text_box.inner.depth);
+        self.text_renderer.needs_gpu_sync = true;
+    }
+
+    /// Prepare the built-in scrollbars of a text edit, if its style has them and the text overflows. See [`TextEditStyle::scrollbar`].
+    pub fn prepare_scrollbars(&mut self, text_edit: &TextEditMut) {
+        let Some(style) = text_edit.text_edit_style().scrollbar else {
+            return;
+        };
+        let decorations_start = self.text_renderer.decorations.len();
+        let (left, top) = text_edit.pos();
+        let (left, top) = (left as f32, top as f32);
+
+        for geometry in text_edit.scrollbar_geometries() {
+            self.text_renderer.add_selection_rect(geometry.track, left, top, color_to_u32(style.track_color), None);
+            self.text_renderer.add_selection_rect(geometry.thumb, left, top, color_to_u32(style.thumb_color), None);
+        }
+        if self.text_renderer.decorations.len() == decorations_start {
+            return;
+        }
+        self.text_renderer.finish_box_decorations(decorations_start, text_edit.text_box.inner.group, text_edit.text_box.inner.depth);
+        self.text_renderer.needs_gpu_sync = true;
+    }
+
+    pub fn prepare_text_box_overlays(&mut self, text_box: &TextBoxMut) {
+        let decorations_start = self.text_renderer.decorations.len();
+        let (left, top) = text_box.pos();
+        let (left, top) = (left as f32, top as f32);
+        let clip_rect = text_box.effective_clip_rect();
+
+        let content_left = left - text_box.scroll_offset().0;
+        let content_top = top - text_box.scroll_offset().1;
+
+        for overlay in &text_box.inner.overlay_rects {
+            let color = color_to_u32(overlay.color);
+            let r = overlay.rect;
+            match overlay.kind {
+                DecorationKind::Fill => {
+                    self.text_renderer.add_selection_rect(r, content_left, content_top, color, clip_rect);
+                }
+                DecorationKind::Underline => {
+                    let bar = parley::Rect { x0: r.x0, y0: r.y1 - 2.0, x1: r.x1, y1: r.y1 };
+                    self.text_renderer.add_selection_rect(bar, content_left, content_top, color, clip_rect);
+                }
+                DecorationKind::Outline => {
+                    self.text_renderer.add_outline(r, content_left, content_top, color, clip_rect);
+                }
+            }
+        }
+        self.text_renderer.finish_box_decorations(decorations_start, text_box.inner.group, text_box.inner.depth);
         self.text_renderer.needs_gpu_sync = true;
     }
 
     pub fn gpu_load(&mut self, device: &Device, queue: &Queue) {
+        profile_span!("TextRenderer::gpu_load");
         self.text_renderer.gpu_load(device, queue);
     }
 
+    /// Store an RGBA image in the color atlas, so that it can be drawn in place of text with [`TextBoxMut::set_image_replacements()`].
+    /// 
+    /// Returns `None` if the image is empty or too large to fit in an atlas page.
+    /// If the renderer shares its [`GlyphCache`], the image can be used with the other renderers too.
+    pub fn register_image(&mut self, image: &RgbaImage) -> Option<ImageId> {
+        let id = self.text_renderer.glyph_cache.lock().register_image(image);
+        self.text_renderer.needs_gpu_sync = true;
+        id
+    }
+
+    /// Free the atlas space used by an image registered with [`TextRenderer::register_image()`].
+    pub fn remove_image(&mut self, id: ImageId) {
+        self.text_renderer.glyph_cache.lock().remove_image(id);
+    }
+
+    /// Set how bright text is on HDR surfaces, as a multiplier where 1.0 is the standard white of SDR content. The default is 1.0.
+    ///
+    /// Colors are converted for the renderer's target format: sRGB targets encode them in hardware, other 8-bit targets get them encoded in the shader, and floating point targets like `Rgba16Float` get linear colors multiplied by this value. Compositors often show HDR content with a brighter white than SDR content, so text can look dim next to it unless this is raised, for example to the ratio between the display's paper white and 80 nits.
+    ///
+    /// Has no effect on targets that aren't floating point.
+    pub fn set_hdr_brightness(&mut self, brightness: f32) {
+        self.text_renderer.hdr_brightness = brightness;
+        self.text_renderer.update_output_params();
+    }
+
+    /// The brightness set with [`TextRenderer::set_hdr_brightness()`].
+    pub fn hdr_brightness(&self) -> f32 {
+        self.text_renderer.hdr_brightness
+    }
+
+    /// Choose how text boxes with a clip rect are clipped. The default is [`ClipMode::Shader`].
+    ///
+    /// With [`ClipMode::Scissor`], the glyphs of each clipped box are drawn with a scissor rect, which saves the per-fragment test on tiled GPUs, at the cost of one draw call per clipped box. Boxes with fadeout clipping or in a [`GroupHandle`] are still clipped in the shader, because their clip area isn't a fixed screen rectangle. Selections, cursors and other decorations are always clipped in the shader.
+    ///
+    /// [`TextRenderer::render()`] sets the scissor rect of the pass in this mode, and leaves it covering the whole target, so any scissor rect set on the pass before is lost.
+    ///
+    /// Takes effect the next time the text is prepared from scratch, for example after [`Text::clear()`] or any change to the text.
+    pub fn set_clip_mode(&mut self, clip_mode: ClipMode) {
+        self.text_renderer.clip_mode = clip_mode;
+    }
+
+    /// The mode set with [`TextRenderer::set_clip_mode()`].
+    pub fn clip_mode(&self) -> ClipMode {
+        self.text_renderer.clip_mode
+    }
+
+    /// Set the size in pixels of the render target that the pass passed to [`TextRenderer::render()`] draws into, or go back to the screen size of the [`Text`] with `None`.
+    ///
+    /// With [`ClipMode::Scissor`], scissor rects are clamped to this size, and wgpu rejects scissor rects that reach outside the target. This only needs to be set when rendering into a target that is smaller than the screen size set by `WindowEvent::Resized`, like an offscreen texture or a surface that wasn't resized yet.
+    pub fn set_target_size(&mut self, size: Option<(u32, u32)>) {
+        self.text_renderer.target_size = size;
+    }
+
+    /// The size set with [`TextRenderer::set_target_size()`].
+    pub fn target_size(&self) -> Option<(u32, u32)> {
+        self.text_renderer.target_size
+    }
+
+    /// Draw text boxes back to front by depth, so that overlapping boxes with transparent text blend in the right order. Disabled by default.
+    ///
+    /// Normally, quads are drawn grouped by atlas page, so that the text of a box in the back can end up on top of a box in front of it. With depth sorting, [`Text::prepare_all()`] prepares the boxes from the highest depth to the lowest, and each box is drawn with its own draw calls, in that order. This costs a draw call per box and atlas page.
+    ///
+    /// Selections, cursors and other decorations are still drawn on top of all the text.
+    ///
+    /// Takes effect the next time the text is prepared from scratch, for example after [`Text::clear()`] or any change to the text.
+    pub fn set_depth_sorting(&mut self, depth_sorting: bool) {
+        self.text_renderer.depth_sorting = depth_sorting;
+    }
+
+    /// Whether depth sorting was enabled with [`TextRenderer::set_depth_sorting()`].
+    pub fn depth_sorting(&self) -> bool {
+        self.text_renderer.depth_sorting
+    }
+
+    /// Draw only the text boxes with a depth in `depth_range`, together with their selections, cursors and other decorations.
+    ///
+    /// This allows drawing the text of a single prepared scene in several steps, interleaved with other draw calls, for example text in the world, then a 3D scene, then UI text on top:
+    ///
+    /// ```ignore
+    /// text_renderer.render_range(&mut pass, 100.0..);
+    /// draw_scene(&mut pass);
+    /// text_renderer.render_range(&mut pass, ..100.0);
+    /// ```
+    ///
+    /// Within the range, boxes are drawn in the order they were prepared, which is back to front with [`TextRenderer::set_depth_sorting()`]. Each box takes at least one draw call, so [`TextRenderer::render()`] is cheaper when everything is drawn at once. Layouts prepared directly with [`TextRenderer::prepare_layout()`] count as depth 0.
+    pub fn render_range(&self, pass: &mut RenderPass<'_>, depth_range: impl RangeBounds<f32>) {
+        profile_span!("TextRenderer::render_range");
+        self.text_renderer.render_range(pass, depth_range);
+    }
+
+    /// The cache holding the rasterized glyphs of this renderer. It can be passed to [`TextRendererParams::glyph_cache`] to create other renderers that share it.
+    pub fn glyph_cache(&self) -> GlyphCache {
+        self.text_renderer.glyph_cache.clone()
+    }
+
     pub fn render(&self, pass: &mut RenderPass<'_>) {
+        profile_span!("TextRenderer::render");
         self.text_renderer.render(pass);
     }
 
+    /// Render a text box into a new texture, so that it can be drawn as a sprite instead of being prepared every frame.
+    ///
+    /// The texture has the size of the box and the same format as the renderer. It's cleared to transparent before drawing. The box's layout should be up to date, for example by calling this after [`Text::prepare_all()`].
+    ///
+    /// The glyphs prepared for the current frame are kept, and they're uploaded again before returning.
+    pub fn rasterize_box_to_texture(&mut self, device: &Device, queue: &Queue, text_box: &TextBox) -> TextureHandle {
+        let width = (text_box.inner.width.ceil() as u32).max(1);
+        let height = (text_box.inner.height.ceil() as u32).max(1);
+        let renderer = &mut self.text_renderer;
+
+        // Put the current frame aside, so that the box is drawn alone.
+        let saved_mask_quads: Vec<Vec<Quad>> = renderer.mask_atlas_pages.iter_mut().map(|page| mem::take(&mut page.quads)).collect();
+        let saved_color_quads: Vec<Vec<Quad>> = renderer.color_atlas_pages.iter_mut().map(|page| mem::take(&mut page.quads)).collect();
+        let saved_decorations = mem::take(&mut renderer.decorations);
+        let saved_cursor_quads = mem::take(&mut renderer.cursor_quads);
+        let saved_draw_batches = mem::take(&mut renderer.draw_batches);
+        let saved_params = renderer.params;
+
+        renderer.update_resolution(width as f32, height as f32);
+        let (scroll_x, scroll_y) = text_box.inner.scroll_offset;
+        renderer.prepare_layout(&text_box.inner.layout, &mut self.scale_cx, -scroll_x, -scroll_y, None, false);
+        renderer.prepare_image_replacements(&text_box.inner.layout, &text_box.inner.image_replacements, -scroll_x, -scroll_y, None, false);
+        renderer.needs_gpu_sync = true;
+        renderer.gpu_load(device, queue);
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("textslabs rasterized text box"),
+            size: Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: renderer.format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        // A multisampled pipeline has to draw into a multisampled texture, which is then resolved into the returned one.
+        let sample_count = renderer.sample_count;
+        let msaa_view = (sample_count > 1).then(|| {
+            device.create_texture(&TextureDescriptor {
+                label: Some("textslabs rasterized text box msaa"),
+                size: Extent3d { width, height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count,
+                dimension: TextureDimension::D2,
+                format: renderer.format,
+                usage: TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            }).create_view(&TextureViewDescriptor::default())
+        });
+
+        // The pipeline might have been created with a depth buffer, in which case the pass needs one too.
+        let depth_view = renderer.depth_stencil_format.map(|format| {
+            device.create_texture(&TextureDescriptor {
+                label: Some("textslabs rasterized text box depth"),
+                size: Extent3d { width, height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count,
+                dimension: TextureDimension::D2,
+                format,
+                usage: TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            }).create_view(&TextureViewDescriptor::default())
+        });
+
+        // Reverse-Z buffers have the far plane at 0.
+        let far_depth = match renderer.depth_stencil.as_ref().map(|depth_stencil| depth_stencil.depth_compare) {
+            Some(CompareFunction::Greater | CompareFunction::GreaterEqual) => 0.0,
+            _ => 1.0,
+        };
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor { label: Some("textslabs rasterize text box") });
+        {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("textslabs rasterize text box"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: msaa_view.as_ref().unwrap_or(&view),
+                    resolve_target: msaa_view.as_ref().map(|_| &view),
+                    ops: Operations { load: LoadOp::Clear(Color::TRANSPARENT), store: StoreOp::Store },
+                })],
+                depth_stencil_attachment: depth_view.as_ref().map(|depth_view| RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(Operations { load: LoadOp::Clear(far_depth), store: StoreOp::Discard }),
+                    stencil_ops: renderer.depth_stencil_format
+                        .filter(|format| format.has_stencil_aspect())
+                        .map(|_| Operations { load: LoadOp::Clear(0), store: StoreOp::Discard }),
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            renderer.render(&mut pass);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        // Restore the current frame. The writes are queued after the submission above, so they don't affect it.
+        // Pages that were added while preparing the box stay empty.
+        for page in &mut renderer.mask_atlas_pages {
+            page.quads.clear();
+        }
+        for page in &mut renderer.color_atlas_pages {
+            page.quads.clear();
+        }
+        for (page, quads) in renderer.mask_atlas_pages.iter_mut().zip(saved_mask_quads) {
+            page.quads = quads;
+        }
+        for (page, quads) in renderer.color_atlas_pages.iter_mut().zip(saved_color_quads) {
+            page.quads = quads;
+        }
+        renderer.decorations = saved_decorations;
+        renderer.cursor_quads = saved_cursor_quads;
+        renderer.draw_batches = saved_draw_batches;
+        renderer.params = saved_params;
+        renderer.needs_gpu_sync = true;
+        renderer.gpu_load(device, queue);
+
+        TextureHandle { texture, view, size: (width, height) }
+    }
+
     pub fn gpu_load_atlas_debug(&mut self, device: &Device, queue: &Queue) {
         let atlas_size = self.text_renderer.atlas_size;
+        self.text_renderer.draw_batches.clear();
         
         for (i, page) in self.text_renderer.mask_atlas_pages.iter_mut().enumerate() {
             let x_offset = i as i32 * (atlas_size as i32 + 10);
@@ -427,7 +1306,9 @@ impl TextRenderer {
                 color: 0xff0000ff,
                 depth: 0.0,
                 flags: pack_flags(CONTENT_TYPE_MASK, false),
-                clip_rect: [0, 0, 32767, 32767]
+                clip_rect: [0, 0, 32767, 32767],
+                origin: [0, 0],
+                fade: 0,
             }];
         }
     
@@ -441,7 +1322,9 @@ impl TextRenderer {
                 color: 0xffffffff,
                 depth: 0.0,
                 flags: pack_flags(CONTENT_TYPE_COLOR, false),
-                clip_rect: [0, 0, 32767, 32767]
+                clip_rect: [0, 0, 32767, 32767],
+                origin: [0, 0],
+                fade: 0,
             }];
         }
         
@@ -507,7 +1390,7 @@ impl TextRenderer {
     }
 }
 
-const SOURCES: &[Source; 3] = &[
+pub(crate) const SOURCES: &[Source; 3] = &[
     Source::ColorOutline(0),
     Source::ColorBitmap(StrikeWith::BestFit),
     Source::Outline,
@@ -521,20 +1404,26 @@ impl ContextlessTextRenderer {
 
         let mut instance_offset = 0u32;
 
-        for page in &self.mask_atlas_pages {
-            if !page.quads.is_empty() {
-                pass.set_bind_group(0, &page.gpu.as_ref().unwrap().bind_group, &[]);
-                pass.draw(0..4, instance_offset..(instance_offset + page.quads.len() as u32));
-                instance_offset += page.quads.len() as u32;
+        let uses_scissor = self.draw_batches.iter().any(|batch| batch.scissor.is_some());
+        if self.depth_sorting && !self.draw_batches.is_empty() {
+            instance_offset = self.draw_batches_in_order(pass, |_| true);
+        } else {
+            for (page_type, pages) in [(AtlasPageType::Mask, &self.mask_atlas_pages), (AtlasPageType::Color, &self.color_atlas_pages)] {
+                for (page_index, page) in pages.iter().enumerate() {
+                    if !page.quads.is_empty() {
+                        pass.set_bind_group(0, &page.gpu.as_ref().unwrap().bind_group, &[]);
+                        if !uses_scissor {
+                            pass.draw(0..4, instance_offset..(instance_offset + page.quads.len() as u32));
+                        } else {
+                            self.draw_page_with_scissor(pass, page_type, page_index as u16, page.quads.len() as u32, instance_offset);
+                        }
+                        instance_offset += page.quads.len() as u32;
+                    }
+                }
             }
         }
-
-        for page in &self.color_atlas_pages {
-            if !page.quads.is_empty() {
-                pass.set_bind_group(0, &page.gpu.as_ref().unwrap().bind_group, &[]);
-                pass.draw(0..4, instance_offset..(instance_offset + page.quads.len() as u32));
-                instance_offset += page.quads.len() as u32;
-            }
+        if uses_scissor {
+            self.set_full_scissor(pass);
         }
 
         // Draw decorations (they use the mask atlas bind group - first page)
@@ -542,6 +1431,198 @@ impl ContextlessTextRenderer {
             pass.set_bind_group(0, &self.mask_atlas_pages[0].gpu.as_ref().unwrap().bind_group, &[]);
             pass.draw(0..4, instance_offset..(instance_offset + self.decorations.len() as u32));
         }
+
+        // The cursor has its own buffer, so that blinking doesn't upload anything else.
+        if !self.cursor_quads.is_empty() {
+            pass.set_bind_group(0, &self.mask_atlas_pages[0].gpu.as_ref().unwrap().bind_group, &[]);
+            pass.set_vertex_buffer(0, self.cursor_buffer.slice(..));
+            pass.draw(0..4, 0..self.cursor_quads.len() as u32);
+        }
+    }
+
+    /// Draw the quads of a page, switching the scissor rect for the ranges that were batched with [`ClipMode::Scissor`].
+    fn draw_page_with_scissor(&self, pass: &mut RenderPass<'_>, page_type: AtlasPageType, page_index: u16, quad_count: u32, instance_offset: u32) {
+        let (width, height) = self.target_size();
+        let mut drawn = 0;
+        let batches = self.draw_batches.iter()
+            .filter(|batch| batch.page_type == page_type && batch.page_index == page_index && batch.quad_end <= quad_count);
+        for batch in batches {
+            let Some(scissor) = batch.scissor else {
+                continue;
+            };
+            if batch.quad_start > drawn {
+                self.set_full_scissor(pass);
+                pass.draw(0..4, (instance_offset + drawn)..(instance_offset + batch.quad_start));
+            }
+            if let Some((x, y, w, h)) = clamp_scissor(scissor, width, height) {
+                pass.set_scissor_rect(x, y, w, h);
+                pass.draw(0..4, (instance_offset + batch.quad_start)..(instance_offset + batch.quad_end));
+            }
+            drawn = batch.quad_end;
+        }
+        if quad_count > drawn {
+            self.set_full_scissor(pass);
+            pass.draw(0..4, (instance_offset + drawn)..(instance_offset + quad_count));
+        }
+    }
+
+    /// Draw only the boxes with a depth in `depth_range`, and their decorations.
+    pub(crate) fn render_range(&self, pass: &mut RenderPass<'_>, depth_range: impl RangeBounds<f32>) {
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(1, &self.params_bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+
+        let instance_offset = self.draw_batches_in_order(pass, |depth| depth_range.contains(&depth));
+        if self.draw_batches.iter().any(|batch| batch.scissor.is_some()) {
+            self.set_full_scissor(pass);
+        }
+
+        let mut decorations_bound = false;
+        for batch in &self.decoration_batches {
+            if !depth_range.contains(&batch.depth) || batch.end as usize > self.decorations.len() {
+                continue;
+            }
+            if !decorations_bound {
+                pass.set_bind_group(0, &self.mask_atlas_pages[0].gpu.as_ref().unwrap().bind_group, &[]);
+                decorations_bound = true;
+            }
+            pass.draw(0..4, (instance_offset + batch.start)..(instance_offset + batch.end));
+        }
+
+        if !self.cursor_quads.is_empty() && depth_range.contains(&self.cursor_depth) {
+            pass.set_bind_group(0, &self.mask_atlas_pages[0].gpu.as_ref().unwrap().bind_group, &[]);
+            pass.set_vertex_buffer(0, self.cursor_buffer.slice(..));
+            pass.draw(0..4, 0..self.cursor_quads.len() as u32);
+        }
+    }
+
+    /// Draw the recorded batches that pass `include`, in the order they were prepared. Returns the instance offset after the last page.
+    fn draw_batches_in_order(&self, pass: &mut RenderPass<'_>, include: impl Fn(f32) -> bool) -> u32 {
+        let (width, height) = self.target_size();
+        // The quads of all pages are in one buffer: mask pages first, then color pages.
+        let mut mask_offsets = Vec::with_capacity(self.mask_atlas_pages.len());
+        let mut color_offsets = Vec::with_capacity(self.color_atlas_pages.len());
+        let mut instance_offset = 0u32;
+        for page in &self.mask_atlas_pages {
+            mask_offsets.push(instance_offset);
+            instance_offset += page.quads.len() as u32;
+        }
+        for page in &self.color_atlas_pages {
+            color_offsets.push(instance_offset);
+            instance_offset += page.quads.len() as u32;
+        }
+
+        let uses_scissor = self.draw_batches.iter().any(|batch| batch.scissor.is_some());
+        for batch in &self.draw_batches {
+            if !include(batch.depth) {
+                continue;
+            }
+            let (pages, offsets) = match batch.page_type {
+                AtlasPageType::Mask => (&self.mask_atlas_pages, &mask_offsets),
+                AtlasPageType::Color => (&self.color_atlas_pages, &color_offsets),
+            };
+            let Some(page) = pages.get(batch.page_index as usize) else {
+                continue;
+            };
+            if batch.quad_end > page.quads.len() as u32 || batch.quad_start == batch.quad_end {
+                continue;
+            }
+            let page_offset = offsets[batch.page_index as usize];
+            match batch.scissor {
+                Some(scissor) => {
+                    let Some((x, y, w, h)) = clamp_scissor(scissor, width, height) else {
+                        continue;
+                    };
+                    pass.set_scissor_rect(x, y, w, h);
+                }
+                None if uses_scissor => self.set_full_scissor(pass),
+                None => {}
+            }
+            pass.set_bind_group(0, &page.gpu.as_ref().unwrap().bind_group, &[]);
+            pass.draw(0..4, (page_offset + batch.quad_start)..(page_offset + batch.quad_end));
+        }
+        instance_offset
+    }
+
+    fn set_full_scissor(&self, pass: &mut RenderPass<'_>) {
+        let (width, height) = self.target_size();
+        pass.set_scissor_rect(0, 0, width, height);
+    }
+
+    /// The size of the render target, from [`TextRenderer::set_target_size()`] or the screen resolution in the params.
+    fn target_size(&self) -> (u32, u32) {
+        self.target_size.unwrap_or((self.params.screen_resolution_width as u32, self.params.screen_resolution_height as u32))
+    }
+
+    /// Record the quad ranges of a box that was just prepared, so that it can be drawn on its own. With [`ClipMode::Scissor`], this also moves the clipping of its quads from the shader to a scissor rect, when `can_scissor` allows it.
+    pub(crate) fn add_draw_batches(&mut self, quad_storage: &QuadStorage, clip_rect: Option<parley::Rect>, left: f32, top: f32, can_scissor: bool, depth: f32) {
+        let scissor = match clip_rect {
+            Some(clip) if can_scissor && self.clip_mode == ClipMode::Scissor => {
+                // Same rounding as clip_quad.
+                let (left, top) = (left as i32, top as i32);
+                Some([left + clip.x0 as i32, top + clip.y0 as i32, left + clip.x1 as i32, top + clip.y1 as i32])
+            }
+            _ => None,
+        };
+        for range in &quad_storage.pages {
+            if scissor.is_some() {
+                let pages = match range.page_type {
+                    AtlasPageType::Mask => &mut self.mask_atlas_pages,
+                    AtlasPageType::Color => &mut self.color_atlas_pages,
+                };
+                let quads = &mut pages[range.page_index as usize].quads[range.quad_start as usize..range.quad_end as usize];
+                for quad in quads {
+                    quad.clip_rect = [0, 0, 32767, 32767];
+                }
+            }
+            self.draw_batches.push(DrawBatch {
+                page_type: range.page_type,
+                page_index: range.page_index,
+                quad_start: range.quad_start,
+                quad_end: range.quad_end,
+                scissor,
+                depth,
+            });
+        }
+    }
+
+    /// Move the quads of a box and the scissor rects of its draw batches by whole pixels, for [`Text::translate_boxes()`].
+    pub(crate) fn translate_quads(&mut self, quad_storage: &QuadStorage, dx: i32, dy: i32) {
+        const NO_CLIP: [i16; 4] = [0, 0, 32767, 32767];
+        for range in &quad_storage.pages {
+            let pages = match range.page_type {
+                AtlasPageType::Mask => &mut self.mask_atlas_pages,
+                AtlasPageType::Color => &mut self.color_atlas_pages,
+            };
+            let Some(page) = pages.get_mut(range.page_index as usize) else {
+                continue;
+            };
+            let Some(quads) = page.quads.get_mut(range.quad_start as usize..range.quad_end as usize) else {
+                continue;
+            };
+            for quad in quads {
+                quad.pos[0] += dx;
+                quad.pos[1] += dy;
+                quad.origin[0] = quad.origin[0].saturating_add(dx as i16);
+                quad.origin[1] = quad.origin[1].saturating_add(dy as i16);
+                if quad.clip_rect != NO_CLIP {
+                    let [x0, y0, x1, y1] = quad.clip_rect;
+                    quad.clip_rect = [x0.saturating_add(dx as i16), y0.saturating_add(dy as i16), x1.saturating_add(dx as i16), y1.saturating_add(dy as i16)];
+                }
+            }
+
+            for batch in &mut self.draw_batches {
+                if batch.page_type == range.page_type && batch.page_index == range.page_index && batch.quad_start == range.quad_start {
+                    if let Some([x0, y0, x1, y1]) = &mut batch.scissor {
+                        *x0 += dx;
+                        *y0 += dy;
+                        *x1 += dx;
+                        *y1 += dy;
+                    }
+                }
+            }
+        }
+        self.needs_gpu_sync = true;
     }
 
     pub fn update_resolution(&mut self, width: f32, height: f32) {
@@ -549,8 +1630,112 @@ impl ContextlessTextRenderer {
         self.params.screen_resolution_height = height;
     }
 
+    pub(crate) fn update_group_transforms(&mut self, groups: &slab::Slab<GroupInner>) {
+        for (i, group) in groups.iter() {
+            let index = GroupHandle { i: i as u32 }.gpu_index() as usize;
+            let gpu_transform = group.to_gpu();
+            if self.params.group_transforms[index] != gpu_transform {
+                self.params.group_transforms[index] = gpu_transform;
+                self.needs_gpu_sync = true;
+            }
+            if let Some(world) = &group.world {
+                if self.params.world_transforms[index] != world.matrix {
+                    self.params.world_transforms[index] = world.matrix;
+                    self.needs_gpu_sync = true;
+                }
+            }
+        }
+    }
+
+    /// Mark decorations added since `start` as belonging to a box: they get the box's group, so that the shader applies the group's transform, and its depth, for [`TextRenderer::render_range()`].
+    pub(crate) fn finish_box_decorations(&mut self, start: usize, group: Option<GroupHandle>, depth: f32) {
+        if let Some(group) = group {
+            for quad in &mut self.decorations[start..] {
+                quad.flags |= group.gpu_index() << GROUP_SHIFT;
+            }
+        }
+        if self.decorations.len() > start {
+            self.decoration_batches.push(DecorationBatch { start: start as u32, end: self.decorations.len() as u32, depth });
+        }
+    }
+
+    fn set_quads_group(&mut self, quad_storage: &QuadStorage, group: Option<GroupHandle>) {
+        let Some(group) = group else {
+            return;
+        };
+        for range in &quad_storage.pages {
+            let quads = match range.page_type {
+                AtlasPageType::Mask => &mut self.mask_atlas_pages[range.page_index as usize].quads,
+                AtlasPageType::Color => &mut self.color_atlas_pages[range.page_index as usize].quads,
+            };
+            for quad in &mut quads[range.quad_start as usize..range.quad_end as usize] {
+                quad.flags |= group.gpu_index() << GROUP_SHIFT;
+            }
+        }
+    }
+
+    /// Hide the glyph and image quads of a box that are drawn after the `revealed` byte index. `left` and `top` are the position of the text content, including scrolling.
+    fn hide_unrevealed_quads(&mut self, quad_storage: &QuadStorage, layout: &Layout<ColorBrush>, text: &str, revealed: usize, left: f32, top: f32) {
+        if revealed >= text.len() {
+            return;
+        }
+        let selection = parley::Selection::new(
+            parley::Cursor::from_byte_index(layout, floor_char_boundary(text, revealed), parley::Affinity::Downstream),
+            parley::Cursor::from_byte_index(layout, text.len(), parley::Affinity::Upstream),
+        );
+        selection.geometry_with(layout, |rect, _line_i| {
+            let (x0, x1) = (left + rect.x0 as f32, left + rect.x1 as f32);
+            let (y0, y1) = (top + rect.y0 as f32, top + rect.y1 as f32);
+            for range in &quad_storage.pages {
+                let quads = match range.page_type {
+                    AtlasPageType::Mask => &mut self.mask_atlas_pages[range.page_index as usize].quads,
+                    AtlasPageType::Color => &mut self.color_atlas_pages[range.page_index as usize].quads,
+                };
+                for quad in &mut quads[range.quad_start as usize..range.quad_end as usize] {
+                    let center_x = quad.pos[0] as f32 + quad.dim[0] as f32 * 0.5;
+                    let center_y = quad.pos[1] as f32 + quad.dim[1] as f32 * 0.5;
+                    if center_x >= x0 && center_x < x1 && center_y >= y0 && center_y < y1 {
+                        quad.dim = [0, 0];
+                    }
+                }
+            }
+        });
+    }
+
+    fn set_quads_fade(&mut self, quad_storage: &QuadStorage, fadeout_style: FadeoutStyle) {
+        let fade = fadeout_style.to_gpu();
+        if fade == 0 {
+            return;
+        }
+        for range in &quad_storage.pages {
+            let quads = match range.page_type {
+                AtlasPageType::Mask => &mut self.mask_atlas_pages[range.page_index as usize].quads,
+                AtlasPageType::Color => &mut self.color_atlas_pages[range.page_index as usize].quads,
+            };
+            for quad in &mut quads[range.quad_start as usize..range.quad_end as usize] {
+                quad.fade = fade;
+            }
+        }
+    }
+
+    /// Store the box position in the quads of a box, and the material of its style if it has one.
+    fn set_quads_material(&mut self, quad_storage: &QuadStorage, material: Option<MaterialId>, origin: (f32, f32)) {
+        let origin = [origin.0 as i16, origin.1 as i16];
+        let material_bits = material.map(|material| (material.i + 1) << MATERIAL_SHIFT).unwrap_or(0);
+        for range in &quad_storage.pages {
+            let quads = match range.page_type {
+                AtlasPageType::Mask => &mut self.mask_atlas_pages[range.page_index as usize].quads,
+                AtlasPageType::Color => &mut self.color_atlas_pages[range.page_index as usize].quads,
+            };
+            for quad in &mut quads[range.quad_start as usize..range.quad_end as usize] {
+                quad.origin = origin;
+                quad.flags |= material_bits;
+            }
+        }
+    }
+
     pub fn clear(&mut self) {
-        self.frame += 1;
+        self.frame = self.glyph_cache.lock().begin_frame(self.renderer_id);
 
         for page in &mut self.mask_atlas_pages {
             page.quads.clear();
@@ -559,12 +1744,24 @@ impl ContextlessTextRenderer {
             page.quads.clear();
         }
         self.decorations.clear();
+        self.decoration_batches.clear();
+        self.draw_batches.clear();
         self.needs_gpu_sync = true;
+        self.clear_cursor();
     }
 
     pub fn clear_decorations(&mut self) {
         self.decorations.clear();
+        self.decoration_batches.clear();
         self.needs_gpu_sync = true;
+        self.clear_cursor();
+    }
+
+    pub fn clear_cursor(&mut self) {
+        if !self.cursor_quads.is_empty() {
+            self.cursor_quads.clear();
+            self.cursor_needs_gpu_sync = true;
+        }
     }
 
 
@@ -594,12 +1791,47 @@ impl ContextlessTextRenderer {
         let run_y = top + glyph_run.baseline();
         let style = glyph_run.style();
 
+        // Fully transparent runs would only produce invisible quads. This is also how text replaced by images is hidden.
+        if style.brush.0[3] == 0 {
+            return;
+        }
+
         let run = glyph_run.run();
 
         let font = run.font();
         let font_size = run.font_size();
-        let font_ref = FontRef::from_index(font.data.as_ref(), font.index as usize).unwrap();
         let font_key = font.data.id();
+        let color = color_to_u32(style.brush);
+
+        let run_key = GlyphRunKey::new(glyph_run, font_key, font_size, color, (run_x, run_y));
+        let origin = [run_x.floor() as i32, run_y.floor() as i32];
+
+        let mut cache = self.glyph_cache.lock();
+        // Another renderer sharing the cache might have evicted glyphs that our cached runs point to.
+        if cache.evictions != self.seen_evictions {
+            self.seen_evictions = cache.evictions;
+            self.run_cache.clear();
+        }
+
+        if let Some(prepared) = self.run_cache.get(&run_key) {
+            for key in &prepared.glyph_keys {
+                cache.get_glyph(key, self.frame);
+            }
+            for (page_type, page, quad) in &prepared.quads {
+                let mut quad = *quad;
+                quad.pos[0] += origin[0];
+                quad.pos[1] += origin[1];
+                if let Some(clipped_quad) = clip_quad(quad, left, top, clip_rect, fade) {
+                    match page_type {
+                        AtlasPageType::Mask => page_quads(&mut self.mask_atlas_pages, *page as usize).push(clipped_quad),
+                        AtlasPageType::Color => page_quads(&mut self.color_atlas_pages, *page as usize).push(clipped_quad),
+                    };
+                }
+            }
+            return;
+        }
+
+        let font_ref = FontRef::from_index(font.data.as_ref(), font.index as usize).unwrap();
 
         // // Why is creating this struct so slow anyway?
         // // This optimization won't do anything if the font size changes a lot.
@@ -632,39 +1864,47 @@ impl ContextlessTextRenderer {
             .normalized_coords(run.normalized_coords())
             .build();
 
+        let evictions = cache.evictions;
+        let mut prepared = PreparedRun::default();
+
         for glyph in glyph_run.glyphs() {
             let glyph_ctx = GlyphWithContext::new(glyph, run_x, run_y, font_key, font_size, style.brush);
+            prepared.glyph_keys.push(glyph_ctx.key());
 
-            if let Some(stored_glyph) = self.glyph_cache.get(&glyph_ctx.key()) {
-                if let Some(stored_glyph) = stored_glyph {
-                    let quad = make_quad(&glyph_ctx, stored_glyph);
-                    if let Some(clipped_quad) = clip_quad(quad, left, top, clip_rect, fade) {
-                        let page = stored_glyph.page as usize;
-
-                        match stored_glyph.content_type {
-                            Content::Mask => self.mask_atlas_pages[page].quads.push(clipped_quad),
-                            Content::Color => self.color_atlas_pages[page].quads.push(clipped_quad),
-                            Content::SubpixelMask => unreachable!()
-                        };
-                    }
-                }
-            } else {
-                if let Some((quad, stored_glyph)) = self.prepare_glyph(&glyph_ctx, &mut scaler) {
-                    if let Some(clipped_quad) = clip_quad(quad, left, top, clip_rect, fade) {
-                        let page = stored_glyph.page as usize;
-
-                        match stored_glyph.content_type {
-                            Content::Mask => self.mask_atlas_pages[page].quads.push(clipped_quad),
-                            Content::Color => self.color_atlas_pages[page].quads.push(clipped_quad),
-                            Content::SubpixelMask => unreachable!()
-                        };
-                    }
+            let stored_glyph = match cache.get_glyph(&glyph_ctx.key(), self.frame) {
+                Some(stored_glyph) => stored_glyph,
+                None => cache.prepare_glyph(&glyph_ctx, &mut scaler, &mut self.tmp_image, self.frame, &mut self.glyphs_rasterized),
+            };
+
+            if let Some(stored_glyph) = stored_glyph {
+                let quad = make_quad(&glyph_ctx, &stored_glyph);
+                let page_type = match stored_glyph.content_type {
+                    Content::Mask => AtlasPageType::Mask,
+                    Content::Color => AtlasPageType::Color,
+                    Content::SubpixelMask => unreachable!()
+                };
+                let mut relative_quad = quad;
+                relative_quad.pos[0] -= origin[0];
+                relative_quad.pos[1] -= origin[1];
+                prepared.quads.push((page_type, stored_glyph.page, relative_quad));
+
+                if let Some(clipped_quad) = clip_quad(quad, left, top, clip_rect, fade) {
+                    let page = stored_glyph.page as usize;
+                    match page_type {
+                        AtlasPageType::Mask => page_quads(&mut self.mask_atlas_pages, page).push(clipped_quad),
+                        AtlasPageType::Color => page_quads(&mut self.color_atlas_pages, page).push(clipped_quad),
+                    };
                 }
             }
 
             run_x += glyph.advance;
         }
 
+        // If glyphs were evicted halfway through, the first quads of the run might already be stale.
+        if cache.evictions == evictions {
+            self.run_cache.put(run_key, prepared);
+        }
+
         // Draw decorations: underline & strikethrough
         // let style = glyph_run.style();
         // let run_metrics = run.metrics();
@@ -682,228 +1922,59 @@ impl ContextlessTextRenderer {
         // }
     }
 
-    fn copy_glyph_to_atlas(&mut self, size: Size2D<i32, UnknownUnit>, alloc: &Allocation, page: usize, content_type: Content) {
-        for y in 0..size.height as i32 {
-            let src_start = (y as usize) * (size.width as usize);
-            let src_slice =
-                &self.tmp_image.data[src_start..(src_start + size.width as usize)];
-
-            let dst_y = (alloc.rectangle.min.y + y) as u32;
-            let dst_x = alloc.rectangle.min.x as u32;
-
-            match content_type {
-                Content::Mask => {
-                    let layout = self.mask_atlas_pages[page].image.as_flat_samples().layout;
-                    let mut samples = self.mask_atlas_pages[page].image.as_flat_samples_mut();
-                    let samples = samples.as_mut_slice();
-                    let dst_start =
-                    (dst_y as usize) * layout.height_stride + (dst_x as usize) * layout.width_stride;
-    
-                samples[dst_start..(dst_start + size.width as usize)].copy_from_slice(src_slice);
-                },
-                Content::Color => {
-                    // todo: rewrite this with a cool copy_from_slice
-                    let layout = self.color_atlas_pages[page].image.as_flat_samples().layout;
-                    let mut samples = self.color_atlas_pages[page].image.as_flat_samples_mut();
-                    let samples = samples.as_mut_slice();
-                    
-                    // For RGBA, each pixel is 4 bytes
-                    for x in 0..size.width as usize {
-                        let src_idx = src_start + x;
-                        let dst_idx = (dst_y as usize) * layout.height_stride + (dst_x as usize + x) * layout.width_stride;
-                        
-                        // Copy all 4 channels
-                        for c in 0..4 {
-                            samples[dst_idx + c] = self.tmp_image.data[src_idx * 4 + c];
-                        }
-                    }
-                },
-                Content::SubpixelMask => unreachable!(),
-            };
+    fn prepare_image_replacements(&mut self, layout: &Layout<ColorBrush>, replacements: &[(std::ops::Range<usize>, ImageId)], left: f32, top: f32, clip_rect: Option<parley::Rect>, fade: bool) {
+        if replacements.is_empty() {
+            return;
         }
-    }
+        let cache = self.glyph_cache.lock();
+        for (range, id) in replacements {
+            let Some(stored_image) = cache.image(*id) else {
+                continue;
+            };
 
-    /// Render a glyph into the `self.tmp_swash_image` buffer
-    // this is going to have to return the Content (color/mask) as well
-    fn _render_glyph(&mut self, glyph: &GlyphWithContext, scaler: &mut Scaler) -> (Content, Placement) {
-        self.tmp_image.clear();
-        Render::new(SOURCES)
-            .format(Format::Alpha)
-            .offset(glyph.frac_offset())
-            .render_into(scaler, glyph.glyph.id, &mut self.tmp_image);
-        return (self.tmp_image.content, self.tmp_image.placement);
-    }
+            let selection = parley::Selection::new(
+                parley::Cursor::from_byte_index(layout, range.start, parley::Affinity::Downstream),
+                parley::Cursor::from_byte_index(layout, range.end, parley::Affinity::Upstream),
+            );
+            let mut first_rect = None;
+            selection.geometry_with(layout, |rect, _line_i| {
+                if first_rect.is_none() {
+                    first_rect = Some(rect);
+                }
+            });
+            let Some(rect) = first_rect else {
+                continue;
+            };
 
-    // /// Helper method to prepare glyph using the cached scaler
-    // fn prepare_glyph_with_cached_scaler(&mut self, glyph: &GlyphWithContext) -> Option<(Quad, StoredGlyph)> {
-    //     if self.cached_scaler.is_none() {
-    //         return None;
-    //     }
+            let x = left + rect.x0 as f32;
+            let y = top + rect.y0 as f32 + (rect.height() as f32 - stored_image.size.height as f32) / 2.0;
 
-    //     let (content, placement) = self.render_glyph_with_cached_scaler(&glyph)?;
-    //     let size = placement.size();
-        
-    //     // For some glyphs there's no image to store, like spaces.
-    //     if size.is_empty() {
-    //         self.glyph_cache.push(glyph.key(), None);
-    //         return None;
-    //     }
-        
-    //     let n_pages = match content {
-    //         Content::Mask => self.mask_atlas_pages.len(),
-    //         Content::Color => self.color_atlas_pages.len(),
-    //         Content::SubpixelMask => unreachable!(),
-    //     };
-        
-    //     // Try to allocate on existing pages
-    //     for page in 0..n_pages {
-    //         if let Some(alloc) = self.pack_rectangle(size, content, page) {
-    //             return self.store_glyph(glyph, size, &alloc, page, &placement, content);
-    //         }
-            
-    //         // Try evicting glyphs from previous frames and retry
-    //         if self.needs_evicting(self.frame) {
-    //             self.evict_old_glyphs();
-                
-    //             if let Some(alloc) = self.pack_rectangle(size, content, page) {
-    //                 return self.store_glyph(glyph, size, &alloc, page, &placement, content);
-    //             }
-    //         }
-    //     }
-        
-    //     // Create a new page and try to allocate there
-    //     let new_page: usize = self.make_new_page(content);
-    //     if let Some(alloc) = self.pack_rectangle(size, content, new_page) {
-    //         return self.store_glyph(glyph, size, &alloc, new_page, &placement, content);
-    //     }
-        
-    //     // Glyph is too large to fit even in a new empty page
-    //     self.glyph_cache.push(glyph.key(), None);
-    //     None
-    // }
-
-    // /// Render a glyph using the cached scaler
-    // fn render_glyph_with_cached_scaler(&mut self, glyph: &GlyphWithContext) -> Option<(Content, Placement)> {
-    //     if let Some(cached) = &mut self.cached_scaler {
-    //         self.tmp_image.clear();
-    //         Render::new(SOURCES)
-    //             .format(Format::Alpha)
-    //             .offset(glyph.frac_offset())
-    //             .render_into(&mut cached.scaler, glyph.glyph.id, &mut self.tmp_image);
-    //         Some((self.tmp_image.content, self.tmp_image.placement))
-    //     } else {
-    //         None
-    //     }
-    // }
-
-    /// Rasterizes the glyph in a texture atlas and returns a Quad that can be used to render it, or None if the glyph was just empty (like a space).
-    fn prepare_glyph(&mut self, glyph: &GlyphWithContext, scaler: &mut Scaler) -> Option<(Quad, StoredGlyph)> {
-        let (content, placement) = self._render_glyph(&glyph, scaler);
-        let size = placement.size();
-        
-        // For some glyphs there's no image to store, like spaces.
-        if size.is_empty() {
-            self.glyph_cache.push(glyph.key(), None);
-            return None;
-        }
-        
-        let n_pages = match content {
-            Content::Mask => self.mask_atlas_pages.len(),
-            Content::Color => self.color_atlas_pages.len(),
-            Content::SubpixelMask => unreachable!(),
-        };
-        // Try to allocate on existing pages
-        for page in 0..n_pages {
-            if let Some(alloc) = self.pack_rectangle(size, content, page) {
-                return self.store_glyph(glyph, size, &alloc, page, &placement, content);
-            }
-            
-            // Try evicting glyphs from previous frames and retry
-            if self.needs_evicting(self.frame) {
-                self.evict_old_glyphs();
-                
-                if let Some(alloc) = self.pack_rectangle(size, content, page) {
-                    return self.store_glyph(glyph, size, &alloc, page, &placement, content);
-                }
+            let quad = Quad {
+                pos: [x.round() as i32, y.round() as i32],
+                dim: [stored_image.size.width as u16, stored_image.size.height as u16],
+                uv_origin: [stored_image.alloc.rectangle.min.x as u16, stored_image.alloc.rectangle.min.y as u16],
+                color: 0xff_ff_ff_ff,
+                depth: 0.0,
+                flags: pack_flags(CONTENT_TYPE_COLOR, false),
+                clip_rect: [0, 0, 32767, 32767],
+                origin: [0, 0],
+                fade: 0,
+            };
+            if let Some(clipped_quad) = clip_quad(quad, left, top, clip_rect, fade) {
+                page_quads(&mut self.color_atlas_pages, stored_image.page as usize).push(clipped_quad);
             }
         }
-        
-        // Create a new page and try to allocate there
-        let new_page: usize = self.make_new_page(content);
-        if let Some(alloc) = self.pack_rectangle(size, content, new_page) {
-            return self.store_glyph(glyph, size, &alloc, new_page, &placement, content);
-        }
-        
-        // Glyph is too large to fit even in a new empty page. It's time to give up.
-        // todo: should probably try to catch these earlier by checking for unreasonable font sizes
-        // todo2: technically, we could split the huge glyph across multiple pages, or render it on the surface directly.
-        self.glyph_cache.push(glyph.key(), None);
-        return None;
-    }
-    
-    // Helper method to store glyph once allocation is successful
-    // todo: don't carry around `size`, alloc probably has the same data
-    fn store_glyph(&mut self, 
-            glyph: &GlyphWithContext,
-            size: Size2D<i32, UnknownUnit>                            , 
-            alloc: &Allocation, 
-            page: usize, 
-            placement: &Placement,
-            content_type: Content,
-        ) -> Option<(Quad, StoredGlyph)> {
-        self.copy_glyph_to_atlas(size, alloc, page, content_type);
-        let stored_glyph = StoredGlyph::create(alloc, placement, page, self.frame, content_type);
-        self.glyph_cache.push(glyph.key(), Some(stored_glyph));
-        let quad = make_quad(glyph, &stored_glyph);
-        Some((quad, stored_glyph))
-    }
-
-    fn pack_rectangle(&mut self, size: Size2D<i32, UnknownUnit>, content_type: Content, page: usize) -> Option<Allocation> {
-        match content_type {
-            Content::Mask => self.mask_atlas_pages[page].packer.allocate(size),
-            Content::Color => self.color_atlas_pages[page].packer.allocate(size),
-            Content::SubpixelMask => unreachable!(),
-        }
-    }
-
-    fn make_new_page(&mut self, content_type: Content) -> usize {
-        let atlas_size = self.atlas_size;
-
-        match content_type {
-            Content::Mask => {
-                // todo, deduplicate these with the ones in Setup
-                self.mask_atlas_pages.push(AtlasPage::<GrayImage> {
-                    image: GrayImage::from_pixel(atlas_size, atlas_size, Luma([0])),
-                    packer: BucketedAtlasAllocator::new(size2(atlas_size as i32, atlas_size as i32)),
-                    quads: Vec::<Quad>::with_capacity(300),
-                    gpu: None, // will be created later
-                    quad_count_before_render: 0,
-                });
-                return self.mask_atlas_pages.len() - 1;
-            },
-            Content::Color => {
-                self.color_atlas_pages.push(AtlasPage::<RgbaImage> {
-                    image: RgbaImage::from_pixel(atlas_size, atlas_size, Rgba([0, 0, 0, 0])),
-                    packer: BucketedAtlasAllocator::new(size2(atlas_size as i32, atlas_size as i32)),
-                    quads: Vec::<Quad>::with_capacity(300),
-                    gpu: None, // will be created later
-                    quad_count_before_render: 0,
-                });
-                return self.color_atlas_pages.len() - 1;
-            },
-            Content::SubpixelMask => unreachable!()
-        };
     }
 }
 
 /// A glyph with the context in which it is being drawn 
-struct GlyphWithContext {
-    glyph: Glyph,
+pub(crate) struct GlyphWithContext {
+    pub(crate) glyph: Glyph,
     color: u32,
     font_key: u64,
     font_size: f32,
-    quantized_pos_x: i32,
-    quantized_pos_y: i32,
+    pub(crate) quantized_pos_x: i32,
+    pub(crate) quantized_pos_y: i32,
     frac_pos_x: f32,
     frac_pos_y: f32,
     subpixel_bin_x: SubpixelBin<4>,
@@ -911,23 +1982,19 @@ struct GlyphWithContext {
 }
 
 impl GlyphWithContext {
-    fn new(glyph: Glyph, run_x: f32, run_y: f32, font_key: u64, font_size: f32, color: ColorBrush) -> Self {
+    pub(crate) fn new(glyph: Glyph, run_x: f32, run_y: f32, font_key: u64, font_size: f32, color: ColorBrush) -> Self {
         let glyph_x = (run_x).round() + glyph.x;
         let glyph_y = (run_y).round() - glyph.y;
 
         let (quantized_pos_x, frac_pos_x, subpixel_bin_x) = quantize(glyph_x);
         let (quantized_pos_y, frac_pos_y, subpixel_bin_y) = quantize(glyph_y);
 
-        let color = 
-          ((color.0[0] as u32) << 24)
-        + ((color.0[1] as u32) << 16)
-        + ((color.0[2] as u32) << 8)
-        + ((color.0[3] as u32) << 0);
+        let color = color_to_u32(color);
 
         Self { glyph, color, font_key, font_size, quantized_pos_x, quantized_pos_y, frac_pos_x, frac_pos_y, subpixel_bin_x, subpixel_bin_y,}
     }
 
-    fn key(&self) -> GlyphKey {
+    pub(crate) fn key(&self) -> GlyphKey {
         GlyphKey {
             font_id: self.font_key,
             glyph_id: self.glyph.id,
@@ -937,14 +2004,14 @@ impl GlyphWithContext {
         }
     }
 
-    fn frac_offset(&self) -> Vector {
+    pub(crate) fn frac_offset(&self) -> Vector {
         Vector::new(self.frac_pos_x, self.frac_pos_y)
     }
 }
 
 
 
-trait UselessTrait2 {
+pub(crate) trait UselessTrait2 {
     fn size(&self) -> Size2D<i32, UnknownUnit>;
 }
 impl UselessTrait2 for Placement {
@@ -0,0 +1,72 @@
+use crate::*;
+
+/// A non-owning reference to a text edit, obtained with [`Text::downgrade_text_edit()`].
+///
+/// Unlike [`TextEditHandle`], this can be cloned freely, and it never refers to the wrong edit: after the edit is removed, for example by [`Text::remove_old_nodes()`], upgrading returns `None` even if a new edit was added in the same slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WeakTextEditHandle {
+    pub(crate) i: u32,
+    pub(crate) generation: u64,
+}
+
+/// A non-owning reference to a text box, obtained with [`Text::downgrade_text_box()`]. See [`WeakTextEditHandle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WeakTextBoxHandle {
+    pub(crate) i: u32,
+    pub(crate) generation: u64,
+}
+
+impl WeakTextEditHandle {
+    pub fn is_alive(&self, text: &Text) -> bool {
+        text.text_edits.get(self.i as usize).is_some_and(|(_, text_box)| text_box.generation == self.generation)
+    }
+
+    /// Get the text edit, if it still exists.
+    pub fn upgrade<'a>(&self, text: &'a mut Text) -> Option<TextEditMut<'a>> {
+        if !self.is_alive(text) {
+            return None;
+        }
+        Some(text.get_full_text_edit_at(self.i))
+    }
+}
+
+impl WeakTextBoxHandle {
+    pub fn is_alive(&self, text: &Text) -> bool {
+        text.text_boxes.get(self.i as usize).is_some_and(|text_box| text_box.generation == self.generation)
+    }
+
+    /// Get the text box, if it still exists.
+    pub fn upgrade<'a>(&self, text: &'a mut Text) -> Option<TextBoxMut<'a>> {
+        if !self.is_alive(text) {
+            return None;
+        }
+        Some(text.get_full_text_box_at(self.i))
+    }
+}
+
+impl Text {
+    /// Returns true if the box that the handle refers to still exists.
+    ///
+    /// For strong handles, this can only be false after [`Text::remove_old_nodes()`] or [`Text::clear()`], the only functions that remove boxes without consuming their handles. It stays false even if a box added later reused the slot. For an [`AnyBox`], only the slot is checked.
+    pub fn is_alive<T: IntoAnyBox>(&self, handle: &T) -> bool {
+        let generation = match handle.into_anybox() {
+            AnyBox::TextEdit(i) => self.text_edits.get(i as usize).map(|(_, text_box)| text_box.generation),
+            AnyBox::TextBox(i) => self.text_boxes.get(i as usize).map(|text_box| text_box.generation),
+        };
+        match (generation, handle.generation()) {
+            (Some(current), Some(generation)) => current == generation,
+            (current, None) => current.is_some(),
+            (None, _) => false,
+        }
+    }
+
+    pub fn downgrade_text_edit(&self, handle: &TextEditHandle) -> WeakTextEditHandle {
+        self.check_handle(handle);
+        WeakTextEditHandle { i: handle.i, generation: handle.generation }
+    }
+
+    pub fn downgrade_text_box(&self, handle: &TextBoxHandle) -> WeakTextBoxHandle {
+        self.check_handle(handle);
+        WeakTextBoxHandle { i: handle.i, generation: handle.generation }
+    }
+}
@@ -0,0 +1,148 @@
+use std::ops::Range;
+
+/// Custom rules for what counts as a word, used by word movement, word deletion and double click selection.
+///
+/// By default, boxes use the Unicode word boundaries computed by Parley, which work well for prose in any language. Set custom rules with [`TextBoxMut::set_word_rules()`] or [`TextEditMut::set_word_rules()`] to make movement feel like a code editor.
+///
+/// With custom rules, word movement is in logical order and a word is a run of letters and digits, plus the characters allowed by the rules below. Ctrl+Right skips any whitespace and then a word or a run of punctuation, and Ctrl+Left does the same backwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WordRules {
+    /// Treat `_` as part of words, so that `snake_case` is one word.
+    pub underscore_in_words: bool,
+    /// Treat `-` as part of words, so that `kebab-case` is one word.
+    pub hyphen_in_words: bool,
+    /// Stop word movement and deletion inside words, at case changes and at underscores and hyphens. Double click still selects the whole word.
+    pub sub_words: bool,
+}
+
+impl Default for WordRules {
+    fn default() -> Self {
+        Self {
+            underscore_in_words: true,
+            hyphen_in_words: false,
+            sub_words: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+impl WordRules {
+    /// Rules for code editors: identifiers with underscores are one word, and movement stops at `camelCase` humps and underscores.
+    pub fn code() -> Self {
+        Self {
+            underscore_in_words: true,
+            hyphen_in_words: false,
+            sub_words: true,
+        }
+    }
+
+    fn class(&self, c: char) -> CharClass {
+        if c.is_whitespace() {
+            CharClass::Whitespace
+        } else if c.is_alphanumeric() || (c == '_' && self.underscore_in_words) || (c == '-' && self.hyphen_in_words) {
+            CharClass::Word
+        } else {
+            CharClass::Punctuation
+        }
+    }
+
+    /// The end of the run of characters of the same class as the one at `index`.
+    fn run_end(&self, text: &str, index: usize) -> usize {
+        let Some(class) = text[index..].chars().next().map(|c| self.class(c)) else {
+            return index;
+        };
+        text[index..]
+            .char_indices()
+            .find(|&(_, c)| self.class(c) != class)
+            .map_or(text.len(), |(i, _)| index + i)
+    }
+
+    /// The start of the run of characters of the same class as the one ending at `index`.
+    fn run_start(&self, text: &str, index: usize) -> usize {
+        let Some(class) = text[..index].chars().next_back().map(|c| self.class(c)) else {
+            return index;
+        };
+        text[..index]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| self.class(c) == class)
+            .last()
+            .map_or(index, |(i, _)| i)
+    }
+
+    /// The next word boundary after `index`, for Ctrl+Right and Ctrl+Delete. With `sub_words`, it stops inside words as well.
+    pub(crate) fn next_boundary(&self, text: &str, index: usize, sub_words: bool) -> usize {
+        let mut index = index.min(text.len());
+        while let Some(c) = text[index..].chars().next().filter(|c| c.is_whitespace()) {
+            index += c.len_utf8();
+        }
+        let end = self.run_end(text, index);
+        if sub_words && text[index..].chars().next().is_some_and(|c| self.class(c) == CharClass::Word) {
+            let start = self.run_start(text, index);
+            if let Some(boundary) = sub_word_boundaries(&text[start..end]).map(|b| start + b).find(|&b| b > index) {
+                return boundary;
+            }
+        }
+        end
+    }
+
+    /// The previous word boundary before `index`, for Ctrl+Left and Ctrl+Backspace. With `sub_words`, it stops inside words as well.
+    pub(crate) fn previous_boundary(&self, text: &str, index: usize, sub_words: bool) -> usize {
+        let mut index = index.min(text.len());
+        while let Some(c) = text[..index].chars().next_back().filter(|c| c.is_whitespace()) {
+            index -= c.len_utf8();
+        }
+        let start = self.run_start(text, index);
+        if sub_words && text[..index].chars().next_back().is_some_and(|c| self.class(c) == CharClass::Word) {
+            let end = self.run_end(text, index);
+            if let Some(boundary) = sub_word_boundaries(&text[start..end]).map(|b| start + b).filter(|&b| b < index).last() {
+                return boundary;
+            }
+        }
+        start
+    }
+
+    /// The word containing the character at `index`, for double click selection. Whitespace and punctuation runs count as words, like with the default boundaries.
+    pub(crate) fn word_range(&self, text: &str, index: usize) -> Range<usize> {
+        let index = index.min(text.len());
+        let index = if index == text.len() {
+            text[..index].char_indices().next_back().map_or(0, |(i, _)| i)
+        } else {
+            index
+        };
+        let end = self.run_end(text, index);
+        let start = match text[index..].chars().next() {
+            Some(c) => self.run_start(text, index + c.len_utf8()),
+            None => index,
+        };
+        start..end
+    }
+
+    /// Whether `c` is part of words under these rules.
+    pub(crate) fn is_word_char(&self, c: char) -> bool {
+        self.class(c) == CharClass::Word
+    }
+}
+
+/// Byte offsets inside `word` where a sub-word starts, in increasing order, not including 0.
+///
+/// A sub-word starts after a run of `_` or `-`, at a lowercase or digit to uppercase change (`fooBar`), and before the last capital of a run of capitals followed by a lowercase letter (`HTMLParser`).
+pub(crate) fn sub_word_boundaries(word: &str) -> impl Iterator<Item = usize> + '_ {
+    let is_separator = |c: char| c == '_' || c == '-';
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    (1..chars.len()).filter_map(move |i| {
+        let (index, c) = chars[i];
+        let prev = chars[i - 1].1;
+        let next = chars.get(i + 1).map(|&(_, c)| c);
+        let boundary = (is_separator(prev) && !is_separator(c))
+            || ((prev.is_lowercase() || prev.is_numeric()) && c.is_uppercase())
+            || (prev.is_uppercase() && c.is_uppercase() && next.is_some_and(|n| n.is_lowercase()));
+        boundary.then_some(index)
+    })
+}
@@ -0,0 +1,44 @@
+use image::RgbaImage;
+use textslabs::*;
+use winit::keyboard::Key;
+
+fn type_char(text: &mut Text, c: &str) {
+    let key = KeyInput {
+        logical_key: Key::Character(c.into()),
+        key_without_modifiers: Key::Character(c.into()),
+        pressed: true,
+        repeat: false,
+    };
+    text.handle_key_input(&key, false);
+}
+
+#[test]
+fn typing_after_a_paste_over_the_async_threshold() {
+    let mut text = Text::new_without_auto_wakeup();
+    text.set_async_layout_threshold(Some(64));
+    let handle = text.add_text_edit("", (0.0, 0.0), (300.0, 200.0), 0.0);
+    text.set_focus(&handle);
+
+    // Multi-byte characters, so that a stale layout gives offsets that aren't char boundaries.
+    let pasted = "àèìòù pasted line\n".repeat(40);
+    text.get_text_edit_mut(&handle).paste(&pasted);
+    // Drawing is allowed to send the layout to the worker thread.
+    let mut image = RgbaImage::new(300, 200);
+    text.render_to_image(&mut image);
+
+    type_char(&mut text, "x");
+    type_char(&mut text, "y");
+    assert_eq!(text.get_text_edit(&handle).raw_text(), format!("{pasted}xy"));
+
+    // Geometry queries right after the text shrank must not use the line ranges of the old layout.
+    let shorter = "short ".repeat(20);
+    text.get_text_edit_mut(&handle).set_text(shorter.clone());
+    text.render_to_image(&mut image);
+    let mut text_edit = text.get_text_edit_mut(&handle);
+    text_edit.wrap_points();
+    let lines = text_edit.lines();
+    assert!(!lines.is_empty());
+    assert_eq!(text.get_text_edit(&handle).raw_text(), shorter);
+
+    text.remove_text_edit(handle);
+}